@@ -1,19 +1,34 @@
 pub mod add;
+pub mod dlx;
+pub mod exec;
 pub mod install;
+pub mod list;
+pub mod outdated;
+pub mod remove;
 pub mod run;
+pub mod start;
 pub mod store;
+pub mod test;
+pub mod why;
 
 use crate::State;
-use add::AddArgs;
+use add::{global_manifest_path, AddArgs};
 use clap::{Parser, Subcommand};
+use dlx::DlxArgs;
+use exec::ExecArgs;
 use install::InstallArgs;
+use list::ListArgs;
 use miette::Context;
-use pacquet_executor::execute_shell;
+use outdated::OutdatedArgs;
 use pacquet_npmrc::Npmrc;
 use pacquet_package_manifest::PackageManifest;
+use remove::RemoveArgs;
 use run::RunArgs;
+use start::StartArgs;
 use std::{env, path::PathBuf};
 use store::StoreCommand;
+use test::TestArgs;
+use why::WhyArgs;
 
 /// Experimental package manager for node.js written in rust.
 #[derive(Debug, Parser)]
@@ -28,6 +43,11 @@ pub struct CliArgs {
     /// Set working directory.
     #[clap(short = 'C', long, default_value = ".")]
     pub dir: PathBuf,
+
+    /// Run scripts as the current user even when it is root.
+    /// By default, scripts run by root are run as the owner of the package directory.
+    #[clap(long, global = true)]
+    pub unsafe_perm: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -38,12 +58,26 @@ pub enum CliCommand {
     Add(AddArgs),
     /// Install packages
     Install(InstallArgs),
+    /// Remove a package
+    Remove(RemoveArgs),
+    /// Print the tree of installed packages
+    #[clap(alias = "ls")]
+    List(ListArgs),
+    /// Check for outdated packages
+    Outdated(OutdatedArgs),
+    /// Show all packages that depend on the specified package
+    Why(WhyArgs),
     /// Runs a package's "test" script, if one was provided.
-    Test,
+    Test(TestArgs),
     /// Runs a defined package script.
     Run(RunArgs),
     /// Runs an arbitrary command specified in the package's start property of its scripts object.
-    Start,
+    Start(StartArgs),
+    /// Install a package into a temporary directory and run its command without adding it
+    /// to the project.
+    Dlx(DlxArgs),
+    /// Run a command of the installed packages, from node_modules/.bin.
+    Exec(ExecArgs),
     /// Managing the package store.
     #[clap(subcommand)]
     Store(StoreCommand),
@@ -52,10 +86,40 @@ pub enum CliCommand {
 impl CliArgs {
     /// Execute the command
     pub async fn run(self) -> miette::Result<()> {
-        let CliArgs { command, dir } = self;
+        let CliArgs { command, dir, unsafe_perm } = self;
+        let (ignore_scripts, offline, prefer_offline) = match &command {
+            CliCommand::Add(args) => (args.ignore_scripts, args.offline, args.prefer_offline),
+            CliCommand::Install(args) => (args.ignore_scripts, args.offline, args.prefer_offline),
+            _ => (false, false, false),
+        };
+        let global = matches!(&command, CliCommand::Add(args) if args.global);
         let manifest_path = || dir.join("package.json");
-        let npmrc = || Npmrc::current(env::current_dir, home::home_dir, Default::default).leak();
-        let state = || State::init(manifest_path(), npmrc()).wrap_err("initialize the state");
+        let npmrc_mut = || -> miette::Result<&'static mut Npmrc> {
+            let npmrc = Npmrc::current(env::current_dir, home::home_dir, || {
+                env::var_os("PREFIX").map(PathBuf::from)
+            })
+            .wrap_err("load .npmrc")?
+            .leak();
+            npmrc.ignore_scripts |= ignore_scripts;
+            npmrc.offline |= offline;
+            npmrc.prefer_offline |= prefer_offline;
+            if global {
+                npmrc.use_global_dir();
+            }
+            Ok(npmrc)
+        };
+        let npmrc = || -> miette::Result<&'static Npmrc> { Ok(npmrc_mut()?) };
+        let state = || -> miette::Result<State> {
+            let npmrc = npmrc()?;
+            let manifest_path = match global {
+                false => manifest_path(),
+                true => global_manifest_path(npmrc)?,
+            };
+            State::init(manifest_path, npmrc).wrap_err("initialize the state")
+        };
+        let unsafe_perm_enabled =
+            || -> miette::Result<bool> { Ok(unsafe_perm || npmrc()?.unsafe_perm) };
+        let ignore_scripts_enabled = || -> miette::Result<bool> { Ok(npmrc()?.ignore_scripts) };
 
         match command {
             CliCommand::Init => {
@@ -63,30 +127,20 @@ impl CliArgs {
             }
             CliCommand::Add(args) => args.run(state()?).await?,
             CliCommand::Install(args) => args.run(state()?).await?,
-            CliCommand::Test => {
-                let manifest = PackageManifest::from_path(manifest_path())
-                    .wrap_err("getting the package.json in current directory")?;
-                if let Some(script) = manifest.script("test", false)? {
-                    execute_shell(script)
-                        .wrap_err(format!("executing command: \"{0}\"", script))?;
-                }
+            CliCommand::Remove(args) => args.run(state()?)?,
+            CliCommand::List(args) => args.run(manifest_path(), npmrc()?)?,
+            CliCommand::Outdated(args) => args.run(state()?).await?,
+            CliCommand::Why(args) => args.run()?,
+            CliCommand::Test(args) => {
+                args.run(manifest_path(), unsafe_perm_enabled()?, ignore_scripts_enabled()?)?
             }
-            CliCommand::Run(args) => args.run(manifest_path())?,
-            CliCommand::Start => {
-                // Runs an arbitrary command specified in the package's start property of its scripts
-                // object. If no start property is specified on the scripts object, it will attempt to
-                // run node server.js as a default, failing if neither are present.
-                // The intended usage of the property is to specify a command that starts your program.
-                let manifest = PackageManifest::from_path(manifest_path())
-                    .wrap_err("getting the package.json in current directory")?;
-                let command = if let Some(script) = manifest.script("start", true)? {
-                    script
-                } else {
-                    "node server.js"
-                };
-                execute_shell(command).wrap_err(format!("executing command: \"{0}\"", command))?;
+            CliCommand::Run(args) => args.run(manifest_path(), unsafe_perm_enabled()?)?,
+            CliCommand::Start(args) => {
+                args.run(manifest_path(), unsafe_perm_enabled()?, ignore_scripts_enabled()?)?
             }
-            CliCommand::Store(command) => command.run(|| npmrc())?,
+            CliCommand::Dlx(args) => args.run(npmrc_mut()?).await?,
+            CliCommand::Exec(args) => args.run(manifest_path())?,
+            CliCommand::Store(command) => command.run(npmrc)?,
         }
 
         Ok(())
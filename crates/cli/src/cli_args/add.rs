@@ -1,9 +1,14 @@
-use crate::State;
+use crate::{
+    workspace_filter::{find_filtered_workspace, select_projects, WorkspaceFilter},
+    State,
+};
 use clap::Args;
-use miette::Context;
-use pacquet_package_manager::Add;
+use miette::{Context, IntoDiagnostic};
+use pacquet_diagnostics::warn;
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manager::{link_bins, parse_package_spec, Add, AddToWorkspace};
 use pacquet_package_manifest::DependencyGroup;
-use std::path::PathBuf;
+use std::{env, fs, path::PathBuf};
 
 #[derive(Debug, Args)]
 pub struct AddDependencyOptions {
@@ -65,8 +70,9 @@ impl AddDependencyOptions {
 
 #[derive(Debug, Args)]
 pub struct AddArgs {
-    /// Name of the package
-    pub package_name: String, // TODO: 1. support version range, 2. multiple arguments, 3. name this `packages`
+    /// Name of the package, optionally followed by a version, a range, or a dist-tag,
+    /// e.g. `foo@1.2.3`, `foo@^1.0.0`, or `foo@next`.
+    pub package_name: String, // TODO: 1. multiple arguments, 2. name this `packages`
     /// --save-prod, --save-dev, --save-optional, --save-peer
     #[clap(flatten)]
     pub dependency_options: AddDependencyOptions,
@@ -74,6 +80,28 @@ pub struct AddArgs {
     /// the default semver range operator.
     #[clap(short = 'E', long = "save-exact")]
     pub save_exact: bool,
+    /// Don't run any scripts of the project or its dependencies.
+    /// Same as `ignore-scripts=true` in `.npmrc`.
+    #[clap(long)]
+    pub ignore_scripts: bool,
+    /// Don't send any request to the network. Packages that are missing from the store
+    /// and the metadata cache fail the command.
+    /// Same as `offline=true` in `.npmrc`.
+    #[clap(long)]
+    pub offline: bool,
+    /// Reuse the cached metadata without revalidating it, only fetching what is missing.
+    /// Same as `prefer-offline=true` in `.npmrc`.
+    #[clap(long)]
+    pub prefer_offline: bool,
+    /// Add the package to the workspace projects that match the filter, by name glob or by path.
+    /// Prefix with `...` to include the dependents, or suffix with `...` to include the
+    /// dependencies. Can be repeated.
+    #[clap(long)]
+    pub filter: Vec<WorkspaceFilter>,
+    /// Install the package globally, into `global-dir` instead of the current project, and
+    /// create its commands in `global-bin-dir`.
+    #[clap(short = 'g', long)]
+    pub global: bool,
     /// The directory with links to the store (default is node_modules/.pacquet).
     /// All direct and indirect dependencies of the project are linked into this directory
     #[clap(long = "virtual-store-dir", default_value = "node_modules/.pacquet")]
@@ -85,8 +113,48 @@ impl AddArgs {
     pub async fn run(self, mut state: State) -> miette::Result<()> {
         // TODO: if a package already exists in another dependency group, don't remove the existing entry.
 
-        let State { tarball_mem_cache, http_client, config, manifest, lockfile, resolved_packages } =
-            &mut state;
+        let State {
+            tarball_mem_cache,
+            http_client,
+            config,
+            manifest,
+            lockfile,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+        } = &mut state;
+
+        if self.global && !self.filter.is_empty() {
+            miette::bail!("--global can't be used with --filter");
+        }
+
+        if !self.filter.is_empty() {
+            let project_dir = manifest.path().parent().expect("manifest path has a parent");
+            let mut workspace = find_filtered_workspace(project_dir)?;
+            let project_dirs = select_projects(&workspace, &self.filter, project_dir)
+                .into_iter()
+                .map(|project| project.dir.clone())
+                .collect::<Vec<_>>();
+            if project_dirs.is_empty() {
+                miette::bail!("No projects matched the filters");
+            }
+            return AddToWorkspace {
+                tarball_mem_cache,
+                resolved_packages,
+                resolved_versions,
+                package_mem_cache,
+                http_client,
+                config,
+                workspace: &mut workspace,
+                project_dirs: &project_dirs,
+                list_dependency_groups: || self.dependency_options.dependency_groups(),
+                package_name: &self.package_name,
+                save_exact: self.save_exact,
+            }
+            .run()
+            .await
+            .wrap_err("adding a new package to the workspace");
+        }
 
         Add {
             tarball_mem_cache,
@@ -98,11 +166,43 @@ impl AddArgs {
             package_name: &self.package_name,
             save_exact: self.save_exact,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
         }
         .run()
         .await
-        .wrap_err("adding a new package")
+        .wrap_err("adding a new package")?;
+
+        if self.global {
+            link_global_bins(config, parse_package_spec(&self.package_name).0)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Path of the manifest of the globally installed packages, whose directory is created if needed.
+pub fn global_manifest_path(config: &Npmrc) -> miette::Result<PathBuf> {
+    let dir = config.global_packages_dir();
+    fs::create_dir_all(&dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("create the global directory at {dir:?}"))?;
+    Ok(dir.join("package.json"))
+}
+
+/// Create the commands of the globally installed `package_name` in `global-bin-dir`.
+fn link_global_bins(config: &Npmrc, package_name: &str) -> miette::Result<()> {
+    let bin_dir = &config.global_bin_dir;
+    link_bins(&config.modules_dir.join(package_name), bin_dir)
+        .wrap_err("linking the commands of the global package")?;
+    let in_path =
+        env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| &dir == bin_dir));
+    if !in_path {
+        warn(format_args!(
+            "The global bin directory {bin_dir:?} is not in PATH, so the commands of global packages are not available"
+        ));
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -0,0 +1,149 @@
+use crate::State;
+use clap::Args;
+use miette::{Context, IntoDiagnostic};
+use pacquet_executor::execute_program;
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manager::{command_path, parse_package_spec, Add};
+use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+
+#[derive(Debug, Args)]
+pub struct DlxArgs {
+    /// The package to install, e.g. `foo`, `foo@1.2.3`, or `foo@next`. When specified, the
+    /// first argument is the command to run instead of a package.
+    #[clap(long)]
+    pub package: Option<String>,
+
+    /// The package whose command to run (or the command with `--package`), followed by the
+    /// arguments of the command.
+    #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+impl DlxArgs {
+    /// Execute the subcommand.
+    ///
+    /// The package is installed into a temporary directory, which is removed after the command
+    /// exits. The store is shared with the projects.
+    pub async fn run(self, config: &'static mut Npmrc) -> miette::Result<()> {
+        let DlxArgs { package, args } = self;
+        let (package, command, args) = match package {
+            Some(package) => (package, Some(args[0].clone()), &args[1..]),
+            None => (args[0].clone(), None, &args[1..]),
+        };
+        let (package_name, _) = parse_package_spec(&package);
+
+        let dir = tempfile::Builder::new()
+            .prefix("pacquet-dlx-")
+            .tempdir()
+            .into_diagnostic()
+            .wrap_err("create a temporary directory")?;
+        config.modules_dir = dir.path().join("node_modules");
+        config.virtual_store_dir = config.modules_dir.join(".pnpm");
+        config.lockfile = false;
+        let config: &'static Npmrc = config;
+
+        let mut state = State::init(dir.path().join("package.json"), config)
+            .wrap_err("initialize the state")?;
+        let State {
+            tarball_mem_cache,
+            http_client,
+            manifest,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+            ..
+        } = &mut state;
+        Add {
+            tarball_mem_cache,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+            http_client,
+            config,
+            manifest,
+            lockfile: None,
+            list_dependency_groups: || [DependencyGroup::Prod],
+            package_name: &package,
+            save_exact: true,
+        }
+        .run()
+        .await
+        .wrap_err_with(|| format!("installing {package}"))?;
+
+        let command = match command {
+            Some(command) => command,
+            None => {
+                let manifest_path = config.modules_dir.join(package_name).join("package.json");
+                let manifest = PackageManifest::from_path(manifest_path)
+                    .wrap_err_with(|| format!("reading the package.json of {package_name}"))?;
+                default_command(&manifest, package_name)?
+            }
+        };
+        let program = command_path(&config.modules_dir.join(".bin"), &command);
+        if !program.exists() {
+            miette::bail!("{package} has no command named {command:?}");
+        }
+        execute_program(dir.path(), &program, args)?;
+
+        drop(dir); // cleanup
+        Ok(())
+    }
+}
+
+/// The command of `manifest` that `pacquet dlx` runs when `--package` isn't specified: the only
+/// command of the package, or the one named after the package.
+fn default_command(manifest: &PackageManifest, package_name: &str) -> miette::Result<String> {
+    let mut commands = manifest.bins()?.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+    if commands.len() == 1 {
+        return Ok(commands.remove(0));
+    }
+    let unscoped_name = package_name.rsplit('/').next().unwrap_or(package_name);
+    if let Some(command) = commands.iter().find(|command| *command == unscoped_name) {
+        return Ok(command.clone());
+    }
+    match commands.is_empty() {
+        true => miette::bail!("{package_name} has no command to run"),
+        false => miette::bail!(
+            "{package_name} has multiple commands, specify one with --package: {}",
+            commands.join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_pick_default_command() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("package.json");
+        let default_command = |manifest: serde_json::Value| {
+            fs::write(&manifest_path, manifest.to_string()).unwrap();
+            let manifest = PackageManifest::from_path(manifest_path.clone()).unwrap();
+            default_command(&manifest, "@scope/foo").ok()
+        };
+
+        eprintln!("CASE: single command");
+        let received = default_command(json!({ "name": "@scope/foo", "bin": "cli.js" }));
+        assert_eq!(received.as_deref(), Some("foo"));
+
+        eprintln!("CASE: command named after the package");
+        let received =
+            default_command(json!({ "bin": { "foo": "cli.js", "foo-helper": "helper.js" } }));
+        assert_eq!(received.as_deref(), Some("foo"));
+
+        eprintln!("CASE: ambiguous commands");
+        let received = default_command(json!({ "bin": { "bar": "bar.js", "baz": "baz.js" } }));
+        assert_eq!(received, None);
+
+        eprintln!("CASE: no command");
+        assert_eq!(default_command(json!({ "name": "@scope/foo" })), None);
+
+        drop(dir); // cleanup
+    }
+}
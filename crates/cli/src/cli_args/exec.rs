@@ -0,0 +1,67 @@
+use clap::Args;
+use pacquet_executor::execute_program;
+use pacquet_package_manager::command_path;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Args)]
+pub struct ExecArgs {
+    /// The command to run from `node_modules/.bin`, followed by its arguments.
+    #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+impl ExecArgs {
+    /// Execute the subcommand.
+    pub fn run(self, manifest_path: PathBuf) -> miette::Result<()> {
+        let ExecArgs { args } = self;
+        let (command, args) = args.split_first().expect("clap requires at least one argument");
+        let project_dir = manifest_path.parent().expect("manifest path has a parent");
+        let Some(program) = find_command(project_dir, command) else {
+            miette::bail!(
+                "Command {command:?} is not installed in {:?}, add the package that provides it first",
+                project_dir.join("node_modules").join(".bin"),
+            );
+        };
+        execute_program(project_dir, &program, args)?;
+        Ok(())
+    }
+}
+
+/// Find the command named `name` in the `node_modules/.bin` directories of `project_dir` and of
+/// its ancestors (e.g. the workspace root), nearest first.
+fn find_command(project_dir: &Path, name: &str) -> Option<PathBuf> {
+    project_dir
+        .ancestors()
+        .map(|dir| command_path(&dir.join("node_modules").join(".bin"), name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_find_command_of_project_or_ancestors() {
+        let dir = tempdir().unwrap();
+        let project_dir = dir.path().join("packages/foo");
+        let create_command = |dir: &Path, name: &str| {
+            let bin_dir = dir.join("node_modules/.bin");
+            fs::create_dir_all(&bin_dir).unwrap();
+            let path = command_path(&bin_dir, name);
+            fs::write(&path, "").unwrap();
+            path
+        };
+        let own = create_command(&project_dir, "shared");
+        create_command(dir.path(), "shared");
+        let root = create_command(dir.path(), "root-only");
+
+        assert_eq!(find_command(&project_dir, "shared"), Some(own));
+        assert_eq!(find_command(&project_dir, "root-only"), Some(root));
+        assert_eq!(find_command(&project_dir, "missing"), None);
+
+        drop(dir); // cleanup
+    }
+}
@@ -1,9 +1,18 @@
-use crate::State;
-use clap::Args;
-use pacquet_package_manager::Install;
+use crate::{
+    workspace_filter::{find_filtered_workspace, select_projects, WorkspaceFilter},
+    State,
+};
+use clap::{Args, ValueEnum};
+use miette::Context;
+use pacquet_diagnostics::warn;
+use pacquet_package_manager::{Install, InstallWorkspace, PruneDevDependencies, Workspace};
 use pacquet_package_manifest::DependencyGroup;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Args)]
+#[derive(Debug, Default, Args)]
 pub struct InstallDependencyOptions {
     /// pacquet will not install any package listed in devDependencies and will remove those insofar
     /// they were already installed, if the NODE_ENV environment variable is set to production.
@@ -18,58 +27,200 @@ pub struct InstallDependencyOptions {
     /// optionalDependencies are not installed.
     #[arg(long)]
     no_optional: bool,
+    /// Comma-separated list of dependency groups to install in addition to the ones
+    /// selected by --prod, --dev, and --no-optional.
+    #[arg(long, value_delimiter = ',')]
+    include: Vec<CliDependencyGroup>,
+    /// Comma-separated list of dependency groups to not install. Takes precedence over
+    /// every other flag.
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<CliDependencyGroup>,
+}
+
+/// Names of dependency groups accepted by `--include` and `--exclude`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CliDependencyGroup {
+    Prod,
+    Dev,
+    Optional,
+    Peer,
 }
 
 impl InstallDependencyOptions {
     /// Convert the dependency options to an iterator of [`DependencyGroup`]
     /// which filters the types of dependencies to install.
-    fn dependency_groups(&self) -> impl Iterator<Item = DependencyGroup> {
-        let &InstallDependencyOptions { prod, dev, no_optional } = self;
+    ///
+    /// **NOTE:** without `--prod` and `--dev`, `NODE_ENV=production` implies `--prod`.
+    pub(crate) fn dependency_groups(&self) -> impl Iterator<Item = DependencyGroup> {
+        self.dependency_groups_with_node_env(env::var("NODE_ENV").ok().as_deref())
+    }
+
+    /// Like [`dependency_groups`](InstallDependencyOptions::dependency_groups), with the value
+    /// of `NODE_ENV` passed explicitly.
+    fn dependency_groups_with_node_env(
+        &self,
+        node_env: Option<&str>,
+    ) -> impl Iterator<Item = DependencyGroup> {
+        let &InstallDependencyOptions { prod, dev, no_optional, ref include, ref exclude } = self;
+        let prod = prod || (!dev && node_env == Some("production"));
         let has_both = prod == dev;
-        let has_prod = has_both || prod;
-        let has_dev = has_both || dev;
-        let has_optional = !no_optional;
-        std::iter::empty()
-            .chain(has_prod.then_some(DependencyGroup::Prod))
-            .chain(has_dev.then_some(DependencyGroup::Dev))
-            .chain(has_optional.then_some(DependencyGroup::Optional))
+        let select = |group: CliDependencyGroup, default: bool| {
+            (default || include.contains(&group)) && !exclude.contains(&group)
+        };
+        let has_prod = select(CliDependencyGroup::Prod, has_both || prod);
+        let has_dev = select(CliDependencyGroup::Dev, has_both || dev);
+        let has_optional = select(CliDependencyGroup::Optional, !no_optional);
+        let has_peer = select(CliDependencyGroup::Peer, false);
+        DependencyGroup::all().filter(move |group| match group {
+            DependencyGroup::Prod => has_prod,
+            DependencyGroup::Dev => has_dev,
+            DependencyGroup::Optional => has_optional,
+            DependencyGroup::Peer => has_peer,
+        })
     }
 }
 
 #[derive(Debug, Args)]
 pub struct InstallArgs {
-    /// --prod, --dev, and --no-optional
+    /// --prod, --dev, --no-optional, --include, and --exclude
     #[clap(flatten)]
     pub dependency_options: InstallDependencyOptions,
 
     /// Don't generate a lockfile and fail if the lockfile is outdated.
     #[clap(long)]
     pub frozen_lockfile: bool,
+
+    /// Don't warn about the hooks of `.pnpmfile.cjs` being ignored.
+    #[clap(long)]
+    pub ignore_pnpmfile: bool,
+
+    /// Don't run any scripts of the project or its dependencies.
+    /// Same as `ignore-scripts=true` in `.npmrc`.
+    #[clap(long)]
+    pub ignore_scripts: bool,
+
+    /// Don't send any request to the network. Packages that are missing from the store
+    /// and the metadata cache fail the command.
+    /// Same as `offline=true` in `.npmrc`.
+    #[clap(long)]
+    pub offline: bool,
+
+    /// Reuse the cached metadata without revalidating it, only fetching what is missing.
+    /// Same as `prefer-offline=true` in `.npmrc`.
+    #[clap(long)]
+    pub prefer_offline: bool,
+
+    /// Only install the workspace projects that match the filter, by name glob or by path.
+    /// Prefix with `...` to include the dependents, or suffix with `...` to include the
+    /// dependencies. Can be repeated.
+    #[clap(long)]
+    pub filter: Vec<WorkspaceFilter>,
 }
 
 impl InstallArgs {
     pub async fn run(self, state: State) -> miette::Result<()> {
-        let State { tarball_mem_cache, http_client, config, manifest, lockfile, resolved_packages } =
-            &state;
-        let InstallArgs { dependency_options, frozen_lockfile } = self;
+        let State {
+            tarball_mem_cache,
+            http_client,
+            config,
+            manifest,
+            lockfile,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+        } = &state;
+        let InstallArgs {
+            dependency_options,
+            frozen_lockfile,
+            ignore_pnpmfile,
+            ignore_scripts: _, // already applied to `config`
+            offline: _,        // already applied to `config`
+            prefer_offline: _, // already applied to `config`
+            filter,
+        } = self;
+
+        let project_dir = manifest.path().parent().expect("manifest path has a parent");
+        if let Some(pnpmfile) = unsupported_pnpmfile(project_dir, ignore_pnpmfile) {
+            warn(format_args!(
+                "The hooks in {pnpmfile:?} are ignored because pacquet doesn't support pnpmfile. Use --ignore-pnpmfile to hide this warning."
+            ));
+        }
 
+        // A project that isn't listed by the workspace it's in is installed on its own, and so is
+        // any project with --frozen-lockfile until workspaces support it.
+        let workspace = match (filter.is_empty(), frozen_lockfile) {
+            (true, true) => None,
+            (true, false) => {
+                Workspace::find(project_dir).wrap_err("find the workspace")?.filter(|workspace| {
+                    workspace.projects.iter().any(|project| project.dir == project_dir)
+                })
+            }
+            (false, true) => miette::bail!("--frozen-lockfile is not supported in workspaces yet"),
+            (false, false) => Some(find_filtered_workspace(project_dir)?),
+        };
+        if let Some(workspace) = workspace {
+            let projects = match filter.is_empty() {
+                true => workspace.projects.iter().collect(),
+                false => select_projects(&workspace, &filter, project_dir),
+            };
+            if projects.is_empty() {
+                miette::bail!("No projects matched the filters");
+            }
+            return InstallWorkspace {
+                tarball_mem_cache,
+                resolved_packages,
+                resolved_versions,
+                package_mem_cache,
+                http_client,
+                config,
+                workspace: &workspace,
+                projects: &projects,
+                dependency_groups: dependency_options.dependency_groups(),
+            }
+            .run()
+            .await
+            .wrap_err("installing the workspace");
+        }
+
+        let dependency_groups = dependency_options.dependency_groups().collect::<Vec<_>>();
         Install {
             tarball_mem_cache,
             http_client,
             config,
             manifest,
             lockfile: lockfile.as_ref(),
-            dependency_groups: dependency_options.dependency_groups(),
+            dependency_groups: dependency_groups.iter().copied(),
             frozen_lockfile,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
         }
         .run()
-        .await;
+        .await
+        .wrap_err("installing dependencies")?;
+
+        // A production install removes the devDependencies that a previous install left behind.
+        if !dependency_groups.contains(&DependencyGroup::Dev) {
+            PruneDevDependencies { config, manifest }.run().wrap_err("removing devDependencies")?;
+        }
 
         Ok(())
     }
 }
 
+/// Name of the file that contains pnpm hooks.
+const PNPMFILE_NAME: &str = ".pnpmfile.cjs";
+
+/// Return the path to the pnpmfile of the project if it exists and the user has not
+/// chosen to ignore it.
+fn unsupported_pnpmfile(project_dir: &Path, ignore_pnpmfile: bool) -> Option<PathBuf> {
+    if ignore_pnpmfile {
+        return None;
+    }
+    let pnpmfile = project_dir.join(PNPMFILE_NAME);
+    pnpmfile.is_file().then_some(pnpmfile)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,50 +235,188 @@ mod tests {
 
         // no flags -> prod + dev + optional
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: false, dev: false, no_optional: false }),
+            create_list(InstallDependencyOptions {
+                prod: false,
+                dev: false,
+                no_optional: false,
+                ..Default::default()
+            }),
             [Prod, Dev, Optional],
         );
 
         // --prod -> prod + optional
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: true, dev: false, no_optional: false }),
+            create_list(InstallDependencyOptions {
+                prod: true,
+                dev: false,
+                no_optional: false,
+                ..Default::default()
+            }),
             [Prod, Optional],
         );
 
         // --dev -> dev + optional
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: false, dev: true, no_optional: false }),
+            create_list(InstallDependencyOptions {
+                prod: false,
+                dev: true,
+                no_optional: false,
+                ..Default::default()
+            }),
             [Dev, Optional],
         );
 
         // --no-optional -> prod + dev
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: false, dev: false, no_optional: true }),
+            create_list(InstallDependencyOptions {
+                prod: false,
+                dev: false,
+                no_optional: true,
+                ..Default::default()
+            }),
             [Prod, Dev],
         );
 
         // --prod --no-optional -> prod
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: true, dev: false, no_optional: true }),
+            create_list(InstallDependencyOptions {
+                prod: true,
+                dev: false,
+                no_optional: true,
+                ..Default::default()
+            }),
             [Prod],
         );
 
         // --dev --no-optional -> dev
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: false, dev: true, no_optional: true }),
+            create_list(InstallDependencyOptions {
+                prod: false,
+                dev: true,
+                no_optional: true,
+                ..Default::default()
+            }),
             [Dev],
         );
 
         // --prod --dev -> prod + dev + optional
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: true, dev: true, no_optional: false }),
+            create_list(InstallDependencyOptions {
+                prod: true,
+                dev: true,
+                no_optional: false,
+                ..Default::default()
+            }),
             [Prod, Dev, Optional],
         );
 
         // --prod --dev --no-optional -> prod + dev
         assert_eq!(
-            create_list(InstallDependencyOptions { prod: true, dev: true, no_optional: true }),
+            create_list(InstallDependencyOptions {
+                prod: true,
+                dev: true,
+                no_optional: true,
+                ..Default::default()
+            }),
             [Prod, Dev],
         );
     }
+
+    #[test]
+    fn include_and_exclude_to_dependency_groups() {
+        use CliDependencyGroup as Cli;
+        use DependencyGroup::{Dev, Optional, Peer, Prod};
+        let create_list = |include: &[CliDependencyGroup], exclude: &[CliDependencyGroup]| {
+            InstallDependencyOptions {
+                include: include.to_vec(),
+                exclude: exclude.to_vec(),
+                ..Default::default()
+            }
+            .dependency_groups()
+            .collect::<Vec<_>>()
+        };
+
+        // --include=peer -> prod + dev + optional + peer
+        assert_eq!(create_list(&[Cli::Peer], &[]), [Prod, Dev, Optional, Peer]);
+
+        // --exclude=dev -> prod + optional
+        assert_eq!(create_list(&[], &[Cli::Dev]), [Prod, Optional]);
+
+        // --exclude=prod,optional -> dev
+        assert_eq!(create_list(&[], &[Cli::Prod, Cli::Optional]), [Dev]);
+
+        // --include=peer --exclude=peer -> prod + dev + optional (exclude wins)
+        assert_eq!(create_list(&[Cli::Peer], &[Cli::Peer]), [Prod, Dev, Optional]);
+
+        // --include=dev --exclude=dev -> prod + optional (exclude wins)
+        assert_eq!(create_list(&[Cli::Dev], &[Cli::Dev]), [Prod, Optional]);
+
+        // --prod --include=dev -> prod + dev + optional
+        assert_eq!(
+            InstallDependencyOptions { prod: true, include: vec![Cli::Dev], ..Default::default() }
+                .dependency_groups()
+                .collect::<Vec<_>>(),
+            [Prod, Dev, Optional],
+        );
+
+        // --no-optional --include=optional,peer -> prod + dev + optional + peer
+        assert_eq!(
+            InstallDependencyOptions {
+                no_optional: true,
+                include: vec![Cli::Optional, Cli::Peer],
+                ..Default::default()
+            }
+            .dependency_groups()
+            .collect::<Vec<_>>(),
+            [Prod, Dev, Optional, Peer],
+        );
+    }
+
+    #[test]
+    fn node_env_to_dependency_groups() {
+        use CliDependencyGroup as Cli;
+        use DependencyGroup::{Dev, Optional, Prod};
+        let create_list = |opts: InstallDependencyOptions, node_env: Option<&str>| {
+            opts.dependency_groups_with_node_env(node_env).collect::<Vec<_>>()
+        };
+
+        // NODE_ENV=production -> prod + optional
+        assert_eq!(
+            create_list(InstallDependencyOptions::default(), Some("production")),
+            [Prod, Optional],
+        );
+
+        // NODE_ENV=development -> prod + dev + optional
+        assert_eq!(
+            create_list(InstallDependencyOptions::default(), Some("development")),
+            [Prod, Dev, Optional],
+        );
+
+        // NODE_ENV=production --dev -> dev + optional
+        assert_eq!(
+            create_list(
+                InstallDependencyOptions { dev: true, ..Default::default() },
+                Some("production")
+            ),
+            [Dev, Optional],
+        );
+
+        // NODE_ENV=production --prod --dev -> prod + dev + optional
+        assert_eq!(
+            create_list(
+                InstallDependencyOptions { prod: true, dev: true, ..Default::default() },
+                Some("production")
+            ),
+            [Prod, Dev, Optional],
+        );
+
+        // NODE_ENV=production --include=dev -> prod + dev + optional
+        assert_eq!(
+            create_list(
+                InstallDependencyOptions { include: vec![Cli::Dev], ..Default::default() },
+                Some("production")
+            ),
+            [Prod, Dev, Optional],
+        );
+    }
 }
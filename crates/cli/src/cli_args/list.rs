@@ -0,0 +1,39 @@
+use crate::cli_args::install::InstallDependencyOptions;
+use clap::Args;
+use miette::Context;
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manager::{render_list_tree, List};
+use pacquet_package_manifest::PackageManifest;
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// --prod, --dev, --no-optional, --include, and --exclude
+    #[clap(flatten)]
+    pub dependency_options: InstallDependencyOptions,
+
+    /// Max display depth of the dependency tree. 0 only lists the direct dependencies.
+    #[clap(long, default_value_t = 0)]
+    pub depth: usize,
+}
+
+impl ListArgs {
+    /// Execute the subcommand.
+    pub fn run(self, manifest_path: PathBuf, config: &'static Npmrc) -> miette::Result<()> {
+        let ListArgs { dependency_options, depth } = self;
+
+        let manifest = PackageManifest::from_path(manifest_path)
+            .wrap_err("getting the package.json in current directory")?;
+
+        let tree = List {
+            config,
+            manifest: &manifest,
+            dependency_groups: dependency_options.dependency_groups(),
+            depth,
+        }
+        .run();
+
+        print!("{}", render_list_tree(&tree));
+        Ok(())
+    }
+}
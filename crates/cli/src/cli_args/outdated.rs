@@ -0,0 +1,203 @@
+use crate::State;
+use clap::Args;
+use miette::{Context, IntoDiagnostic};
+use pacquet_package_manager::{Outdated, OutdatedPackage};
+use pacquet_package_manifest::DependencyGroup;
+use std::process;
+
+#[derive(Debug, Args)]
+pub struct OutdatedArgs {
+    /// Check only dependencies and optionalDependencies.
+    #[clap(short = 'P', long)]
+    prod: bool,
+    /// Check only devDependencies.
+    #[clap(short = 'D', long)]
+    dev: bool,
+    /// Don't check optionalDependencies.
+    #[clap(long)]
+    no_optional: bool,
+    /// Print the outdated packages as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+impl OutdatedArgs {
+    /// Convert the flags to an iterator of [`DependencyGroup`] to check.
+    fn dependency_groups(&self) -> impl Iterator<Item = DependencyGroup> {
+        let &OutdatedArgs { prod, dev, no_optional, .. } = self;
+        let has_both = prod == dev;
+        std::iter::empty()
+            .chain((has_both || prod).then_some(DependencyGroup::Prod))
+            .chain((has_both || dev).then_some(DependencyGroup::Dev))
+            .chain((!no_optional && (has_both || prod)).then_some(DependencyGroup::Optional))
+    }
+
+    /// Execute the subcommand.
+    ///
+    /// The process exits with code 1 when any dependency is outdated.
+    pub async fn run(self, state: State) -> miette::Result<()> {
+        let State { http_client, config, manifest, lockfile, .. } = &state;
+
+        let outdated = Outdated {
+            http_client,
+            config,
+            manifest,
+            lockfile: lockfile.as_ref(),
+            dependency_groups: self.dependency_groups(),
+        }
+        .run()
+        .await
+        .wrap_err("checking for outdated packages")?;
+
+        if self.json {
+            let json =
+                serde_json::to_string_pretty(&outdated_to_json(&outdated)).into_diagnostic()?;
+            println!("{json}");
+        } else if !outdated.is_empty() {
+            print!("{}", render_table(&outdated));
+        }
+        if outdated.is_empty() {
+            return Ok(());
+        }
+        process::exit(1);
+    }
+}
+
+/// Map the names of the outdated packages to their versions and the type of their updates.
+fn outdated_to_json(outdated: &[OutdatedPackage]) -> serde_json::Value {
+    outdated
+        .iter()
+        .map(|package| {
+            let version = |version: &Option<_>| version.as_ref().map(ToString::to_string);
+            let entry = serde_json::json!({
+                "current": version(&package.current),
+                "wanted": version(&package.wanted),
+                "latest": package.latest.to_string(),
+                "type": package.update_type().map(|update_type| update_type.to_string()),
+            });
+            (package.name.clone(), entry)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Render the outdated packages as a table with aligned columns.
+fn render_table(outdated: &[OutdatedPackage]) -> String {
+    let header = ["Package", "Current", "Wanted", "Latest"].map(String::from);
+    let rows = outdated.iter().map(|package| {
+        let version = |version: &Option<_>| {
+            version.as_ref().map_or_else(|| "missing".to_string(), ToString::to_string)
+        };
+        [
+            package.name.clone(),
+            version(&package.current),
+            version(&package.wanted),
+            package.latest.to_string(),
+        ]
+    });
+    let rows = std::iter::once(header).chain(rows).collect::<Vec<_>>();
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = cell.len().max(*width);
+        }
+    }
+
+    let mut table = String::new();
+    for row in &rows {
+        let cells = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>();
+        table += cells.join(" | ").trim_end();
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block_fnl;
+
+    #[test]
+    fn dependency_groups() {
+        use DependencyGroup::{Dev, Optional, Prod};
+        let create_list = |prod, dev, no_optional| {
+            OutdatedArgs { prod, dev, no_optional, json: false }
+                .dependency_groups()
+                .collect::<Vec<_>>()
+        };
+
+        // no flags -> all groups
+        assert_eq!(create_list(false, false, false), [Prod, Dev, Optional]);
+
+        // --prod -> prod + optional
+        assert_eq!(create_list(true, false, false), [Prod, Optional]);
+
+        // --dev -> dev
+        assert_eq!(create_list(false, true, false), [Dev]);
+
+        // --no-optional -> prod + dev
+        assert_eq!(create_list(false, false, true), [Prod, Dev]);
+    }
+
+    #[test]
+    fn render_outdated_packages() {
+        let version = |version: &str| version.parse().unwrap();
+        let outdated = [
+            OutdatedPackage {
+                name: "@pnpm.e2e/hello-world-js-bin".to_string(),
+                current: Some(version("1.0.0")),
+                wanted: Some(version("1.0.0")),
+                latest: version("2.0.0"),
+            },
+            OutdatedPackage {
+                name: "foo".to_string(),
+                current: None,
+                wanted: None,
+                latest: version("10.0.0"),
+            },
+        ];
+        let expected = text_block_fnl! {
+            "Package                      | Current | Wanted  | Latest"
+            "@pnpm.e2e/hello-world-js-bin | 1.0.0   | 1.0.0   | 2.0.0"
+            "foo                          | missing | missing | 10.0.0"
+        };
+        assert_eq!(render_table(&outdated), expected);
+    }
+
+    #[test]
+    fn outdated_packages_to_json() {
+        let version = |version: &str| version.parse().unwrap();
+        let outdated = [
+            OutdatedPackage {
+                name: "major".to_string(),
+                current: Some(version("1.0.0")),
+                wanted: Some(version("1.0.0")),
+                latest: version("2.0.0"),
+            },
+            OutdatedPackage {
+                name: "missing".to_string(),
+                current: None,
+                wanted: None,
+                latest: version("1.0.0"),
+            },
+            OutdatedPackage {
+                name: "patch".to_string(),
+                current: Some(version("1.0.0")),
+                wanted: Some(version("1.0.1")),
+                latest: version("1.0.1"),
+            },
+        ];
+        let expected = serde_json::json!({
+            "major": { "current": "1.0.0", "wanted": "1.0.0", "latest": "2.0.0", "type": "major" },
+            "missing": { "current": null, "wanted": null, "latest": "1.0.0", "type": null },
+            "patch": { "current": "1.0.0", "wanted": "1.0.1", "latest": "1.0.1", "type": "patch" },
+        });
+        assert_eq!(outdated_to_json(&outdated), expected);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::State;
+use clap::Args;
+use miette::Context;
+use pacquet_package_manager::Remove;
+use pacquet_package_manifest::DependencyGroup;
+
+#[derive(Debug, Args)]
+pub struct RemoveDependencyOptions {
+    /// Remove the specified packages from dependencies only.
+    #[clap(short = 'P', long)]
+    save_prod: bool,
+    /// Remove the specified packages from devDependencies only.
+    #[clap(short = 'D', long)]
+    save_dev: bool,
+    /// Remove the specified packages from optionalDependencies only.
+    #[clap(short = 'O', long)]
+    save_optional: bool,
+}
+
+impl RemoveDependencyOptions {
+    /// Convert the `--save-*` flags to an iterator of [`DependencyGroup`]
+    /// which selects which groups to remove from.
+    ///
+    /// **NOTE:** no `--save-*` flags implies all groups.
+    fn dependency_groups(&self) -> impl Iterator<Item = DependencyGroup> {
+        let &RemoveDependencyOptions { save_prod, save_dev, save_optional } = self;
+        let all = !save_prod && !save_dev && !save_optional;
+        DependencyGroup::all().filter(move |group| match group {
+            DependencyGroup::Prod => all || save_prod,
+            DependencyGroup::Dev => all || save_dev,
+            DependencyGroup::Optional => all || save_optional,
+            DependencyGroup::Peer => all,
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveArgs {
+    /// Name of the package
+    pub package_name: String, // TODO: support multiple arguments
+    /// --save-prod, --save-dev, --save-optional
+    #[clap(flatten)]
+    pub dependency_options: RemoveDependencyOptions,
+}
+
+impl RemoveArgs {
+    /// Execute the subcommand.
+    pub fn run(self, mut state: State) -> miette::Result<()> {
+        let State { config, manifest, lockfile, .. } = &mut state;
+
+        Remove {
+            config,
+            manifest,
+            lockfile: lockfile.as_mut(),
+            dependency_groups: self.dependency_options.dependency_groups(),
+            package_name: &self.package_name,
+        }
+        .run()
+        .wrap_err("removing a package")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn dependency_options_to_dependency_groups() {
+        use DependencyGroup::{Dev, Optional, Peer, Prod};
+        let create_list = |save_prod, save_dev, save_optional| {
+            RemoveDependencyOptions { save_prod, save_dev, save_optional }
+                .dependency_groups()
+                .collect::<Vec<_>>()
+        };
+
+        // no flags -> all groups
+        assert_eq!(create_list(false, false, false), [Prod, Dev, Optional, Peer]);
+
+        // --save-prod -> prod
+        assert_eq!(create_list(true, false, false), [Prod]);
+
+        // --save-dev -> dev
+        assert_eq!(create_list(false, true, false), [Dev]);
+
+        // --save-optional -> optional
+        assert_eq!(create_list(false, false, true), [Optional]);
+
+        // --save-prod --save-dev -> prod + dev
+        assert_eq!(create_list(true, true, false), [Prod, Dev]);
+    }
+}
@@ -1,13 +1,14 @@
+use crate::workspace_filter::{find_filtered_workspace, select_projects, WorkspaceFilter};
 use clap::Args;
 use miette::Context;
-use pacquet_executor::execute_shell;
+use pacquet_executor::{execute_shell_as, ScriptUser};
 use pacquet_package_manifest::PackageManifest;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Args)]
 pub struct RunArgs {
-    /// A pre-defined package script.
-    pub command: String,
+    /// A pre-defined package script. List the scripts of the package if omitted.
+    pub command: Option<String>,
 
     /// Any additional arguments passed after the script name
     pub args: Vec<String>,
@@ -17,25 +18,176 @@ pub struct RunArgs {
     /// execution chain.
     #[clap(long)]
     pub if_present: bool,
+
+    /// Run the script in the workspace projects that match the filter, by name glob or by path.
+    /// Prefix with `...` to include the dependents, or suffix with `...` to include the
+    /// dependencies. Can be repeated.
+    #[clap(long, requires = "command")]
+    pub filter: Vec<WorkspaceFilter>,
 }
 
 impl RunArgs {
     /// Execute the subcommand.
-    pub fn run(self, manifest_path: PathBuf) -> miette::Result<()> {
-        let RunArgs { command, args, if_present } = self;
+    pub fn run(self, manifest_path: PathBuf, unsafe_perm: bool) -> miette::Result<()> {
+        let RunArgs { command, args, if_present, filter } = self;
+
+        let Some(command) = command else {
+            let manifest = PackageManifest::from_path(manifest_path)
+                .wrap_err("getting the package.json in current directory")?;
+            print!("{}", list_scripts(&manifest));
+            return Ok(());
+        };
+
+        if !filter.is_empty() {
+            return run_in_projects(
+                &manifest_path,
+                &filter,
+                &command,
+                &args,
+                if_present,
+                unsafe_perm,
+            );
+        }
 
         let manifest = PackageManifest::from_path(manifest_path)
             .wrap_err("getting the package.json in current directory")?;
 
         if let Some(script) = manifest.script(&command, if_present)? {
-            let mut command = script.to_string();
-            // append an empty space between script and additional args
-            command.push(' ');
-            // then append the additional args
-            command.push_str(&args.join(" "));
-            execute_shell(command.trim())?;
+            let user = script_user(&manifest, unsafe_perm)?;
+            execute_script(&manifest, script, &args, user)?;
         }
 
         Ok(())
     }
 }
+
+/// Describe the scripts of `manifest` the way `pnpm run` without a script name does.
+fn list_scripts(manifest: &PackageManifest) -> String {
+    let mut scripts = manifest.scripts().peekable();
+    if scripts.peek().is_none() {
+        return "There are no scripts specified.\n".to_string();
+    }
+    let mut output = "Commands available via \"pacquet run\":\n".to_string();
+    for (name, script) in scripts {
+        output.push_str(&format!("  {name}\n    {script}\n"));
+    }
+    output
+}
+
+/// Run the script named `command` in every project selected by `filter` that defines it.
+///
+/// Fail if no selected project defines the script, unless `if_present` is set.
+fn run_in_projects(
+    manifest_path: &Path,
+    filter: &[WorkspaceFilter],
+    command: &str,
+    args: &[String],
+    if_present: bool,
+    unsafe_perm: bool,
+) -> miette::Result<()> {
+    let project_dir = manifest_path.parent().expect("manifest path has a parent");
+    let workspace = find_filtered_workspace(project_dir)?;
+    let mut found = false;
+    for project in select_projects(&workspace, filter, project_dir) {
+        let Some(script) = project.manifest.script(command, true)? else { continue };
+        found = true;
+        let user = script_user(&project.manifest, unsafe_perm)?;
+        execute_script(&project.manifest, script, args, user)
+            .wrap_err_with(|| format!("running {command:?} in {:?}", project.dir))?;
+    }
+    if !found && !if_present {
+        miette::bail!("None of the selected projects has the script {command:?}");
+    }
+    Ok(())
+}
+
+/// Decide which user the scripts of `manifest` should run as.
+pub(crate) fn script_user(
+    manifest: &PackageManifest,
+    unsafe_perm: bool,
+) -> miette::Result<Option<ScriptUser>> {
+    let dir = manifest.path().parent().expect("manifest path has a parent");
+    Ok(ScriptUser::for_dir(dir, unsafe_perm)?)
+}
+
+/// Execute a script of `manifest` with additional arguments appended to it.
+pub(crate) fn execute_script(
+    manifest: &PackageManifest,
+    script: &str,
+    args: &[String],
+    user: Option<ScriptUser>,
+) -> miette::Result<()> {
+    let mut command = script.to_string();
+    // append an empty space between script and additional args
+    command.push(' ');
+    // then append the additional args
+    command.push_str(&args.join(" "));
+    let project_dir = manifest.path().parent().expect("manifest path has a parent");
+    execute_shell_as(project_dir, command.trim(), user)?;
+    Ok(())
+}
+
+/// Execute `script` of the script named `name` surrounded by the `pre{name}` and `post{name}` scripts (if defined).
+///
+/// The additional arguments are only passed to the main script.
+/// The `pre{name}` and `post{name}` scripts are skipped if `ignore_scripts` is set.
+pub(crate) fn run_script_with_hooks(
+    manifest: &PackageManifest,
+    name: &str,
+    script: &str,
+    args: &[String],
+    user: Option<ScriptUser>,
+    ignore_scripts: bool,
+) -> miette::Result<()> {
+    let hook = |prefix: &str| match ignore_scripts {
+        true => Ok(None),
+        false => manifest.script(&format!("{prefix}{name}"), true),
+    };
+
+    if let Some(pre_script) = hook("pre")? {
+        execute_script(manifest, pre_script, &[], user)
+            .wrap_err(format!("executing command: \"{pre_script}\""))?;
+    }
+
+    execute_script(manifest, script, args, user)
+        .wrap_err(format!("executing command: \"{script}\""))?;
+
+    if let Some(post_script) = hook("post")? {
+        execute_script(manifest, post_script, &[], user)
+            .wrap_err(format!("executing command: \"{post_script}\""))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_list_scripts() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("package.json");
+        let list_scripts = |manifest: serde_json::Value| {
+            fs::write(&manifest_path, manifest.to_string()).unwrap();
+            let manifest = PackageManifest::from_path(manifest_path.clone()).unwrap();
+            list_scripts(&manifest)
+        };
+
+        eprintln!("CASE: some scripts");
+        let received = list_scripts(json!({ "scripts": { "build": "tsc", "test": "jest" } }));
+        let expected =
+            "Commands available via \"pacquet run\":\n  build\n    tsc\n  test\n    jest\n";
+        assert_eq!(received, expected);
+
+        eprintln!("CASE: no scripts");
+        let received = list_scripts(json!({}));
+        assert_eq!(received, "There are no scripts specified.\n");
+
+        drop(dir); // cleanup
+    }
+}
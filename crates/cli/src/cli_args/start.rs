@@ -0,0 +1,60 @@
+use crate::cli_args::run::{run_script_with_hooks, script_user};
+use clap::Args;
+use derive_more::{Display, Error};
+use miette::{Context, Diagnostic};
+use pacquet_package_manifest::PackageManifest;
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+pub struct StartArgs {
+    /// Any additional arguments passed after `--` to the start script.
+    #[clap(last = true)]
+    pub args: Vec<String>,
+}
+
+/// Error when there is neither a start script nor a `server.js` file.
+#[derive(Debug, Display, Error, Diagnostic)]
+#[display("Missing script: \"start\" and there is no server.js to fall back to")]
+#[diagnostic(
+    code(pacquet_cli::no_start_script),
+    help("Define a \"start\" script in package.json or create a server.js file")
+)]
+pub struct NoStartScriptError;
+
+/// Command to run when the start script isn't defined.
+const DEFAULT_START_COMMAND: &str = "node server.js";
+
+impl StartArgs {
+    /// Execute the subcommand.
+    ///
+    /// The `prestart` and `poststart` scripts are skipped if `ignore_scripts` is set.
+    pub fn run(
+        self,
+        manifest_path: PathBuf,
+        unsafe_perm: bool,
+        ignore_scripts: bool,
+    ) -> miette::Result<()> {
+        let StartArgs { args } = self;
+
+        let manifest = PackageManifest::from_path(manifest_path)
+            .wrap_err("getting the package.json in current directory")?;
+
+        // Runs an arbitrary command specified in the package's start property of its scripts
+        // object. If no start property is specified on the scripts object, it will attempt to
+        // run node server.js as a default, failing if neither are present.
+        // The intended usage of the property is to specify a command that starts your program.
+        let script = match manifest.script("start", true)? {
+            Some(script) => script,
+            None if has_server_js(&manifest) => DEFAULT_START_COMMAND,
+            None => return Err(NoStartScriptError.into()),
+        };
+
+        let user = script_user(&manifest, unsafe_perm)?;
+        run_script_with_hooks(&manifest, "start", script, &args, user, ignore_scripts)
+    }
+}
+
+/// Whether there is a `server.js` file next to the `package.json`.
+fn has_server_js(manifest: &PackageManifest) -> bool {
+    manifest.path().parent().is_some_and(|dir| dir.join("server.js").is_file())
+}
@@ -1,11 +1,18 @@
+use crate::cli_args::why::NoLockfileError;
 use clap::Subcommand;
 use miette::Context;
+use pacquet_lockfile::Lockfile;
 use pacquet_npmrc::Npmrc;
+use pacquet_package_manager::{ModifiedPackage, StoreStatus};
+use pacquet_store_dir::{CorruptedCasFile, PackageFilesIssue, PruneStats};
+use std::process;
 
 #[derive(Debug, Subcommand)]
 pub enum StoreCommand {
     /// Checks for modified packages in the store.
-    Store,
+    ///
+    /// The process exits with code 1 when any installed package differs from the store.
+    Status,
     /// Functionally equivalent to pnpm add, except this adds new packages to the store directly
     /// without modifying any projects or files outside of the store.
     Add,
@@ -16,26 +23,157 @@ pub enum StoreCommand {
     Prune,
     /// Returns the path to the active store directory.
     Path,
+    /// Checks the files in the store against the integrities recorded in the index files.
+    ///
+    /// The process exits with code 1 when any file is corrupted, unless `--repair` is specified.
+    Verify {
+        /// Remove the corrupted files so that they will be fetched again on the next install.
+        #[clap(long)]
+        repair: bool,
+    },
 }
 
 impl StoreCommand {
     /// Execute the subcommand.
-    pub fn run<'a>(self, config: impl FnOnce() -> &'a Npmrc) -> miette::Result<()> {
+    pub fn run(
+        self,
+        config: impl FnOnce() -> miette::Result<&'static Npmrc>,
+    ) -> miette::Result<()> {
         match self {
-            StoreCommand::Store => {
-                panic!("Not implemented")
+            StoreCommand::Status => {
+                let config = config()?;
+                let lockfile = Lockfile::load_from_current_dir()
+                    .wrap_err("loading the lockfile")?
+                    .ok_or(NoLockfileError)?;
+                let modified = StoreStatus { config, lockfile: &lockfile }.run();
+                if modified.is_empty() {
+                    return Ok(());
+                }
+                print!("{}", render_modified_packages(&modified));
+                process::exit(1);
             }
             StoreCommand::Add => {
                 panic!("Not implemented")
             }
             StoreCommand::Prune => {
-                config().store_dir.prune().wrap_err("pruning store")?;
+                let PruneStats { removed_files, removed_bytes } =
+                    config()?.store_dir.prune().wrap_err("pruning store")?;
+                println!("Removed {removed_files} files ({removed_bytes} bytes)");
             }
             StoreCommand::Path => {
-                println!("{}", config().store_dir.display());
+                println!("{}", config()?.store_dir.display());
+            }
+            StoreCommand::Verify { repair } => {
+                let store_dir = &config()?.store_dir;
+                let corrupted = store_dir.verify();
+                if corrupted.is_empty() {
+                    return Ok(());
+                }
+                print!("{}", render_corrupted_files(&corrupted));
+                if !repair {
+                    process::exit(1);
+                }
+                let removed = store_dir.repair(&corrupted).wrap_err("repairing store")?;
+                println!("Removed {removed} corrupted files");
             }
         }
 
         Ok(())
     }
 }
+
+/// Render each modified package followed by its issues.
+fn render_modified_packages(modified: &[ModifiedPackage]) -> String {
+    modified
+        .iter()
+        .map(|ModifiedPackage { dependency_path, issues }| {
+            let issues = issues
+                .iter()
+                .map(|issue| match issue {
+                    PackageFilesIssue::MissingIndex => "  missing from the store\n".to_string(),
+                    PackageFilesIssue::MissingCasFile { path } => {
+                        format!("  {path}: missing from the store\n")
+                    }
+                    PackageFilesIssue::ModifiedCasFile { path } => {
+                        format!("  {path}: modified in the store\n")
+                    }
+                    PackageFilesIssue::ModifiedFile { path } => format!("  {path}: modified\n"),
+                })
+                .collect::<String>();
+            format!("{dependency_path}\n{issues}")
+        })
+        .collect()
+}
+
+/// Render the corrupted files grouped by the tarballs that contain them.
+fn render_corrupted_files(corrupted: &[CorruptedCasFile]) -> String {
+    let mut rendered = String::new();
+    let mut previous = None;
+    for CorruptedCasFile { tarball_integrity, path, missing, .. } in corrupted {
+        if previous != Some(tarball_integrity) {
+            rendered += &format!("{tarball_integrity}\n");
+            previous = Some(tarball_integrity);
+        }
+        let problem = if *missing { "missing" } else { "modified" };
+        rendered += &format!("  {path}: {problem}\n");
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+    use text_block_macros::text_block_fnl;
+
+    #[test]
+    fn render_issues_by_package() {
+        let modified = [
+            ModifiedPackage {
+                dependency_path: "/bar@1.0.0".parse().unwrap(),
+                issues: vec![PackageFilesIssue::MissingIndex],
+            },
+            ModifiedPackage {
+                dependency_path: "/foo@1.0.0".parse().unwrap(),
+                issues: vec![
+                    PackageFilesIssue::ModifiedFile { path: "index.js".to_string() },
+                    PackageFilesIssue::ModifiedCasFile { path: "lib/a.js".to_string() },
+                    PackageFilesIssue::MissingCasFile { path: "package.json".to_string() },
+                ],
+            },
+        ];
+        let expected = text_block_fnl! {
+            "/bar@1.0.0"
+            "  missing from the store"
+            "/foo@1.0.0"
+            "  index.js: modified"
+            "  lib/a.js: modified in the store"
+            "  package.json: missing from the store"
+        };
+        assert_eq!(render_modified_packages(&modified), expected);
+    }
+
+    #[test]
+    fn render_corrupted_files_by_tarball() {
+        let corrupted_file = |tarball: &str, path: &str, missing| CorruptedCasFile {
+            tarball_integrity: tarball.parse().unwrap(),
+            path: path.to_string(),
+            cas_file: PathBuf::from("CAS_FILE"),
+            missing,
+        };
+        let corrupted = [
+            corrupted_file("sha512-AAAA", "index.js", false),
+            corrupted_file("sha512-AAAA", "package.json", true),
+            corrupted_file("sha512-BBBB", "lib/a.js", false),
+        ];
+        let expected = text_block_fnl! {
+            "sha512-AAAA"
+            "  index.js: modified"
+            "  package.json: missing"
+            "sha512-BBBB"
+            "  lib/a.js: modified"
+        };
+        assert_eq!(render_corrupted_files(&corrupted), expected);
+    }
+}
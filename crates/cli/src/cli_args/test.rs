@@ -0,0 +1,41 @@
+use crate::cli_args::run::{run_script_with_hooks, script_user};
+use clap::Args;
+use miette::Context;
+use pacquet_package_manifest::PackageManifest;
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+pub struct TestArgs {
+    /// Any additional arguments passed after `--` to the test script.
+    #[clap(last = true)]
+    pub args: Vec<String>,
+
+    /// Don't exit with a non-zero exit code when the test script is undefined.
+    #[clap(long)]
+    pub if_present: bool,
+}
+
+impl TestArgs {
+    /// Execute the subcommand.
+    ///
+    /// The `pretest` and `posttest` scripts are skipped if `ignore_scripts` is set.
+    pub fn run(
+        self,
+        manifest_path: PathBuf,
+        unsafe_perm: bool,
+        ignore_scripts: bool,
+    ) -> miette::Result<()> {
+        let TestArgs { args, if_present } = self;
+
+        let manifest = PackageManifest::from_path(manifest_path)
+            .wrap_err("getting the package.json in current directory")?;
+
+        match manifest.script("test", if_present)? {
+            Some(script) => {
+                let user = script_user(&manifest, unsafe_perm)?;
+                run_script_with_hooks(&manifest, "test", script, &args, user, ignore_scripts)
+            }
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use clap::Args;
+use derive_more::{Display, Error};
+use miette::{Context, Diagnostic, IntoDiagnostic};
+use pacquet_lockfile::{DependencyChain, Lockfile, PkgName};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Args)]
+pub struct WhyArgs {
+    /// Name of the package
+    pub package_name: String,
+
+    /// Print the dependency paths as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Error when there is no lockfile to read the dependency graph from.
+#[derive(Debug, Display, Error, Diagnostic)]
+#[display("No pnpm-lock.yaml was found in the current directory")]
+#[diagnostic(
+    code(pacquet_cli::no_lockfile),
+    help("Run `pnpm install` to generate the lockfile first")
+)]
+pub struct NoLockfileError;
+
+impl WhyArgs {
+    /// Execute the subcommand.
+    pub fn run(self) -> miette::Result<()> {
+        let WhyArgs { package_name, json } = self;
+
+        let name = package_name
+            .parse::<PkgName>()
+            .into_diagnostic()
+            .wrap_err("parsing the package name")?;
+        let lockfile = Lockfile::load_from_current_dir()
+            .wrap_err("loading the lockfile")?
+            .ok_or(NoLockfileError)?;
+
+        let chains = lockfile.dependency_chains(&name);
+        if json {
+            let chains = chains_to_json(&lockfile, &chains);
+            println!("{}", serde_json::to_string_pretty(&chains).into_diagnostic()?);
+        } else {
+            print!("{}", render_chains(&chains));
+        }
+        Ok(())
+    }
+}
+
+/// List the chains as `name@version` paths, marking those whose last package depends back on the path.
+fn chains_to_json(lockfile: &Lockfile, chains: &[DependencyChain]) -> serde_json::Value {
+    chains
+        .iter()
+        .map(|chain| {
+            let path = chain
+                .iter()
+                .map(|link| link.dependency_path.package_specifier.to_string())
+                .collect::<Vec<_>>();
+            serde_json::json!({ "path": path, "circular": lockfile.is_circular(chain) })
+        })
+        .collect()
+}
+
+/// Render the chains grouped by the installed version that ends them.
+fn render_chains(chains: &[DependencyChain]) -> String {
+    let mut groups = BTreeMap::<String, Vec<String>>::new();
+    for chain in chains {
+        let Some(last) = chain.last() else { continue };
+        let chain = chain
+            .iter()
+            .map(|link| format!("{}@{}", link.name, link.specifier))
+            .collect::<Vec<_>>()
+            .join(" > ");
+        groups.entry(last.dependency_path.package_specifier.to_string()).or_default().push(chain);
+    }
+
+    groups
+        .into_iter()
+        .map(|(package, chains)| {
+            let chains = chains.iter().map(|chain| format!("  {chain}\n")).collect::<String>();
+            format!("{package}\n{chains}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_lockfile::{DependencyLink, DependencyPath};
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block_fnl;
+
+    #[test]
+    fn render_chains_by_version() {
+        let link = |name: &str, specifier: &str, version: &str| DependencyLink {
+            name: name.parse().unwrap(),
+            specifier: specifier.to_string(),
+            dependency_path: format!("/{name}@{version}").parse::<DependencyPath>().unwrap(),
+        };
+        let chains = [
+            vec![link("a", "^1.0.0", "1.0.0"), link("c", "1.0.0", "1.0.0")],
+            vec![link("b", "~1.0.0", "1.0.0"), link("c", "2.0.0", "2.0.0")],
+            vec![
+                link("b", "~1.0.0", "1.0.0"),
+                link("d", "1.0.0", "1.0.0"),
+                link("c", "1.0.0", "1.0.0"),
+            ],
+        ];
+        let expected = text_block_fnl! {
+            "c@1.0.0"
+            "  a@^1.0.0 > c@1.0.0"
+            "  b@~1.0.0 > d@1.0.0 > c@1.0.0"
+            ""
+            "c@2.0.0"
+            "  b@~1.0.0 > c@2.0.0"
+        };
+        assert_eq!(render_chains(&chains), expected);
+    }
+}
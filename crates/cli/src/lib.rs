@@ -1,14 +1,32 @@
 mod cli_args;
 mod state;
+mod workspace_filter;
 
 use clap::Parser;
 use cli_args::CliArgs;
 use miette::set_panic_hook;
 use pacquet_diagnostics::enable_tracing_by_env;
+use pacquet_executor::ExecutorError;
 use state::State;
+use std::process;
 
 pub async fn main() -> miette::Result<()> {
     enable_tracing_by_env();
     set_panic_hook();
-    CliArgs::parse().run().await
+    let result = CliArgs::parse().run().await;
+    if let Some(code) = result.as_ref().err().and_then(script_exit_code) {
+        eprintln!("{:?}", result.unwrap_err());
+        process::exit(code);
+    }
+    result
+}
+
+/// Exit code of the failed script that caused `error`, if any.
+///
+/// A script terminated by a signal has no exit code, so 1 is used instead.
+fn script_exit_code(error: &miette::Report) -> Option<i32> {
+    error.chain().find_map(|error| match error.downcast_ref::<ExecutorError>()? {
+        ExecutorError::NonZeroExit { code } => Some(code.unwrap_or(1)),
+        _ => None,
+    })
 }
@@ -1,13 +1,14 @@
 use derive_more::{Display, Error};
 use miette::Diagnostic;
 use pacquet_lockfile::{LoadLockfileError, Lockfile};
-use pacquet_network::ThrottledClient;
-use pacquet_npmrc::Npmrc;
-use pacquet_package_manager::ResolvedPackages;
+use pacquet_network::{ClientOptions, CreateClientError, ProxyConfig, ThrottledClient};
+use pacquet_npmrc::{Npmrc, RegistryClientCertificate};
+use pacquet_package_manager::{ResolvedPackages, ResolvedVersions};
 use pacquet_package_manifest::{PackageManifest, PackageManifestError};
+use pacquet_registry::PackageMemCache;
 use pacquet_tarball::MemCache;
 use pipe_trait::Pipe;
-use std::path::PathBuf;
+use std::{env, path::PathBuf, time::Duration};
 
 /// Application state when running `pacquet run` or `pacquet install`.
 pub struct State {
@@ -23,6 +24,10 @@ pub struct State {
     pub lockfile: Option<Lockfile>,
     /// In-memory cache for packages that have started resolving dependencies.
     pub resolved_packages: ResolvedPackages,
+    /// In-memory cache for version ranges that have been resolved.
+    pub resolved_versions: ResolvedVersions,
+    /// In-memory cache for packuments that have been fetched.
+    pub package_mem_cache: PackageMemCache,
 }
 
 /// Error type of [`State::init`].
@@ -34,6 +39,22 @@ pub enum InitStateError {
 
     #[diagnostic(transparent)]
     LoadLockfile(#[error(source)] LoadLockfileError),
+
+    #[diagnostic(transparent)]
+    CreateClient(#[error(source)] CreateClientError),
+
+    #[display("Both certfile and keyfile must be specified to use a client certificate")]
+    #[diagnostic(code(pacquet_cli::incomplete_client_certificate))]
+    IncompleteClientCertificate,
+
+    #[display(
+        "Both certfile and keyfile must be specified to use a client certificate for {registry}"
+    )]
+    #[diagnostic(code(pacquet_cli::incomplete_registry_client_certificate))]
+    IncompleteRegistryClientCertificate {
+        #[error(not(source))]
+        registry: String,
+    },
 }
 
 impl State {
@@ -46,11 +67,60 @@ impl State {
                 .map_err(InitStateError::LoadManifest)?,
             lockfile: call_load_lockfile(config.lockfile, Lockfile::load_from_current_dir)
                 .map_err(InitStateError::LoadLockfile)?,
-            http_client: ThrottledClient::new_from_cpu_count(),
+            http_client: create_http_client(config)?,
             tarball_mem_cache: MemCache::new(),
             resolved_packages: ResolvedPackages::new(),
+            resolved_versions: ResolvedVersions::new(),
+            package_mem_cache: PackageMemCache::new(),
+        })
+    }
+}
+
+/// Create the HTTP client from the network settings of `.npmrc`: client certificates, proxies,
+/// certificate authorities, `strict-ssl`, `fetch-timeout`, `network-concurrency`, and auth tokens.
+fn create_http_client(config: &Npmrc) -> Result<ThrottledClient, InitStateError> {
+    let client_certificate = match (&config.certfile, &config.keyfile) {
+        (None, None) => None,
+        (Some(cert_file), Some(key_file)) => Some((cert_file.clone(), key_file.clone())),
+        (Some(_), None) | (None, Some(_)) => {
+            return Err(InitStateError::IncompleteClientCertificate)
+        }
+    };
+    let registry_client_certificates = config
+        .registry_client_certificates
+        .iter()
+        .map(|(registry, RegistryClientCertificate { certfile, keyfile })| {
+            match (certfile, keyfile) {
+                (Some(cert_file), Some(key_file)) => {
+                    Ok((registry.clone(), (cert_file.clone(), key_file.clone())))
+                }
+                _ => Err(InitStateError::IncompleteRegistryClientCertificate {
+                    registry: registry.clone(),
+                }),
+            }
         })
+        .collect::<Result<_, _>>()?;
+    let proxy = ProxyConfig {
+        http_proxy: config.proxy.clone(),
+        https_proxy: config.https_proxy.clone().or_else(|| config.proxy.clone()),
+        no_proxy: config.no_proxy.clone(),
+    }
+    .or_env(|name| env::var(name).ok());
+    let options = ClientOptions {
+        client_certificate,
+        registry_client_certificates,
+        proxy,
+        ca: config.ca.clone(),
+        ca_file: config.cafile.clone(),
+        danger_accept_invalid_certs: !config.strict_ssl,
+        timeout: (config.fetch_timeout > 0).then(|| Duration::from_millis(config.fetch_timeout)),
+    };
+    let mut http_client =
+        ThrottledClient::new_with_options(&options).map_err(InitStateError::CreateClient)?;
+    if let Some(permits) = config.network_concurrency {
+        http_client = http_client.with_permits(permits);
     }
+    Ok(http_client.with_auth_tokens(config.auth_tokens.clone()).with_offline(config.offline))
 }
 
 /// Private function to load lockfile from current directory should `config.lockfile` is `true`.
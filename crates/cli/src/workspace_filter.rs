@@ -0,0 +1,246 @@
+use miette::Context;
+use pacquet_package_manager::{matches_glob, Workspace, WorkspaceProject};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Value of `--filter` which selects projects of a workspace.
+///
+/// * `foo` and `@scope/*` select the projects whose names match the glob.
+/// * `./packages/foo` and `{packages/foo}` select the projects in the directory.
+/// * `...foo` selects the matching projects and the projects that depend on them.
+/// * `foo...` selects the matching projects and the projects they depend on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceFilter {
+    /// Name glob or path of the projects.
+    pub pattern: FilterPattern,
+    /// Whether to also select the projects that depend on the matching projects.
+    pub with_dependents: bool,
+    /// Whether to also select the projects that the matching projects depend on.
+    pub with_dependencies: bool,
+}
+
+/// Pattern of a [`WorkspaceFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterPattern {
+    /// Glob of the project names.
+    Name(String),
+    /// Directory of the projects, relative to the current directory.
+    Path(PathBuf),
+}
+
+impl FromStr for WorkspaceFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (with_dependents, value) = match value.strip_prefix("...") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let (with_dependencies, value) = match value.strip_suffix("...") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        if value.is_empty() {
+            return Err("the filter has no package name or path".to_string());
+        }
+        let pattern =
+            if let Some(path) = value.strip_prefix('{').and_then(|value| value.strip_suffix('}')) {
+                FilterPattern::Path(PathBuf::from(path))
+            } else if value == "."
+                || value == ".."
+                || value.starts_with("./")
+                || value.starts_with("../")
+            {
+                FilterPattern::Path(PathBuf::from(value))
+            } else {
+                FilterPattern::Name(value.to_string())
+            };
+        Ok(WorkspaceFilter { pattern, with_dependents, with_dependencies })
+    }
+}
+
+impl FilterPattern {
+    /// Whether `project` matches the pattern. Paths are resolved against `cwd`.
+    fn matches(&self, project: &WorkspaceProject, cwd: &Path) -> bool {
+        match self {
+            FilterPattern::Name(pattern) => {
+                project.name().is_some_and(|name| matches_glob(pattern, name))
+            }
+            FilterPattern::Path(path) => {
+                let path = normalize(&cwd.join(path));
+                normalize(&project.dir).starts_with(path)
+            }
+        }
+    }
+}
+
+/// Find the workspace that contains `dir` for the projects selected by `--filter`.
+pub fn find_filtered_workspace(dir: &Path) -> miette::Result<Workspace> {
+    match Workspace::find(dir).wrap_err("find the workspace")? {
+        Some(workspace) => Ok(workspace),
+        None => miette::bail!("--filter can only be used inside a workspace"),
+    }
+}
+
+/// Select the projects of `workspace` that match any of `filters`, in the order of the workspace.
+pub fn select_projects<'a>(
+    workspace: &'a Workspace,
+    filters: &[WorkspaceFilter],
+    cwd: &Path,
+) -> Vec<&'a WorkspaceProject> {
+    let dependencies_of = |project: &WorkspaceProject| workspace.workspace_dependencies(project);
+    let dependents_of = |project: &WorkspaceProject| {
+        workspace
+            .projects
+            .iter()
+            .filter(|dependent| {
+                dependencies_of(dependent).iter().any(|dependency| dependency.dir == project.dir)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut selected = HashSet::<&Path>::new();
+    for filter in filters {
+        let matching =
+            workspace.projects.iter().filter(|project| filter.pattern.matches(project, cwd));
+        for project in matching {
+            selected.insert(&project.dir);
+            if filter.with_dependencies {
+                walk(project, &dependencies_of, &mut selected);
+            }
+            if filter.with_dependents {
+                walk(project, &dependents_of, &mut selected);
+            }
+        }
+    }
+
+    workspace.projects.iter().filter(|project| selected.contains(project.dir.as_path())).collect()
+}
+
+/// Add the projects that are reachable from `project` through `neighbors` to `selected`.
+fn walk<'a>(
+    project: &'a WorkspaceProject,
+    neighbors: impl Fn(&'a WorkspaceProject) -> Vec<&'a WorkspaceProject>,
+    selected: &mut HashSet<&'a Path>,
+) {
+    let mut visited = HashSet::<&Path>::new();
+    let mut pending = vec![project];
+    while let Some(project) = pending.pop() {
+        if !visited.insert(&project.dir) {
+            continue;
+        }
+        selected.insert(&project.dir);
+        pending.extend(neighbors(project));
+    }
+}
+
+/// Remove the `.` and `..` components of `path` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn parse_filter() {
+        macro_rules! case {
+            ($input:literal => $pattern:expr, $with_dependents:literal, $with_dependencies:literal) => {{
+                eprintln!("CASE: {:?}", $input);
+                let expected = WorkspaceFilter {
+                    pattern: $pattern,
+                    with_dependents: $with_dependents,
+                    with_dependencies: $with_dependencies,
+                };
+                assert_eq!($input.parse::<WorkspaceFilter>().unwrap(), expected);
+            }};
+        }
+
+        let name = |name: &str| FilterPattern::Name(name.to_string());
+        let path = |path: &str| FilterPattern::Path(PathBuf::from(path));
+        case!("foo" => name("foo"), false, false);
+        case!("@scope/*" => name("@scope/*"), false, false);
+        case!("...foo" => name("foo"), true, false);
+        case!("foo..." => name("foo"), false, true);
+        case!("...foo..." => name("foo"), true, true);
+        case!("./packages/foo" => path("./packages/foo"), false, false);
+        case!("{packages}..." => path("packages"), false, true);
+        case!("." => path("."), false, false);
+
+        eprintln!("CASE: empty filter");
+        assert!("...".parse::<WorkspaceFilter>().is_err());
+    }
+
+    #[test]
+    fn should_select_projects() {
+        let dir = tempdir().unwrap();
+        let write_manifest = |path: &str, manifest: serde_json::Value| {
+            let project_dir = dir.path().join(path);
+            fs::create_dir_all(&project_dir).unwrap();
+            fs::write(project_dir.join("package.json"), manifest.to_string()).unwrap();
+        };
+        write_manifest("", json!({ "name": "root", "private": true }));
+        write_manifest(
+            "apps/web",
+            json!({ "name": "web", "dependencies": { "@scope/ui": "workspace:*" } }),
+        );
+        write_manifest(
+            "packages/ui",
+            json!({ "name": "@scope/ui", "dependencies": { "@scope/utils": "workspace:*" } }),
+        );
+        write_manifest("packages/utils", json!({ "name": "@scope/utils" }));
+        fs::write(
+            dir.path().join("pnpm-workspace.yaml"),
+            "packages:\n  - 'apps/*'\n  - 'packages/*'\n",
+        )
+        .unwrap();
+        let workspace = Workspace::load(dir.path()).unwrap();
+
+        let select = |filters: &[&str]| {
+            let filters = filters.iter().map(|filter| filter.parse().unwrap()).collect::<Vec<_>>();
+            select_projects(&workspace, &filters, dir.path())
+                .into_iter()
+                .map(|project| project.name().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        eprintln!("CASE: name glob");
+        assert_eq!(select(&["@scope/*"]), ["@scope/ui", "@scope/utils"]);
+
+        eprintln!("CASE: path");
+        assert_eq!(select(&["./apps"]), ["web"]);
+        assert_eq!(select(&["{packages/utils}"]), ["@scope/utils"]);
+
+        eprintln!("CASE: with dependencies");
+        assert_eq!(select(&["@scope/ui..."]), ["@scope/ui", "@scope/utils"]);
+
+        eprintln!("CASE: with dependents");
+        assert_eq!(select(&["...@scope/utils"]), ["web", "@scope/ui", "@scope/utils"]);
+
+        eprintln!("CASE: multiple filters");
+        assert_eq!(select(&["web", "root"]), ["root", "web"]);
+
+        eprintln!("CASE: no match");
+        assert_eq!(select(&["missing"]), Vec::<&str>::new());
+
+        drop(dir); // cleanup
+    }
+}
@@ -43,9 +43,10 @@ fn should_install_all_dependencies() {
     assert_eq!(get_filenames_in_folder(&path), ["@pnpm.e2e"]);
     assert_eq!(get_filenames_in_folder(&path.join("@pnpm.e2e")), ["hello-world-js-bin"]);
 
-    eprintln!("Ensure that @pnpm.e2e/hello-world-js-bin-parent has correct dependencies");
+    eprintln!("Ensure that @pnpm.e2e/hello-world-js-bin-parent has correct dependencies and bins");
     let path = virtual_store_dir.join("@pnpm.e2e+hello-world-js-bin-parent@1.0.0/node_modules");
-    assert_eq!(get_filenames_in_folder(&path), ["@pnpm.e2e"]);
+    assert_eq!(get_filenames_in_folder(&path), [".bin", "@pnpm.e2e"]);
+    assert_eq!(get_filenames_in_folder(&path.join(".bin")), ["hello-world-js-bin"]);
     assert_eq!(
         get_filenames_in_folder(&path.join("@pnpm.e2e")),
         ["hello-world-js-bin", "hello-world-js-bin-parent"],
@@ -134,3 +135,41 @@ fn should_add_peer_dependency() {
         .any(|(k, _)| k == "@pnpm.e2e/hello-world-js-bin"));
     drop((root, anchor)); // cleanup
 }
+
+#[test]
+#[cfg(unix)]
+fn should_add_global_package() {
+    let CommandTempCwd { pacquet, root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { npmrc_path, store_dir, mock_instance, .. } = npmrc_info;
+
+    eprintln!("Patching .npmrc...");
+    let npmrc_text = fs::read_to_string(&npmrc_path).expect("read .npmrc");
+    let npmrc_text = format!("{npmrc_text}\nglobal-dir=../global\nglobal-bin-dir=../global-bin\n");
+    fs::write(&npmrc_path, npmrc_text).expect("write to .npmrc");
+
+    eprintln!("Executing command...");
+    pacquet.with_args(["add", "--global", "@pnpm.e2e/hello-world-js-bin"]).assert().success();
+
+    let global_packages_dir = root.path().join("global/5");
+    eprintln!("Ensure the package is added to the global manifest");
+    let file = PackageManifest::from_path(global_packages_dir.join("package.json")).unwrap();
+    assert!(file
+        .dependencies([DependencyGroup::Prod])
+        .any(|(k, _)| k == "@pnpm.e2e/hello-world-js-bin"));
+
+    eprintln!("Ensure the package is installed into the global directory");
+    assert!(global_packages_dir.join("node_modules/@pnpm.e2e/hello-world-js-bin").exists());
+    assert!(!workspace.join("node_modules").exists());
+    assert!(!workspace.join("package.json").exists());
+
+    eprintln!("Ensure the command is created in the global bin directory");
+    let bin_path = root.path().join("global-bin/hello-world-js-bin");
+    assert!(fs::symlink_metadata(&bin_path).unwrap().is_symlink());
+    assert!(bin_path.exists());
+
+    eprintln!("Ensure the global package shares the store");
+    assert!(store_dir.exists());
+
+    drop((root, mock_instance)); // cleanup
+}
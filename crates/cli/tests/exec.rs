@@ -0,0 +1,43 @@
+use assert_cmd::prelude::*;
+use command_extra::CommandExtra;
+use pacquet_testing_utils::bin::CommandTempCwd;
+use std::fs;
+
+#[test]
+#[cfg(unix)]
+fn should_forward_args_and_exit_code() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating node_modules/.bin/exit-with...");
+    let bin_dir = workspace.join("node_modules/.bin");
+    fs::create_dir_all(&bin_dir).expect("create node_modules/.bin");
+    let bin_path = bin_dir.join("exit-with");
+    fs::write(&bin_path, "#!/bin/sh\necho \"$1\"\nexit \"$2\"\n").expect("write the command");
+    fs::set_permissions(&bin_path, fs::Permissions::from_mode(0o755)).expect("make executable");
+
+    eprintln!("Executing pacquet exec exit-with --hello 5...");
+    let output = pacquet.with_args(["exec", "exit-with", "--hello", "5"]).assert().code(5);
+    let stdout = String::from_utf8_lossy(&output.get_output().stdout).into_owned();
+    eprintln!("STDOUT:\n{stdout}");
+    assert_eq!(stdout.trim(), "--hello");
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_fail_on_missing_command() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    fs::write(workspace.join("package.json"), "{}").expect("write to package.json");
+
+    eprintln!("Executing pacquet exec missing...");
+    let output = pacquet.with_args(["exec", "missing"]).assert().failure().get_output().clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains("\"missing\" is not installed"));
+
+    drop(root); // cleanup
+}
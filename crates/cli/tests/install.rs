@@ -12,6 +12,7 @@ use pipe_trait::Pipe;
 use std::{
     fs::{self, OpenOptions},
     io::Write,
+    process::Command,
 };
 
 #[test]
@@ -86,23 +87,33 @@ fn should_install_exec_files() {
             .partition::<Vec<_>, _>(|name| store_dir.join(name).as_path().pipe(is_path_executable));
         assert_eq!((&suffix_exec, &suffix_other), (&mode_exec, &mode_other));
 
+        let modes = |names: &[&String]| -> Vec<_> {
+            names
+                .iter()
+                .map(|name| {
+                    let mode = store_dir
+                        .join(name)
+                        .pipe(File::open)
+                        .expect("open file to get mode")
+                        .metadata()
+                        .expect("get metadata")
+                        .mode();
+                    (name.as_str(), mode & 0o777)
+                })
+                .collect()
+        };
+
         eprintln!("All executable files have mode 755");
-        let actual_modes: Vec<_> = mode_exec
-            .iter()
-            .map(|name| {
-                let mode = store_dir
-                    .join(name)
-                    .pipe(File::open)
-                    .expect("open file to get mode")
-                    .metadata()
-                    .expect("get metadata")
-                    .mode();
-                (name.as_str(), mode & 0o777)
-            })
-            .collect();
         let expected_modes: Vec<_> =
             mode_exec.iter().map(|name| name.as_str()).zip(repeat(0o755)).collect();
-        assert_eq!(&actual_modes, &expected_modes);
+        assert_eq!(&modes(&mode_exec), &expected_modes);
+
+        eprintln!("All other files of packages have mode 644");
+        let package_files: Vec<_> =
+            mode_other.into_iter().filter(|name| !name.ends_with("-index.json")).collect();
+        let expected_modes: Vec<_> =
+            package_files.iter().map(|name| name.as_str()).zip(repeat(0o644)).collect();
+        assert_eq!(&modes(&package_files), &expected_modes);
     }
 
     eprintln!("Snapshot");
@@ -168,6 +179,46 @@ fn frozen_lockfile_should_be_able_to_handle_big_lockfile() {
     drop((root, mock_instance)); // cleanup
 }
 
+#[test]
+fn frozen_lockfile_should_reject_outdated_lockfile() {
+    let CommandTempCwd { pacquet, root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, .. } = npmrc_info;
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "dependencies": {
+            "@pnpm.e2e/hello-world-js-bin-parent": "1.0.0",
+        },
+    });
+    fs::write(manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Creating pnpm-lock.yaml...");
+    let lockfile_path = workspace.join("pnpm-lock.yaml");
+    fs::write(lockfile_path, "lockfileVersion: '6.0'\n").expect("write to pnpm-lock.yaml");
+
+    eprintln!("Patching .npmrc...");
+    let npmrc_path = workspace.join(".npmrc");
+    OpenOptions::new()
+        .append(true)
+        .write(true)
+        .open(npmrc_path)
+        .expect("open .npmrc to append")
+        .write_all(b"\nlockfile=true\n")
+        .expect("append to .npmrc");
+
+    eprintln!("Executing command...");
+    let output = pacquet.with_args(["install", "--frozen-lockfile"]).output().expect("run pacquet");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains("+ dependencies: @pnpm.e2e/hello-world-js-bin-parent@1.0.0"));
+    assert!(!workspace.join("node_modules").exists());
+
+    drop((root, mock_instance)); // cleanup
+}
+
 #[test]
 fn should_install_circular_dependencies() {
     let CommandTempCwd { pacquet, root, workspace, npmrc_info, .. } =
@@ -192,3 +243,212 @@ fn should_install_circular_dependencies() {
 
     drop((root, mock_instance)); // cleanup
 }
+
+#[test]
+fn should_warn_about_ignored_pnpmfile() {
+    let CommandTempCwd { root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, .. } = npmrc_info;
+
+    eprintln!("Creating package.json and .pnpmfile.cjs...");
+    fs::write(workspace.join("package.json"), "{}").expect("write to package.json");
+    fs::write(workspace.join(".pnpmfile.cjs"), "module.exports = { hooks: {} }")
+        .expect("write to .pnpmfile.cjs");
+
+    let install = |args: &[&str]| -> String {
+        let output = Command::cargo_bin("pacquet")
+            .expect("find the pacquet binary")
+            .with_current_dir(&workspace)
+            .with_arg("install")
+            .with_args(args)
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        String::from_utf8(output.stderr).expect("stderr is UTF-8")
+    };
+
+    eprintln!("Executing command without --ignore-pnpmfile...");
+    let stderr = install(&[]);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains(".pnpmfile.cjs"));
+
+    eprintln!("Executing command with --ignore-pnpmfile...");
+    let stderr = install(&["--ignore-pnpmfile"]);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(!stderr.contains(".pnpmfile.cjs"));
+
+    drop((root, mock_instance)); // cleanup
+}
+
+#[test]
+fn should_warn_about_incompatible_package_manager() {
+    let CommandTempCwd { pacquet, root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, .. } = npmrc_info;
+
+    eprintln!("Creating package.json...");
+    let manifest = serde_json::json!({ "packageManager": "yarn@3.6.1" });
+    fs::write(workspace.join("package.json"), manifest.to_string()).expect("write to package.json");
+
+    eprintln!("Executing command...");
+    let output = pacquet.with_arg("install").assert().success().get_output().clone();
+    let stderr = String::from_utf8(output.stderr).expect("stderr is UTF-8");
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains("WARN: This project wants yarn@3.6.1"));
+
+    drop((root, mock_instance)); // cleanup
+}
+
+#[test]
+fn should_prune_dev_dependencies_on_prod_install() {
+    let CommandTempCwd { root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, .. } = npmrc_info;
+
+    eprintln!("Creating package.json...");
+    let package_json_content = serde_json::json!({
+        "dependencies": {
+            "@pnpm.e2e/hello-world-js-bin-parent": "1.0.0",
+        },
+        "devDependencies": {
+            "@pnpm/xyz": "1.0.0",
+        },
+    });
+    fs::write(workspace.join("package.json"), package_json_content.to_string())
+        .expect("write to package.json");
+
+    let install = |args: &[&str]| {
+        Command::cargo_bin("pacquet")
+            .expect("find the pacquet binary")
+            .with_current_dir(&workspace)
+            .with_arg("install")
+            .with_args(args)
+            .assert()
+            .success();
+    };
+    let dev_symlink_path = workspace.join("node_modules/@pnpm/xyz");
+    let dev_virtual_path = workspace.join("node_modules/.pnpm/@pnpm+xyz@1.0.0");
+
+    eprintln!("Installing all dependencies...");
+    install(&[]);
+    assert!(is_symlink_or_junction(&dev_symlink_path).unwrap());
+    assert!(dev_virtual_path.exists());
+
+    eprintln!("Installing production dependencies...");
+    install(&["--prod"]);
+    assert!(!dev_symlink_path.exists());
+    assert!(!dev_virtual_path.exists());
+    let prod_symlink_path = workspace.join("node_modules/@pnpm.e2e/hello-world-js-bin-parent");
+    assert!(is_symlink_or_junction(&prod_symlink_path).unwrap());
+    assert!(workspace.join("node_modules/.pnpm/@pnpm.e2e+hello-world-js-bin@1.0.0").exists());
+
+    drop((root, mock_instance)); // cleanup
+}
+
+#[test]
+fn should_skip_dev_dependencies_in_production_env() {
+    let CommandTempCwd { root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, .. } = npmrc_info;
+
+    eprintln!("Creating package.json...");
+    let package_json_content = serde_json::json!({
+        "dependencies": {
+            "@pnpm.e2e/hello-world-js-bin-parent": "1.0.0",
+        },
+        "devDependencies": {
+            "@pnpm/xyz": "1.0.0",
+        },
+    });
+    fs::write(workspace.join("package.json"), package_json_content.to_string())
+        .expect("write to package.json");
+
+    eprintln!("Executing command with NODE_ENV=production...");
+    Command::cargo_bin("pacquet")
+        .expect("find the pacquet binary")
+        .with_current_dir(&workspace)
+        .with_arg("install")
+        .with_env("NODE_ENV", "production")
+        .assert()
+        .success();
+
+    eprintln!("Make sure only the production dependencies are installed");
+    let prod_symlink_path = workspace.join("node_modules/@pnpm.e2e/hello-world-js-bin-parent");
+    assert!(is_symlink_or_junction(&prod_symlink_path).unwrap());
+    assert!(!workspace.join("node_modules/@pnpm/xyz").exists());
+
+    drop((root, mock_instance)); // cleanup
+}
+
+#[test]
+fn should_install_project_not_listed_by_workspace_on_its_own() {
+    let CommandTempCwd { root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, npmrc_path, .. } = npmrc_info;
+
+    eprintln!("Creating a workspace that doesn't list the project...");
+    fs::write(root.path().join("pnpm-workspace.yaml"), "packages:\n  - packages/*\n")
+        .expect("write to pnpm-workspace.yaml");
+
+    eprintln!("Creating package.json...");
+    let package_json_content = serde_json::json!({
+        "dependencies": {
+            "@pnpm.e2e/hello-world-js-bin-parent": "1.0.0",
+        },
+    });
+    fs::write(workspace.join("package.json"), package_json_content.to_string())
+        .expect("write to package.json");
+
+    eprintln!("Patching .npmrc...");
+    OpenOptions::new()
+        .append(true)
+        .write(true)
+        .open(npmrc_path)
+        .expect("open .npmrc to append")
+        .write_all(b"\nlockfile=true\n")
+        .expect("append to .npmrc");
+
+    let install = |args: &[&str]| {
+        Command::cargo_bin("pacquet")
+            .expect("find the pacquet binary")
+            .with_current_dir(&workspace)
+            .with_arg("install")
+            .with_args(args)
+            .assert()
+            .success();
+    };
+    let symlink_path = workspace.join("node_modules/@pnpm.e2e/hello-world-js-bin-parent");
+
+    eprintln!("Installing without a lockfile...");
+    install(&[]);
+    assert!(is_symlink_or_junction(&symlink_path).unwrap());
+    assert!(workspace.join("pnpm-lock.yaml").exists());
+
+    eprintln!("Installing with the frozen lockfile...");
+    fs::remove_dir_all(workspace.join("node_modules")).expect("remove node_modules");
+    install(&["--frozen-lockfile"]);
+    assert!(is_symlink_or_junction(&symlink_path).unwrap());
+
+    drop((root, mock_instance)); // cleanup
+}
+
+#[test]
+fn should_fail_when_no_projects_match_filter() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating the workspace...");
+    fs::write(workspace.join("pnpm-workspace.yaml"), "packages:\n  - packages/*\n")
+        .expect("write to pnpm-workspace.yaml");
+    fs::write(workspace.join("package.json"), "{}").expect("write to package.json");
+
+    eprintln!("Executing pacquet install --filter missing...");
+    let output =
+        pacquet.with_args(["install", "--filter", "missing"]).output().expect("run pacquet");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains("No projects matched the filters"));
+
+    drop(root); // cleanup
+}
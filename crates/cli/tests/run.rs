@@ -0,0 +1,118 @@
+use assert_cmd::prelude::*;
+use command_extra::CommandExtra;
+use pacquet_testing_utils::bin::CommandTempCwd;
+use std::fs;
+
+#[test]
+fn should_skip_missing_script_with_if_present() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "build": "echo build",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Executing pacquet run missing --if-present...");
+    pacquet.with_args(["run", "missing", "--if-present"]).assert().success();
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_fail_on_missing_script_without_if_present() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    fs::write(&manifest_path, "{}").expect("write to package.json");
+
+    eprintln!("Executing pacquet run missing...");
+    let output = pacquet.with_args(["run", "missing"]).assert().failure().get_output().clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains("missing"));
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_exit_with_code_of_failed_script() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "build": "exit 3",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Executing pacquet run build...");
+    pacquet.with_args(["run", "build"]).assert().code(3);
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_list_scripts_without_script_name() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "build": "echo build",
+            "test": "echo test",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Executing pacquet run...");
+    let output = pacquet.with_arg("run").assert().success().get_output().clone();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    eprintln!("STDOUT:\n{stdout}");
+    assert!(stdout.contains("  build\n    echo build\n"));
+    assert!(stdout.contains("  test\n    echo test\n"));
+
+    drop(root); // cleanup
+}
+
+#[test]
+#[cfg(unix)]
+fn should_run_filtered_script_in_project_dir() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating the workspace...");
+    fs::write(workspace.join("pnpm-workspace.yaml"), "packages:\n  - packages/*\n")
+        .expect("write to pnpm-workspace.yaml");
+    fs::write(workspace.join("package.json"), "{}").expect("write to package.json");
+    let project_dir = workspace.join("packages/foo");
+    fs::create_dir_all(&project_dir).expect("create the project directory");
+    let package_json_content = serde_json::json!({
+        "name": "foo",
+        "scripts": {
+            "where": "echo \"$PWD\" > cwd.txt",
+        },
+    });
+    fs::write(project_dir.join("package.json"), package_json_content.to_string())
+        .expect("write to package.json");
+
+    eprintln!("Executing pacquet run --filter foo where...");
+    pacquet.with_args(["run", "--filter", "foo", "where"]).assert().success();
+
+    eprintln!("The script should run in the directory of the project");
+    assert!(!workspace.join("cwd.txt").exists());
+    let cwd = fs::read_to_string(project_dir.join("cwd.txt")).expect("read cwd.txt");
+    eprintln!("CWD: {cwd}");
+    assert_eq!(
+        fs::canonicalize(cwd.trim()).expect("canonicalize cwd"),
+        fs::canonicalize(&project_dir).expect("canonicalize project dir"),
+    );
+
+    drop(root); // cleanup
+}
@@ -0,0 +1,65 @@
+#![cfg(unix)] // the scripts rely on a POSIX shell
+
+use assert_cmd::prelude::*;
+use command_extra::CommandExtra;
+use pacquet_testing_utils::bin::CommandTempCwd;
+use pretty_assertions::assert_eq;
+use std::fs;
+
+#[test]
+fn should_run_start_script_with_hooks() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "prestart": "echo prestart >> log.txt",
+            "start": "echo start >> log.txt",
+            "poststart": "echo poststart >> log.txt",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Executing pacquet start -- --port=3000...");
+    pacquet.with_args(["start", "--", "--port=3000"]).assert().success();
+
+    let log = fs::read_to_string(workspace.join("log.txt")).expect("read log.txt");
+    assert_eq!(log, "prestart\nstart --port=3000\npoststart\n");
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_fallback_to_server_js() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json and server.js...");
+    fs::write(workspace.join("package.json"), "{}").expect("write to package.json");
+    let server_js = "require('fs').writeFileSync('started.txt', process.argv.slice(2).join(' '))";
+    fs::write(workspace.join("server.js"), server_js).expect("write to server.js");
+
+    eprintln!("Executing pacquet start -- --port=3000...");
+    pacquet.with_args(["start", "--", "--port=3000"]).assert().success();
+
+    let started = fs::read_to_string(workspace.join("started.txt")).expect("read started.txt");
+    assert_eq!(started, "--port=3000");
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_fail_without_start_script_nor_server_js() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    fs::write(workspace.join("package.json"), "{}").expect("write to package.json");
+
+    eprintln!("Executing pacquet start...");
+    let output = pacquet.with_arg("start").assert().failure().get_output().clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("STDERR:\n{stderr}");
+    assert!(stderr.contains("server.js"));
+
+    drop(root); // cleanup
+}
@@ -0,0 +1,80 @@
+#![cfg(unix)] // the scripts rely on a POSIX shell
+
+use assert_cmd::prelude::*;
+use command_extra::CommandExtra;
+use pacquet_testing_utils::bin::CommandTempCwd;
+use pretty_assertions::assert_eq;
+use std::fs;
+
+#[test]
+fn should_run_pre_and_post_scripts_around_test() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "pretest": "echo pretest >> log.txt",
+            "test": "echo test >> log.txt",
+            "posttest": "echo posttest >> log.txt",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Executing pacquet test -- --extra...");
+    pacquet.with_args(["test", "--", "--extra"]).assert().success();
+
+    eprintln!("Make sure the hooks ran around the test script, which received the extra args");
+    let log = fs::read_to_string(workspace.join("log.txt")).expect("read log.txt");
+    assert_eq!(log, "pretest\ntest --extra\nposttest\n");
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_skip_missing_test_with_if_present() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "pretest": "echo pretest >> log.txt",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+
+    eprintln!("Executing pacquet test --if-present...");
+    pacquet.with_args(["test", "--if-present"]).assert().success();
+
+    eprintln!("Make sure the hooks didn't run");
+    assert!(!workspace.join("log.txt").exists());
+
+    drop(root); // cleanup
+}
+
+#[test]
+fn should_skip_pre_and_post_scripts_with_ignore_scripts() {
+    let CommandTempCwd { pacquet, root, workspace, .. } = CommandTempCwd::init();
+
+    eprintln!("Creating package.json and .npmrc...");
+    let manifest_path = workspace.join("package.json");
+    let package_json_content = serde_json::json!({
+        "scripts": {
+            "pretest": "echo pretest >> log.txt",
+            "test": "echo test >> log.txt",
+            "posttest": "echo posttest >> log.txt",
+        },
+    });
+    fs::write(&manifest_path, package_json_content.to_string()).expect("write to package.json");
+    fs::write(workspace.join(".npmrc"), "ignore-scripts=true\n").expect("write to .npmrc");
+
+    eprintln!("Executing pacquet test...");
+    pacquet.with_arg("test").assert().success();
+
+    eprintln!("Make sure only the test script ran");
+    let log = fs::read_to_string(workspace.join("log.txt")).expect("read log.txt");
+    assert_eq!(log, "test\n");
+
+    drop(root); // cleanup
+}
@@ -0,0 +1,64 @@
+use assert_cmd::prelude::*;
+use command_extra::CommandExtra;
+use pacquet_testing_utils::bin::{AddMockedRegistry, CommandTempCwd};
+use pretty_assertions::assert_eq;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    process::Command,
+};
+
+#[test]
+fn should_print_circular_dependency_paths_as_json() {
+    let CommandTempCwd { root, workspace, npmrc_info, .. } =
+        CommandTempCwd::init().add_mocked_registry();
+    let AddMockedRegistry { mock_instance, npmrc_path, .. } = npmrc_info;
+
+    eprintln!("Creating package.json...");
+    let package_json_content = serde_json::json!({
+        "dependencies": {
+            "@pnpm.e2e/circular-deps-1-of-2": "1.0.2",
+        },
+    });
+    fs::write(workspace.join("package.json"), package_json_content.to_string())
+        .expect("write to package.json");
+
+    eprintln!("Patching .npmrc...");
+    OpenOptions::new()
+        .append(true)
+        .write(true)
+        .open(npmrc_path)
+        .expect("open .npmrc to append")
+        .write_all(b"\nlockfile=true\n")
+        .expect("append to .npmrc");
+
+    let pacquet = || {
+        Command::cargo_bin("pacquet").expect("find the pacquet binary").with_current_dir(&workspace)
+    };
+
+    eprintln!("Installing...");
+    pacquet().with_arg("install").assert().success();
+
+    eprintln!("Executing pacquet why --json...");
+    let output = pacquet()
+        .with_args(["why", "@pnpm.e2e/circular-deps-2-of-2", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    eprintln!("STDOUT:\n{stdout}");
+    let received: serde_json::Value = serde_json::from_str(&stdout).expect("parse the output");
+    let expected = serde_json::json!([
+        {
+            "path": [
+                "@pnpm.e2e/circular-deps-1-of-2@1.0.2",
+                "@pnpm.e2e/circular-deps-2-of-2@1.0.2",
+            ],
+            "circular": true,
+        },
+    ]);
+    assert_eq!(received, expected);
+
+    drop((root, mock_instance)); // cleanup
+}
@@ -1,6 +1,8 @@
 mod local_tracing;
+mod warn;
 
 pub use miette;
 pub use tracing;
 
 pub use local_tracing::enable_tracing_by_env;
+pub use warn::warn;
@@ -0,0 +1,10 @@
+use std::fmt::Display;
+
+/// Show a warning to the user, like the `WARN` lines of pnpm.
+///
+/// Unlike [`tracing::warn!`], which is only shown when `TRACE` is set, the warning is always
+/// printed to stderr. It is still recorded as a tracing event under the `pacquet::warn` target.
+pub fn warn(message: impl Display) {
+    tracing::warn!(target: "pacquet::warn", "{message}");
+    eprintln!("WARN: {message}");
+}
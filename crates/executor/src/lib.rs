@@ -1,24 +1,313 @@
 use derive_more::{Display, Error};
 use miette::Diagnostic;
-use std::process::Command;
+use std::{
+    env,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 #[derive(Debug, Display, Error, Diagnostic)]
 #[non_exhaustive]
 pub enum ExecutorError {
     #[display("Failed to spawn command: {_0}")]
     #[diagnostic(code(pacquet_executor::spawn_command))]
-    SpawnCommand(#[error(source)] std::io::Error),
+    SpawnCommand(#[error(source)] io::Error),
 
     #[display("Process exits with an error: {_0}")]
     #[diagnostic(code(pacquet_executor::wait_process))]
-    WaitProcess(#[error(source)] std::io::Error),
+    WaitProcess(#[error(source)] io::Error),
+
+    #[display("Process exits with a non-zero status (code: {code:?})")]
+    #[diagnostic(code(pacquet_executor::non_zero_exit))]
+    NonZeroExit {
+        /// [`None`] if the process was terminated by a signal.
+        #[error(not(source))]
+        code: Option<i32>,
+    },
+
+    #[display("Failed to add the bin directories of {project_dir:?} to PATH: {error}")]
+    #[diagnostic(code(pacquet_executor::join_paths))]
+    JoinPaths {
+        project_dir: PathBuf,
+        #[error(source)]
+        error: env::JoinPathsError,
+    },
+
+    #[display("Failed to read the owner of {dir:?}: {error}")]
+    #[diagnostic(code(pacquet_executor::read_owner))]
+    ReadOwner {
+        dir: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to run the script as uid={} gid={}: {error}", user.uid, user.gid)]
+    #[diagnostic(
+        code(pacquet_executor::drop_privileges),
+        help("Use --unsafe-perm to run the script as the current user")
+    )]
+    DropPrivileges {
+        user: ScriptUser,
+        #[error(source)]
+        error: io::Error,
+    },
+}
+
+/// User and group to run a script as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptUser {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl ScriptUser {
+    /// Decide which user a script should run as, [`None`] means the current user.
+    ///
+    /// When the current user is root and `unsafe_perm` is not set, the privileges are
+    /// dropped to the owner of the package directory (unless the owner is also root).
+    pub fn decide(current_uid: u32, owner: ScriptUser, unsafe_perm: bool) -> Option<Self> {
+        const ROOT_UID: u32 = 0;
+        (current_uid == ROOT_UID && !unsafe_perm && owner.uid != ROOT_UID).then_some(owner)
+    }
+
+    /// Decide which user the scripts of the package in `dir` should run as.
+    ///
+    /// Privileges are only ever dropped on Unix.
+    pub fn for_dir(dir: &Path, unsafe_perm: bool) -> Result<Option<Self>, ExecutorError> {
+        #[cfg(unix)]
+        {
+            use std::{fs, os::unix::fs::MetadataExt};
+            let metadata = fs::metadata(dir)
+                .map_err(|error| ExecutorError::ReadOwner { dir: dir.to_path_buf(), error })?;
+            let owner = ScriptUser { uid: metadata.uid(), gid: metadata.gid() };
+            let current_uid = unsafe { libc::geteuid() };
+            Ok(ScriptUser::decide(current_uid, owner, unsafe_perm))
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = (dir, unsafe_perm);
+            Ok(None)
+        }
+    }
 }
 
-pub fn execute_shell(command: &str) -> Result<(), ExecutorError> {
-    let mut cmd =
-        Command::new("sh").arg("-c").arg(command).spawn().map_err(ExecutorError::SpawnCommand)?;
+/// Execute a shell command of the project in `project_dir`.
+pub fn execute_shell(project_dir: &Path, command: &str) -> Result<(), ExecutorError> {
+    execute_shell_as(project_dir, command, None)
+}
+
+/// Execute a shell command of the project in `project_dir` as `user`,
+/// or as the current user if `user` is [`None`].
+pub fn execute_shell_as(
+    project_dir: &Path,
+    command: &str,
+    user: Option<ScriptUser>,
+) -> Result<(), ExecutorError> {
+    let mut cmd = script_command(project_dir, command)?;
+
+    #[cfg(unix)]
+    if let Some(ScriptUser { uid, gid }) = user {
+        use std::os::unix::process::CommandExt;
+        cmd.uid(uid).gid(gid);
+    }
+
+    let mut child = cmd.spawn().map_err(|error| match user {
+        Some(user) if error.kind() == io::ErrorKind::PermissionDenied => {
+            ExecutorError::DropPrivileges { user, error }
+        }
+        _ => ExecutorError::SpawnCommand(error),
+    })?;
+
+    let status = child.wait().map_err(ExecutorError::WaitProcess)?;
+    if !status.success() {
+        return Err(ExecutorError::NonZeroExit { code: status.code() });
+    }
 
-    cmd.wait().map_err(ExecutorError::WaitProcess)?;
+    Ok(())
+}
 
+/// Execute `program` with `args` directly, without a shell, in `project_dir`, with the executables
+/// of the project available in `PATH`.
+///
+/// The standard streams are inherited, and a non-zero exit status is reported as
+/// [`ExecutorError::NonZeroExit`] so that the caller can forward it.
+pub fn execute_program(
+    project_dir: &Path,
+    program: &Path,
+    args: &[String],
+) -> Result<(), ExecutorError> {
+    let path = script_path_env(project_dir, env::var_os("PATH")).map_err(|error| {
+        ExecutorError::JoinPaths { project_dir: project_dir.to_path_buf(), error }
+    })?;
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(project_dir)
+        .env("PATH", path)
+        .status()
+        .map_err(ExecutorError::SpawnCommand)?;
+    if !status.success() {
+        return Err(ExecutorError::NonZeroExit { code: status.code() });
+    }
     Ok(())
 }
+
+/// Create a [`Command`] that runs `command` in the shell in `project_dir`, with the executables of
+/// the project available in `PATH`.
+fn script_command(project_dir: &Path, command: &str) -> Result<Command, ExecutorError> {
+    let path = script_path_env(project_dir, env::var_os("PATH")).map_err(|error| {
+        ExecutorError::JoinPaths { project_dir: project_dir.to_path_buf(), error }
+    })?;
+    let mut cmd = shell_command(command);
+    cmd.current_dir(project_dir).env("PATH", path);
+    Ok(cmd)
+}
+
+/// Prepend the `node_modules/.bin` directories of `project_dir` and of its ancestors
+/// (e.g. the workspace root), nearest first, to the inherited `PATH`.
+fn script_path_env(
+    project_dir: &Path,
+    inherited: Option<OsString>,
+) -> Result<OsString, env::JoinPathsError> {
+    let bin_dirs = project_dir.ancestors().map(|dir| dir.join("node_modules").join(".bin"));
+    let inherited = inherited.iter().flat_map(env::split_paths);
+    env::join_paths(bin_dirs.chain(inherited))
+}
+
+/// Create a [`Command`] that runs `command` with the shell of the platform:
+/// `%ComSpec% /C` (or `cmd /C`) on Windows, `$SHELL -c` (or `sh -c`) elsewhere.
+fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        let shell = env::var_os("ComSpec").filter(|shell| !shell.is_empty());
+        let mut cmd = Command::new(shell.as_deref().unwrap_or("cmd".as_ref()));
+        cmd.arg("/C").raw_arg(command); // cmd has its own quoting rules
+        cmd
+    }
+
+    #[cfg(not(windows))]
+    {
+        let shell = env::var_os("SHELL").filter(|shell| !shell.is_empty());
+        let mut cmd = Command::new(shell.as_deref().unwrap_or("sh".as_ref()));
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn run_command_with_platform_shell() {
+        let output = shell_command("echo hello").output().unwrap();
+        dbg!(&output);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello");
+        let dir = tempfile::tempdir().unwrap();
+        execute_shell(dir.path(), "echo hello").unwrap();
+    }
+
+    #[test]
+    fn fail_on_non_zero_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = execute_shell(dir.path(), "exit 3").unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, ExecutorError::NonZeroExit { code: Some(3) }));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn prepend_bin_dirs_to_path() {
+        let received = script_path_env(
+            Path::new("/workspace/packages/foo"),
+            Some(OsString::from("/usr/local/bin:/usr/bin")),
+        )
+        .unwrap();
+        dbg!(&received);
+        let expected = [
+            "/workspace/packages/foo/node_modules/.bin",
+            "/workspace/packages/node_modules/.bin",
+            "/workspace/node_modules/.bin",
+            "/node_modules/.bin",
+            "/usr/local/bin",
+            "/usr/bin",
+        ]
+        .join(":");
+        assert_eq!(received, OsString::from(expected));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_executables_of_project() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin_dir = dir.path().join("node_modules/.bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let bin_path = bin_dir.join("hello-from-bin");
+        fs::write(&bin_path, "#!/bin/sh\necho hello from bin\n").unwrap();
+        fs::set_permissions(&bin_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = script_command(dir.path(), "hello-from-bin").unwrap().output().unwrap();
+        dbg!(&output);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello from bin");
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn execute_program_with_args() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("exit-with");
+        fs::write(&program, "#!/bin/sh\nexit \"$1\"\n").unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+        execute_program(dir.path(), &program, &["0".to_string()]).unwrap();
+        let error = execute_program(dir.path(), &program, &["7".to_string()]).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, ExecutorError::NonZeroExit { code: Some(7) }));
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn decide_script_user() {
+        macro_rules! case {
+            ($current_uid:expr, $owner_uid:expr, $unsafe_perm:expr => $output:expr) => {{
+                let current_uid: u32 = $current_uid;
+                let owner = ScriptUser { uid: $owner_uid, gid: 1000 };
+                let unsafe_perm: bool = $unsafe_perm;
+                eprintln!(
+                    "CASE: current_uid={current_uid}, owner={owner:?}, unsafe_perm={unsafe_perm}"
+                );
+                let output: Option<ScriptUser> = $output;
+                assert_eq!(ScriptUser::decide(current_uid, owner, unsafe_perm), output);
+            }};
+        }
+
+        case!(0, 1000, false => Some(ScriptUser { uid: 1000, gid: 1000 }));
+        case!(0, 1000, true => None);
+        case!(0, 0, false => None);
+        case!(1000, 1000, false => None);
+        case!(1000, 1001, false => None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn script_user_for_own_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let received = ScriptUser::for_dir(dir.path(), false).unwrap();
+        dbg!(&received);
+        assert_eq!(received, None); // the directory is owned by the current user
+    }
+}
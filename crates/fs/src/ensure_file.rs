@@ -29,18 +29,56 @@ pub enum EnsureFileError {
     },
 }
 
+/// What [`ensure_file`] and [`overwrite_file`] did to the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnsureFileStatus {
+    /// The file didn't exist and has been created.
+    Created,
+    /// The file already existed and has been left untouched.
+    Skipped,
+    /// The file already existed with different content and has been overwritten.
+    Overwritten,
+}
+
 /// Write `content` to `file_path` unless it already exists.
 ///
 /// Ancestor directories will be created if they don't already exist.
 pub fn ensure_file(
     file_path: &Path,
     content: &[u8],
-    #[cfg_attr(windows, allow(unused))] mode: Option<u32>,
-) -> Result<(), EnsureFileError> {
+    mode: Option<u32>,
+) -> Result<EnsureFileStatus, EnsureFileError> {
     if file_path.exists() {
-        return Ok(());
+        return Ok(EnsureFileStatus::Skipped);
     }
+    write_file(file_path, content, mode)?;
+    Ok(EnsureFileStatus::Created)
+}
+
+/// Write `content` to `file_path`, replacing the content of an existing file.
+///
+/// An existing file that already has `content` is left untouched. `mode` only applies to new
+/// files. Ancestor directories will be created if they don't already exist.
+pub fn overwrite_file(
+    file_path: &Path,
+    content: &[u8],
+    mode: Option<u32>,
+) -> Result<EnsureFileStatus, EnsureFileError> {
+    let status = match fs::read(file_path) {
+        Ok(existing) if existing == content => return Ok(EnsureFileStatus::Skipped),
+        Ok(_) => EnsureFileStatus::Overwritten,
+        Err(_) => EnsureFileStatus::Created,
+    };
+    write_file(file_path, content, mode)?;
+    Ok(status)
+}
 
+/// Create or truncate `file_path` and write `content` to it.
+fn write_file(
+    file_path: &Path,
+    content: &[u8],
+    #[cfg_attr(windows, allow(unused))] mode: Option<u32>,
+) -> Result<(), EnsureFileError> {
     let parent_dir = file_path.parent().unwrap();
     fs::create_dir_all(parent_dir).map_err(|error| EnsureFileError::CreateDir {
         parent_dir: parent_dir.to_path_buf(),
@@ -48,7 +86,7 @@ pub fn ensure_file(
     })?;
 
     let mut options = OpenOptions::new();
-    options.write(true).create(true);
+    options.write(true).create(true).truncate(true);
 
     #[cfg(unix)]
     {
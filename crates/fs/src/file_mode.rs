@@ -6,12 +6,15 @@ pub const EXEC_MASK: u32 = 0b001_001_001;
 /// All can read and execute, but only owner can write (`rwxr-xr-x`).
 pub const EXEC_MODE: u32 = 0b111_101_101;
 
+/// All can read, but only owner can write (`rw-r--r--`).
+pub const FILE_MODE: u32 = 0b110_100_100;
+
 /// Whether a file mode has all executable bits.
 pub fn is_all_exec(mode: u32) -> bool {
     mode & EXEC_MASK == EXEC_MASK
 }
 
-/// Set file mode to 777 on POSIX platforms such as Linux or macOS,
+/// Add the executable bits to the file mode on POSIX platforms such as Linux or macOS,
 /// or do nothing on Windows.
 #[cfg_attr(windows, allow(unused))]
 pub fn make_file_executable(file: &std::fs::File) -> io::Result<()> {
@@ -3,9 +3,40 @@ use std::{io, path::Path};
 /// Create a symlink to a directory.
 ///
 /// The `link` path will be a symbolic link pointing to `original`.
+///
+/// On Windows, creating a symlink requires Developer Mode or the `SeCreateSymbolicLinkPrivilege`
+/// privilege. Without them, a junction is created instead.
 pub fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
     #[cfg(unix)]
     return std::os::unix::fs::symlink(original, link);
     #[cfg(windows)]
-    return junction::create(original, link); // junctions instead of symlinks because symlinks may require elevated privileges.
+    return symlink_or_junction(original, link);
+}
+
+/// Create a symlink on Windows, or a junction if the privilege to create symlinks isn't held.
+#[cfg(windows)]
+fn symlink_or_junction(original: &Path, link: &Path) -> io::Result<()> {
+    /// Error code of `CreateSymbolicLinkW` when the privilege isn't held.
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+    match std::os::windows::fs::symlink_dir(original, link) {
+        Ok(()) => {
+            tracing::debug!(target: "pacquet::import", ?original, ?link, "Created a symlink");
+            Ok(())
+        }
+        Err(error) if error.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) => {
+            junction::create(original, link)?;
+            tracing::debug!(target: "pacquet::import", ?original, ?link, "Created a junction because symlinks aren't permitted");
+            Ok(())
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Remove a symlink created by [`symlink_dir`] without touching the directory it points to.
+pub fn remove_symlink_dir(link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::fs::remove_file(link);
+    #[cfg(windows)]
+    return std::fs::remove_dir(link); // removes the junction itself, not the target directory
 }
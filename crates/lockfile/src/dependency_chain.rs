@@ -0,0 +1,210 @@
+use crate::{
+    DependencyPath, Lockfile, PackageSnapshotDependency, PkgName, PkgNameVerPeer, PkgVerPeer,
+    RootProjectSnapshot,
+};
+use pacquet_package_manifest::DependencyGroup;
+use std::collections::HashSet;
+
+/// A dependency edge in a [`DependencyChain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyLink {
+    /// Name of the dependency.
+    pub name: PkgName,
+    /// How the dependent refers to the dependency in the lockfile.
+    ///
+    /// It is the version range for a dependency of the root project, and the resolved version otherwise.
+    pub specifier: String,
+    /// Key of the dependency in the `packages` map.
+    pub dependency_path: DependencyPath,
+}
+
+/// Chain of dependencies from a dependency of the root project down to a package.
+pub type DependencyChain = Vec<DependencyLink>;
+
+/// Key of a package from the default registry in the `packages` map.
+pub(crate) fn default_registry_path(name: &PkgName, ver_peer: &PkgVerPeer) -> DependencyPath {
+    DependencyPath {
+        custom_registry: None,
+        package_specifier: PkgNameVerPeer::new(name.clone(), ver_peer.clone()),
+    }
+}
+
+impl Lockfile {
+    /// List every chain of dependencies that leads from the root project to a package named `name`.
+    ///
+    /// * Each installed version of `name` ends its own chains, so they can be told apart by the last link.
+    /// * A chain stops at the first package named `name` it reaches.
+    /// * Chains are sorted by the dependency paths of their links.
+    pub fn dependency_chains(&self, name: &PkgName) -> Vec<DependencyChain> {
+        let RootProjectSnapshot::Single(project_snapshot) = &self.project_snapshot else {
+            return Vec::new(); // TODO: support monorepo
+        };
+
+        let mut direct_dependencies = project_snapshot
+            .dependencies_by_groups([
+                DependencyGroup::Prod,
+                DependencyGroup::Dev,
+                DependencyGroup::Optional,
+            ])
+            .map(|(dependency_name, spec)| DependencyLink {
+                name: dependency_name.clone(),
+                specifier: spec.specifier.clone(),
+                dependency_path: default_registry_path(dependency_name, &spec.version),
+            })
+            .collect::<Vec<_>>();
+        direct_dependencies.sort_by_cached_key(|link| link.dependency_path.to_string());
+
+        let mut chains = Vec::new();
+        for link in direct_dependencies {
+            self.collect_dependency_chains(name, &mut vec![link], &mut chains);
+        }
+        chains
+    }
+
+    /// Extend `chain` with the dependencies of its last link until a package named `target` is reached.
+    fn collect_dependency_chains(
+        &self,
+        target: &PkgName,
+        chain: &mut DependencyChain,
+        chains: &mut Vec<DependencyChain>,
+    ) {
+        let last = chain.last().expect("chain is not empty");
+        if &last.name == target {
+            chains.push(chain.clone());
+            return;
+        }
+
+        for link in self.dependency_links(&last.dependency_path) {
+            if chain.iter().any(|ancestor| ancestor.dependency_path == link.dependency_path) {
+                continue; // circular dependency
+            }
+            chain.push(link);
+            self.collect_dependency_chains(target, chain, chains);
+            chain.pop();
+        }
+    }
+
+    /// Whether the package that ends `chain` depends, directly or transitively, on a package of `chain`.
+    pub fn is_circular(&self, chain: &DependencyChain) -> bool {
+        let Some(last) = chain.last() else { return false };
+        let mut visited = HashSet::from([&last.dependency_path]);
+        let mut stack = self.dependency_links(&last.dependency_path);
+        while let Some(link) = stack.pop() {
+            if chain.iter().any(|ancestor| ancestor.dependency_path == link.dependency_path) {
+                return true;
+            }
+            if visited.insert(&link.dependency_path) {
+                stack.extend(self.dependency_links(&link.dependency_path));
+            }
+        }
+        false
+    }
+
+    /// List the dependencies of the package at `dependency_path`, sorted by their dependency paths.
+    fn dependency_links(&self, dependency_path: &DependencyPath) -> Vec<DependencyLink> {
+        let Some(dependencies) = self
+            .packages
+            .as_ref()
+            .and_then(|packages| packages.get(dependency_path))
+            .and_then(|snapshot| snapshot.dependencies.as_ref())
+        else {
+            return Vec::new();
+        };
+
+        let mut links = dependencies
+            .iter()
+            .map(|(name, dependency)| DependencyLink {
+                name: name.clone(),
+                specifier: dependency.to_string(),
+                dependency_path: match dependency {
+                    PackageSnapshotDependency::PkgVerPeer(ver_peer) => {
+                        default_registry_path(name, ver_peer)
+                    }
+                    PackageSnapshotDependency::DependencyPath(path) => path.clone(),
+                },
+            })
+            .collect::<Vec<_>>();
+        links.sort_by_cached_key(|link| link.dependency_path.to_string());
+        links
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block_fnl;
+
+    const INTEGRITY: &str = "sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==";
+
+    fn render(chains: &[DependencyChain]) -> Vec<String> {
+        chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .iter()
+                    .map(|link| format!("{}@{}", link.name, link.specifier))
+                    .collect::<Vec<_>>()
+                    .join(" > ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chains_to_every_version() {
+        let yaml = text_block_fnl! {
+            "lockfileVersion: '6.0'"
+            "dependencies:"
+            "  a:"
+            "    specifier: ^1.0.0"
+            "    version: 1.0.0"
+            "devDependencies:"
+            "  b:"
+            "    specifier: ~1.0.0"
+            "    version: 1.0.0"
+            "packages:"
+            "  /a@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      c: 1.0.0"
+            "  /b@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      c: 2.0.0"
+            "      d: 1.0.0"
+            "  /c@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      d: 1.0.0"
+            "  /c@2.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "  /d@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      c: 1.0.0"
+        };
+        let lockfile: Lockfile =
+            serde_yaml::from_str(&yaml.replace("INTEGRITY", INTEGRITY)).unwrap();
+
+        let chains = lockfile.dependency_chains(&"c".parse().unwrap());
+        let received = render(&chains);
+        dbg!(&received);
+        assert_eq!(
+            received,
+            ["a@^1.0.0 > c@1.0.0", "b@~1.0.0 > c@2.0.0", "b@~1.0.0 > d@1.0.0 > c@1.0.0"],
+        );
+
+        eprintln!("CASE: circular dependencies terminate");
+        let chains = lockfile.dependency_chains(&"missing".parse().unwrap());
+        assert_eq!(chains, Vec::<DependencyChain>::new());
+
+        eprintln!("CASE: direct dependency");
+        let chains = lockfile.dependency_chains(&"a".parse().unwrap());
+        assert_eq!(render(&chains), ["a@^1.0.0"]);
+
+        eprintln!("CASE: c@1.0.0 and d@1.0.0 depend on each other");
+        let chains = lockfile.dependency_chains(&"c".parse().unwrap());
+        let circular = chains.iter().map(|chain| lockfile.is_circular(chain)).collect::<Vec<_>>();
+        assert_eq!(circular, [true, false, true]);
+    }
+}
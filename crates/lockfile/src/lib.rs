@@ -1,6 +1,8 @@
 mod comver;
+mod dependency_chain;
 mod dependency_path;
 mod load_lockfile;
+mod lockfile_v9;
 mod lockfile_version;
 mod multi_project_snapshot;
 mod package_snapshot;
@@ -11,13 +13,19 @@ mod pkg_name_ver;
 mod pkg_name_ver_peer;
 mod pkg_ver_peer;
 mod project_snapshot;
+mod prune_packages;
 mod resolution;
 mod resolved_dependency;
 mod root_project_snapshot;
+mod satisfies_manifest;
+mod save_lockfile;
+mod serialize_sorted;
 
 pub use comver::*;
+pub use dependency_chain::*;
 pub use dependency_path::*;
 pub use load_lockfile::*;
+pub use lockfile_v9::*;
 pub use lockfile_version::*;
 pub use multi_project_snapshot::*;
 pub use package_snapshot::*;
@@ -31,6 +39,8 @@ pub use project_snapshot::*;
 pub use resolution::*;
 pub use resolved_dependency::*;
 pub use root_project_snapshot::*;
+pub use satisfies_manifest::*;
+pub use save_lockfile::*;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -42,6 +52,23 @@ pub struct LockfileSettings {
     exclude_links_from_lockfile: bool,
 }
 
+impl LockfileSettings {
+    /// Create the settings of a lockfile.
+    pub fn new(auto_install_peers: bool, exclude_links_from_lockfile: bool) -> Self {
+        LockfileSettings { auto_install_peers, exclude_links_from_lockfile }
+    }
+}
+
+/// Value of [`Lockfile::patched_dependencies`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchInfo {
+    /// Hash of the content of the patch file.
+    pub hash: String,
+    /// Path to the patch file, relative to the directory of the lockfile.
+    pub path: String,
+}
+
 /// * Specification: <https://github.com/pnpm/spec/blob/master/lockfile/6.0.md>
 /// * Reference: <https://github.com/pnpm/pnpm/blob/main/lockfile/lockfile-types/src/index.ts>
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -50,13 +77,28 @@ pub struct Lockfile {
     pub lockfile_version: LockfileVersion<6>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub settings: Option<LockfileSettings>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_list"
+    )]
     pub never_built_dependencies: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub overrides: Option<HashMap<String, String>>,
+    /// Patches to apply to packages, keyed by `{name}@{version}`.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
+    pub patched_dependencies: Option<HashMap<String, PatchInfo>>,
     #[serde(flatten)]
     pub project_snapshot: RootProjectSnapshot,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub packages: Option<HashMap<DependencyPath, PackageSnapshot>>,
 }
 
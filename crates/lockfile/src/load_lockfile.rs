@@ -1,7 +1,8 @@
-use crate::Lockfile;
+use crate::{ComVer, Lockfile, LockfileV9, LockfileVersion, MissingPackageInfoError};
 use derive_more::{Display, Error};
 use pacquet_diagnostics::miette::{self, Diagnostic};
 use pipe_trait::Pipe;
+use serde::Deserialize;
 use std::{
     env, fs,
     io::{self, ErrorKind},
@@ -22,6 +23,35 @@ pub enum LoadLockfileError {
     #[display("Failed to parse lockfile content as YAML: {_0}")]
     #[diagnostic(code(pacquet_lockfile::parse_yaml))]
     ParseYaml(serde_yaml::Error),
+
+    #[display("The lockfileVersion of {_0} is not supported")]
+    #[diagnostic(
+        code(pacquet_lockfile::unsupported_version),
+        help("pacquet only supports lockfileVersion 6.x and 9.x. Regenerate the lockfile with pnpm v8 or v9, or remove it to let pacquet resolve the dependencies")
+    )]
+    UnsupportedVersion(#[error(not(source))] ComVer),
+
+    #[display("Failed to convert lockfile v9: {_0}")]
+    #[diagnostic(code(pacquet_lockfile::convert_v9))]
+    ConvertV9(MissingPackageInfoError),
+}
+
+/// The `lockfileVersion` field alone, which is checked before the rest of the lockfile is parsed.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LockfileVersionOnly {
+    lockfile_version: serde_yaml::Value,
+}
+
+impl LockfileVersionOnly {
+    /// Extract the version, older lockfiles may write it as a number instead of a string.
+    fn comver(&self) -> Option<ComVer> {
+        match &self.lockfile_version {
+            serde_yaml::Value::String(version) => version.parse().ok(),
+            serde_yaml::Value::Number(version) => version.to_string().parse().ok(),
+            _ => None,
+        }
+    }
 }
 
 impl Lockfile {
@@ -34,6 +64,101 @@ impl Lockfile {
             Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
             Err(error) => return error.pipe(LoadLockfileError::ReadFile).pipe(Err),
         };
-        content.pipe_as_ref(serde_yaml::from_str).map_err(LoadLockfileError::ParseYaml)
+        content.pipe_as_ref(Lockfile::from_yaml_str)
+    }
+
+    /// Read the `lockfileVersion` of the content of a lockfile without parsing the rest.
+    pub(crate) fn version_of(content: &str) -> Option<ComVer> {
+        serde_yaml::from_str::<LockfileVersionOnly>(content).ok()?.comver()
+    }
+
+    /// Parse the content of a lockfile, failing early with a clear error if its version is unsupported.
+    ///
+    /// Lockfiles of v9 are converted into the layout of v6.
+    fn from_yaml_str(content: &str) -> Result<Option<Self>, LoadLockfileError> {
+        let comver = Lockfile::version_of(content);
+        match comver {
+            Some(comver) if LockfileVersion::<9>::is_compatible(comver) => {
+                let lockfile: Option<LockfileV9> =
+                    serde_yaml::from_str(content).map_err(LoadLockfileError::ParseYaml)?;
+                lockfile.map(Lockfile::try_from).transpose().map_err(LoadLockfileError::ConvertV9)
+            }
+            Some(comver) if !LockfileVersion::<6>::is_compatible(comver) => {
+                Err(LoadLockfileError::UnsupportedVersion(comver))
+            }
+            _ => serde_yaml::from_str(content).map_err(LoadLockfileError::ParseYaml),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block;
+
+    #[test]
+    fn unsupported_version() {
+        let content = text_block! {
+            "lockfileVersion: '7.0'"
+            ""
+            "dependencies: {}"
+        };
+        let error = Lockfile::from_yaml_str(content).unwrap_err();
+        dbg!(&error);
+        assert_eq!(error.to_string(), "The lockfileVersion of 7.0 is not supported");
+        assert!(matches!(
+            error,
+            LoadLockfileError::UnsupportedVersion(ComVer { major: 7, minor: 0 }),
+        ));
+    }
+
+    #[test]
+    fn unsupported_numeric_version() {
+        let error = Lockfile::from_yaml_str("lockfileVersion: 5.4\n").unwrap_err();
+        dbg!(&error);
+        assert!(matches!(
+            error,
+            LoadLockfileError::UnsupportedVersion(ComVer { major: 5, minor: 4 }),
+        ));
+    }
+
+    #[test]
+    fn supported_v9() {
+        let content = text_block! {
+            "lockfileVersion: '9.0'"
+            ""
+            "importers:"
+            "  .:"
+            "    dependencies:"
+            "      a:"
+            "        specifier: ^1.0.0"
+            "        version: 1.0.0"
+            ""
+            "packages:"
+            "  a@1.0.0:"
+            "    resolution: {integrity: sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==}"
+            ""
+            "snapshots:"
+            "  a@1.0.0: {}"
+        };
+        let lockfile = Lockfile::from_yaml_str(content).unwrap().unwrap();
+        dbg!(&lockfile);
+        assert_eq!(lockfile.lockfile_version.major, 6);
+        let packages = lockfile.packages.unwrap();
+        assert!(packages.contains_key(&"/a@1.0.0".parse().unwrap()));
+    }
+
+    #[test]
+    fn supported_version() {
+        let content = text_block! {
+            "lockfileVersion: '6.0'"
+            ""
+            "settings:"
+            "  autoInstallPeers: true"
+            "  excludeLinksFromLockfile: false"
+        };
+        let lockfile = Lockfile::from_yaml_str(content).unwrap().unwrap();
+        assert_eq!(lockfile.lockfile_version.major, 6);
     }
 }
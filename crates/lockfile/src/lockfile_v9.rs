@@ -0,0 +1,383 @@
+use crate::{
+    ComVer, DependencyPath, Lockfile, LockfilePeerDependencyMetaValue, LockfileResolution,
+    LockfileSettings, LockfileVersion, MultiProjectSnapshot, PackageSnapshot,
+    PackageSnapshotDependency, PatchInfo, PkgName, PkgNameVer, PkgNameVerPeer, PkgVerPeer,
+    ProjectSnapshot, RootProjectSnapshot,
+};
+use derive_more::{Display, Error};
+use pacquet_diagnostics::miette::{self, Diagnostic};
+use pacquet_package_manifest::DependencyGroup;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// Lockfile of pnpm v9, which is converted into [`Lockfile`] after being parsed.
+///
+/// Compared to v6, the keys of the maps have no leading slash, the metadata of each package
+/// is in `packages`, and the dependencies of each peer-resolved variant are in `snapshots`.
+///
+/// * Reference: <https://github.com/pnpm/pnpm/blob/main/lockfile/types/src/lockfileFileTypes.ts>
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockfileV9 {
+    pub lockfile_version: LockfileVersion<9>,
+    pub settings: Option<LockfileSettings>,
+    pub never_built_dependencies: Option<Vec<String>>,
+    pub overrides: Option<HashMap<String, String>>,
+    pub patched_dependencies: Option<HashMap<String, PatchInfo>>,
+    #[serde(default)]
+    pub importers: HashMap<String, ProjectSnapshot>,
+    #[serde(default)]
+    pub packages: HashMap<PkgNameVer, PackageInfoV9>,
+    #[serde(default)]
+    pub snapshots: HashMap<PkgNameVerPeer, PackageSnapshotV9>,
+}
+
+/// Value of [`LockfileV9::packages`], the metadata of a package that doesn't depend on peers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageInfoV9 {
+    pub resolution: LockfileResolution,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub engines: Option<HashMap<String, String>>,
+    pub cpu: Option<Vec<String>>,
+    pub os: Option<Vec<String>>,
+    pub libc: Option<Vec<String>>,
+    pub deprecated: Option<String>,
+    pub has_bin: Option<bool>,
+    pub prepare: Option<bool>,
+    pub requires_build: Option<bool>,
+    pub bundled_dependencies: Option<Vec<String>>,
+    pub peer_dependencies: Option<HashMap<String, String>>,
+    pub peer_dependencies_meta: Option<HashMap<String, LockfilePeerDependencyMetaValue>>,
+}
+
+/// Value of [`LockfileV9::snapshots`], the dependencies of a package with its peers resolved.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageSnapshotV9 {
+    pub dependencies: Option<HashMap<PkgName, SnapshotDependencyV9>>,
+    pub optional_dependencies: Option<HashMap<PkgName, SnapshotDependencyV9>>,
+    pub transitive_peer_dependencies: Option<Vec<String>>,
+    pub optional: Option<bool>,
+}
+
+/// Value of [`PackageSnapshotV9::dependencies`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SnapshotDependencyV9 {
+    /// Version of the package with the same name, e.g. `1.0.0(react@18.2.0)`.
+    PkgVerPeer(PkgVerPeer),
+    /// Another package installed under an alias, e.g. `string-width@4.2.3`.
+    Alias(PkgNameVerPeer),
+}
+
+impl SnapshotDependencyV9 {
+    /// Key of the dependency in [`LockfileV9::snapshots`].
+    fn snapshot_key(&self, name: &PkgName) -> PkgNameVerPeer {
+        match self {
+            SnapshotDependencyV9::PkgVerPeer(ver_peer) => {
+                PkgNameVerPeer::new(name.clone(), ver_peer.clone())
+            }
+            SnapshotDependencyV9::Alias(key) => key.clone(),
+        }
+    }
+}
+
+impl From<SnapshotDependencyV9> for PackageSnapshotDependency {
+    fn from(value: SnapshotDependencyV9) -> Self {
+        match value {
+            SnapshotDependencyV9::PkgVerPeer(ver_peer) => ver_peer.into(),
+            SnapshotDependencyV9::Alias(package_specifier) => {
+                DependencyPath { custom_registry: None, package_specifier }.into()
+            }
+        }
+    }
+}
+
+/// Error when a package in [`LockfileV9::snapshots`] has no metadata in [`LockfileV9::packages`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[display("Package {_0} has a snapshot but no entry in packages")]
+#[diagnostic(code(pacquet_lockfile::missing_package_info))]
+pub struct MissingPackageInfoError(#[error(not(source))] pub PkgNameVerPeer);
+
+impl LockfileV9 {
+    /// Find the snapshots that are reachable from the direct dependencies in `groups`.
+    fn reachable_from(&self, groups: &[DependencyGroup]) -> HashSet<PkgNameVerPeer> {
+        let mut pending = self
+            .importers
+            .values()
+            .flat_map(|project_snapshot| {
+                project_snapshot.dependencies_by_groups(groups.iter().copied())
+            })
+            .map(|(name, spec)| PkgNameVerPeer::new(name.clone(), spec.version.clone()))
+            .collect::<Vec<_>>();
+        let mut reachable = HashSet::new();
+        while let Some(key) = pending.pop() {
+            let Some(snapshot) = self.snapshots.get(&key) else { continue };
+            if !reachable.insert(key) {
+                continue;
+            }
+            let dependencies = snapshot.dependencies.iter().flatten();
+            let optional_dependencies = snapshot.optional_dependencies.iter().flatten();
+            pending.extend(
+                dependencies
+                    .chain(optional_dependencies)
+                    .map(|(name, dependency)| dependency.snapshot_key(name)),
+            );
+        }
+        reachable
+    }
+}
+
+impl TryFrom<LockfileV9> for Lockfile {
+    type Error = MissingPackageInfoError;
+
+    /// Convert the lockfile into the layout of v6.
+    ///
+    /// v9 doesn't record whether a package is only used by `devDependencies`, so the `dev` flags
+    /// are computed from the dependency graph. The result is saved as a v6 lockfile.
+    fn try_from(lockfile: LockfileV9) -> Result<Self, Self::Error> {
+        let prod_reachable =
+            lockfile.reachable_from(&[DependencyGroup::Prod, DependencyGroup::Optional]);
+        let dev_reachable = lockfile.reachable_from(&[DependencyGroup::Dev]);
+
+        let LockfileV9 {
+            settings,
+            never_built_dependencies,
+            overrides,
+            patched_dependencies,
+            mut importers,
+            packages: package_infos,
+            snapshots,
+            ..
+        } = lockfile;
+
+        let packages = snapshots
+            .into_iter()
+            .map(|(key, snapshot)| {
+                let info_key = PkgNameVer::new(key.name.clone(), key.suffix.version().clone());
+                let Some(info) = package_infos.get(&info_key).cloned() else {
+                    return Err(MissingPackageInfoError(key));
+                };
+                let dev = match (prod_reachable.contains(&key), dev_reachable.contains(&key)) {
+                    (true, false) => Some(false),
+                    (false, true) => Some(true),
+                    _ => None,
+                };
+                let path = DependencyPath { custom_registry: None, package_specifier: key };
+                Ok((path, convert_package(info, snapshot, dev)))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let project_snapshot = match (importers.remove("."), importers.is_empty()) {
+            (Some(project_snapshot), true) => RootProjectSnapshot::Single(project_snapshot),
+            (None, true) => RootProjectSnapshot::Single(ProjectSnapshot::default()),
+            (root, false) => {
+                importers.extend(root.map(|project_snapshot| (".".to_string(), project_snapshot)));
+                RootProjectSnapshot::Multi(MultiProjectSnapshot { importers })
+            }
+        };
+
+        Ok(Lockfile {
+            lockfile_version: ComVer::new(6, 0).try_into().expect("6.0 is compatible with 6.x"),
+            settings,
+            never_built_dependencies,
+            overrides,
+            patched_dependencies,
+            project_snapshot,
+            packages: (!packages.is_empty()).then_some(packages),
+        })
+    }
+}
+
+/// Merge the metadata and the snapshot of a package into an entry of [`Lockfile::packages`].
+fn convert_package(
+    info: PackageInfoV9,
+    snapshot: PackageSnapshotV9,
+    dev: Option<bool>,
+) -> PackageSnapshot {
+    let PackageInfoV9 {
+        resolution,
+        id,
+        name,
+        version,
+        engines,
+        cpu,
+        os,
+        libc,
+        deprecated,
+        has_bin,
+        prepare,
+        requires_build,
+        bundled_dependencies,
+        peer_dependencies,
+        peer_dependencies_meta,
+    } = info;
+    let PackageSnapshotV9 {
+        dependencies,
+        optional_dependencies,
+        transitive_peer_dependencies,
+        optional,
+    } = snapshot;
+
+    let dependencies = dependencies.map(|dependencies| {
+        dependencies.into_iter().map(|(name, dependency)| (name, dependency.into())).collect()
+    });
+    let optional_dependencies = optional_dependencies.map(|dependencies| {
+        dependencies
+            .into_iter()
+            .map(|(name, dependency)| {
+                (name.to_string(), PackageSnapshotDependency::from(dependency).to_string())
+            })
+            .collect()
+    });
+
+    PackageSnapshot {
+        resolution,
+        id,
+        name,
+        version,
+        engines,
+        cpu,
+        os,
+        libc,
+        deprecated,
+        has_bin,
+        prepare,
+        requires_build,
+        bundled_dependencies,
+        peer_dependencies,
+        peer_dependencies_meta,
+        dependencies,
+        optional_dependencies,
+        transitive_peer_dependencies,
+        dev,
+        optional,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block_fnl;
+
+    const INTEGRITY: &str = "sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==";
+
+    #[test]
+    fn convert_to_v6() {
+        let v9 = text_block_fnl! {
+            "lockfileVersion: '9.0'"
+            "settings:"
+            "  autoInstallPeers: true"
+            "  excludeLinksFromLockfile: false"
+            "importers:"
+            "  .:"
+            "    dependencies:"
+            "      a:"
+            "        specifier: ^1.0.0"
+            "        version: 1.0.0(react@18.2.0)"
+            "      react:"
+            "        specifier: ^18.2.0"
+            "        version: 18.2.0"
+            "    devDependencies:"
+            "      b:"
+            "        specifier: ^1.0.0"
+            "        version: 1.0.0"
+            "packages:"
+            "  a@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    peerDependencies:"
+            "      react: '*'"
+            "  b@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    hasBin: true"
+            "  c@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "  d@2.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "  react@18.2.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "snapshots:"
+            "  a@1.0.0(react@18.2.0):"
+            "    dependencies:"
+            "      c: 1.0.0"
+            "      react: 18.2.0"
+            "  b@1.0.0:"
+            "    dependencies:"
+            "      c: 1.0.0"
+            "      e: d@2.0.0"
+            "  c@1.0.0: {}"
+            "  d@2.0.0: {}"
+            "  react@18.2.0: {}"
+        };
+        let v6 = text_block_fnl! {
+            "lockfileVersion: '6.0'"
+            "settings:"
+            "  autoInstallPeers: true"
+            "  excludeLinksFromLockfile: false"
+            "dependencies:"
+            "  a:"
+            "    specifier: ^1.0.0"
+            "    version: 1.0.0(react@18.2.0)"
+            "  react:"
+            "    specifier: ^18.2.0"
+            "    version: 18.2.0"
+            "devDependencies:"
+            "  b:"
+            "    specifier: ^1.0.0"
+            "    version: 1.0.0"
+            "packages:"
+            "  /a@1.0.0(react@18.2.0):"
+            "    resolution: {integrity: INTEGRITY}"
+            "    peerDependencies:"
+            "      react: '*'"
+            "    dependencies:"
+            "      c: 1.0.0"
+            "      react: 18.2.0"
+            "    dev: false"
+            "  /b@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    hasBin: true"
+            "    dependencies:"
+            "      c: 1.0.0"
+            "      e: /d@2.0.0"
+            "    dev: true"
+            "  /c@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "  /d@2.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dev: true"
+            "  /react@18.2.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dev: false"
+        };
+
+        let v9: LockfileV9 = serde_yaml::from_str(&v9.replace("INTEGRITY", INTEGRITY)).unwrap();
+        let received = Lockfile::try_from(v9).unwrap();
+        dbg!(&received);
+        let expected: Lockfile = serde_yaml::from_str(&v6.replace("INTEGRITY", INTEGRITY)).unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn missing_package_info() {
+        let v9 = text_block_fnl! {
+            "lockfileVersion: '9.0'"
+            "importers:"
+            "  .:"
+            "    dependencies:"
+            "      a:"
+            "        specifier: ^1.0.0"
+            "        version: 1.0.0"
+            "snapshots:"
+            "  a@1.0.0: {}"
+        };
+        let v9: LockfileV9 = serde_yaml::from_str(v9).unwrap();
+        let error = Lockfile::try_from(v9).unwrap_err();
+        dbg!(&error);
+        assert_eq!(error.to_string(), "Package a@1.0.0 has a snapshot but no entry in packages");
+    }
+}
@@ -6,5 +6,6 @@ use std::collections::HashMap;
 #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct MultiProjectSnapshot {
+    #[serde(serialize_with = "crate::serialize_sorted::sorted_map")]
     pub importers: HashMap<String, ProjectSnapshot>,
 }
@@ -1,8 +1,9 @@
 use crate::{LockfileResolution, PackageSnapshotDependency, PkgName};
+use pacquet_package_manifest::DependencyGroup;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LockfilePeerDependencyMetaValue {
     optional: bool,
 }
@@ -20,7 +21,10 @@ pub struct PackageSnapshot {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>, // TODO: name and version are required on non-default registry, create a struct for it
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub engines: Option<HashMap<String, String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cpu: Option<Vec<String>>,
@@ -39,17 +43,100 @@ pub struct PackageSnapshot {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bundled_dependencies: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub peer_dependencies: Option<HashMap<String, String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub peer_dependencies_meta: Option<HashMap<String, LockfilePeerDependencyMetaValue>>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub dependencies: Option<HashMap<PkgName, PackageSnapshotDependency>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub optional_dependencies: Option<HashMap<String, String>>,
 
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_list"
+    )]
     pub transitive_peer_dependencies: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub dev: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub optional: Option<bool>,
 }
+
+impl PackageSnapshot {
+    /// Whether the package is needed when only the dependencies of `groups` are to be installed.
+    ///
+    /// This relies on the flags computed by pnpm for every package:
+    /// * `dev: true` means the package is only reachable from `devDependencies`.
+    /// * `dev: false` means the package is only reachable from `dependencies` or `optionalDependencies`.
+    /// * Absent `dev` means the package is reachable from both.
+    /// * `optional: true` means the package is only reachable via optional dependencies.
+    pub fn is_required_by(&self, groups: &[DependencyGroup]) -> bool {
+        let has = |group| groups.contains(&group);
+        let has_prod = has(DependencyGroup::Prod) || has(DependencyGroup::Optional);
+        let has_dev = has(DependencyGroup::Dev);
+        let by_dev_flag = match self.dev {
+            Some(true) => has_dev,
+            Some(false) => has_prod,
+            None => has_prod || has_dev,
+        };
+        let by_optional_flag = self.optional != Some(true) || has(DependencyGroup::Optional);
+        by_dev_flag && by_optional_flag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn is_required_by() {
+        use DependencyGroup::{Dev, Optional, Prod};
+
+        macro_rules! case {
+            ($dev:expr, $optional:expr, $groups:expr => $output:expr) => {{
+                let (dev, optional, groups): (Option<bool>, Option<bool>, &[DependencyGroup]) =
+                    ($dev, $optional, &$groups);
+                eprintln!("CASE: dev={dev:?} optional={optional:?} groups={groups:?}");
+                let snapshot: PackageSnapshot = serde_yaml::from_str(
+                    "resolution: {integrity: sha512-gf6ZldcfCDyNXPRiW3lQjEP1Z9rrUM/4Cn7BZbv3SdTA82zxWRP8OmLwvGR974uuENhGCFgFdN11z3n1Ofpprg==}",
+                )
+                .unwrap();
+                let snapshot = PackageSnapshot { dev, optional, ..snapshot };
+                assert_eq!(snapshot.is_required_by(groups), $output);
+            }};
+        }
+
+        case!(Some(false), None, [Prod, Dev, Optional] => true);
+        case!(Some(true), None, [Prod, Dev, Optional] => true);
+        case!(None, None, [Prod, Dev, Optional] => true);
+
+        case!(Some(false), None, [Prod, Optional] => true);
+        case!(Some(true), None, [Prod, Optional] => false);
+        case!(None, None, [Prod, Optional] => true);
+
+        case!(Some(false), None, [Dev, Optional] => true);
+        case!(Some(true), None, [Dev, Optional] => true);
+        case!(Some(false), None, [Dev] => false);
+        case!(None, None, [Dev] => true);
+
+        case!(Some(false), Some(true), [Prod, Optional] => true);
+        case!(Some(false), Some(true), [Prod] => false);
+        case!(Some(true), Some(true), [Dev] => false);
+        case!(None, Some(false), [Prod] => true);
+    }
+}
@@ -73,6 +73,12 @@ impl<'a> TryFrom<&'a str> for PkgVerPeer {
     }
 }
 
+impl From<Version> for PkgVerPeer {
+    fn from(version: Version) -> Self {
+        PkgVerPeer { version, peer: String::new() }
+    }
+}
+
 impl From<PkgVerPeer> for String {
     fn from(value: PkgVerPeer) -> Self {
         value.to_string()
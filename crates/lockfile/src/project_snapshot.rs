@@ -7,13 +7,25 @@ use std::collections::HashMap;
 #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProjectSnapshot {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub specifiers: Option<HashMap<String, String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub dependencies: Option<ResolvedDependencyMap>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub optional_dependencies: Option<ResolvedDependencyMap>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serialize_sorted::optional_sorted_map"
+    )]
     pub dev_dependencies: Option<ResolvedDependencyMap>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dependencies_meta: Option<serde_yaml::Value>, // TODO: DependenciesMeta
@@ -32,6 +44,17 @@ impl ProjectSnapshot {
         }
     }
 
+    /// Remove `name` from the dependency map of `group`, return whether the dependency was there.
+    pub fn remove_dependency(&mut self, name: &PkgName, group: DependencyGroup) -> bool {
+        let map = match group {
+            DependencyGroup::Prod => &mut self.dependencies,
+            DependencyGroup::Optional => &mut self.optional_dependencies,
+            DependencyGroup::Dev => &mut self.dev_dependencies,
+            DependencyGroup::Peer => return false,
+        };
+        map.as_mut().and_then(|map| map.remove(name)).is_some()
+    }
+
     /// Iterate over combination of dependency maps according to groups.
     pub fn dependencies_by_groups(
         &self,
@@ -0,0 +1,116 @@
+use crate::{
+    default_registry_path, DependencyPath, Lockfile, PackageSnapshotDependency, PkgName,
+    PkgVerPeer, ProjectSnapshot, RootProjectSnapshot,
+};
+use pacquet_package_manifest::DependencyGroup;
+use std::collections::HashSet;
+
+impl Lockfile {
+    /// Remove the entries of `packages` that can no longer be reached from the dependencies of
+    /// the importers.
+    pub fn prune_packages(&mut self) {
+        let Some(packages) = &mut self.packages else {
+            return;
+        };
+
+        let importers: Vec<&ProjectSnapshot> = match &self.project_snapshot {
+            RootProjectSnapshot::Single(project_snapshot) => vec![project_snapshot],
+            RootProjectSnapshot::Multi(snapshot) => snapshot.importers.values().collect(),
+        };
+        let mut pending = importers
+            .into_iter()
+            .flat_map(|project_snapshot| {
+                project_snapshot.dependencies_by_groups([
+                    DependencyGroup::Prod,
+                    DependencyGroup::Dev,
+                    DependencyGroup::Optional,
+                ])
+            })
+            .map(|(name, spec)| default_registry_path(name, &spec.version))
+            .collect::<Vec<_>>();
+
+        let mut reachable = HashSet::<DependencyPath>::new();
+        while let Some(dependency_path) = pending.pop() {
+            let Some(snapshot) = packages.get(&dependency_path) else {
+                continue;
+            };
+            if !reachable.insert(dependency_path) {
+                continue;
+            }
+
+            let dependencies =
+                snapshot.dependencies.iter().flatten().map(|(name, dependency)| match dependency {
+                    PackageSnapshotDependency::PkgVerPeer(ver_peer) => {
+                        default_registry_path(name, ver_peer)
+                    }
+                    PackageSnapshotDependency::DependencyPath(path) => path.clone(),
+                });
+            let optional_dependencies =
+                snapshot.optional_dependencies.iter().flatten().filter_map(|(name, version)| {
+                    let name = PkgName::parse(name.as_str()).ok()?;
+                    let version = version.parse::<PkgVerPeer>().ok()?;
+                    Some(default_registry_path(&name, &version))
+                });
+            pending.extend(dependencies.chain(optional_dependencies));
+        }
+
+        packages.retain(|dependency_path, _| reachable.contains(dependency_path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block_fnl;
+
+    const INTEGRITY: &str = "sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==";
+
+    fn package_keys(lockfile: &Lockfile) -> Vec<String> {
+        let mut keys = lockfile
+            .packages
+            .iter()
+            .flatten()
+            .map(|(dependency_path, _)| dependency_path.to_string())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn remove_unreachable_packages() {
+        let yaml = text_block_fnl! {
+            "lockfileVersion: '6.0'"
+            "dependencies:"
+            "  a:"
+            "    specifier: ^1.0.0"
+            "    version: 1.0.0"
+            "packages:"
+            "  /a@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      b: 1.0.0"
+            "    optionalDependencies:"
+            "      c: 1.0.0"
+            "  /b@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      a: 1.0.0"
+            "  /c@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "  /d@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+            "    dependencies:"
+            "      e: 1.0.0"
+            "  /e@1.0.0:"
+            "    resolution: {integrity: INTEGRITY}"
+        };
+        let mut lockfile: Lockfile =
+            serde_yaml::from_str(&yaml.replace("INTEGRITY", INTEGRITY)).unwrap();
+
+        lockfile.prune_packages();
+        let received = package_keys(&lockfile);
+        dbg!(&received);
+        assert_eq!(received, ["/a@1.0.0", "/b@1.0.0", "/c@1.0.0"]);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{Lockfile, ProjectSnapshot, RootProjectSnapshot};
+use derive_more::Error;
+use pacquet_diagnostics::miette::{self, Diagnostic};
+use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+use std::{collections::BTreeMap, fmt};
+
+/// A direct dependency whose specifier differs between the lockfile and the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecifierDiff {
+    /// Group of the dependency.
+    pub group: DependencyGroup,
+    /// Name of the dependency.
+    pub name: String,
+    /// Specifier in the lockfile, `None` if the lockfile lacks the dependency.
+    pub lockfile: Option<String>,
+    /// Specifier in the manifest, `None` if the manifest lacks the dependency.
+    pub manifest: Option<String>,
+}
+
+/// Error when the lockfile no longer satisfies the manifest.
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(pacquet_lockfile::lockfile_mismatch),
+    help("Run `pacquet install` without --frozen-lockfile to update the lockfile")
+)]
+pub struct LockfileMismatch {
+    /// Differences by group, then by name.
+    pub diffs: Vec<SpecifierDiff>,
+}
+
+impl fmt::Display for LockfileMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The lockfile is not up to date with package.json (- lockfile, + package.json):"
+        )?;
+        for SpecifierDiff { group, name, lockfile, manifest } in &self.diffs {
+            let group: &str = (*group).into();
+            if let Some(specifier) = lockfile {
+                write!(f, "\n- {group}: {name}@{specifier}")?;
+            }
+            if let Some(specifier) = manifest {
+                write!(f, "\n+ {group}: {name}@{specifier}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Groups of direct dependencies recorded in the lockfile.
+const LOCKED_DEPENDENCY_GROUPS: [DependencyGroup; 3] =
+    [DependencyGroup::Prod, DependencyGroup::Dev, DependencyGroup::Optional];
+
+impl Lockfile {
+    /// Check that the direct dependencies of the root project have the same specifiers as `manifest`.
+    pub fn satisfies(&self, manifest: &PackageManifest) -> Result<(), LockfileMismatch> {
+        let project_snapshot = match &self.project_snapshot {
+            RootProjectSnapshot::Single(project_snapshot) => Some(project_snapshot),
+            RootProjectSnapshot::Multi(snapshot) => snapshot.importers.get("."),
+        };
+
+        let diffs = LOCKED_DEPENDENCY_GROUPS
+            .into_iter()
+            .flat_map(|group| diff_group(project_snapshot, manifest, group))
+            .collect::<Vec<_>>();
+
+        if diffs.is_empty() {
+            Ok(())
+        } else {
+            Err(LockfileMismatch { diffs })
+        }
+    }
+}
+
+/// Compare the specifiers of a single group, sorted by name.
+fn diff_group(
+    project_snapshot: Option<&ProjectSnapshot>,
+    manifest: &PackageManifest,
+    group: DependencyGroup,
+) -> Vec<SpecifierDiff> {
+    let mut specifiers = BTreeMap::<String, (Option<String>, Option<String>)>::new();
+    let locked =
+        project_snapshot.and_then(|project_snapshot| project_snapshot.get_map_by_group(group));
+    for (name, spec) in locked.into_iter().flatten() {
+        specifiers.entry(name.to_string()).or_default().0 = Some(spec.specifier.clone());
+    }
+    for (name, specifier) in manifest.dependencies([group]) {
+        specifiers.entry(name.to_string()).or_default().1 = Some(specifier.to_string());
+    }
+
+    specifiers
+        .into_iter()
+        .filter(|(_, (lockfile, manifest))| lockfile != manifest)
+        .map(|(name, (lockfile, manifest))| SpecifierDiff { group, name, lockfile, manifest })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+    use text_block_macros::{text_block, text_block_fnl};
+
+    #[test]
+    fn satisfies() {
+        let lockfile = text_block_fnl! {
+            "lockfileVersion: '6.0'"
+            "dependencies:"
+            "  a:"
+            "    specifier: ^1.0.0"
+            "    version: 1.0.0"
+            "  b:"
+            "    specifier: ^1.0.0"
+            "    version: 1.0.0"
+            "devDependencies:"
+            "  c:"
+            "    specifier: ~1.0.0"
+            "    version: 1.0.0"
+        };
+        let lockfile: Lockfile = serde_yaml::from_str(lockfile).unwrap();
+
+        let dir = tempdir().unwrap();
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("a", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("b", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("c", "~1.0.0", DependencyGroup::Dev).unwrap();
+        manifest.add_dependency("p", "*", DependencyGroup::Peer).unwrap();
+        lockfile.satisfies(&manifest).unwrap();
+
+        eprintln!("CASE: changed, added, and removed dependencies");
+        manifest.add_dependency("a", "^2.0.0", DependencyGroup::Prod).unwrap();
+        manifest.remove_dependency("b", DependencyGroup::Prod);
+        manifest.add_dependency("d", "1.0.0", DependencyGroup::Optional).unwrap();
+        let error = lockfile.satisfies(&manifest).unwrap_err();
+        dbg!(&error);
+        let expected = text_block! {
+            "The lockfile is not up to date with package.json (- lockfile, + package.json):"
+            "- dependencies: a@^1.0.0"
+            "+ dependencies: a@^2.0.0"
+            "- dependencies: b@^1.0.0"
+            "+ optionalDependencies: d@1.0.0"
+        };
+        assert_eq!(error.to_string(), expected);
+
+        drop(dir); // cleanup
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{ComVer, Lockfile};
+use derive_more::{Display, Error};
+use pacquet_diagnostics::miette::{self, Diagnostic};
+use std::{env, fs, io, path::Path};
+
+/// Error when writing lockfile to the filesystem.
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum SaveLockfileError {
+    #[display("Failed to get current_dir: {_0}")]
+    #[diagnostic(code(pacquet_lockfile::current_dir))]
+    CurrentDir(io::Error),
+
+    #[display("Failed to serialize lockfile content as YAML: {_0}")]
+    #[diagnostic(code(pacquet_lockfile::serialize_yaml))]
+    SerializeYaml(serde_yaml::Error),
+
+    #[display("Failed to write lockfile content: {_0}")]
+    #[diagnostic(code(pacquet_lockfile::write_file))]
+    WriteFile(io::Error),
+
+    #[display("Refusing to overwrite the lockfile of version {_0} with an older version")]
+    #[diagnostic(
+        code(pacquet_lockfile::downgrade_version),
+        help("pacquet can only write lockfileVersion 6.0. Update the lockfile with pnpm, or install with --frozen-lockfile")
+    )]
+    DowngradeVersion(#[error(not(source))] ComVer),
+}
+
+impl Lockfile {
+    /// Save lockfile to the current directory.
+    pub fn save_to_current_dir(&self) -> Result<(), SaveLockfileError> {
+        let current_dir = env::current_dir().map_err(SaveLockfileError::CurrentDir)?;
+        self.write_to(&current_dir)
+    }
+
+    /// Write lockfile to `pnpm-lock.yaml` in `dir`.
+    ///
+    /// Maps are serialized with sorted keys so that the output is stable and matches pnpm's.
+    ///
+    /// An existing lockfile of a newer major version (e.g. v9) isn't downgraded.
+    pub fn write_to(&self, dir: &Path) -> Result<(), SaveLockfileError> {
+        let path = dir.join(Lockfile::FILE_NAME);
+        let existing_version =
+            fs::read_to_string(&path).ok().and_then(|content| Lockfile::version_of(&content));
+        if let Some(existing_version) = existing_version {
+            if existing_version.major > self.lockfile_version.major {
+                return Err(SaveLockfileError::DowngradeVersion(existing_version));
+            }
+        }
+        let content = serde_yaml::to_string(self).map_err(SaveLockfileError::SerializeYaml)?;
+        fs::write(path, content).map_err(SaveLockfileError::WriteFile)
+    }
+}
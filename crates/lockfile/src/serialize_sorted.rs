@@ -0,0 +1,142 @@
+use serde::{Serialize, Serializer};
+use std::{collections::HashMap, fmt::Display};
+
+/// Serialize a map with its entries sorted by the string representation of the keys.
+///
+/// This makes the generated lockfile byte-stable regardless of the iteration order of [`HashMap`].
+pub(crate) fn sorted_map<Key, Value, S>(
+    map: &HashMap<Key, Value>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    Key: Display + Serialize,
+    Value: Serialize,
+    S: Serializer,
+{
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by_cached_key(|(key, _)| key.to_string());
+    serializer.collect_map(entries)
+}
+
+/// Optional version of [`sorted_map`].
+pub(crate) fn optional_sorted_map<Key, Value, S>(
+    map: &Option<HashMap<Key, Value>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    Key: Display + Serialize,
+    Value: Serialize,
+    S: Serializer,
+{
+    match map {
+        Some(map) => sorted_map(map, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serialize an optional list of strings in lexical order.
+pub(crate) fn optional_sorted_list<S>(
+    list: &Option<Vec<String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match list {
+        Some(list) => {
+            let mut list = list.iter().collect::<Vec<_>>();
+            list.sort();
+            serializer.collect_seq(list)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Lockfile;
+    use pretty_assertions::assert_eq;
+    use text_block_macros::text_block_fnl;
+
+    #[test]
+    fn lockfile_serialization_is_canonical() {
+        let input = text_block_fnl! {
+            "lockfileVersion: '6.0'"
+            "neverBuiltDependencies: [esbuild, core-js]"
+            "importers:"
+            "  packages/b:"
+            "    dependencies:"
+            "      zod:"
+            "        specifier: ^3.22.0"
+            "        version: 3.22.4"
+            "      '@types/node':"
+            "        specifier: ^18.7.19"
+            "        version: 18.7.19"
+            "  .:"
+            "    specifiers:"
+            "      typescript: ^5.1.6"
+            "      react: ^17.0.2"
+            "packages:"
+            "  /zod@3.22.4:"
+            "    resolution: {integrity: sha512-iC+8Io04lddc+mVqQ9AZ7OQ2MrUKGN+oIQyq1vemgt46jwCwLfhq7/pwnBnNXXXZb8VTVLKwp9EDkx+ryxIWmg==}"
+            "    peerDependencies:"
+            "      typescript: '*'"
+            "      react: '*'"
+            "    transitivePeerDependencies: [supports-color, bufferutil]"
+            "    dev: false"
+            "  /@types/node@18.7.19:"
+            "    resolution: {integrity: sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==}"
+            "    engines: {node: '>=14', bun: '>=1'}"
+            "    dev: true"
+        };
+        let expected = text_block_fnl! {
+            "lockfileVersion: '6.0'"
+            "neverBuiltDependencies:"
+            "- core-js"
+            "- esbuild"
+            "importers:"
+            "  .:"
+            "    specifiers:"
+            "      react: ^17.0.2"
+            "      typescript: ^5.1.6"
+            "  packages/b:"
+            "    dependencies:"
+            "      '@types/node':"
+            "        specifier: ^18.7.19"
+            "        version: 18.7.19"
+            "      zod:"
+            "        specifier: ^3.22.0"
+            "        version: 3.22.4"
+            "packages:"
+            "  /@types/node@18.7.19:"
+            "    resolution:"
+            "      integrity: sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA=="
+            "    engines:"
+            "      bun: '>=1'"
+            "      node: '>=14'"
+            "    dev: true"
+            "  /zod@3.22.4:"
+            "    resolution:"
+            "      integrity: sha512-iC+8Io04lddc+mVqQ9AZ7OQ2MrUKGN+oIQyq1vemgt46jwCwLfhq7/pwnBnNXXXZb8VTVLKwp9EDkx+ryxIWmg=="
+            "    peerDependencies:"
+            "      react: '*'"
+            "      typescript: '*'"
+            "    transitivePeerDependencies:"
+            "    - bufferutil"
+            "    - supports-color"
+            "    dev: false"
+        };
+
+        let generate = |yaml: &str| {
+            let lockfile: Lockfile = serde_yaml::from_str(yaml).unwrap();
+            serde_yaml::to_string(&lockfile).unwrap()
+        };
+
+        let generated = generate(input);
+        eprintln!("GENERATED:\n{generated}");
+        assert_eq!(generated, expected);
+
+        let regenerated = generate(&generated);
+        assert_eq!(regenerated, generated);
+    }
+}
@@ -1,6 +1,15 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
 use pipe_trait::Pipe;
-use reqwest::Client;
-use std::future::IntoFuture;
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy, RequestBuilder};
+use std::{
+    collections::HashMap,
+    fs,
+    future::{Future, IntoFuture},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::sync::Semaphore;
 
 /// Wrapper around [`Client`] with concurrent request limit enforced by the [`Semaphore`] mechanism.
@@ -8,36 +17,702 @@ use tokio::sync::Semaphore;
 pub struct ThrottledClient {
     semaphore: Semaphore,
     client: Client,
+    /// Clients that present the client certificates of specific registries, keyed by registry URLs
+    /// without the scheme and the leading `//`.
+    registry_clients: HashMap<String, Client>,
+    /// Auth tokens keyed by registry URLs without the scheme and the leading `//`.
+    auth_tokens: HashMap<String, String>,
+    /// Whether requests must not be sent at all.
+    offline: bool,
+    /// Maximum duration to wait for the response and for each chunk of its body.
+    read_timeout: Option<Duration>,
 }
 
 impl ThrottledClient {
-    /// Acquire a permit and run `proc` with the underlying [`Client`].
+    /// Acquire a permit and run `proc` with this client.
     pub async fn run_with_permit<Proc, ProcFuture>(&self, proc: Proc) -> ProcFuture::Output
     where
-        Proc: FnOnce(&Client) -> ProcFuture,
+        Proc: FnOnce(&Self) -> ProcFuture,
         ProcFuture: IntoFuture,
     {
         let permit =
             self.semaphore.acquire().await.expect("semaphore shouldn't have been closed this soon");
-        let result = proc(&self.client).await;
+        let result = proc(self).await;
         drop(permit);
         result
     }
 
+    /// Create a GET request to `url`, which is authorized with the auth token of its registry if there is one.
+    ///
+    /// The request is sent with the client certificate of its registry if there is one.
+    pub fn get(&self, url: impl AsRef<str>) -> RequestBuilder {
+        let url = url.as_ref();
+        let request = self.client_for(url).get(url);
+        match self.auth_token(url) {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Wait for `future`, which receives a response or a chunk of its body, for at most the read timeout.
+    ///
+    /// Unlike a timeout of the whole request, this doesn't fail large downloads from slow
+    /// registries as long as data keeps arriving.
+    pub async fn with_read_timeout<Output>(
+        &self,
+        future: impl Future<Output = reqwest::Result<Output>>,
+    ) -> Result<Output, RequestError> {
+        let Some(timeout) = self.read_timeout else {
+            return future.await.map_err(RequestError::Network);
+        };
+        tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| RequestError::ReadTimeout { timeout })?
+            .map_err(RequestError::Network)
+    }
+
+    /// Limit the number of concurrent requests to `permits`, which is at least 1.
+    pub fn with_permits(mut self, permits: usize) -> Self {
+        self.semaphore = Semaphore::new(permits.max(1));
+        self
+    }
+
+    /// Authorize the requests to registries with `auth_tokens`.
+    ///
+    /// The keys are registry URLs without the scheme as written in `.npmrc`,
+    /// e.g. `//registry.example.com/` for `//registry.example.com/:_authToken=...`.
+    pub fn with_auth_tokens(mut self, auth_tokens: HashMap<String, String>) -> Self {
+        self.auth_tokens = auth_tokens
+            .into_iter()
+            .map(|(registry, token)| (registry_key(&registry), token))
+            .collect();
+        self
+    }
+
+    /// Forbid the requests to the network, see [`ThrottledClient::offline`].
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Whether the client is in offline mode, in which case the callers must serve everything
+    /// from local caches and fail with [`OfflineModeError`] on a cache miss.
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Find the auth token of the registry of `url`.
+    fn auth_token(&self, url: &str) -> Option<&str> {
+        find_by_registry(&self.auth_tokens, url).map(String::as_str)
+    }
+
+    /// Find the client that presents the client certificate of the registry of `url`,
+    /// or the default client.
+    fn client_for(&self, url: &str) -> &Client {
+        find_by_registry(&self.registry_clients, url).unwrap_or(&self.client)
+    }
+
     /// Construct a new throttled client based on the number of CPUs.
     /// If the number of CPUs is greater than 16, the number of permits will be equal to the number of CPUs.
     /// Otherwise, the number of permits will be 16.
     pub fn new_from_cpu_count() -> Self {
-        const MIN_PERMITS: usize = 16;
-        let semaphore = num_cpus::get().max(MIN_PERMITS).pipe(Semaphore::new);
+        let semaphore = num_cpus::get().pipe(permits_from_cpu_count).pipe(Semaphore::new);
         let client = Client::new();
-        ThrottledClient { semaphore, client }
+        ThrottledClient {
+            semaphore,
+            client,
+            registry_clients: HashMap::new(),
+            auth_tokens: HashMap::new(),
+            offline: false,
+            read_timeout: None,
+        }
+    }
+
+    /// Construct a new throttled client based on the number of CPUs with custom settings.
+    pub fn new_with_options(options: &ClientOptions) -> Result<Self, CreateClientError> {
+        let ClientOptions {
+            client_certificate,
+            registry_client_certificates,
+            proxy,
+            ca,
+            ca_file,
+            danger_accept_invalid_certs,
+            timeout,
+        } = options;
+        let root_certificates = load_root_certificates(ca.as_deref(), ca_file.as_deref())?;
+        let proxies = proxy.proxies()?;
+        let build_client =
+            |client_certificate: Option<&(PathBuf, PathBuf)>| -> Result<Client, CreateClientError> {
+                let mut builder =
+                    Client::builder().danger_accept_invalid_certs(*danger_accept_invalid_certs);
+                if let Some(timeout) = *timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some((cert_file, key_file)) = client_certificate {
+                    builder = builder.identity(load_identity(cert_file, key_file)?);
+                }
+                for certificate in &root_certificates {
+                    builder = builder.add_root_certificate(certificate.clone());
+                }
+                for proxy in &proxies {
+                    builder = builder.proxy(proxy.clone());
+                }
+                builder.build().map_err(CreateClientError::BuildClient)
+            };
+        let client = build_client(client_certificate.as_ref())?;
+        let registry_clients = registry_client_certificates
+            .iter()
+            .map(|(registry, client_certificate)| {
+                Ok((registry_key(registry), build_client(Some(client_certificate))?))
+            })
+            .collect::<Result<_, CreateClientError>>()?;
+        let semaphore = num_cpus::get().pipe(permits_from_cpu_count).pipe(Semaphore::new);
+        Ok(ThrottledClient {
+            semaphore,
+            client,
+            registry_clients,
+            auth_tokens: HashMap::new(),
+            offline: false,
+            read_timeout: *timeout,
+        })
+    }
+}
+
+/// Settings of the HTTP client of [`ThrottledClient::new_with_options`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// PEM files of a client certificate and its private key to present to the servers (mutual TLS).
+    /// The key must be in PKCS#8 format.
+    pub client_certificate: Option<(PathBuf, PathBuf)>,
+    /// Client certificates of specific registries, in the same form as `client_certificate`.
+    ///
+    /// The keys are registry URLs without the scheme as written in `.npmrc`,
+    /// e.g. `//registry.example.com/` for `//registry.example.com/:certfile=...`.
+    /// The requests to these registries present their own certificates instead of `client_certificate`.
+    pub registry_client_certificates: HashMap<String, (PathBuf, PathBuf)>,
+    /// Proxies to send the requests through.
+    pub proxy: ProxyConfig,
+    /// Certificate authorities in PEM format to trust in addition to the system ones.
+    pub ca: Option<String>,
+    /// PEM file of certificate authorities to trust in addition to the system ones.
+    pub ca_file: Option<PathBuf>,
+    /// Accept any TLS certificate presented by the servers, including self-signed, expired,
+    /// or mismatched ones.
+    ///
+    /// **Warning:** this makes the connections vulnerable to man-in-the-middle attacks,
+    /// which could serve tampered metadata and tarballs. Prefer trusting the certificate
+    /// authority of the registry with `ca` or `ca_file` instead.
+    pub danger_accept_invalid_certs: bool,
+    /// Maximum duration of connecting, and of waiting for the response and for each chunk of its
+    /// body (see [`ThrottledClient::with_read_timeout`]). [`None`] means no limit.
+    pub timeout: Option<Duration>,
+}
+
+/// Proxy settings of [`ClientOptions`].
+///
+/// When no proxy is set, the proxies are detected from the environment variables by [`reqwest`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Proxy of `http://` requests.
+    pub http_proxy: Option<String>,
+    /// Proxy of `https://` requests.
+    pub https_proxy: Option<String>,
+    /// Comma-separated list of hosts that are requested directly.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Fill the unset settings from `HTTP_PROXY`, `HTTPS_PROXY`, and `NO_PROXY` (or their lowercase forms).
+    pub fn or_env<Env>(self, env: Env) -> Self
+    where
+        Env: Fn(&str) -> Option<String>,
+    {
+        let var = |name: &str| {
+            env(name).or_else(|| env(&name.to_lowercase())).filter(|value| !value.is_empty())
+        };
+        ProxyConfig {
+            http_proxy: self.http_proxy.or_else(|| var("HTTP_PROXY")),
+            https_proxy: self.https_proxy.or_else(|| var("HTTPS_PROXY")),
+            no_proxy: self.no_proxy.or_else(|| var("NO_PROXY")),
+        }
+    }
+
+    /// Create the proxies to register to the [`Client`].
+    fn proxies(&self) -> Result<Vec<Proxy>, CreateClientError> {
+        let ProxyConfig { http_proxy, https_proxy, no_proxy } = self;
+        let no_proxy = no_proxy.as_deref().and_then(NoProxy::from_string);
+        let create_proxy = |url: &str, create: fn(&str) -> reqwest::Result<Proxy>| {
+            create(url)
+                .map(|proxy| proxy.no_proxy(no_proxy.clone()))
+                .map_err(|error| CreateClientError::InvalidProxy { url: url.to_string(), error })
+        };
+        [(http_proxy, Proxy::http::<&str> as fn(_) -> _), (https_proxy, Proxy::https::<&str>)]
+            .into_iter()
+            .filter_map(|(url, create)| url.as_deref().map(|url| create_proxy(url, create)))
+            .collect()
     }
 }
 
+/// Error type of [`ThrottledClient::new_with_options`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum CreateClientError {
+    #[diagnostic(transparent)]
+    ClientCertificate(#[error(source)] ClientCertificateError),
+
+    #[diagnostic(transparent)]
+    CaCertificate(#[error(source)] CaCertificateError),
+
+    #[display("Invalid proxy {url:?}: {error}")]
+    #[diagnostic(code(pacquet_network::invalid_proxy))]
+    InvalidProxy {
+        url: String,
+        #[error(source)]
+        error: reqwest::Error,
+    },
+
+    #[display("Failed to create the HTTP client: {_0}")]
+    #[diagnostic(code(pacquet_network::build_client))]
+    BuildClient(reqwest::Error),
+}
+
+impl From<CaCertificateError> for CreateClientError {
+    fn from(error: CaCertificateError) -> Self {
+        CreateClientError::CaCertificate(error)
+    }
+}
+
+impl From<ClientCertificateError> for CreateClientError {
+    fn from(error: ClientCertificateError) -> Self {
+        CreateClientError::ClientCertificate(error)
+    }
+}
+
+/// Error of a request sent by [`ThrottledClient`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum RequestError {
+    #[diagnostic(code(pacquet_network::request))]
+    Network(#[error(source)] reqwest::Error),
+
+    #[display("No data was received for {timeout:?}")]
+    #[diagnostic(
+        code(pacquet_network::read_timeout),
+        help("The timeout can be changed with the fetch-timeout setting")
+    )]
+    ReadTimeout { timeout: Duration },
+}
+
+impl RequestError {
+    /// Whether the request failed to connect.
+    pub fn is_connect(&self) -> bool {
+        matches!(self, RequestError::Network(error) if error.is_connect())
+    }
+
+    /// Whether the request timed out.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            RequestError::Network(error) => error.is_timeout(),
+            RequestError::ReadTimeout { .. } => true,
+        }
+    }
+}
+
+/// Error of requesting a package that isn't available locally in offline mode.
+#[derive(Debug, Display, Error, Diagnostic)]
+#[display("Cannot fetch {package} in offline mode because it is missing from the local cache")]
+#[diagnostic(
+    code(pacquet_network::offline_mode),
+    help("Run the command without --offline, or with --prefer-offline to download only the missing packages")
+)]
+pub struct OfflineModeError {
+    /// Name or URL of the missing package.
+    pub package: String,
+}
+
+/// Error of loading the client certificate of [`ClientOptions`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum ClientCertificateError {
+    #[display("Failed to read {path:?}: {error}")]
+    #[diagnostic(code(pacquet_network::read_client_certificate))]
+    ReadFile {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to load the client certificate and its key: {_0}")]
+    #[diagnostic(
+        code(pacquet_network::invalid_client_certificate),
+        help("Make sure that certfile and keyfile are PEM files of a matching certificate and PKCS#8 private key")
+    )]
+    InvalidIdentity(reqwest::Error),
+}
+
+/// Load a client certificate and its private key from PEM files.
+fn load_identity(cert_file: &Path, key_file: &Path) -> Result<Identity, ClientCertificateError> {
+    let read = |path: &Path| {
+        fs::read(path)
+            .map_err(|error| ClientCertificateError::ReadFile { path: path.to_path_buf(), error })
+    };
+    let cert = read(cert_file)?;
+    let key = read(key_file)?;
+    Identity::from_pkcs8_pem(&cert, &key).map_err(ClientCertificateError::InvalidIdentity)
+}
+
+/// Error of loading the certificate authorities of [`ClientOptions`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum CaCertificateError {
+    #[display("Failed to read {path:?}: {error}")]
+    #[diagnostic(code(pacquet_network::read_ca_file))]
+    ReadFile {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("No certificate found in {origin}")]
+    #[diagnostic(
+        code(pacquet_network::no_ca_certificate),
+        help("The certificates must be in PEM format, e.g. -----BEGIN CERTIFICATE-----...")
+    )]
+    NoCertificate {
+        #[error(not(source))]
+        origin: String,
+    },
+
+    #[display("Failed to load a certificate authority: {_0}")]
+    #[diagnostic(code(pacquet_network::invalid_ca_certificate))]
+    InvalidCertificate(reqwest::Error),
+}
+
+/// Load the certificate authorities from the PEM text `ca` and the PEM file `ca_file`.
+fn load_root_certificates(
+    ca: Option<&str>,
+    ca_file: Option<&Path>,
+) -> Result<Vec<Certificate>, CaCertificateError> {
+    let ca_file = ca_file
+        .map(|path| {
+            fs::read_to_string(path)
+                .map(|content| (format!("{path:?}"), content))
+                .map_err(|error| CaCertificateError::ReadFile { path: path.to_path_buf(), error })
+        })
+        .transpose()?;
+    let ca = ca.map(|content| ("ca".to_string(), content.to_string()));
+
+    let mut certificates = Vec::new();
+    for (origin, bundle) in ca.into_iter().chain(ca_file) {
+        let pems = split_pem_certificates(&bundle);
+        if pems.is_empty() {
+            return Err(CaCertificateError::NoCertificate { origin });
+        }
+        for pem in pems {
+            let certificate = Certificate::from_pem(pem.as_bytes())
+                .map_err(CaCertificateError::InvalidCertificate)?;
+            certificates.push(certificate);
+        }
+    }
+    Ok(certificates)
+}
+
+/// Split a PEM bundle into the individual certificates.
+fn split_pem_certificates(bundle: &str) -> Vec<&str> {
+    const END: &str = "-----END CERTIFICATE-----";
+    bundle.split_inclusive(END).map(str::trim).filter(|pem| pem.ends_with(END)).collect()
+}
+
+/// Normalize a registry URL without the scheme (e.g. `//registry.example.com`) into a key
+/// of the maps of [`ThrottledClient`] (e.g. `registry.example.com/`).
+fn registry_key(registry: &str) -> String {
+    let registry = registry.trim_start_matches('/');
+    match registry.ends_with('/') {
+        true => registry.to_string(),
+        false => format!("{registry}/"),
+    }
+}
+
+/// Find the value of the registry with the longest URL that `url` starts with.
+fn find_by_registry<'a, Value>(map: &'a HashMap<String, Value>, url: &str) -> Option<&'a Value> {
+    let (_, address) = url.split_once("://")?;
+    map.iter()
+        .filter(|(registry, _)| address.starts_with(registry.as_str()))
+        .max_by_key(|(registry, _)| registry.len())
+        .map(|(_, value)| value)
+}
+
+/// Compute the number of permits from the reported number of CPUs.
+///
+/// Containers may report a CPU count of 0, so the result is never less than [`MIN_PERMITS`].
+fn permits_from_cpu_count(cpu_count: usize) -> usize {
+    cpu_count.max(MIN_PERMITS)
+}
+
+/// Minimum number of concurrent requests allowed by [`ThrottledClient::new_from_cpu_count`].
+const MIN_PERMITS: usize = 16;
+
 /// This is only necessary for tests.
 impl Default for ThrottledClient {
     fn default() -> Self {
         ThrottledClient::new_from_cpu_count()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn permits_from_low_cpu_count() {
+        assert_eq!(permits_from_cpu_count(0), MIN_PERMITS);
+        assert_eq!(permits_from_cpu_count(1), MIN_PERMITS);
+        assert_eq!(permits_from_cpu_count(MIN_PERMITS), MIN_PERMITS);
+    }
+
+    #[test]
+    fn permits_from_high_cpu_count() {
+        assert_eq!(permits_from_cpu_count(64), 64);
+    }
+
+    #[tokio::test]
+    async fn limit_concurrent_requests_by_permits() {
+        let http_client = ThrottledClient::new_from_cpu_count().with_permits(2);
+        assert_eq!(http_client.semaphore.available_permits(), 2);
+        http_client
+            .run_with_permit(|client| async move {
+                assert_eq!(client.semaphore.available_permits(), 1);
+            })
+            .await;
+        assert_eq!(http_client.semaphore.available_permits(), 2);
+
+        let http_client = ThrottledClient::new_from_cpu_count().with_permits(0);
+        assert_eq!(http_client.semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn attach_auth_token_of_matching_registry() {
+        let http_client = ThrottledClient::new_from_cpu_count().with_auth_tokens(
+            [
+                ("//registry.example.com/".to_string(), "TOKEN".to_string()),
+                ("//registry.example.com/private".to_string(), "PRIVATE_TOKEN".to_string()),
+            ]
+            .into(),
+        );
+        let authorization = |url: &str| {
+            let request = http_client.get(url).build().unwrap();
+            request
+                .headers()
+                .get(reqwest::header::AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+
+        macro_rules! case {
+            ($url:expr => $output:expr) => {{
+                let url = $url;
+                eprintln!("CASE: {url:?}");
+                let output: Option<&str> = $output;
+                assert_eq!(authorization(url).as_deref(), output);
+            }};
+        }
+
+        case!("https://registry.example.com/foo" => Some("Bearer TOKEN"));
+        case!("http://registry.example.com/foo/-/foo-1.0.0.tgz" => Some("Bearer TOKEN"));
+        case!("https://registry.example.com/private/foo" => Some("Bearer PRIVATE_TOKEN"));
+        case!("https://registry.example.com/privateer/foo" => Some("Bearer TOKEN"));
+        case!("https://registry.example.com.evil.com/foo" => None);
+        case!("https://registry.npmjs.org/foo" => None);
+    }
+
+    #[test]
+    fn choose_client_of_matching_registry() {
+        let http_client = ThrottledClient {
+            registry_clients: [("registry.example.com/private/".to_string(), Client::new())].into(),
+            ..ThrottledClient::new_from_cpu_count()
+        };
+        let private_client = &http_client.registry_clients["registry.example.com/private/"];
+        let uses_private_client =
+            |url: &str| std::ptr::eq(http_client.client_for(url), private_client);
+
+        assert!(uses_private_client("https://registry.example.com/private/foo"));
+        assert!(!uses_private_client("https://registry.example.com/foo"));
+        assert!(!uses_private_client("https://registry.npmjs.org/foo"));
+        assert!(std::ptr::eq(
+            http_client.client_for("https://registry.npmjs.org/foo"),
+            &http_client.client
+        ));
+    }
+
+    #[test]
+    fn registry_client_certificate_file_not_found() {
+        let dir = tempdir().unwrap();
+        let cert_file = dir.path().join("client.crt");
+        let options = ClientOptions {
+            registry_client_certificates: [(
+                "//registry.example.com/".to_string(),
+                (cert_file.clone(), dir.path().join("client.key")),
+            )]
+            .into(),
+            ..ClientOptions::default()
+        };
+        let error = ThrottledClient::new_with_options(&options).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(
+            error,
+            CreateClientError::ClientCertificate(ClientCertificateError::ReadFile { path, .. })
+                if path == cert_file
+        ));
+    }
+
+    #[test]
+    fn proxy_config_or_env() {
+        let env = |name: &str| match name {
+            "HTTP_PROXY" => Some("http://env-proxy:8080".to_string()),
+            "https_proxy" => Some("http://env-secure-proxy:8080".to_string()),
+            "NO_PROXY" => Some(String::new()),
+            _ => None,
+        };
+
+        eprintln!("Settings from .npmrc take precedence");
+        let proxy = ProxyConfig {
+            http_proxy: Some("http://npmrc-proxy:8080".to_string()),
+            https_proxy: None,
+            no_proxy: Some("localhost".to_string()),
+        };
+        let expected = ProxyConfig {
+            http_proxy: Some("http://npmrc-proxy:8080".to_string()),
+            https_proxy: Some("http://env-secure-proxy:8080".to_string()),
+            no_proxy: Some("localhost".to_string()),
+        };
+        assert_eq!(proxy.or_env(env), expected);
+
+        eprintln!("Empty environment variables are ignored");
+        let expected = ProxyConfig {
+            http_proxy: Some("http://env-proxy:8080".to_string()),
+            https_proxy: Some("http://env-secure-proxy:8080".to_string()),
+            no_proxy: None,
+        };
+        assert_eq!(ProxyConfig::default().or_env(env), expected);
+    }
+
+    #[tokio::test]
+    async fn send_requests_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_url = format!("http://{}", listener.local_addr().unwrap());
+        let proxy = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request_line = BufReader::new(&stream).lines().next().unwrap().unwrap();
+            let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            stream.write_all(response.as_bytes()).unwrap();
+            request_line
+        });
+
+        let proxy_config = ProxyConfig { http_proxy: Some(proxy_url), ..Default::default() };
+        let options = ClientOptions { proxy: proxy_config, ..Default::default() };
+        let http_client = ThrottledClient::new_with_options(&options).unwrap();
+        let response = http_client
+            .run_with_permit(|client| client.get("http://registry.example.com/foo").send())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let request_line = proxy.join().unwrap();
+        dbg!(&request_line);
+        assert_eq!(request_line, "GET http://registry.example.com/foo HTTP/1.1");
+    }
+
+    #[tokio::test]
+    async fn time_out_stalled_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/foo", listener.local_addr().unwrap());
+        let (done_sender, done_receiver) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            done_receiver.recv().ok(); // keep the connection open without responding
+        });
+
+        let options =
+            ClientOptions { timeout: Some(Duration::from_millis(100)), ..Default::default() };
+        let http_client = ThrottledClient::new_with_options(&options).unwrap();
+        let request = http_client.get(&url);
+        let error = http_client
+            .run_with_permit(|_| http_client.with_read_timeout(request.send()))
+            .await
+            .unwrap_err();
+        dbg!(&error);
+        assert!(
+            matches!(error, RequestError::ReadTimeout { timeout } if timeout == Duration::from_millis(100))
+        );
+
+        done_sender.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn split_pem_bundle() {
+        let bundle = "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let received = split_pem_certificates(bundle);
+        dbg!(&received);
+        let expected = [
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+            "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----",
+        ];
+        assert_eq!(received, expected);
+        assert!(split_pem_certificates("not a certificate").is_empty());
+    }
+
+    #[test]
+    fn ca_file_not_found() {
+        let dir = tempdir().unwrap();
+        let ca_file = dir.path().join("ca.pem");
+        let error = load_root_certificates(None, Some(&ca_file)).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, CaCertificateError::ReadFile { path, .. } if path == ca_file));
+    }
+
+    #[test]
+    fn ca_without_certificate() {
+        let error = load_root_certificates(Some("not a certificate"), None).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, CaCertificateError::NoCertificate { origin } if origin == "ca"));
+    }
+
+    #[test]
+    fn invalid_ca_certificate() {
+        let ca = "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----";
+        let error = load_root_certificates(Some(ca), None).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, CaCertificateError::InvalidCertificate(_)));
+    }
+
+    #[test]
+    fn client_certificate_file_not_found() {
+        let dir = tempdir().unwrap();
+        let cert_file = dir.path().join("client.crt");
+        let key_file = dir.path().join("client.key");
+        let error = load_identity(&cert_file, &key_file).unwrap_err();
+        dbg!(&error);
+        assert!(
+            matches!(error, ClientCertificateError::ReadFile { path, .. } if path == cert_file)
+        );
+    }
+
+    #[test]
+    fn invalid_client_certificate() {
+        let dir = tempdir().unwrap();
+        let cert_file = dir.path().join("client.crt");
+        let key_file = dir.path().join("client.key");
+        fs::write(&cert_file, "not a certificate").unwrap();
+        fs::write(&key_file, "not a key").unwrap();
+        let error = load_identity(&cert_file, &key_file).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, ClientCertificateError::InvalidIdentity(_)));
+    }
+}
@@ -1,6 +1,7 @@
+use crate::RegistryClientCertificate;
 use pacquet_store_dir::StoreDir;
 use serde::{de, Deserialize, Deserializer};
-use std::{env, path::PathBuf, str::FromStr};
+use std::{collections::HashMap, env, path::PathBuf, str::FromStr};
 
 #[cfg(windows)]
 use std::{path::Component, path::Path};
@@ -79,6 +80,37 @@ pub fn default_store_dir() -> StoreDir {
     }
 }
 
+/// If the $PNPM_HOME env variable is set, then $PNPM_HOME
+/// If the $XDG_DATA_HOME env variable is set, then $XDG_DATA_HOME/pnpm
+/// On Windows: ~/AppData/Local/pnpm
+/// On macOS: ~/Library/pnpm
+/// On Linux: ~/.local/share/pnpm
+fn default_pnpm_home() -> PathBuf {
+    if let Ok(pnpm_home) = env::var("PNPM_HOME") {
+        return PathBuf::from(pnpm_home);
+    }
+
+    if let Ok(xdg_data_home) = env::var("XDG_DATA_HOME") {
+        return PathBuf::from(xdg_data_home).join("pnpm");
+    }
+
+    let home_dir = home::home_dir().expect("Home directory is not available");
+    match env::consts::OS {
+        "windows" => home_dir.join("AppData/Local/pnpm"),
+        "linux" => home_dir.join(".local/share/pnpm"),
+        "macos" => home_dir.join("Library/pnpm"),
+        _ => panic!("unsupported operating system: {}", env::consts::OS),
+    }
+}
+
+pub fn default_global_dir() -> PathBuf {
+    default_pnpm_home().join("global")
+}
+
+pub fn default_global_bin_dir() -> PathBuf {
+    default_pnpm_home()
+}
+
 pub fn default_modules_dir() -> PathBuf {
     // TODO: find directory with package.json
     env::current_dir().expect("current directory is unavailable").join("node_modules")
@@ -97,6 +129,22 @@ pub fn default_modules_cache_max_age() -> u64 {
     10080
 }
 
+pub fn default_fetch_retries() -> u64 {
+    2
+}
+
+pub fn default_fetch_retry_mintimeout() -> u64 {
+    10_000
+}
+
+pub fn default_fetch_retry_maxtimeout() -> u64 {
+    60_000
+}
+
+pub fn default_fetch_timeout() -> u64 {
+    60_000
+}
+
 pub fn deserialize_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
@@ -113,6 +161,14 @@ where
     u64::from_str(&s).map_err(de::Error::custom)
 }
 
+pub fn deserialize_optional_usize<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    usize::from_str(&s).map(Some).map_err(de::Error::custom)
+}
+
 pub fn deserialize_pathbuf<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
 where
     D: Deserializer<'de>,
@@ -127,6 +183,30 @@ where
     Ok(env::current_dir().map_err(de::Error::custom)?.join(path))
 }
 
+pub fn deserialize_optional_pathbuf<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_pathbuf(deserializer).map(Some)
+}
+
+/// Treat an empty value as unset.
+pub fn deserialize_optional_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    Ok((!value.is_empty()).then_some(value))
+}
+
+/// PEM certificates are written on a single line in `.npmrc`, with the line breaks escaped as `\n`.
+pub fn deserialize_ca<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_optional_string(deserializer).map(|ca| ca.map(|ca| ca.replace("\\n", "\n")))
+}
+
 pub fn deserialize_store_dir<'de, D>(deserializer: D) -> Result<StoreDir, D::Error>
 where
     D: Deserializer<'de>,
@@ -134,6 +214,70 @@ where
     deserialize_pathbuf(deserializer).map(StoreDir::from)
 }
 
+/// Collect the `//registry.example.com/:_authToken=...` entries, keyed by the registry URL
+/// without the scheme (e.g. `//registry.example.com/`).
+pub fn deserialize_auth_tokens<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let auth_tokens = HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .filter_map(|(key, token)| {
+            let registry = key.strip_suffix(":_authToken")?;
+            registry.starts_with("//").then(|| (registry.to_string(), token))
+        })
+        .collect();
+    Ok(auth_tokens)
+}
+
+/// Collect the `//registry.example.com/:certfile=...` and `//registry.example.com/:keyfile=...` entries,
+/// keyed by the registry URL without the scheme (e.g. `//registry.example.com/`).
+pub fn deserialize_registry_client_certificates<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, RegistryClientCertificate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut certificates = HashMap::<String, RegistryClientCertificate>::new();
+    for (key, path) in HashMap::<String, String>::deserialize(deserializer)? {
+        let (registry, is_certfile) =
+            match (key.strip_suffix(":certfile"), key.strip_suffix(":keyfile")) {
+                (Some(registry), _) => (registry, true),
+                (_, Some(registry)) => (registry, false),
+                (None, None) => continue,
+            };
+        if !registry.starts_with("//") {
+            continue;
+        }
+        let certificate = certificates.entry(registry.to_string()).or_default();
+        let path = Some(PathBuf::from(path));
+        match is_certfile {
+            true => certificate.certfile = path,
+            false => certificate.keyfile = path,
+        }
+    }
+    Ok(certificates)
+}
+
+/// Collect the `@scope:registry=...` entries, keyed by the scope (e.g. `@scope`).
+/// Like [`deserialize_registry`], a trailing "/" is added to the registry URLs if not exist.
+pub fn deserialize_scoped_registries<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let scoped_registries = HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .filter_map(|(key, registry)| {
+            let scope = key.strip_suffix(":registry")?;
+            let registry = if registry.ends_with('/') { registry } else { format!("{registry}/") };
+            scope.starts_with('@').then(|| (scope.to_string(), registry))
+        })
+        .collect();
+    Ok(scoped_registries)
+}
+
 /// This deserializer adds a trailing "/" if not exist to make our life easier.
 pub fn deserialize_registry<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
@@ -201,4 +345,48 @@ mod tests {
         let store_dir = default_store_dir_windows(&home_dir, &current_dir);
         assert_eq!(store_dir, Path::new("C:\\Users\\user\\AppData\\Local\\pnpm\\store"));
     }
+
+    #[test]
+    fn test_deserialize_auth_tokens() {
+        let npmrc: crate::Npmrc = serde_ini::from_str(
+            "registry=https://registry.example.com/\n//registry.example.com/:_authToken=xxx\n_authToken=ignored\n",
+        )
+        .unwrap();
+        let expected = HashMap::from([("//registry.example.com/".to_string(), "xxx".to_string())]);
+        assert_eq!(npmrc.auth_tokens, expected);
+        assert_eq!(npmrc.registry, "https://registry.example.com/");
+    }
+
+    #[test]
+    fn test_deserialize_registry_client_certificates() {
+        let npmrc: crate::Npmrc = serde_ini::from_str(
+            "certfile=/certs/default.crt
+\
+             //registry.example.com/:certfile=/certs/example.crt
+\
+             //registry.example.com/:keyfile=/certs/example.key
+\
+             //localhost:4873/:certfile=/certs/local.crt
+",
+        )
+        .unwrap();
+        let expected = HashMap::from([
+            (
+                "//registry.example.com/".to_string(),
+                RegistryClientCertificate {
+                    certfile: Some(PathBuf::from("/certs/example.crt")),
+                    keyfile: Some(PathBuf::from("/certs/example.key")),
+                },
+            ),
+            (
+                "//localhost:4873/".to_string(),
+                RegistryClientCertificate {
+                    certfile: Some(PathBuf::from("/certs/local.crt")),
+                    keyfile: None,
+                },
+            ),
+        ]);
+        assert_eq!(npmrc.registry_client_certificates, expected);
+        assert_eq!(npmrc.certfile, Some(PathBuf::from("/certs/default.crt")));
+    }
 }
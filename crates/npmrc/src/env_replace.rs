@@ -0,0 +1,124 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+
+/// Error type of [`env_replace`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum EnvReplaceError {
+    #[display("Environment variable {name} is not set")]
+    #[diagnostic(
+        code(pacquet_npmrc::unset_env),
+        help("Set the environment variable, or provide a default with ${{{name}-default}}")
+    )]
+    UnsetEnv {
+        #[error(not(source))]
+        name: String,
+    },
+}
+
+/// Replace the `${NAME}` occurrences in the non-comment lines of `.npmrc` with the values of
+/// the environment variables.
+///
+/// * `${NAME-default}` falls back to `default` when `NAME` is unset.
+/// * `${NAME:-default}` falls back to `default` when `NAME` is unset or empty.
+/// * `\${NAME}` is kept literally as `${NAME}`.
+pub fn env_replace<Env>(text: &str, env: Env) -> Result<String, EnvReplaceError>
+where
+    Env: Fn(&str) -> Option<String>,
+{
+    let lines = text
+        .lines()
+        .map(|line| match line.trim_start().starts_with(['#', ';']) {
+            true => Ok(line.to_string()),
+            false => replace_line(line, &env),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines.join("\n"))
+}
+
+/// Replace the `${...}` occurrences in a single line.
+fn replace_line<Env>(line: &str, env: &Env) -> Result<String, EnvReplaceError>
+where
+    Env: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("${") {
+        let (before, after) = rest.split_at(start);
+        if let Some(before) = before.strip_suffix('\\') {
+            result.push_str(before);
+            result.push_str("${");
+            rest = &after[2..];
+            continue;
+        }
+        result.push_str(before);
+        let Some(end) = after.find('}') else {
+            break; // unterminated, keep the rest as is
+        };
+        result.push_str(&resolve_env(&after[2..end], env)?);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Resolve the value of an expression between `${` and `}`.
+fn resolve_env<Env>(expression: &str, env: &Env) -> Result<String, EnvReplaceError>
+where
+    Env: Fn(&str) -> Option<String>,
+{
+    let Some((name, default)) = expression.split_once('-') else {
+        return env(expression)
+            .ok_or_else(|| EnvReplaceError::UnsetEnv { name: expression.to_string() });
+    };
+    let value = match name.strip_suffix(':') {
+        Some(name) => env(name).filter(|value| !value.is_empty()),
+        None => env(name),
+    };
+    Ok(value.unwrap_or_else(|| default.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "NPM_TOKEN" => Some("secret".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn replace_env() {
+        macro_rules! case {
+            ($input:expr => $output:expr) => {{
+                let input = $input;
+                eprintln!("CASE: {input:?}");
+                let output = env_replace(input, env).unwrap();
+                assert_eq!(output, $output);
+            }};
+        }
+
+        case!("//registry.npmjs.org/:_authToken=${NPM_TOKEN}" => "//registry.npmjs.org/:_authToken=secret");
+        case!("a=${NPM_TOKEN}-${NPM_TOKEN}" => "a=secret-secret");
+        case!("a=${UNSET-fallback}" => "a=fallback");
+        case!("a=${EMPTY-fallback}" => "a=");
+        case!("a=${EMPTY:-fallback}" => "a=fallback");
+        case!("a=${NPM_TOKEN:-fallback}" => "a=secret");
+        case!("a=\\${UNSET}" => "a=${UNSET}");
+        case!("a=${UNSET" => "a=${UNSET");
+        case!("# a=${UNSET}\nb=${NPM_TOKEN}" => "# a=${UNSET}\nb=secret");
+        case!("; a=${UNSET}" => "; a=${UNSET}");
+    }
+
+    #[test]
+    fn unset_env() {
+        let error =
+            env_replace("registry=https://npm.example.com/\ntoken=${UNSET}", env).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, EnvReplaceError::UnsetEnv { name } if name == "UNSET"));
+    }
+}
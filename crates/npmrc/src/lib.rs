@@ -1,15 +1,24 @@
 mod custom_deserializer;
+mod env_replace;
 
+pub use env_replace::*;
+
+use derive_more::{Display, Error};
+use miette::Diagnostic;
 use pacquet_store_dir::StoreDir;
 use pipe_trait::Pipe;
-use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, env, fs, path::PathBuf};
 
 use crate::custom_deserializer::{
-    bool_true, default_hoist_pattern, default_modules_cache_max_age, default_modules_dir,
+    bool_true, default_fetch_retries, default_fetch_retry_maxtimeout,
+    default_fetch_retry_mintimeout, default_fetch_timeout, default_global_bin_dir,
+    default_global_dir, default_hoist_pattern, default_modules_cache_max_age, default_modules_dir,
     default_public_hoist_pattern, default_registry, default_store_dir, default_virtual_store_dir,
-    deserialize_bool, deserialize_pathbuf, deserialize_registry, deserialize_store_dir,
-    deserialize_u64,
+    deserialize_auth_tokens, deserialize_bool, deserialize_ca, deserialize_optional_pathbuf,
+    deserialize_optional_string, deserialize_optional_usize, deserialize_pathbuf,
+    deserialize_registry, deserialize_registry_client_certificates, deserialize_scoped_registries,
+    deserialize_store_dir, deserialize_u64,
 };
 
 #[derive(Debug, Deserialize, Default, PartialEq)]
@@ -29,7 +38,35 @@ pub enum NodeLinker {
     Pnp,
 }
 
+/// Prefix of the version ranges that `pacquet add` saves to `package.json`.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SavePrefix {
+    /// `^1.2.3`, allow minor and patch updates.
+    #[default]
+    #[serde(rename = "^")]
+    Caret,
+
+    /// `~1.2.3`, allow patch updates.
+    #[serde(rename = "~")]
+    Tilde,
+
+    /// `1.2.3`, the exact version.
+    #[serde(rename = "")]
+    Exact,
+}
+
+impl SavePrefix {
+    /// The prefix as written before the version.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SavePrefix::Caret => "^",
+            SavePrefix::Tilde => "~",
+            SavePrefix::Exact => "",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum PackageImportMethod {
     ///  try to clone packages from the store. If cloning is not supported then hardlink packages
@@ -40,13 +77,13 @@ pub enum PackageImportMethod {
     /// hard link packages from the store
     Hardlink,
 
-    /// try to clone packages from the store. If cloning is not supported then fall back to copying
+    /// copy packages from the store
     Copy,
 
-    /// copy packages from the store
+    /// clone (AKA copy-on-write or reference link) packages from the store
     Clone,
 
-    /// clone (AKA copy-on-write or reference link) packages from the store
+    /// try to clone packages from the store. If cloning is not supported then fall back to copying
     CloneOrCopy,
 }
 
@@ -151,43 +188,218 @@ pub struct Npmrc {
     /// projects in the workspace use the same versions of the peer dependencies.
     #[serde(default = "bool_true", deserialize_with = "deserialize_bool")]
     pub resolve_peers_from_workspace_root: bool,
+
+    /// Path to a PEM file of the client certificate to present to the registry (mutual TLS).
+    /// Must be specified together with `keyfile`.
+    #[serde(default, deserialize_with = "deserialize_optional_pathbuf")]
+    pub certfile: Option<PathBuf>,
+
+    /// Path to a PEM file of the private key of the client certificate specified by `certfile`.
+    #[serde(default, deserialize_with = "deserialize_optional_pathbuf")]
+    pub keyfile: Option<PathBuf>,
+
+    /// A proxy to use for outgoing HTTP requests. Also used for HTTPS requests unless `https-proxy` is set.
+    /// Falls back to the `HTTP_PROXY` environment variable.
+    #[serde(default, deserialize_with = "deserialize_optional_string")]
+    pub proxy: Option<String>,
+
+    /// A proxy to use for outgoing HTTPS requests.
+    /// Falls back to the `HTTPS_PROXY` environment variable.
+    #[serde(default, deserialize_with = "deserialize_optional_string")]
+    pub https_proxy: Option<String>,
+
+    /// A comma-separated list of domains that should be requested without the proxy.
+    /// Falls back to the `NO_PROXY` environment variable.
+    #[serde(default, deserialize_with = "deserialize_optional_string")]
+    pub no_proxy: Option<String>,
+
+    /// Certificate authorities in PEM format to trust when connecting to the registry,
+    /// in addition to the system ones.
+    #[serde(default, deserialize_with = "deserialize_ca")]
+    pub ca: Option<String>,
+
+    /// Path to a PEM file of certificate authorities to trust when connecting to the registry,
+    /// in addition to the system ones.
+    #[serde(default, deserialize_with = "deserialize_optional_pathbuf")]
+    pub cafile: Option<PathBuf>,
+
+    /// Whether to validate the TLS certificates of the registry.
+    ///
+    /// Setting this to false exposes the installation to man-in-the-middle attacks,
+    /// which could serve tampered packages. Prefer `ca` or `cafile` for registries
+    /// with certificates signed by an internal certificate authority.
+    #[serde(default = "bool_true", deserialize_with = "deserialize_bool")]
+    pub strict_ssl: bool,
+
+    /// Don't run any scripts of the project or its dependencies, e.g. `postinstall` or `prepare`.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub ignore_scripts: bool,
+
+    /// When running as root, scripts are run as the owner of the package directory
+    /// unless this is set to true.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub unsafe_perm: bool,
+
+    /// How many times to retry if pacquet fails to fetch a tarball from the registry.
+    #[serde(default = "default_fetch_retries", deserialize_with = "deserialize_u64")]
+    pub fetch_retries: u64,
+
+    /// The minimum (base) timeout in milliseconds for retrying requests.
+    #[serde(default = "default_fetch_retry_mintimeout", deserialize_with = "deserialize_u64")]
+    pub fetch_retry_mintimeout: u64,
+
+    /// The maximum fallback timeout in milliseconds to ensure the retry factor does not make
+    /// requests too long.
+    #[serde(default = "default_fetch_retry_maxtimeout", deserialize_with = "deserialize_u64")]
+    pub fetch_retry_maxtimeout: u64,
+
+    /// The maximum amount of time in milliseconds to wait for a request to complete,
+    /// 0 for no limit. Timed out tarball downloads are retried.
+    #[serde(default = "default_fetch_timeout", deserialize_with = "deserialize_u64")]
+    pub fetch_timeout: u64,
+
+    /// The maximum number of concurrent network requests.
+    /// When unset, it is the number of CPUs, but no less than 16.
+    #[serde(default, deserialize_with = "deserialize_optional_usize")]
+    pub network_concurrency: Option<usize>,
+
+    /// Use the cached registry metadata without revalidating it with the registry.
+    /// Metadata that isn't cached is still fetched.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub prefer_offline: bool,
+
+    /// The maximum age in milliseconds of the cached registry metadata that `prefer-offline`
+    /// reuses, 0 for no limit. Older metadata is revalidated with the registry.
+    #[serde(default, deserialize_with = "deserialize_u64")]
+    pub metadata_max_age: u64,
+
+    /// Don't send any request to the network, only use the store and the cached registry metadata.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub offline: bool,
+
+    /// Prefix of the version ranges that `pacquet add` saves to `package.json`: `^`, `~`, or
+    /// empty for exact versions. `--save-exact` takes precedence.
+    #[serde(default)]
+    pub save_prefix: SavePrefix,
+
+    /// Fail instead of only warning when the running Node.js version doesn't satisfy the
+    /// `engines.node` field of the project.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub engine_strict: bool,
+
+    /// The directory of the globally installed packages, `pacquet add --global`.
+    #[serde(default = "default_global_dir", deserialize_with = "deserialize_pathbuf")]
+    pub global_dir: PathBuf,
+
+    /// The directory of the commands of the globally installed packages.
+    /// It should be in `PATH` for the commands to be available.
+    #[serde(default = "default_global_bin_dir", deserialize_with = "deserialize_pathbuf")]
+    pub global_bin_dir: PathBuf,
+
+    /// Auth tokens of registries, keyed by the registry URL without the scheme,
+    /// e.g. `//registry.example.com/:_authToken=xxx` is stored as `//registry.example.com/` → `xxx`.
+    #[serde(flatten, deserialize_with = "deserialize_auth_tokens")]
+    pub auth_tokens: HashMap<String, String>,
+
+    /// Client certificates of registries, keyed by the registry URL without the scheme,
+    /// e.g. `//registry.example.com/:certfile=/certs/client.crt` is stored as
+    /// `//registry.example.com/` → `certfile: /certs/client.crt`.
+    /// They are presented to these registries instead of `certfile` and `keyfile`.
+    #[serde(flatten, deserialize_with = "deserialize_registry_client_certificates")]
+    pub registry_client_certificates: HashMap<String, RegistryClientCertificate>,
+
+    /// Registries of scoped packages, keyed by the scope (with the `@` prefix),
+    /// e.g. `@acme:registry=https://npm.acme.internal/` is stored as `@acme` → `https://npm.acme.internal/`.
+    /// Like `registry`, the URLs always have a trailing slash.
+    #[serde(flatten, deserialize_with = "deserialize_scoped_registries")]
+    pub scoped_registries: HashMap<String, String>,
+}
+
+/// Client certificate of a registry, see [`Npmrc::registry_client_certificates`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RegistryClientCertificate {
+    /// Path to a PEM file of the client certificate. Must be specified together with `keyfile`.
+    pub certfile: Option<PathBuf>,
+    /// Path to a PEM file of the private key of the client certificate.
+    pub keyfile: Option<PathBuf>,
 }
 
+/// Version of the layout of the global directory, appended to `global-dir` like pnpm does.
+const GLOBAL_LAYOUT_VERSION: &str = "5";
+
 impl Npmrc {
     pub fn new() -> Self {
         let config: Npmrc = serde_ini::from_str("").unwrap(); // TODO: derive `SmartDefault` for `Npmrc and call `Npmrc::default()`
         config
     }
 
-    /// Try loading `.npmrc` in the current directory.
-    /// If fails, try in the home directory.
-    /// If fails again, return the default.
-    pub fn current<Error, CurrentDir, HomeDir, Default>(
+    /// Load and merge the `.npmrc` files, from the lowest precedence to the highest:
+    /// * `$PREFIX/etc/npmrc` (global),
+    /// * `~/.npmrc` (user),
+    /// * the nearest `.npmrc` in the current directory or its ancestors (project).
+    ///
+    /// A setting in a file overrides the same setting in the files before it.
+    /// Settings that no file specifies take the default values.
+    ///
+    /// The `${NAME}` occurrences in the files are replaced with the environment variables.
+    pub fn current<Error, CurrentDir, HomeDir, GlobalPrefix>(
         current_dir: CurrentDir,
         home_dir: HomeDir,
-        default: Default,
-    ) -> Self
+        global_prefix: GlobalPrefix,
+    ) -> Result<Self, LoadNpmrcError>
     where
         CurrentDir: FnOnce() -> Result<PathBuf, Error>,
         HomeDir: FnOnce() -> Option<PathBuf>,
-        Default: FnOnce() -> Npmrc,
+        GlobalPrefix: FnOnce() -> Option<PathBuf>,
     {
-        // TODO: this code makes no sense.
-        // TODO: it should have merged the settings.
-
-        let load = |dir: PathBuf| -> Option<Npmrc> {
-            dir.join(".npmrc")
-                .pipe(fs::read_to_string)
-                .ok()? // TODO: should it throw error instead?
-                .pipe_as_ref(serde_ini::from_str)
-                .ok() // TODO: should it throw error instead?
-        };
-
-        current_dir()
+        let global_config = global_prefix().map(|prefix| prefix.join("etc").join("npmrc"));
+        let user_config = home_dir().map(|dir| dir.join(".npmrc"));
+        let project_config = current_dir()
             .ok()
-            .and_then(load)
-            .or_else(|| home_dir().and_then(load))
-            .unwrap_or_else(default)
+            .and_then(|dir| {
+                dir.ancestors().map(|dir| dir.join(".npmrc")).find(|path| path.is_file())
+            })
+            .filter(|path| Some(path) != user_config.as_ref());
+
+        let mut settings = HashMap::new();
+        for path in [global_config, user_config, project_config].into_iter().flatten() {
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue; // TODO: should it throw error instead?
+            };
+            let text = env_replace(&text, |name| env::var(name).ok())
+                .map_err(|error| LoadNpmrcError::EnvReplace { path, error })?;
+            settings.extend(parse_settings(&text));
+        }
+
+        let text: String =
+            settings.into_iter().map(|(key, value)| format!("{key}={value}\n")).collect();
+        serde_ini::from_str(&text).map_err(LoadNpmrcError::ParseSettings)
+    }
+
+    /// The project directory of the globally installed packages, versioned by its layout like
+    /// pnpm does, e.g. `~/.local/share/pnpm/global/5`.
+    pub fn global_packages_dir(&self) -> PathBuf {
+        self.global_dir.join(GLOBAL_LAYOUT_VERSION)
+    }
+
+    /// Install into [`Npmrc::global_packages_dir`] instead of the current project.
+    ///
+    /// The store directory is kept, so that global packages share the store with the projects.
+    /// The lockfile is disabled because it would be read from and written to the current directory.
+    pub fn use_global_dir(&mut self) {
+        self.modules_dir = self.global_packages_dir().join("node_modules");
+        self.virtual_store_dir = self.modules_dir.join(".pnpm");
+        self.lockfile = false;
+    }
+
+    /// The registry of `package_name`: the registry of its scope if there is one, or the default registry.
+    pub fn registry_for(&self, package_name: &str) -> &str {
+        package_name
+            .starts_with('@')
+            .then(|| package_name.split_once('/'))
+            .flatten()
+            .and_then(|(scope, _)| self.scoped_registries.get(scope))
+            .unwrap_or(&self.registry)
     }
 
     /// Persist the config data until the program terminates.
@@ -196,6 +408,32 @@ impl Npmrc {
     }
 }
 
+/// Parse the `key=value` lines of an `.npmrc` file, skipping blank lines, comments, and sections.
+fn parse_settings(text: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(['#', ';', '[']))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+}
+
+/// Error type of [`Npmrc::current`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum LoadNpmrcError {
+    #[display("Failed to load {path:?}: {error}")]
+    #[diagnostic(code(pacquet_npmrc::env_replace))]
+    EnvReplace {
+        path: PathBuf,
+        #[error(source)]
+        error: EnvReplaceError,
+    },
+
+    #[display("Failed to parse the settings of .npmrc: {_0}")]
+    #[diagnostic(code(pacquet_npmrc::parse_settings))]
+    ParseSettings(#[error(source)] serde_ini::de::Error),
+}
+
 impl Default for Npmrc {
     fn default() -> Self {
         Self::new()
@@ -251,6 +489,36 @@ mod tests {
         assert_eq!(value.modules_cache_max_age, 1000);
     }
 
+    #[test]
+    pub fn parse_fetch_retries() {
+        let value: Npmrc = serde_ini::from_str("").unwrap();
+        assert_eq!(
+            (value.fetch_retries, value.fetch_retry_mintimeout, value.fetch_retry_maxtimeout),
+            (2, 10_000, 60_000),
+        );
+        let value: Npmrc = serde_ini::from_str(
+            "fetch-retries=5\nfetch-retry-mintimeout=100\nfetch-retry-maxtimeout=1000",
+        )
+        .unwrap();
+        assert_eq!(
+            (value.fetch_retries, value.fetch_retry_mintimeout, value.fetch_retry_maxtimeout),
+            (5, 100, 1000),
+        );
+    }
+
+    #[test]
+    pub fn registry_for_scoped_packages() {
+        let value: Npmrc = serde_ini::from_str(
+            "registry=https://registry.example.com\n@acme:registry=https://npm.acme.internal",
+        )
+        .unwrap();
+        dbg!(&value.scoped_registries);
+        assert_eq!(value.registry_for("@acme/foo"), "https://npm.acme.internal/");
+        assert_eq!(value.registry_for("@other/foo"), "https://registry.example.com/");
+        assert_eq!(value.registry_for("acme"), "https://registry.example.com/");
+        assert_eq!(value.registry_for("@acme"), "https://registry.example.com/");
+    }
+
     #[test]
     pub fn should_use_pnpm_home_env_var() {
         env::set_var("PNPM_HOME", "/hello"); // TODO: change this to dependency injection
@@ -267,6 +535,22 @@ mod tests {
         env::remove_var("XDG_DATA_HOME");
     }
 
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    pub fn should_use_global_dir() {
+        let mut value: Npmrc =
+            serde_ini::from_str("global-dir=/hello/global\nglobal-bin-dir=/hello/bin").unwrap();
+        assert_eq!(value.global_bin_dir, PathBuf::from("/hello/bin"));
+        assert_eq!(value.global_packages_dir(), PathBuf::from("/hello/global/5"));
+        let store_dir = display_store_dir(&value.store_dir);
+        value.lockfile = true;
+        value.use_global_dir();
+        assert_eq!(value.modules_dir, PathBuf::from("/hello/global/5/node_modules"));
+        assert_eq!(value.virtual_store_dir, PathBuf::from("/hello/global/5/node_modules/.pnpm"));
+        assert_eq!(display_store_dir(&value.store_dir), store_dir);
+        assert!(!value.lockfile);
+    }
+
     #[test]
     pub fn should_use_relative_virtual_store_dir() {
         let value: Npmrc = serde_ini::from_str("virtual-store-dir=node_modules/.pacquet").unwrap();
@@ -283,6 +567,114 @@ mod tests {
         assert_eq!(value.virtual_store_dir, PathBuf::from_str("/node_modules/.pacquet").unwrap());
     }
 
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    pub fn parse_client_certificate() {
+        let value: Npmrc =
+            serde_ini::from_str("certfile=/certs/client.crt\nkeyfile=/certs/client.key").unwrap();
+        assert_eq!(value.certfile, Some(PathBuf::from("/certs/client.crt")));
+        assert_eq!(value.keyfile, Some(PathBuf::from("/certs/client.key")));
+
+        let value = Npmrc::new();
+        assert_eq!((value.certfile, value.keyfile), (None, None));
+    }
+
+    #[test]
+    pub fn parse_fetch_timeout() {
+        assert_eq!(Npmrc::new().fetch_timeout, 60_000);
+        let value: Npmrc = serde_ini::from_str("fetch-timeout=5000").unwrap();
+        assert_eq!(value.fetch_timeout, 5000);
+    }
+
+    #[test]
+    pub fn parse_network_concurrency() {
+        let value: Npmrc = serde_ini::from_str("network-concurrency=4").unwrap();
+        assert_eq!(value.network_concurrency, Some(4));
+        assert_eq!(Npmrc::new().network_concurrency, None);
+        assert!(serde_ini::from_str::<Npmrc>("network-concurrency=many").is_err());
+    }
+
+    #[test]
+    pub fn parse_prefer_offline() {
+        let value: Npmrc = serde_ini::from_str("prefer-offline=true").unwrap();
+        assert!(value.prefer_offline);
+        assert!(!Npmrc::new().prefer_offline);
+        let value: Npmrc = serde_ini::from_str("metadata-max-age=60000").unwrap();
+        assert_eq!(value.metadata_max_age, 60_000);
+        assert_eq!(Npmrc::new().metadata_max_age, 0);
+    }
+
+    #[test]
+    pub fn parse_offline() {
+        let value: Npmrc = serde_ini::from_str("offline=true").unwrap();
+        assert!(value.offline);
+        assert!(!Npmrc::new().offline);
+    }
+
+    #[test]
+    pub fn parse_save_prefix() {
+        macro_rules! case {
+            ($input:literal => $output:expr) => {{
+                eprintln!("CASE: {:?}", $input);
+                let value: Npmrc = serde_ini::from_str($input).unwrap();
+                assert_eq!(value.save_prefix, $output);
+            }};
+        }
+
+        case!("save-prefix=~" => SavePrefix::Tilde);
+        case!("save-prefix=^" => SavePrefix::Caret);
+        case!("save-prefix=" => SavePrefix::Exact);
+        assert_eq!(Npmrc::new().save_prefix, SavePrefix::Caret);
+        assert!(serde_ini::from_str::<Npmrc>("save-prefix=>=").is_err());
+    }
+
+    #[test]
+    pub fn parse_engine_strict() {
+        let value: Npmrc = serde_ini::from_str("engine-strict=true").unwrap();
+        assert!(value.engine_strict);
+        assert!(!Npmrc::new().engine_strict);
+    }
+
+    #[test]
+    pub fn parse_ignore_scripts() {
+        let value: Npmrc = serde_ini::from_str("ignore-scripts=true").unwrap();
+        assert!(value.ignore_scripts);
+        assert!(!Npmrc::new().ignore_scripts);
+    }
+
+    #[test]
+    pub fn parse_proxy() {
+        let value: Npmrc = serde_ini::from_str(
+            "proxy=http://proxy.example.com:8080\nhttps-proxy=\nno-proxy=localhost,.internal",
+        )
+        .unwrap();
+        assert_eq!(value.proxy.as_deref(), Some("http://proxy.example.com:8080"));
+        assert_eq!(value.https_proxy, None);
+        assert_eq!(value.no_proxy.as_deref(), Some("localhost,.internal"));
+
+        let value = Npmrc::new();
+        assert_eq!((value.proxy, value.https_proxy, value.no_proxy), (None, None, None));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    pub fn parse_ca() {
+        let value: Npmrc = serde_ini::from_str(
+            "ca=-----BEGIN CERTIFICATE-----\\nAAAA\\n-----END CERTIFICATE-----\ncafile=/certs/ca.pem\nstrict-ssl=false",
+        )
+        .unwrap();
+        assert_eq!(
+            value.ca.as_deref(),
+            Some("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----"),
+        );
+        assert_eq!(value.cafile, Some(PathBuf::from("/certs/ca.pem")));
+        assert!(!value.strict_ssl);
+
+        let value = Npmrc::new();
+        assert_eq!((value.ca, value.cafile), (None, None));
+        assert!(value.strict_ssl);
+    }
+
     #[test]
     pub fn add_slash_to_registry_end() {
         let without_slash: Npmrc = serde_ini::from_str("registry=https://yagiz.co").unwrap();
@@ -296,21 +688,55 @@ mod tests {
     pub fn test_current_folder_for_npmrc() {
         let tmp = tempdir().unwrap();
         fs::write(tmp.path().join(".npmrc"), "symlink=false").expect("write to .npmrc");
-        let config = Npmrc::current(
-            || tmp.path().to_path_buf().pipe(Ok::<_, ()>),
-            || unreachable!("shouldn't reach home dir"),
-            || unreachable!("shouldn't reach default"),
-        );
+        let config =
+            Npmrc::current(|| tmp.path().to_path_buf().pipe(Ok::<_, ()>), || None, || None)
+                .unwrap();
         assert!(!config.symlink);
     }
 
+    #[test]
+    pub fn test_current_folder_for_npmrc_with_env() {
+        let tmp = tempdir().unwrap();
+        fs::write(
+            tmp.path().join(".npmrc"),
+            "registry=${PACQUET_TEST_UNSET_REGISTRY-https://npm.example.com}",
+        )
+        .expect("write to .npmrc");
+        let config =
+            Npmrc::current(|| tmp.path().to_path_buf().pipe(Ok::<_, ()>), || None, || None)
+                .unwrap();
+        assert_eq!(config.registry, "https://npm.example.com/");
+
+        fs::write(tmp.path().join(".npmrc"), "registry=${PACQUET_TEST_UNSET_REGISTRY}")
+            .expect("write to .npmrc");
+        let error = Npmrc::current(|| tmp.path().to_path_buf().pipe(Ok::<_, ()>), || None, || None)
+            .unwrap_err();
+        dbg!(&error);
+        assert!(matches!(
+            error,
+            LoadNpmrcError::EnvReplace { error: EnvReplaceError::UnsetEnv { name }, .. }
+                if name == "PACQUET_TEST_UNSET_REGISTRY"
+        ));
+    }
+
+    #[test]
+    pub fn test_current_folder_for_npmrc_with_invalid_value() {
+        let tmp = tempdir().unwrap();
+        fs::write(tmp.path().join(".npmrc"), "network-concurrency=many").expect("write to .npmrc");
+        let error = Npmrc::current(|| tmp.path().to_path_buf().pipe(Ok::<_, ()>), || None, || None)
+            .unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, LoadNpmrcError::ParseSettings(_)));
+    }
+
     #[test]
     pub fn test_current_folder_for_invalid_npmrc() {
         let tmp = tempdir().unwrap();
         // write invalid utf-8 value to npmrc
         fs::write(tmp.path().join(".npmrc"), b"Hello \xff World").expect("write to .npmrc");
         let config =
-            Npmrc::current(|| tmp.path().to_path_buf().pipe(Ok::<_, ()>), || None, Npmrc::new);
+            Npmrc::current(|| tmp.path().to_path_buf().pipe(Ok::<_, ()>), || None, || None)
+                .unwrap();
         assert!(config.symlink); // TODO: what the hell? why succeed?
     }
 
@@ -323,8 +749,9 @@ mod tests {
         let config = Npmrc::current(
             || current_dir.path().to_path_buf().pipe(Ok::<_, ()>),
             || home_dir.path().to_path_buf().pipe(Some),
-            || unreachable!("shouldn't reach home dir"),
-        );
+            || None,
+        )
+        .unwrap();
         assert!(!config.symlink);
     }
 
@@ -335,8 +762,48 @@ mod tests {
         let config = Npmrc::current(
             || current_dir.path().to_path_buf().pipe(Ok::<_, ()>),
             || home_dir.path().to_path_buf().pipe(Some),
-            || serde_ini::from_str("symlink=false").unwrap(),
-        );
-        assert!(!config.symlink);
+            || None,
+        )
+        .unwrap();
+        assert!(config.symlink);
+        assert_eq!(config.registry, "https://registry.npmjs.org/");
+    }
+
+    #[test]
+    pub fn test_merge_npmrc_layers() {
+        let prefix_dir = tempdir().unwrap();
+        let home_dir = tempdir().unwrap();
+        let project_dir = tempdir().unwrap();
+        let current_dir = project_dir.path().join("packages/foo");
+        fs::create_dir_all(&current_dir).unwrap();
+        fs::create_dir_all(prefix_dir.path().join("etc")).unwrap();
+        fs::write(
+            prefix_dir.path().join("etc/npmrc"),
+            "symlink=false\nhoist=false\nregistry=https://global.example.com/",
+        )
+        .expect("write to global npmrc");
+        fs::write(
+            home_dir.path().join(".npmrc"),
+            "# user settings\nhoist=true\nregistry=https://user.example.com/\nlockfile=true",
+        )
+        .expect("write to user .npmrc");
+        fs::write(
+            project_dir.path().join(".npmrc"),
+            "lockfile=false\nstrict-peer-dependencies=true",
+        )
+        .expect("write to project .npmrc");
+
+        let config = Npmrc::current(
+            || current_dir.clone().pipe(Ok::<_, ()>),
+            || home_dir.path().to_path_buf().pipe(Some),
+            || prefix_dir.path().to_path_buf().pipe(Some),
+        )
+        .unwrap();
+        assert!(!config.symlink); // global
+        assert!(config.hoist); // user overrides global
+        assert_eq!(config.registry, "https://user.example.com/"); // user overrides global
+        assert!(!config.lockfile); // project overrides user
+        assert!(config.strict_peer_dependencies); // project
+        assert!(config.prefer_frozen_lockfile); // default
     }
 }
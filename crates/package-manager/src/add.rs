@@ -1,4 +1,7 @@
-use crate::{Install, ResolvedPackages};
+use crate::{
+    metadata_cache, Install, InstallError, InstallWorkspace, InstallWorkspaceError,
+    ResolvedPackages, ResolvedVersions, Workspace,
+};
 use derive_more::{Display, Error};
 use miette::Diagnostic;
 use pacquet_lockfile::Lockfile;
@@ -6,8 +9,9 @@ use pacquet_network::ThrottledClient;
 use pacquet_npmrc::Npmrc;
 use pacquet_package_manifest::PackageManifestError;
 use pacquet_package_manifest::{DependencyGroup, PackageManifest};
-use pacquet_registry::{PackageTag, PackageVersion};
+use pacquet_registry::{Package, PackageMemCache, PackageVersion, RegistryError};
 use pacquet_tarball::MemCache;
+use std::path::PathBuf;
 
 /// This subroutine does everything `pacquet add` is supposed to do.
 #[must_use]
@@ -18,12 +22,14 @@ where
 {
     pub tarball_mem_cache: &'a MemCache,
     pub resolved_packages: &'a ResolvedPackages,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub package_mem_cache: &'a PackageMemCache,
     pub http_client: &'a ThrottledClient,
     pub config: &'static Npmrc,
     pub manifest: &'a mut PackageManifest,
     pub lockfile: Option<&'a Lockfile>,
     pub list_dependency_groups: ListDependencyGroups, // must be a function because it is called multiple times
-    pub package_name: &'a str, // TODO: 1. support version range, 2. multiple arguments, 3. name this `packages`
+    pub package_name: &'a str, // TODO: 1. multiple arguments, 2. name this `packages`
     pub save_exact: bool,      // TODO: add `save-exact` to `.npmrc`, merge configs, and remove this
 }
 
@@ -34,6 +40,15 @@ pub enum AddError {
     AddDependencyToManifest(#[error(source)] PackageManifestError),
     #[display("Failed save the manifest file: {_0}")]
     SaveManifest(#[error(source)] PackageManifestError),
+    #[diagnostic(transparent)]
+    FetchPackage(#[error(source)] RegistryError),
+    #[display("No version of {name} matches {spec:?}")]
+    #[diagnostic(code(pacquet_package_manager::no_matching_version))]
+    NoMatchingVersion { name: String, spec: String },
+    #[diagnostic(transparent)]
+    Install(#[error(source)] InstallError),
+    #[diagnostic(transparent)]
+    InstallWorkspace(#[error(source)] InstallWorkspaceError),
 }
 
 impl<'a, ListDependencyGroups, DependencyGroupList>
@@ -53,18 +68,15 @@ where
             package_name,
             save_exact,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
         } = self;
 
-        let latest_version = PackageVersion::fetch_from_registry(
-            package_name,
-            PackageTag::Latest, // TODO: add support for specifying tags
-            http_client,
-            &config.registry,
-        )
-        .await
-        .expect("resolve latest tag"); // TODO: properly propagate this error
-
-        let version_range = latest_version.serialize(save_exact);
+        let (package_name, spec) = parse_package_spec(package_name);
+        let package_version =
+            resolve_package_spec(http_client, package_mem_cache, config, package_name, spec)
+                .await?;
+        let version_range = saved_version_range(config, &package_version, spec, save_exact);
         for dependency_group in list_dependency_groups() {
             manifest
                 .add_dependency(package_name, &version_range, dependency_group)
@@ -80,12 +92,345 @@ where
             dependency_groups: list_dependency_groups(),
             frozen_lockfile: false,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
         }
         .run()
-        .await;
+        .await
+        .map_err(AddError::Install)?;
 
         manifest.save().map_err(AddError::SaveManifest)?;
 
         Ok(())
     }
 }
+
+/// Split an argument of `pacquet add` such as `foo`, `@scope/foo@^1.0.0`, or `foo@next` into
+/// the package name and the version, range, or dist-tag after it.
+pub fn parse_package_spec(argument: &str) -> (&str, Option<&str>) {
+    // The `@` of a scope isn't a separator.
+    match argument.char_indices().skip(1).find(|&(_, char)| char == '@') {
+        Some((index, _)) => (&argument[..index], Some(&argument[index + 1..])),
+        None => (argument, None),
+    }
+}
+
+/// Find the version of `package_name` that `spec` refers to, the `latest` dist-tag by default.
+async fn resolve_package_spec(
+    http_client: &ThrottledClient,
+    package_mem_cache: &PackageMemCache,
+    config: &Npmrc,
+    package_name: &str,
+    spec: Option<&str>,
+) -> Result<PackageVersion, AddError> {
+    let spec = spec.unwrap_or("latest");
+    let package = Package::fetch_from_registry_with_mem_cache(
+        package_name,
+        http_client,
+        config.registry_for(package_name),
+        Some(&metadata_cache(config)),
+        package_mem_cache,
+    )
+    .await
+    .map_err(AddError::FetchPackage)?;
+    package.resolve_spec(spec).cloned().ok_or_else(|| AddError::NoMatchingVersion {
+        name: package_name.to_string(),
+        spec: spec.to_string(),
+    })
+}
+
+/// The version range to save to the manifest.
+///
+/// Exact versions and ranges are saved as they were written. Dist-tags, or no spec at all, save
+/// the resolved version prefixed by `save-prefix`, or without a prefix with `--save-exact`.
+fn saved_version_range(
+    config: &Npmrc,
+    package_version: &PackageVersion,
+    spec: Option<&str>,
+    save_exact: bool,
+) -> String {
+    if save_exact {
+        return package_version.serialize("");
+    }
+    match spec {
+        Some(spec) if spec.parse::<node_semver::Range>().is_ok() => spec.to_string(),
+        _ => package_version.serialize(config.save_prefix.as_str()),
+    }
+}
+
+/// This subroutine does everything `pacquet add --filter` is supposed to do.
+///
+/// The package is added to the manifests of the selected projects, then the whole selection
+/// is installed with [`InstallWorkspace`].
+#[must_use]
+pub struct AddToWorkspace<'a, ListDependencyGroups, DependencyGroupList>
+where
+    ListDependencyGroups: Fn() -> DependencyGroupList,
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    pub tarball_mem_cache: &'a MemCache,
+    pub resolved_packages: &'a ResolvedPackages,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub package_mem_cache: &'a PackageMemCache,
+    pub http_client: &'a ThrottledClient,
+    pub config: &'static Npmrc,
+    pub workspace: &'a mut Workspace,
+    pub project_dirs: &'a [PathBuf], // directories of the selected projects
+    pub list_dependency_groups: ListDependencyGroups, // must be a function because it is called multiple times
+    pub package_name: &'a str,
+    pub save_exact: bool,
+}
+
+impl<'a, ListDependencyGroups, DependencyGroupList>
+    AddToWorkspace<'a, ListDependencyGroups, DependencyGroupList>
+where
+    ListDependencyGroups: Fn() -> DependencyGroupList,
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    pub async fn run(self) -> Result<(), AddError> {
+        let AddToWorkspace {
+            tarball_mem_cache,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+            http_client,
+            config,
+            workspace,
+            project_dirs,
+            list_dependency_groups,
+            package_name,
+            save_exact,
+        } = self;
+
+        let (package_name, spec) = parse_package_spec(package_name);
+        let package_version =
+            resolve_package_spec(http_client, package_mem_cache, config, package_name, spec)
+                .await?;
+        let version_range = saved_version_range(config, &package_version, spec, save_exact);
+        for project in &mut workspace.projects {
+            if !project_dirs.contains(&project.dir) {
+                continue;
+            }
+            for dependency_group in list_dependency_groups() {
+                project
+                    .manifest
+                    .add_dependency(package_name, &version_range, dependency_group)
+                    .map_err(AddError::AddDependencyToManifest)?;
+            }
+        }
+
+        let projects = workspace
+            .projects
+            .iter()
+            .filter(|project| project_dirs.contains(&project.dir))
+            .collect::<Vec<_>>();
+
+        InstallWorkspace {
+            tarball_mem_cache,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+            http_client,
+            config,
+            workspace,
+            projects: &projects,
+            dependency_groups: list_dependency_groups(),
+        }
+        .run()
+        .await
+        .map_err(AddError::InstallWorkspace)?;
+
+        for project in projects {
+            project.manifest.save().map_err(AddError::SaveManifest)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_npmrc::SavePrefix;
+    use pacquet_registry_mock::CustomRegistry;
+    use pacquet_testing_utils::tarball::TarballBuilder;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn should_save_range_with_prefix() {
+        let mut registry = CustomRegistry::new().await;
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.2.3" }"#)
+            .build();
+        let tarball_url = registry.add_tarball("foo-1.2.3.tgz", tarball.data).await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.2.3" },
+            "versions": {
+                "1.2.3": {
+                    "name": "foo",
+                    "version": "1.2.3",
+                    "dist": { "integrity": tarball.integrity.to_string(), "tarball": tarball_url },
+                },
+            },
+        });
+        registry.add_packument("foo", &packument).await;
+
+        macro_rules! case {
+            ($save_prefix:expr, $save_exact:literal => $output:literal) => {{
+                eprintln!("CASE: {:?}, save_exact={:?}", $save_prefix, $save_exact);
+                let dir = tempdir().unwrap();
+                let modules_dir = dir.path().join("node_modules");
+                let manifest_path = dir.path().join("package.json");
+                let mut manifest =
+                    PackageManifest::create_if_needed(manifest_path.clone()).unwrap();
+
+                let mut config = Npmrc::new();
+                config.store_dir = dir.path().join("pacquet-store").into();
+                config.virtual_store_dir = modules_dir.join(".pacquet");
+                config.modules_dir = modules_dir;
+                config.registry = registry.url();
+                config.lockfile = false;
+                config.save_prefix = $save_prefix;
+                let config = config.leak();
+
+                Add {
+                    tarball_mem_cache: &Default::default(),
+                    resolved_packages: &Default::default(),
+                    resolved_versions: &Default::default(),
+                    package_mem_cache: &Default::default(),
+                    http_client: &Default::default(),
+                    config,
+                    manifest: &mut manifest,
+                    lockfile: None,
+                    list_dependency_groups: || [DependencyGroup::Prod],
+                    package_name: "foo",
+                    save_exact: $save_exact,
+                }
+                .run()
+                .await
+                .unwrap();
+
+                let saved = PackageManifest::from_path(manifest_path).unwrap();
+                let saved = saved.dependencies([DependencyGroup::Prod]).collect::<Vec<_>>();
+                assert_eq!(saved, [("foo", $output)]);
+                drop(dir); // cleanup
+            }};
+        }
+
+        case!(SavePrefix::Caret, false => "^1.2.3");
+        case!(SavePrefix::Tilde, false => "~1.2.3");
+        case!(SavePrefix::Exact, false => "1.2.3");
+        case!(SavePrefix::Tilde, true => "1.2.3");
+
+        drop(registry); // cleanup
+    }
+
+    #[test]
+    fn parse_package_specs() {
+        macro_rules! case {
+            ($input:literal => $name:literal, $spec:expr) => {{
+                eprintln!("CASE: {:?}", $input);
+                let spec: Option<&str> = $spec;
+                assert_eq!(parse_package_spec($input), ($name, spec));
+            }};
+        }
+
+        case!("react" => "react", None);
+        case!("react@next" => "react", Some("next"));
+        case!("react@^18.0.0" => "react", Some("^18.0.0"));
+        case!("@scope/name" => "@scope/name", None);
+        case!("@scope/name@1.2.3" => "@scope/name", Some("1.2.3"));
+    }
+
+    #[tokio::test]
+    async fn should_add_package_spec() {
+        let mut registry = CustomRegistry::new().await;
+        let mut versions = serde_json::Map::new();
+        for version in ["1.0.0", "1.1.0", "2.0.0-rc.0"] {
+            let tarball = TarballBuilder::new()
+                .file(
+                    "package/package.json",
+                    json!({ "name": "@scope/foo", "version": version }).to_string(),
+                )
+                .build();
+            let tarball_url =
+                registry.add_tarball(&format!("foo-{version}.tgz"), tarball.data).await;
+            let manifest = json!({
+                "name": "@scope/foo",
+                "version": version,
+                "dist": { "integrity": tarball.integrity.to_string(), "tarball": tarball_url },
+            });
+            versions.insert(version.to_string(), manifest);
+        }
+        let packument = json!({
+            "name": "@scope/foo",
+            "dist-tags": { "latest": "1.1.0", "next": "2.0.0-rc.0" },
+            "versions": versions,
+        });
+        registry.add_packument("@scope/foo", &packument).await;
+
+        let add = |argument: &'static str| {
+            let registry_url = registry.url();
+            async move {
+                let dir = tempdir().unwrap();
+                let modules_dir = dir.path().join("node_modules");
+                let manifest_path = dir.path().join("package.json");
+                let mut manifest =
+                    PackageManifest::create_if_needed(manifest_path.clone()).unwrap();
+
+                let mut config = Npmrc::new();
+                config.store_dir = dir.path().join("pacquet-store").into();
+                config.virtual_store_dir = modules_dir.join(".pacquet");
+                config.modules_dir = modules_dir;
+                config.registry = registry_url;
+                config.lockfile = false;
+                let config: &'static Npmrc = config.leak();
+
+                let result = Add {
+                    tarball_mem_cache: &Default::default(),
+                    resolved_packages: &Default::default(),
+                    resolved_versions: &Default::default(),
+                    package_mem_cache: &Default::default(),
+                    http_client: &Default::default(),
+                    config,
+                    manifest: &mut manifest,
+                    lockfile: None,
+                    list_dependency_groups: || [DependencyGroup::Prod],
+                    package_name: argument,
+                    save_exact: false,
+                }
+                .run()
+                .await;
+
+                let saved = result.map(|()| {
+                    let saved = PackageManifest::from_path(manifest_path).unwrap();
+                    let saved = saved.dependencies([DependencyGroup::Prod]).collect::<Vec<_>>();
+                    assert_eq!(saved.len(), 1);
+                    let (name, version_range) = saved[0];
+                    assert_eq!(name, "@scope/foo");
+                    version_range.to_string()
+                });
+                drop(dir); // cleanup
+                saved
+            }
+        };
+
+        eprintln!("CASE: no spec");
+        assert_eq!(add("@scope/foo").await.unwrap(), "^1.1.0");
+        eprintln!("CASE: dist-tag");
+        assert_eq!(add("@scope/foo@next").await.unwrap(), "^2.0.0-rc.0");
+        eprintln!("CASE: exact version");
+        assert_eq!(add("@scope/foo@1.0.0").await.unwrap(), "1.0.0");
+        eprintln!("CASE: range");
+        assert_eq!(add("@scope/foo@~1.0.0").await.unwrap(), "~1.0.0");
+        eprintln!("CASE: no matching version");
+        let error = add("@scope/foo@^3.0.0").await.unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, AddError::NoMatchingVersion { .. }));
+
+        drop(registry); // cleanup
+    }
+}
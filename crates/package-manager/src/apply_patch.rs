@@ -0,0 +1,517 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_lockfile::{PatchInfo, PkgNameVerPeer};
+use pacquet_registry::PackageVersion;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Error type of [`apply_patch`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum ApplyPatchError {
+    #[display("Failed to read the patch file at {path:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::read_patch))]
+    ReadPatch {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Invalid patch at line {line}: {reason}")]
+    #[diagnostic(code(pacquet_package_manager::invalid_patch))]
+    InvalidPatch { line: usize, reason: &'static str },
+
+    #[display("The patch refers to a file outside of the package: {path}")]
+    #[diagnostic(code(pacquet_package_manager::patch_outside_package))]
+    OutsidePackage { path: String },
+
+    #[display("Failed to read {path:?} to patch it: {error}")]
+    #[diagnostic(code(pacquet_package_manager::read_patched_file))]
+    ReadFile {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to write the patched file at {path:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::write_patched_file))]
+    WriteFile {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Hunk #{hunk} failed to apply to {file} at line {line}")]
+    #[diagnostic(
+        code(pacquet_package_manager::patch_hunk_failed),
+        help("The patch may have been created for another version of the package, recreate it with `pnpm patch`")
+    )]
+    HunkFailed { file: String, hunk: usize, line: usize },
+}
+
+/// A line of a [`Hunk`], which includes its line ending.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+impl HunkLine {
+    /// Remove the line ending, for the last line of a file that doesn't end with one.
+    fn strip_line_ending(&mut self) {
+        let (HunkLine::Context(text) | HunkLine::Remove(text) | HunkLine::Add(text)) = self;
+        if text.ends_with('\n') {
+            text.pop();
+            if text.ends_with('\r') {
+                text.pop();
+            }
+        }
+    }
+}
+
+/// A contiguous change in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hunk {
+    /// Line number (1-based) of the first line in the original file.
+    old_start: usize,
+    lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    /// Lines that are expected in the original file.
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    /// Lines that replace [`old_lines`](Hunk::old_lines).
+    fn new_lines(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Add(text) => Some(text.clone()),
+                HunkLine::Remove(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Changes to a single file, `None` paths stand for `/dev/null`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FilePatch {
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+/// Find the patch of `package_specifier` in `patched_dependencies`, which is keyed by `{name}@{version}`.
+pub fn find_patch<'a>(
+    patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    package_specifier: &PkgNameVerPeer,
+) -> Option<&'a PatchInfo> {
+    let key = format!("{}@{}", package_specifier.name, package_specifier.suffix.version());
+    patched_dependencies?.get(&key)
+}
+
+/// Name of the directory of `package_specifier` in the virtual store.
+///
+/// Like pnpm, the hash of the patch is part of the name of a patched package, so that a changed
+/// patch is applied to a fresh directory instead of on top of the previous one.
+pub fn virtual_store_name(
+    package_specifier: &PkgNameVerPeer,
+    patched_dependencies: Option<&HashMap<String, PatchInfo>>,
+) -> String {
+    let name = package_specifier.to_virtual_store_name();
+    match find_patch(patched_dependencies, package_specifier) {
+        Some(patch) if !name.contains("patch_hash=") => format!("{name}_patch_hash={}", patch.hash),
+        _ => name,
+    }
+}
+
+/// Find the patch of a package resolved from the registry in `patched_dependencies`.
+pub fn find_package_patch<'a>(
+    patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    package: &PackageVersion,
+) -> Option<&'a PatchInfo> {
+    patched_dependencies?.get(&format!("{}@{}", package.name, package.version))
+}
+
+/// Name of the directory of a package resolved from the registry in the virtual store,
+/// see [`virtual_store_name`].
+pub fn package_virtual_store_name(
+    package: &PackageVersion,
+    patched_dependencies: Option<&HashMap<String, PatchInfo>>,
+) -> String {
+    let name = package.to_virtual_store_name();
+    match find_package_patch(patched_dependencies, package) {
+        Some(patch) => format!("{name}_patch_hash={}", patch.hash),
+        None => name,
+    }
+}
+
+/// Apply the unified diff at `patch_path` to the files in `package_dir`.
+///
+/// Patched files are recreated instead of modified in place, because they may be hard links
+/// to the files in the store. The recreated files keep the permissions of the original ones.
+pub fn apply_patch(package_dir: &Path, patch_path: &Path) -> Result<(), ApplyPatchError> {
+    let patch = fs::read_to_string(patch_path)
+        .map_err(|error| ApplyPatchError::ReadPatch { path: patch_path.to_path_buf(), error })?;
+
+    for FilePatch { old_path, new_path, hunks } in parse_patch(&patch)? {
+        let old_path = old_path.map(|path| resolve_path(package_dir, &path)).transpose()?;
+        let new_path = new_path.map(|path| resolve_path(package_dir, &path)).transpose()?;
+        let Some(file) = new_path.as_ref().or(old_path.as_ref()) else {
+            continue; // neither side exists, there is nothing to change
+        };
+        let file = file.strip_prefix(package_dir).unwrap_or(file).display().to_string();
+
+        let (content, permissions) = match &old_path {
+            Some(path) => {
+                let read = || Ok::<_, io::Error>((fs::read_to_string(path)?, fs::metadata(path)?));
+                let (content, metadata) = read()
+                    .map_err(|error| ApplyPatchError::ReadFile { path: path.clone(), error })?;
+                (content, Some(metadata.permissions()))
+            }
+            None => (String::new(), None),
+        };
+        let patched = apply_hunks(&file, &content, &hunks)?;
+
+        if let Some(path) = &old_path {
+            fs::remove_file(path)
+                .map_err(|error| ApplyPatchError::WriteFile { path: path.clone(), error })?;
+        }
+        if let Some(path) = &new_path {
+            let write = || {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(path, &patched)?;
+                match &permissions {
+                    Some(permissions) => fs::set_permissions(path, permissions.clone()),
+                    None => Ok(()),
+                }
+            };
+            write().map_err(|error| ApplyPatchError::WriteFile { path: path.clone(), error })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Join a path from the patch to `package_dir`, rejecting paths that escape it.
+fn resolve_path(package_dir: &Path, path: &str) -> Result<PathBuf, ApplyPatchError> {
+    let is_inside = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !is_inside {
+        return Err(ApplyPatchError::OutsidePackage { path: path.to_string() });
+    }
+    Ok(package_dir.join(path))
+}
+
+/// Parse the file path of a `---` or `+++` line.
+fn parse_path(raw: &str, prefix: &str) -> Option<String> {
+    let path = raw.split('\t').next().unwrap_or_default().trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+/// Parse the ranges of a hunk header such as `@@ -1,3 +1,4 @@`, return the start and the lengths.
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, usize)> {
+    let parse_range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let mut ranges = header.strip_prefix("@@ ")?.split(' ');
+    let (old_start, old_count) = parse_range(ranges.next()?.strip_prefix('-')?)?;
+    let (_, new_count) = parse_range(ranges.next()?.strip_prefix('+')?)?;
+    Some((old_start, old_count, new_count))
+}
+
+/// Parse a unified diff, headers other than `---`, `+++`, and `@@` are ignored.
+fn parse_patch(patch: &str) -> Result<Vec<FilePatch>, ApplyPatchError> {
+    let lines = patch.split_inclusive('\n').collect::<Vec<_>>();
+    let invalid = |index: usize, reason| ApplyPatchError::InvalidPatch { line: index + 1, reason };
+
+    let mut files = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let Some(old_path) = lines[index].strip_prefix("--- ") else {
+            index += 1;
+            continue;
+        };
+        let new_path = lines
+            .get(index + 1)
+            .and_then(|line| line.strip_prefix("+++ "))
+            .ok_or_else(|| invalid(index + 1, "expected a line that starts with +++"))?;
+        index += 2;
+
+        let mut hunks = Vec::new();
+        while let Some(header) = lines.get(index).filter(|line| line.starts_with("@@ ")) {
+            let (old_start, mut old_count, mut new_count) =
+                parse_hunk_header(header).ok_or_else(|| invalid(index, "malformed hunk header"))?;
+            index += 1;
+
+            let mut hunk_lines = Vec::<HunkLine>::new();
+            while old_count > 0
+                || new_count > 0
+                || lines.get(index).is_some_and(|line| line.starts_with('\\'))
+            {
+                let line =
+                    *lines.get(index).ok_or_else(|| invalid(index, "unexpected end of hunk"))?;
+                if line.starts_with('\\') {
+                    // `\ No newline at end of file` applies to the previous line
+                    hunk_lines
+                        .last_mut()
+                        .ok_or_else(|| invalid(index, "unexpected line in hunk"))?
+                        .strip_line_ending();
+                    index += 1;
+                    continue;
+                }
+
+                let (hunk_line, is_old, is_new) = if let Some(text) = line.strip_prefix(' ') {
+                    (HunkLine::Context(text.to_string()), true, true)
+                } else if let Some(text) = line.strip_prefix('-') {
+                    (HunkLine::Remove(text.to_string()), true, false)
+                } else if let Some(text) = line.strip_prefix('+') {
+                    (HunkLine::Add(text.to_string()), false, true)
+                } else if line.trim_end_matches(['\r', '\n']).is_empty() {
+                    // some editors strip the space of empty context lines
+                    (HunkLine::Context(line.to_string()), true, true)
+                } else {
+                    return Err(invalid(index, "unexpected line in hunk"));
+                };
+                let too_long = || invalid(index, "hunk is longer than its header says");
+                if is_old {
+                    old_count = old_count.checked_sub(1).ok_or_else(too_long)?;
+                }
+                if is_new {
+                    new_count = new_count.checked_sub(1).ok_or_else(too_long)?;
+                }
+                hunk_lines.push(hunk_line);
+                index += 1;
+            }
+
+            hunks.push(Hunk { old_start, lines: hunk_lines });
+        }
+
+        files.push(FilePatch {
+            old_path: parse_path(old_path, "a/"),
+            new_path: parse_path(new_path, "b/"),
+            hunks,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Apply the hunks to the content of a file.
+///
+/// Each hunk is searched for near its expected position, so changes above it may shift it.
+fn apply_hunks(file: &str, content: &str, hunks: &[Hunk]) -> Result<String, ApplyPatchError> {
+    let mut lines = content.split_inclusive('\n').map(String::from).collect::<Vec<_>>();
+    let mut offset = 0isize;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old_lines = hunk.old_lines();
+        let new_lines = hunk.new_lines();
+
+        // a hunk without old lines is inserted after line `old_start`
+        let base =
+            if old_lines.is_empty() { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        let expected = (base as isize + offset).clamp(0, lines.len() as isize) as usize;
+        let position = find_lines(&lines, &old_lines, expected).ok_or_else(|| {
+            ApplyPatchError::HunkFailed {
+                file: file.to_string(),
+                hunk: index + 1,
+                line: hunk.old_start,
+            }
+        })?;
+
+        offset =
+            position as isize - base as isize + new_lines.len() as isize - old_lines.len() as isize;
+        lines.splice(position..position + old_lines.len(), new_lines);
+    }
+
+    Ok(lines.concat())
+}
+
+/// Find where `needle` occurs in `lines`, starting from `expected` and moving outward.
+fn find_lines(lines: &[String], needle: &[&str], expected: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(needle.len())?;
+    let matches = |position: usize| {
+        lines[position..position + needle.len()]
+            .iter()
+            .zip(needle)
+            .all(|(line, needle)| line == needle)
+    };
+    let expected = expected.min(last);
+    (0..=last)
+        .flat_map(|distance| {
+            let before = expected.checked_sub(distance);
+            let after =
+                (distance > 0).then(|| expected + distance).filter(|position| *position <= last);
+            before.into_iter().chain(after)
+        })
+        .find(|position| matches(*position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    const PATCH: &str = "\
+diff --git a/index.js b/index.js
+index 8e020ca..1e8ae0c 100644
+--- a/index.js
++++ b/index.js
+@@ -1,4 +1,4 @@
+ 'use strict'
+-module.exports = (n) => n > 0
++module.exports = (n) => n >= 0
+
+ // end
+diff --git a/lib/new.js b/lib/new.js
+new file mode 100644
+index 0000000..3b18e51
+--- /dev/null
++++ b/lib/new.js
+@@ -0,0 +1 @@
++module.exports = 'new'
+\\ No newline at end of file
+diff --git a/old.js b/old.js
+deleted file mode 100644
+index 3b18e51..0000000
+--- a/old.js
++++ /dev/null
+@@ -1 +0,0 @@
+-module.exports = 'old'
+";
+
+    #[test]
+    fn should_apply_patch() {
+        let dir = tempdir().unwrap();
+        let package_dir = dir.path().join("package");
+        fs::create_dir_all(&package_dir).unwrap();
+        let store_file = dir.path().join("store-file");
+        fs::write(&store_file, "'use strict'\nmodule.exports = (n) => n > 0\n\n// end\n").unwrap();
+        fs::hard_link(&store_file, package_dir.join("index.js")).unwrap();
+        fs::write(package_dir.join("old.js"), "module.exports = 'old'\n").unwrap();
+        let patch_path = dir.path().join("is-positive@1.0.0.patch");
+        fs::write(&patch_path, PATCH).unwrap();
+
+        apply_patch(&package_dir, &patch_path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(package_dir.join("index.js")).unwrap(),
+            "'use strict'\nmodule.exports = (n) => n >= 0\n\n// end\n",
+        );
+        assert_eq!(
+            fs::read_to_string(package_dir.join("lib/new.js")).unwrap(),
+            "module.exports = 'new'"
+        );
+        assert!(!package_dir.join("old.js").exists());
+
+        eprintln!("CASE: hard links to the store are not modified");
+        assert_eq!(
+            fs::read_to_string(&store_file).unwrap(),
+            "'use strict'\nmodule.exports = (n) => n > 0\n\n// end\n",
+        );
+
+        drop(dir); // cleanup
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_keep_mode_of_patched_files() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let package_dir = dir.path().join("package");
+        fs::create_dir_all(&package_dir).unwrap();
+        let script_path = package_dir.join("cli.js");
+        fs::write(&script_path, "console.log('original')\n").unwrap();
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755)).unwrap();
+        let patch_path = dir.path().join("cli.patch");
+        fs::write(
+            &patch_path,
+            "--- a/cli.js\n+++ b/cli.js\n@@ -1 +1 @@\n-console.log('original')\n+console.log('patched')\n",
+        )
+        .unwrap();
+
+        apply_patch(&package_dir, &patch_path).unwrap();
+
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), "console.log('patched')\n");
+        assert_eq!(fs::metadata(&script_path).unwrap().permissions().mode() & 0o777, 0o755);
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn patched_virtual_store_name() {
+        let patched_dependencies = HashMap::from([(
+            "foo@1.0.0".to_string(),
+            PatchInfo { hash: "abc".to_string(), path: "patches/foo@1.0.0.patch".to_string() },
+        )]);
+        let name = |specifier: &str| {
+            let specifier = specifier.parse::<PkgNameVerPeer>().unwrap();
+            virtual_store_name(&specifier, Some(&patched_dependencies))
+        };
+        assert_eq!(name("foo@1.0.0"), "foo@1.0.0_patch_hash=abc");
+        assert_eq!(name("foo@1.0.0(bar@2.0.0)"), "foo@1.0.0_bar@2.0.0_patch_hash=abc");
+        assert_eq!(name("foo@1.0.1"), "foo@1.0.1");
+        assert_eq!(name("bar@1.0.0"), "bar@1.0.0");
+    }
+
+    #[test]
+    fn should_apply_hunks_with_offset() {
+        let hunks = match parse_patch(PATCH).unwrap().as_slice() {
+            [first, ..] => first.hunks.clone(),
+            [] => panic!("expected file patches"),
+        };
+        let content =
+            "// license\n// header\n'use strict'\nmodule.exports = (n) => n > 0\n\n// end\n";
+        let received = apply_hunks("index.js", content, &hunks).unwrap();
+        assert_eq!(
+            received,
+            "// license\n// header\n'use strict'\nmodule.exports = (n) => n >= 0\n\n// end\n"
+        );
+    }
+
+    #[test]
+    fn should_fail_when_hunk_does_not_match() {
+        let hunks = match parse_patch(PATCH).unwrap().as_slice() {
+            [first, ..] => first.hunks.clone(),
+            [] => panic!("expected file patches"),
+        };
+        let error = apply_hunks("index.js", "'use strict'\nmodule.exports = () => true\n", &hunks)
+            .unwrap_err();
+        dbg!(&error);
+        assert_eq!(error.to_string(), "Hunk #1 failed to apply to index.js at line 1");
+    }
+
+    #[test]
+    fn should_reject_paths_outside_package() {
+        let dir = tempdir().unwrap();
+        let patch_path = dir.path().join("evil.patch");
+        fs::write(&patch_path, "--- /dev/null\n+++ b/../evil.js\n@@ -0,0 +1 @@\n+evil\n").unwrap();
+        let error = apply_patch(dir.path(), &patch_path).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, ApplyPatchError::OutsidePackage { .. }));
+        drop(dir); // cleanup
+    }
+}
@@ -0,0 +1,182 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use node_semver::{Range, Version};
+use pacquet_diagnostics::warn;
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::{PackageManager, PackageManifest, PackageManifestError};
+use std::process::Command;
+
+/// Error type of [`check_engines`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum CheckEnginesError {
+    #[diagnostic(transparent)]
+    ReadEngines(#[error(source)] PackageManifestError),
+
+    #[display(
+        "Unsupported engine: {wanted:?} of node is wanted, but the current version is {current}"
+    )]
+    #[diagnostic(
+        code(pacquet_package_manager::unsupported_engine),
+        help("Switch to a supported version of node, or set engine-strict=false to only warn about it")
+    )]
+    UnsupportedEngine { wanted: String, current: Version },
+}
+
+/// Major version of pnpm whose behavior pacquet follows.
+pub const PNPM_COMPATIBLE_MAJOR_VERSION: u64 = 8;
+
+/// Get the version of the `node` executable in `PATH`, `None` if it can't be run.
+pub fn node_version() -> Option<Version> {
+    let output = Command::new("node").arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let version = String::from_utf8(output.stdout).ok()?;
+    version.trim().trim_start_matches('v').parse().ok()
+}
+
+/// Check the `engines.node` field of `manifest` against the version of the `node` executable.
+///
+/// Fail if the version doesn't satisfy the field and `engine-strict` is enabled, otherwise
+/// only show a warning. The check is skipped if `node` can't be run.
+pub fn check_engines(config: &Npmrc, manifest: &PackageManifest) -> Result<(), CheckEnginesError> {
+    check_node_engine(config, manifest, node_version)
+}
+
+/// Warn if the `packageManager` field of `manifest` pins a package manager that pacquet
+/// isn't compatible with. The field is advisory, so this never fails.
+pub fn check_package_manager(manifest: &PackageManifest) {
+    if let Some(warning) = package_manager_warning(manifest) {
+        warn(warning);
+    }
+}
+
+/// Describe why the `packageManager` field of `manifest` doesn't fit pacquet, if it doesn't.
+fn package_manager_warning(manifest: &PackageManifest) -> Option<String> {
+    let package_manager = match manifest.package_manager() {
+        Ok(package_manager) => package_manager?,
+        Err(error) => return Some(error.to_string()),
+    };
+    let PackageManager { name, version } = package_manager;
+    match name.as_str() {
+        "pacquet" => None,
+        "pnpm" => match version.parse::<Version>() {
+            Ok(version) if version.major == PNPM_COMPATIBLE_MAJOR_VERSION => None,
+            Ok(_) => Some(format!(
+                "This project wants pnpm@{version}, but pacquet behaves like pnpm {PNPM_COMPATIBLE_MAJOR_VERSION}"
+            )),
+            Err(_) => Some(format!("packageManager has an invalid version of pnpm: {version:?}")),
+        },
+        _ => Some(format!("This project wants {name}@{version}, which pacquet isn't compatible with")),
+    }
+}
+
+/// Check the `engines.node` field of `manifest` against the version that `node_version` returns.
+fn check_node_engine(
+    config: &Npmrc,
+    manifest: &PackageManifest,
+    node_version: impl FnOnce() -> Option<Version>,
+) -> Result<(), CheckEnginesError> {
+    let engines = manifest.engines().map_err(CheckEnginesError::ReadEngines)?;
+    let Some(wanted) = engines.node else { return Ok(()) };
+    let Ok(range) = wanted.parse::<Range>() else {
+        warn(format_args!("Skip checking engines.node because {wanted:?} isn't a valid range"));
+        return Ok(());
+    };
+    let Some(current) = node_version() else {
+        tracing::debug!(target: "pacquet::install", "Skip checking engines.node because node can't be run");
+        return Ok(());
+    };
+    if current.satisfies(&range) {
+        return Ok(());
+    }
+    let error = CheckEnginesError::UnsupportedEngine { wanted, current };
+    if config.engine_strict {
+        return Err(error);
+    }
+    warn(error);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_check_node_engine() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("package.json");
+        let load_manifest = |engines: serde_json::Value| {
+            fs::write(&manifest_path, json!({ "engines": engines }).to_string()).unwrap();
+            PackageManifest::from_path(manifest_path.clone()).unwrap()
+        };
+        let version = |version: &'static str| move || Some(version.parse::<Version>().unwrap());
+        let config = Npmrc::new().leak();
+
+        eprintln!("CASE: satisfied");
+        let manifest = load_manifest(json!({ "node": ">=18" }));
+        assert!(check_node_engine(config, &manifest, version("20.1.0")).is_ok());
+
+        eprintln!("CASE: unsatisfied without engine-strict");
+        let manifest = load_manifest(json!({ "node": "^16" }));
+        assert!(check_node_engine(config, &manifest, version("20.1.0")).is_ok());
+
+        eprintln!("CASE: node can't be run");
+        config.engine_strict = true;
+        assert!(check_node_engine(config, &manifest, || None).is_ok());
+
+        eprintln!("CASE: unsatisfied with engine-strict");
+        let error = check_node_engine(config, &manifest, version("20.1.0")).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(
+            error,
+            CheckEnginesError::UnsupportedEngine { wanted, .. } if wanted == "^16"
+        ));
+
+        eprintln!("CASE: no engines");
+        let manifest = load_manifest(json!({ "pnpm": ">=8" }));
+        assert!(check_node_engine(config, &manifest, || unreachable!()).is_ok());
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn should_warn_about_package_manager() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("package.json");
+        let warning = |manifest: serde_json::Value| {
+            fs::write(&manifest_path, manifest.to_string()).unwrap();
+            let manifest = PackageManifest::from_path(manifest_path.clone()).unwrap();
+            package_manager_warning(&manifest)
+        };
+
+        macro_rules! case {
+            ($package_manager:expr => $warns:literal) => {{
+                let package_manager: Option<&str> = $package_manager;
+                eprintln!("CASE: {package_manager:?}");
+                let manifest = match package_manager {
+                    Some(package_manager) => json!({ "packageManager": package_manager }),
+                    None => json!({}),
+                };
+                let warning = warning(manifest);
+                dbg!(&warning);
+                assert_eq!(warning.is_some(), $warns);
+            }};
+        }
+
+        case!(None => false);
+        case!(Some("pnpm@8.6.0") => false);
+        case!(Some("pnpm@8.15.1+sha256.abcdef") => false);
+        case!(Some("pacquet@0.2.1") => false);
+        case!(Some("pnpm@9.0.0") => true);
+        case!(Some("pnpm@latest") => true);
+        case!(Some("yarn@3.6.1") => true);
+        case!(Some("pnpm") => true);
+
+        drop(dir); // cleanup
+    }
+}
@@ -23,12 +23,6 @@ pub fn create_cas_files(
     dir_path: &Path,
     cas_paths: &HashMap<String, PathBuf>,
 ) -> Result<(), CreateCasFilesError> {
-    assert_eq!(
-        import_method,
-        PackageImportMethod::Auto,
-        "Only PackageImportMethod::Auto is currently supported, but {dir_path:?} requires {import_method:?}",
-    );
-
     if dir_path.exists() {
         return Ok(());
     }
@@ -36,7 +30,58 @@ pub fn create_cas_files(
     cas_paths
         .par_iter()
         .try_for_each(|(cleaned_entry, store_path)| {
-            link_file(store_path, &dir_path.join(cleaned_entry))
+            link_file(import_method, store_path, &dir_path.join(cleaned_entry))
         })
         .map_err(CreateCasFilesError::LinkFile)
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{fs, os::unix::fs::MetadataExt};
+    use tempfile::tempdir;
+
+    /// Import a single file with `import_method`, return the paths of the store file and the imported file.
+    fn import_file(dir: &Path, import_method: PackageImportMethod) -> (PathBuf, PathBuf) {
+        let store_path = dir.join("store/abc-index");
+        fs::create_dir_all(store_path.parent().unwrap()).unwrap();
+        fs::write(&store_path, "module.exports = 'foo'").unwrap();
+        let cas_paths = HashMap::from([("lib/index.js".to_string(), store_path.clone())]);
+        let package_dir = dir.join("node_modules/foo");
+        create_cas_files(import_method, &package_dir, &cas_paths).unwrap();
+        let target_path = package_dir.join("lib/index.js");
+        assert_eq!(fs::read_to_string(&target_path).unwrap(), "module.exports = 'foo'");
+        (store_path, target_path)
+    }
+
+    fn inode(path: &Path) -> u64 {
+        fs::metadata(path).unwrap().ino()
+    }
+
+    #[test]
+    fn copy_files_from_store() {
+        for import_method in [PackageImportMethod::Copy, PackageImportMethod::CloneOrCopy] {
+            eprintln!("CASE: {import_method:?}");
+            let dir = tempdir().unwrap();
+            let (store_path, target_path) = import_file(dir.path(), import_method);
+            assert_ne!(inode(&target_path), inode(&store_path));
+            drop(dir); // cleanup
+        }
+    }
+
+    #[test]
+    fn auto_import_files_from_store() {
+        let dir = tempdir().unwrap();
+        import_file(dir.path(), PackageImportMethod::Auto);
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn hardlink_files_from_store() {
+        let dir = tempdir().unwrap();
+        let (store_path, target_path) = import_file(dir.path(), PackageImportMethod::Hardlink);
+        assert_eq!(inode(&target_path), inode(&store_path));
+        drop(dir); // cleanup
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{Overrides, ResolvedVersions};
+use pacquet_lockfile::{
+    ComVer, DependencyPath, Lockfile, LockfileResolution, LockfileSettings, PackageSnapshot,
+    PackageSnapshotDependency, PkgName, PkgNameVerPeer, ProjectSnapshot, RegistryResolution,
+    ResolvedDependencyMap, ResolvedDependencySpec, RootProjectSnapshot, TarballResolution,
+};
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+use pacquet_registry::PackageVersion;
+use std::collections::{HashMap, HashSet};
+
+/// This subroutine creates a lockfile from the versions resolved by
+/// [`InstallWithoutLockfile`](crate::InstallWithoutLockfile).
+///
+/// Only the packages whose versions were resolved end up in the lockfile, so the install
+/// must have covered `dependencies`, `devDependencies`, and `optionalDependencies`.
+///
+/// The version ranges are looked up after `overrides` are applied, the same way the install
+/// resolved them, and the overrides are recorded in the lockfile.
+///
+/// The fields that aren't resolved by the install, `neverBuiltDependencies` and
+/// `patchedDependencies`, are carried over from `previous_lockfile`.
+#[must_use]
+pub struct CreateLockfile<'a> {
+    pub config: &'static Npmrc,
+    pub manifest: &'a PackageManifest,
+    pub overrides: &'a Overrides,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub previous_lockfile: Option<&'a Lockfile>,
+}
+
+/// A resolved package along with the dependency paths of its dependencies.
+struct GraphNode {
+    package: PackageVersion,
+    dependencies: Vec<(PkgName, DependencyPath)>,
+}
+
+impl<'a> CreateLockfile<'a> {
+    /// Execute the subroutine.
+    pub fn run(self) -> Lockfile {
+        let CreateLockfile { config, manifest, overrides, resolved_versions, previous_lockfile } =
+            self;
+
+        let mut project_snapshot = ProjectSnapshot::default();
+        let mut direct_dependencies = Vec::<(DependencyGroup, DependencyPath)>::new();
+        let mut pending = Vec::<(DependencyPath, PackageVersion)>::new();
+        for group in [DependencyGroup::Prod, DependencyGroup::Optional, DependencyGroup::Dev] {
+            let map = manifest
+                .dependencies([group])
+                .filter_map(|(name, range)| {
+                    let resolved_range = overrides.apply(None, name, range)?;
+                    let package = resolve_version(resolved_versions, name, resolved_range)?;
+                    let name = parse_name(name);
+                    let path = dependency_path(&name, &package);
+                    let spec = ResolvedDependencySpec {
+                        specifier: range.to_string(),
+                        version: package.version.clone().into(),
+                    };
+                    direct_dependencies.push((group, path.clone()));
+                    pending.push((path, package));
+                    Some((name, spec))
+                })
+                .collect::<ResolvedDependencyMap>();
+            let map = (!map.is_empty()).then_some(map);
+            match group {
+                DependencyGroup::Prod => project_snapshot.dependencies = map,
+                DependencyGroup::Optional => project_snapshot.optional_dependencies = map,
+                DependencyGroup::Dev => project_snapshot.dev_dependencies = map,
+                DependencyGroup::Peer => unreachable!("peer dependencies aren't listed"),
+            }
+        }
+
+        let mut graph = HashMap::<DependencyPath, GraphNode>::new();
+        while let Some((path, package)) = pending.pop() {
+            if graph.contains_key(&path) {
+                continue;
+            }
+            let dependencies = package
+                .dependencies(config.auto_install_peers)
+                .filter_map(|(name, range)| {
+                    let range = overrides.apply(Some(&package), name, range)?;
+                    let dependency = resolve_version(resolved_versions, name, range)?;
+                    let name = parse_name(name);
+                    let path = dependency_path(&name, &dependency);
+                    pending.push((path.clone(), dependency));
+                    Some((name, path))
+                })
+                .collect();
+            graph.insert(path, GraphNode { package, dependencies });
+        }
+
+        let reachable_from = |groups: &[DependencyGroup]| {
+            let mut reachable = HashSet::<&DependencyPath>::new();
+            let mut pending = direct_dependencies
+                .iter()
+                .filter(|(group, _)| groups.contains(group))
+                .map(|(_, path)| path)
+                .collect::<Vec<_>>();
+            while let Some(path) = pending.pop() {
+                if !reachable.insert(path) {
+                    continue;
+                }
+                if let Some(node) = graph.get(path) {
+                    pending.extend(node.dependencies.iter().map(|(_, path)| path));
+                }
+            }
+            reachable
+        };
+        let prod = reachable_from(&[DependencyGroup::Prod, DependencyGroup::Optional]);
+        let dev = reachable_from(&[DependencyGroup::Dev]);
+        let required = reachable_from(&[DependencyGroup::Prod, DependencyGroup::Dev]);
+
+        let packages = graph
+            .iter()
+            .map(|(path, node)| {
+                let dev = match (prod.contains(path), dev.contains(path)) {
+                    (true, true) => None,
+                    (true, false) => Some(false),
+                    (false, _) => Some(true),
+                };
+                let optional = (!required.contains(path)).then_some(true);
+                (path.clone(), node.to_package_snapshot(config, dev, optional))
+            })
+            .collect::<HashMap<_, _>>();
+
+        Lockfile {
+            lockfile_version: ComVer::new(6, 0).try_into().expect("6.0 is compatible with 6.x"),
+            settings: Some(LockfileSettings::new(config.auto_install_peers, false)),
+            never_built_dependencies: previous_lockfile
+                .and_then(|lockfile| lockfile.never_built_dependencies.clone()),
+            overrides: overrides.to_lockfile(),
+            patched_dependencies: previous_lockfile
+                .and_then(|lockfile| lockfile.patched_dependencies.clone()),
+            project_snapshot: RootProjectSnapshot::Single(project_snapshot),
+            packages: (!packages.is_empty()).then_some(packages),
+        }
+    }
+}
+
+impl GraphNode {
+    /// Create the entry of the package in the `packages` map.
+    fn to_package_snapshot(
+        &self,
+        config: &Npmrc,
+        dev: Option<bool>,
+        optional: Option<bool>,
+    ) -> PackageSnapshot {
+        let GraphNode { package, dependencies } = self;
+
+        let integrity = package.dist.tarball_integrity();
+        let resolution: LockfileResolution = match integrity {
+            Some(integrity) if !config.lockfile_include_tarball_url => {
+                RegistryResolution { integrity }.into()
+            }
+            integrity => {
+                TarballResolution { tarball: package.dist.tarball.clone(), integrity }.into()
+            }
+        };
+
+        let dependencies = dependencies
+            .iter()
+            .map(|(name, path)| {
+                let version = path.package_specifier.suffix.clone();
+                (name.clone(), PackageSnapshotDependency::PkgVerPeer(version))
+            })
+            .collect::<HashMap<_, _>>();
+
+        PackageSnapshot {
+            resolution,
+            id: None,
+            name: None,
+            version: None,
+            engines: None,
+            cpu: None,
+            os: None,
+            libc: None,
+            deprecated: None,
+            has_bin: None,
+            prepare: None,
+            requires_build: None,
+            bundled_dependencies: None,
+            peer_dependencies: package.peer_dependencies.clone(),
+            peer_dependencies_meta: None,
+            dependencies: (!dependencies.is_empty()).then_some(dependencies),
+            optional_dependencies: None,
+            transitive_peer_dependencies: None,
+            dev,
+            optional,
+        }
+    }
+}
+
+/// Get the version that `range` of package `name` was resolved to.
+fn resolve_version(
+    resolved_versions: &ResolvedVersions,
+    name: &str,
+    range: &str,
+) -> Option<PackageVersion> {
+    resolved_versions.get(&(name.to_string(), range.to_string()))?.get().cloned()
+}
+
+/// Parse the name of a package that was successfully resolved.
+fn parse_name(name: &str) -> PkgName {
+    name.parse().expect("resolved packages have valid names")
+}
+
+/// Key of a package from the default registry in the `packages` map.
+fn dependency_path(name: &PkgName, package: &PackageVersion) -> DependencyPath {
+    DependencyPath {
+        custom_registry: None,
+        package_specifier: PkgNameVerPeer::new(name.clone(), package.version.clone().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tokio::sync::OnceCell;
+
+    const INTEGRITY: &str = "sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==";
+
+    /// Record that `range` of package `name` was resolved to `version`.
+    fn resolve(
+        resolved_versions: &ResolvedVersions,
+        name: &str,
+        range: &str,
+        version: &str,
+        dependencies: serde_json::Value,
+    ) {
+        let package: PackageVersion = serde_json::from_value(json!({
+            "name": name,
+            "version": version,
+            "dist": {
+                "integrity": INTEGRITY,
+                "tarball": format!("https://registry.npmjs.org/{name}/-/{name}-{version}.tgz"),
+            },
+            "dependencies": dependencies,
+        }))
+        .unwrap();
+        let key = (name.to_string(), range.to_string());
+        resolved_versions.insert(key, Arc::new(OnceCell::new_with(Some(package))));
+    }
+
+    #[test]
+    fn should_create_lockfile() {
+        let dir = tempdir().unwrap();
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("a", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("b", "^1.0.0", DependencyGroup::Optional).unwrap();
+        manifest.add_dependency("c", "~1.0.0", DependencyGroup::Dev).unwrap();
+
+        let resolved_versions = ResolvedVersions::new();
+        resolve(&resolved_versions, "a", "^1.0.0", "1.1.0", json!({ "d": "^1.0.0" }));
+        resolve(&resolved_versions, "b", "^1.0.0", "1.0.0", json!({ "e": "1.0.0" }));
+        resolve(&resolved_versions, "c", "~1.0.0", "1.0.2", json!({ "d": "1.0.0", "f": "1.0.0" }));
+        resolve(&resolved_versions, "d", "^1.0.0", "1.0.0", json!({}));
+        resolve(&resolved_versions, "d", "1.0.0", "1.0.0", json!({}));
+        resolve(&resolved_versions, "e", "1.0.0", "1.0.0", json!({}));
+        resolve(&resolved_versions, "f", "1.0.0", "1.0.0", json!({}));
+
+        let config = Npmrc::new().leak();
+        let lockfile = CreateLockfile {
+            config,
+            manifest: &manifest,
+            overrides: &Overrides::default(),
+            resolved_versions: &resolved_versions,
+            previous_lockfile: None,
+        }
+        .run();
+        lockfile.write_to(dir.path()).unwrap();
+
+        let content = std::fs::read_to_string(dir.path().join("pnpm-lock.yaml")).unwrap();
+        insta::assert_snapshot!(content.replace(INTEGRITY, "INTEGRITY"));
+
+        eprintln!("CASE: the written lockfile can be loaded");
+        let loaded: Lockfile = serde_yaml::from_str(&content).unwrap();
+        assert_eq!(loaded, lockfile);
+
+        drop(dir); // cleanup
+    }
+}
@@ -1,24 +1,27 @@
-use crate::symlink_package;
-use pacquet_lockfile::{PackageSnapshotDependency, PkgName, PkgNameVerPeer};
+use crate::{symlink_package, virtual_store_name};
+use pacquet_lockfile::{PackageSnapshotDependency, PatchInfo, PkgName, PkgNameVerPeer};
 use rayon::prelude::*;
 use std::{collections::HashMap, path::Path};
 
 /// Create symlink layout of dependencies for a package in a virtual dir.
 ///
 /// **NOTE:** `virtual_node_modules_dir` is assumed to already exist.
+///
+/// The directories of the dependencies are named after their patches in `patched_dependencies`.
 pub fn create_symlink_layout(
     dependencies: &HashMap<PkgName, PackageSnapshotDependency>,
     virtual_root: &Path,
     virtual_node_modules_dir: &Path,
+    patched_dependencies: Option<&HashMap<String, PatchInfo>>,
 ) {
     dependencies.par_iter().for_each(|(name, spec)| {
         let virtual_store_name = match spec {
             PackageSnapshotDependency::PkgVerPeer(ver_peer) => {
                 let package_specifier = PkgNameVerPeer::new(name.clone(), ver_peer.clone()); // TODO: remove copying here
-                package_specifier.to_virtual_store_name()
+                virtual_store_name(&package_specifier, patched_dependencies)
             }
             PackageSnapshotDependency::DependencyPath(dependency_path) => {
-                dependency_path.package_specifier.to_virtual_store_name()
+                virtual_store_name(&dependency_path.package_specifier, patched_dependencies)
             }
         };
         let name_str = name.to_string();
@@ -1,7 +1,10 @@
-use crate::{create_cas_files, create_symlink_layout, CreateCasFilesError};
+use crate::{
+    apply_patch, create_cas_files, create_symlink_layout, find_patch, virtual_store_name,
+    ApplyPatchError, CreateCasFilesError,
+};
 use derive_more::{Display, Error};
 use miette::Diagnostic;
-use pacquet_lockfile::{DependencyPath, PackageSnapshot};
+use pacquet_lockfile::{DependencyPath, PackageSnapshot, PatchInfo};
 use pacquet_npmrc::PackageImportMethod;
 use std::{
     collections::HashMap,
@@ -9,7 +12,11 @@ use std::{
     path::{Path, PathBuf},
 };
 
-/// This subroutine installs the files from [`cas_paths`](Self::cas_paths) then creates the symlink layout.
+/// This subroutine installs the files from [`cas_paths`](Self::cas_paths), applies the patch
+/// from [`patched_dependencies`](Self::patched_dependencies) if any, then creates the symlink layout.
+///
+/// The patch is only applied to a freshly imported directory, because an existing directory
+/// was already patched by the install that created it.
 #[must_use]
 pub struct CreateVirtualDirBySnapshot<'a> {
     pub virtual_store_dir: &'a Path,
@@ -17,6 +24,9 @@ pub struct CreateVirtualDirBySnapshot<'a> {
     pub import_method: PackageImportMethod,
     pub dependency_path: &'a DependencyPath,
     pub package_snapshot: &'a PackageSnapshot,
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    /// Directory that the paths of the patch files are relative to.
+    pub lockfile_dir: &'a Path,
 }
 
 /// Error type of [`CreateVirtualDirBySnapshot`].
@@ -32,6 +42,9 @@ pub enum CreateVirtualDirError {
 
     #[diagnostic(transparent)]
     CreateCasFiles(#[error(source)] CreateCasFilesError),
+
+    #[diagnostic(transparent)]
+    ApplyPatch(#[error(source)] ApplyPatchError),
 }
 
 impl<'a> CreateVirtualDirBySnapshot<'a> {
@@ -43,11 +56,14 @@ impl<'a> CreateVirtualDirBySnapshot<'a> {
             import_method,
             dependency_path,
             package_snapshot,
+            patched_dependencies,
+            lockfile_dir,
         } = self;
+        let package_specifier = &dependency_path.package_specifier;
 
         // node_modules/.pacquet/pkg-name@x.y.z/node_modules
         let virtual_node_modules_dir = virtual_store_dir
-            .join(dependency_path.package_specifier.to_virtual_store_name())
+            .join(virtual_store_name(package_specifier, patched_dependencies))
             .join("node_modules");
         fs::create_dir_all(&virtual_node_modules_dir).map_err(|error| {
             CreateVirtualDirError::CreateNodeModulesDir {
@@ -57,14 +73,32 @@ impl<'a> CreateVirtualDirBySnapshot<'a> {
         })?;
 
         // 1. Install the files from `cas_paths`
-        let save_path =
-            virtual_node_modules_dir.join(dependency_path.package_specifier.name.to_string());
+        let save_path = virtual_node_modules_dir.join(package_specifier.name.to_string());
+        let is_imported = save_path.exists();
         create_cas_files(import_method, &save_path, cas_paths)
             .map_err(CreateVirtualDirError::CreateCasFiles)?;
 
-        // 2. Create the symlink layout
+        // 2. Apply the patch from `patchedDependencies`
+        if let (false, Some(patch)) =
+            (is_imported, find_patch(patched_dependencies, package_specifier))
+        {
+            apply_patch(&save_path, &lockfile_dir.join(&patch.path)).map_err(|error| {
+                // let the next install start over instead of leaving a partially patched package
+                if let Err(error) = fs::remove_dir_all(&save_path) {
+                    tracing::warn!(target: "pacquet::install", ?error, ?save_path, "Failed to remove the partially patched package");
+                }
+                CreateVirtualDirError::ApplyPatch(error)
+            })?;
+        }
+
+        // 3. Create the symlink layout
         if let Some(dependencies) = &package_snapshot.dependencies {
-            create_symlink_layout(dependencies, virtual_store_dir, &virtual_node_modules_dir)
+            create_symlink_layout(
+                dependencies,
+                virtual_store_dir,
+                &virtual_node_modules_dir,
+                patched_dependencies,
+            )
         }
 
         Ok(())
@@ -1,24 +1,41 @@
 use crate::InstallPackageBySnapshot;
 use futures_util::future;
-use pacquet_lockfile::{DependencyPath, PackageSnapshot, RootProjectSnapshot};
+use pacquet_lockfile::{DependencyPath, PackageSnapshot, PatchInfo, RootProjectSnapshot};
 use pacquet_network::ThrottledClient;
 use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::DependencyGroup;
 use pipe_trait::Pipe;
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
 /// This subroutine generates filesystem layout for the virtual store at `node_modules/.pacquet`.
+///
+/// Packages that aren't required by any of [`Self::dependency_groups`] are skipped.
+///
+/// Packages listed in [`Self::patched_dependencies`] are patched with the patch files,
+/// whose paths are relative to [`Self::lockfile_dir`].
 #[must_use]
 pub struct CreateVirtualStore<'a> {
     pub http_client: &'a ThrottledClient,
     pub config: &'static Npmrc,
     pub packages: Option<&'a HashMap<DependencyPath, PackageSnapshot>>,
     pub project_snapshot: &'a RootProjectSnapshot,
+    pub dependency_groups: &'a [DependencyGroup],
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    pub lockfile_dir: &'a Path,
 }
 
 impl<'a> CreateVirtualStore<'a> {
     /// Execute the subroutine.
     pub async fn run(self) {
-        let CreateVirtualStore { http_client, config, packages, project_snapshot } = self;
+        let CreateVirtualStore {
+            http_client,
+            config,
+            packages,
+            project_snapshot,
+            dependency_groups,
+            patched_dependencies,
+            lockfile_dir,
+        } = self;
 
         let packages = packages.unwrap_or_else(|| {
             dbg!(project_snapshot);
@@ -27,11 +44,19 @@ impl<'a> CreateVirtualStore<'a> {
 
         packages
             .iter()
+            .filter(|(_, package_snapshot)| package_snapshot.is_required_by(dependency_groups))
             .map(|(dependency_path, package_snapshot)| async move {
-                InstallPackageBySnapshot { http_client, config, dependency_path, package_snapshot }
-                    .run()
-                    .await
-                    .unwrap(); // TODO: properly propagate this error
+                InstallPackageBySnapshot {
+                    http_client,
+                    config,
+                    dependency_path,
+                    package_snapshot,
+                    patched_dependencies,
+                    lockfile_dir,
+                }
+                .run()
+                .await
+                .unwrap(); // TODO: properly propagate this error
             })
             .pipe(future::join_all)
             .await;
@@ -1,8 +1,15 @@
-use crate::{InstallFrozenLockfile, InstallWithoutLockfile, ResolvedPackages};
-use pacquet_lockfile::Lockfile;
+use crate::{
+    check_engines, check_package_manager, check_peer_dependencies, CheckEnginesError,
+    CreateLockfile, InstallFrozenLockfile, InstallFrozenLockfileError, InstallWithoutLockfile,
+    Overrides, ResolvedPackages, ResolvedVersions, UnmetPeerDependencies,
+};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_lockfile::{Lockfile, LockfileMismatch, SaveLockfileError};
 use pacquet_network::ThrottledClient;
 use pacquet_npmrc::Npmrc;
-use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+use pacquet_package_manifest::{DependencyGroup, PackageManifest, PackageManifestError};
+use pacquet_registry::PackageMemCache;
 use pacquet_tarball::MemCache;
 
 /// This subroutine does everything `pacquet install` is supposed to do.
@@ -13,6 +20,8 @@ where
 {
     pub tarball_mem_cache: &'a MemCache,
     pub resolved_packages: &'a ResolvedPackages,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub package_mem_cache: &'a PackageMemCache,
     pub http_client: &'a ThrottledClient,
     pub config: &'static Npmrc,
     pub manifest: &'a PackageManifest,
@@ -21,15 +30,43 @@ where
     pub frozen_lockfile: bool,
 }
 
+/// Error type of [`Install`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum InstallError {
+    #[diagnostic(transparent)]
+    CheckEngines(#[error(source)] CheckEnginesError),
+
+    #[diagnostic(transparent)]
+    ReadOverrides(#[error(source)] PackageManifestError),
+
+    #[diagnostic(transparent)]
+    ReadDependencies(#[error(source)] PackageManifestError),
+
+    #[diagnostic(transparent)]
+    SaveLockfile(#[error(source)] SaveLockfileError),
+
+    #[diagnostic(transparent)]
+    OutdatedLockfile(#[error(source)] LockfileMismatch),
+
+    #[diagnostic(transparent)]
+    UnmetPeerDependencies(#[error(source)] UnmetPeerDependencies),
+
+    #[diagnostic(transparent)]
+    InstallFrozenLockfile(#[error(source)] InstallFrozenLockfileError),
+}
+
 impl<'a, DependencyGroupList> Install<'a, DependencyGroupList>
 where
     DependencyGroupList: IntoIterator<Item = DependencyGroup>,
 {
     /// Execute the subroutine.
-    pub async fn run(self) {
+    pub async fn run(self) -> Result<(), InstallError> {
         let Install {
             tarball_mem_cache,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
             http_client,
             config,
             manifest,
@@ -40,25 +77,86 @@ where
 
         tracing::info!(target: "pacquet::install", "Start all");
 
+        check_engines(config, manifest).map_err(InstallError::CheckEngines)?;
+        check_package_manager(manifest);
+        manifest
+            .try_dependencies(DependencyGroup::all())
+            .map_err(InstallError::ReadDependencies)?;
+        let overrides = &Overrides::from_manifest(manifest).map_err(InstallError::ReadOverrides)?;
+        let lockfile_dir = manifest.path().parent().expect("manifest path has a parent");
+        let patched_dependencies =
+            lockfile.and_then(|lockfile| lockfile.patched_dependencies.as_ref());
+
         match (config.lockfile, frozen_lockfile, lockfile) {
             (false, _, _) => {
                 InstallWithoutLockfile {
                     tarball_mem_cache,
                     resolved_packages,
+                    resolved_versions,
+                    package_mem_cache,
                     http_client,
                     config,
                     manifest,
+                    overrides,
+                    patched_dependencies,
+                    lockfile_dir,
                     dependency_groups,
                 }
                 .run()
                 .await;
+
+                check_peer_dependencies(config, resolved_versions)
+                    .map_err(InstallError::UnmetPeerDependencies)?;
             }
-            (true, false, Some(_)) | (true, false, None) | (true, true, None) => {
+            (true, false, _) => {
+                let dependency_groups = dependency_groups.into_iter().collect::<Vec<_>>();
+                InstallWithoutLockfile {
+                    tarball_mem_cache,
+                    resolved_packages,
+                    resolved_versions,
+                    package_mem_cache,
+                    http_client,
+                    config,
+                    manifest,
+                    overrides,
+                    patched_dependencies,
+                    lockfile_dir,
+                    dependency_groups: dependency_groups.iter().copied(),
+                }
+                .run()
+                .await;
+
+                check_peer_dependencies(config, resolved_versions)
+                    .map_err(InstallError::UnmetPeerDependencies)?;
+
+                // A lockfile generated from a subset of the groups would lack the other groups.
+                let is_complete =
+                    [DependencyGroup::Prod, DependencyGroup::Dev, DependencyGroup::Optional]
+                        .iter()
+                        .all(|group| dependency_groups.contains(group));
+                if is_complete {
+                    CreateLockfile {
+                        config,
+                        manifest,
+                        overrides,
+                        resolved_versions,
+                        previous_lockfile: lockfile,
+                    }
+                    .run()
+                    .write_to(lockfile_dir)
+                    .map_err(InstallError::SaveLockfile)?;
+                } else {
+                    tracing::warn!(target: "pacquet::install", "Skip writing the lockfile because not every dependency group was installed");
+                }
+            }
+            (true, true, None) => {
                 unimplemented!();
             }
             (true, true, Some(lockfile)) => {
-                let Lockfile { lockfile_version, project_snapshot, packages, .. } = lockfile;
-                assert_eq!(lockfile_version.major, 6); // compatibility check already happens at serde, but this still helps preventing programmer mistakes.
+                lockfile.satisfies(manifest).map_err(InstallError::OutdatedLockfile)?;
+
+                // compatibility of `lockfileVersion` was already checked when the lockfile was loaded.
+                let Lockfile { project_snapshot, packages, .. } = lockfile;
 
                 InstallFrozenLockfile {
                     http_client,
@@ -66,24 +164,34 @@ where
                     project_snapshot,
                     packages: packages.as_ref(),
                     dependency_groups,
+                    patched_dependencies,
+                    lockfile_dir,
                 }
                 .run()
-                .await;
+                .await
+                .map_err(InstallError::InstallFrozenLockfile)?;
             }
         }
 
         tracing::info!(target: "pacquet::install", "Complete all");
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pacquet_lockfile::RootProjectSnapshot;
     use pacquet_npmrc::Npmrc;
-    use pacquet_package_manifest::{DependencyGroup, PackageManifest};
-    use pacquet_registry_mock::AutoMockInstance;
-    use pacquet_testing_utils::fs::{get_all_folders, is_symlink_or_junction};
-    use std::env;
+    use pacquet_package_manifest::{DependencyGroup, PackageManifest, PackageManifestError};
+    use pacquet_registry_mock::{AutoMockInstance, CustomRegistry};
+    use pacquet_testing_utils::{
+        fs::{get_all_folders, get_filenames_in_folder, is_symlink_or_junction},
+        tarball::TarballBuilder,
+    };
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::{env, fs};
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -126,9 +234,12 @@ mod tests {
             ],
             frozen_lockfile: false,
             resolved_packages: &Default::default(),
+            resolved_versions: &Default::default(),
+            package_mem_cache: &Default::default(),
         }
         .run()
-        .await;
+        .await
+        .unwrap();
 
         // Make sure the package is installed
         let path = project_root.join("node_modules/@pnpm.e2e/hello-world-js-bin");
@@ -145,4 +256,241 @@ mod tests {
 
         drop((dir, mock_instance)); // cleanup
     }
+
+    #[tokio::test]
+    async fn should_write_lockfile() {
+        let mock_instance = AutoMockInstance::load_or_init();
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest
+            .add_dependency("@pnpm.e2e/hello-world-js-bin", "1.0.0", DependencyGroup::Prod)
+            .unwrap();
+        manifest.add_dependency("@pnpm/xyz", "1.0.0", DependencyGroup::Dev).unwrap();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir;
+        config.registry = mock_instance.url();
+        config.lockfile = true;
+        let config = config.leak();
+
+        let tarball_mem_cache = MemCache::default();
+        let http_client = ThrottledClient::default();
+        let resolved_packages = ResolvedPackages::default();
+        let resolved_versions = ResolvedVersions::default();
+        let package_mem_cache = PackageMemCache::default();
+        let install = |dependency_groups: Vec<DependencyGroup>| Install {
+            tarball_mem_cache: &tarball_mem_cache,
+            http_client: &http_client,
+            config,
+            manifest: &manifest,
+            lockfile: None,
+            dependency_groups,
+            frozen_lockfile: false,
+            resolved_packages: &resolved_packages,
+            resolved_versions: &resolved_versions,
+            package_mem_cache: &package_mem_cache,
+        };
+        let lockfile_path = dir.path().join("pnpm-lock.yaml");
+
+        eprintln!("CASE: a subset of the groups doesn't write the lockfile");
+        install(vec![DependencyGroup::Prod]).run().await.unwrap();
+        assert!(!lockfile_path.exists());
+
+        eprintln!("CASE: all groups write the lockfile");
+        install(vec![DependencyGroup::Prod, DependencyGroup::Dev, DependencyGroup::Optional])
+            .run()
+            .await
+            .unwrap();
+        let lockfile: Lockfile =
+            serde_yaml::from_str(&fs::read_to_string(&lockfile_path).unwrap()).unwrap();
+        dbg!(&lockfile);
+        let RootProjectSnapshot::Single(project_snapshot) = &lockfile.project_snapshot else {
+            panic!("expected a single project snapshot");
+        };
+        let dependencies_of = |group| {
+            project_snapshot
+                .get_map_by_group(group)
+                .into_iter()
+                .flatten()
+                .map(|(name, spec)| format!("{name}@{}", spec.version))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dependencies_of(DependencyGroup::Prod), ["@pnpm.e2e/hello-world-js-bin@1.0.0"]);
+        assert_eq!(dependencies_of(DependencyGroup::Dev), ["@pnpm/xyz@1.0.0"]);
+        let packages = lockfile.packages.as_ref().unwrap();
+        let snapshot = &packages[&"/@pnpm.e2e/hello-world-js-bin@1.0.0".parse().unwrap()];
+        assert_eq!(snapshot.dev, Some(false));
+        let snapshot = &packages[&"/@pnpm/xyz@1.0.0".parse().unwrap()];
+        assert_eq!(snapshot.dev, Some(true));
+
+        drop((dir, mock_instance)); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_update_existing_lockfile() {
+        let mock_instance = AutoMockInstance::load_or_init();
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest
+            .add_dependency("@pnpm.e2e/hello-world-js-bin", "1.0.0", DependencyGroup::Prod)
+            .unwrap();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir;
+        config.registry = mock_instance.url();
+        config.lockfile = true;
+        let config = config.leak();
+
+        let lockfile_path = dir.path().join("pnpm-lock.yaml");
+        let existing_content = "lockfileVersion: '6.0'\n\
+            neverBuiltDependencies:\n  - foo\n\
+            patchedDependencies:\n  foo@1.0.0:\n    hash: HASH\n    path: patches/foo@1.0.0.patch\n\
+            dependencies:\n  '@pnpm.e2e/hello-world-js-bin':\n    specifier: 1.0.0\n    version: 1.0.0\n";
+        let existing: Lockfile = serde_yaml::from_str(existing_content).unwrap();
+
+        let tarball_mem_cache = MemCache::default();
+        let http_client = ThrottledClient::default();
+        let resolved_packages = ResolvedPackages::default();
+        let resolved_versions = ResolvedVersions::default();
+        let package_mem_cache = PackageMemCache::default();
+        let install = || Install {
+            tarball_mem_cache: &tarball_mem_cache,
+            http_client: &http_client,
+            config,
+            manifest: &manifest,
+            lockfile: Some(&existing),
+            dependency_groups: [
+                DependencyGroup::Prod,
+                DependencyGroup::Dev,
+                DependencyGroup::Optional,
+            ],
+            frozen_lockfile: false,
+            resolved_packages: &resolved_packages,
+            resolved_versions: &resolved_versions,
+            package_mem_cache: &package_mem_cache,
+        };
+
+        eprintln!("CASE: the fields that aren't resolved are carried over");
+        fs::write(&lockfile_path, existing_content).unwrap();
+        install().run().await.unwrap();
+        let lockfile: Lockfile =
+            serde_yaml::from_str(&fs::read_to_string(&lockfile_path).unwrap()).unwrap();
+        dbg!(&lockfile);
+        assert_eq!(lockfile.never_built_dependencies, existing.never_built_dependencies);
+        assert_eq!(lockfile.patched_dependencies, existing.patched_dependencies);
+        assert!(lockfile.packages.is_some());
+
+        eprintln!("CASE: a v9 lockfile isn't downgraded");
+        let v9_content = "lockfileVersion: '9.0'\n\nimporters:\n  .: {}\n";
+        fs::write(&lockfile_path, v9_content).unwrap();
+        let error = install().run().await.unwrap_err();
+        dbg!(&error);
+        assert!(matches!(
+            error,
+            InstallError::SaveLockfile(SaveLockfileError::DowngradeVersion(_)),
+        ));
+        assert_eq!(fs::read_to_string(&lockfile_path).unwrap(), v9_content);
+
+        drop((dir, mock_instance)); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_apply_patches_without_frozen_lockfile() {
+        let mut registry = CustomRegistry::new().await;
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{"name":"foo","version":"1.0.0"}"#)
+            .file("package/index.js", "module.exports = 'original'\n")
+            .build();
+        let integrity = tarball.integrity.to_string();
+        let tarball_url = registry.add_tarball("foo-1.0.0.tgz", tarball.data).await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": {
+                    "name": "foo",
+                    "version": "1.0.0",
+                    "dist": { "integrity": integrity, "tarball": tarball_url },
+                },
+            },
+        });
+        registry.add_packument("foo", &packument).await;
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        fs::create_dir_all(dir.path().join("patches")).unwrap();
+        fs::write(
+            dir.path().join("patches/foo@1.0.0.patch"),
+            "--- a/index.js\n+++ b/index.js\n@@ -1 +1 @@\n-module.exports = 'original'\n+module.exports = 'patched'\n",
+        )
+        .unwrap();
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("foo", "1.0.0", DependencyGroup::Prod).unwrap();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir.clone();
+        config.registry = registry.url();
+        config.lockfile = true;
+        let config = config.leak();
+
+        let existing: Lockfile = serde_yaml::from_str(
+            "lockfileVersion: '6.0'\n\
+             patchedDependencies:\n  foo@1.0.0:\n    hash: HASH\n    path: patches/foo@1.0.0.patch\n\
+             dependencies:\n  foo:\n    specifier: 1.0.0\n    version: 1.0.0\n",
+        )
+        .unwrap();
+
+        Install {
+            tarball_mem_cache: &Default::default(),
+            http_client: &Default::default(),
+            config,
+            manifest: &manifest,
+            lockfile: Some(&existing),
+            dependency_groups: [
+                DependencyGroup::Prod,
+                DependencyGroup::Dev,
+                DependencyGroup::Optional,
+            ],
+            frozen_lockfile: false,
+            resolved_packages: &Default::default(),
+            resolved_versions: &Default::default(),
+            package_mem_cache: &Default::default(),
+        }
+        .run()
+        .await
+        .unwrap();
+
+        eprintln!("The package should be patched in its own virtual directory");
+        let content = fs::read_to_string(modules_dir.join("foo/index.js")).unwrap();
+        assert_eq!(content, "module.exports = 'patched'\n");
+        assert_eq!(
+            get_filenames_in_folder(&config.virtual_store_dir),
+            ["foo@1.0.0_patch_hash=HASH"],
+        );
+
+        eprintln!("The generated lockfile should record the patch");
+        let lockfile: Lockfile =
+            serde_yaml::from_str(&fs::read_to_string(dir.path().join("pnpm-lock.yaml")).unwrap())
+                .unwrap();
+        dbg!(&lockfile);
+        assert_eq!(lockfile.patched_dependencies, existing.patched_dependencies);
+
+        drop((dir, registry)); // cleanup
+    }
 }
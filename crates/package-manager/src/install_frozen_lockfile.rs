@@ -1,9 +1,17 @@
-use crate::{CreateVirtualStore, SymlinkDirectDependencies};
-use pacquet_lockfile::{DependencyPath, PackageSnapshot, RootProjectSnapshot};
+use crate::{CreateVirtualStore, SymlinkDirectDependencies, SymlinkDirectDependenciesError};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_lockfile::{DependencyPath, PackageSnapshot, PatchInfo, RootProjectSnapshot};
 use pacquet_network::ThrottledClient;
-use pacquet_npmrc::Npmrc;
+use pacquet_npmrc::{Npmrc, PackageImportMethod};
 use pacquet_package_manifest::DependencyGroup;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
 
 /// This subroutine installs dependencies from a frozen lockfile.
 ///
@@ -12,8 +20,11 @@ use std::collections::HashMap;
 /// * Fetch a tarball of each package.
 /// * Extract each tarball into the store directory.
 /// * Import (by reflink, hardlink, or copy) the files from the store dir to each `node_modules/.pacquet/{name}@{version}/node_modules/{name}/`.
+/// * Apply the patches from [`Self::patched_dependencies`].
 /// * Create dependency symbolic links in each `node_modules/.pacquet/{name}@{version}/node_modules/`.
 /// * Create a symbolic link at each `node_modules/{name}`.
+/// * Record the hash of the lockfile and the settings in `node_modules/.pacquet-state.yaml`,
+///   so that the next install can be skipped if nothing changed and the direct dependencies are still linked.
 #[must_use]
 pub struct InstallFrozenLockfile<'a, DependencyGroupList>
 where
@@ -24,6 +35,16 @@ where
     pub project_snapshot: &'a RootProjectSnapshot,
     pub packages: Option<&'a HashMap<DependencyPath, PackageSnapshot>>,
     pub dependency_groups: DependencyGroupList,
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    pub lockfile_dir: &'a Path,
+}
+
+/// Error type of [`InstallFrozenLockfile`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum InstallFrozenLockfileError {
+    #[diagnostic(transparent)]
+    SymlinkDirectDependencies(#[error(source)] SymlinkDirectDependenciesError),
 }
 
 impl<'a, DependencyGroupList> InstallFrozenLockfile<'a, DependencyGroupList>
@@ -31,21 +52,330 @@ where
     DependencyGroupList: IntoIterator<Item = DependencyGroup>,
 {
     /// Execute the subroutine.
-    pub async fn run(self) {
+    pub async fn run(self) -> Result<(), InstallFrozenLockfileError> {
         let InstallFrozenLockfile {
             http_client,
             config,
             project_snapshot,
             packages,
             dependency_groups,
+            patched_dependencies,
+            lockfile_dir,
         } = self;
 
         // TODO: check if the lockfile is out-of-date
 
         assert!(config.prefer_frozen_lockfile, "Non frozen lockfile is not yet supported");
 
-        CreateVirtualStore { http_client, config, packages, project_snapshot }.run().await;
+        let dependency_groups = dependency_groups.into_iter().collect::<Vec<_>>();
+
+        let modules_state = ModulesState {
+            lockfile_hash: lockfile_hash(project_snapshot, packages, patched_dependencies),
+            included: dependency_groups
+                .iter()
+                .copied()
+                .map(<&str>::from)
+                .map(String::from)
+                .collect(),
+            store_dir: config.store_dir.display().to_string(),
+            virtual_store_dir: config.virtual_store_dir.clone(),
+            package_import_method: config.package_import_method,
+        };
+        if config.virtual_store_dir.is_dir()
+            && ModulesState::load(config).as_ref() == Some(&modules_state)
+            && are_direct_dependencies_linked(config, project_snapshot, &dependency_groups)
+        {
+            tracing::info!(target: "pacquet::install", "node_modules is up-to-date, skip");
+            return Ok(());
+        }
+
+        CreateVirtualStore {
+            http_client,
+            config,
+            packages,
+            project_snapshot,
+            dependency_groups: &dependency_groups,
+            patched_dependencies,
+            lockfile_dir,
+        }
+        .run()
+        .await;
+
+        SymlinkDirectDependencies {
+            config,
+            project_snapshot,
+            dependency_groups: dependency_groups.iter().copied(),
+            patched_dependencies,
+        }
+        .run()
+        .map_err(InstallFrozenLockfileError::SymlinkDirectDependencies)?;
+
+        modules_state.save(config);
+        Ok(())
+    }
+}
+
+/// Content of the modules state file at [`ModulesState::path`], which records the last
+/// frozen install so that the next install can be skipped if nothing changed.
+///
+/// Its schema is specific to pacquet, so it isn't named after the `.modules.yaml` of pnpm.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModulesState {
+    /// Hash of the installed parts of the lockfile, see [`lockfile_hash`].
+    lockfile_hash: String,
+    /// Installed dependency groups.
+    included: Vec<String>,
+    store_dir: String,
+    virtual_store_dir: PathBuf,
+    package_import_method: PackageImportMethod,
+}
+
+impl ModulesState {
+    /// Path to the file in the `node_modules` directory.
+    fn path(config: &Npmrc) -> PathBuf {
+        config.modules_dir.join(".pacquet-state.yaml")
+    }
+
+    /// Load the state of the last install, if any.
+    fn load(config: &Npmrc) -> Option<Self> {
+        let content = fs::read_to_string(ModulesState::path(config)).ok()?;
+        serde_yaml::from_str(&content).ok()
+    }
+
+    /// Record the state after a successful install.
+    fn save(&self, config: &Npmrc) {
+        let content = serde_yaml::to_string(self).expect("serialize the modules state");
+        if let Err(error) = fs::write(ModulesState::path(config), content) {
+            tracing::warn!(target: "pacquet::install", ?error, "Failed to record the modules state");
+        }
+    }
+}
+
+/// Hash of the parts of the lockfile that [`InstallFrozenLockfile`] installs.
+///
+/// The maps are sorted so that the hash doesn't depend on the iteration order of [`HashMap`].
+fn lockfile_hash(
+    project_snapshot: &RootProjectSnapshot,
+    packages: Option<&HashMap<DependencyPath, PackageSnapshot>>,
+    patched_dependencies: Option<&HashMap<String, PatchInfo>>,
+) -> String {
+    let packages = packages.map(|packages| {
+        packages
+            .iter()
+            .map(|(path, snapshot)| (path.to_string(), snapshot))
+            .collect::<BTreeMap<_, _>>()
+    });
+    let patched_dependencies =
+        patched_dependencies.map(|patches| patches.iter().collect::<BTreeMap<_, _>>());
+    let content = serde_yaml::to_string(&(project_snapshot, packages, patched_dependencies))
+        .expect("serialize the lockfile");
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Check whether the direct dependencies in `dependency_groups` are linked into `node_modules`.
+fn are_direct_dependencies_linked(
+    config: &Npmrc,
+    project_snapshot: &RootProjectSnapshot,
+    dependency_groups: &[DependencyGroup],
+) -> bool {
+    let RootProjectSnapshot::Single(project_snapshot) = project_snapshot else {
+        return false;
+    };
+    project_snapshot
+        .dependencies_by_groups(dependency_groups.iter().copied())
+        .all(|(name, _)| config.modules_dir.join(name.to_string()).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_lockfile::Lockfile;
+    use pacquet_registry_mock::CustomRegistry;
+    use pacquet_testing_utils::{fs::get_filenames_in_folder, tarball::TarballBuilder};
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn should_skip_dev_dependencies_with_prod() {
+        let mut registry = CustomRegistry::new().await;
+        let mut package_entries = String::new();
+        for (name, dev) in [("prod-pkg", false), ("dev-pkg", true)] {
+            let tarball = TarballBuilder::new()
+                .file("package/package.json", format!(r#"{{"name":"{name}","version":"1.0.0"}}"#))
+                .build();
+            let integrity = tarball.integrity.to_string();
+            let url = registry.add_tarball(&format!("{name}-1.0.0.tgz"), tarball.data).await;
+            package_entries += &format!(
+                "  /{name}@1.0.0:\n    resolution: {{integrity: {integrity}, tarball: '{url}'}}\n    dev: {dev}\n"
+            );
+        }
+        let lockfile = format!(
+            "lockfileVersion: '6.0'\n\
+             dependencies:\n  prod-pkg:\n    specifier: 1.0.0\n    version: 1.0.0\n\
+             devDependencies:\n  dev-pkg:\n    specifier: 1.0.0\n    version: 1.0.0\n\
+             packages:\n{package_entries}"
+        );
+        eprintln!("LOCKFILE:\n{lockfile}");
+        let Lockfile { project_snapshot, packages, .. } = serde_yaml::from_str(&lockfile).unwrap();
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir.clone();
+        let config = config.leak();
+
+        InstallFrozenLockfile {
+            http_client: &Default::default(),
+            config,
+            project_snapshot: &project_snapshot,
+            packages: packages.as_ref(),
+            dependency_groups: [DependencyGroup::Prod, DependencyGroup::Optional],
+            patched_dependencies: None,
+            lockfile_dir: dir.path(),
+        }
+        .run()
+        .await
+        .unwrap();
+
+        assert_eq!(
+            get_filenames_in_folder(&modules_dir),
+            [".pacquet", ".pacquet-state.yaml", "prod-pkg"],
+        );
+        assert_eq!(get_filenames_in_folder(&modules_dir.join(".pacquet")), ["prod-pkg@1.0.0"]);
+
+        drop((dir, registry)); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_skip_when_node_modules_is_up_to_date() {
+        let mut registry = CustomRegistry::new().await;
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{"name":"foo","version":"1.0.0"}"#)
+            .build();
+        let integrity = tarball.integrity.to_string();
+        let url = registry.add_tarball("foo-1.0.0.tgz", tarball.data).await;
+        let lockfile = format!(
+            "lockfileVersion: '6.0'\n\
+             dependencies:\n  foo:\n    specifier: 1.0.0\n    version: 1.0.0\n\
+             packages:\n  /foo@1.0.0:\n    resolution: {{integrity: {integrity}, tarball: '{url}'}}\n"
+        );
+        eprintln!("LOCKFILE:\n{lockfile}");
+        let Lockfile { project_snapshot, packages, .. } = serde_yaml::from_str(&lockfile).unwrap();
+
+        let dir = tempdir().unwrap();
+        let store_dir = dir.path().join("pacquet-store");
+        let modules_dir = dir.path().join("node_modules");
+        let mut config = Npmrc::new();
+        config.store_dir = store_dir.clone().into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir.clone();
+        let config = config.leak();
+
+        let http_client = ThrottledClient::default();
+        let install = || InstallFrozenLockfile {
+            http_client: &http_client,
+            config,
+            project_snapshot: &project_snapshot,
+            packages: packages.as_ref(),
+            dependency_groups: [DependencyGroup::Prod, DependencyGroup::Optional],
+            patched_dependencies: None,
+            lockfile_dir: dir.path(),
+        };
+
+        eprintln!("First install");
+        install().run().await.unwrap();
+        assert!(modules_dir.join("foo").exists());
+
+        eprintln!("A missing direct dependency is linked again");
+        pacquet_fs::remove_symlink_dir(&modules_dir.join("foo")).unwrap();
+        install().run().await.unwrap();
+        assert!(modules_dir.join("foo").exists());
+
+        eprintln!("Make sure that any download attempt would fail");
+        drop(registry);
+        fs::remove_dir_all(&store_dir).unwrap();
+
+        eprintln!("Second install");
+        install().run().await.unwrap();
+        assert!(modules_dir.join("foo").exists());
+        assert!(!store_dir.exists());
+
+        drop(dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_apply_patched_dependencies() {
+        let mut registry = CustomRegistry::new().await;
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{"name":"foo","version":"1.0.0"}"#)
+            .file("package/index.js", "module.exports = 'original'\n")
+            .build();
+        let integrity = tarball.integrity.to_string();
+        let url = registry.add_tarball("foo-1.0.0.tgz", tarball.data).await;
+        let lockfile = format!(
+            "lockfileVersion: '6.0'\n\
+             patchedDependencies:\n  foo@1.0.0:\n    hash: HASH\n    path: patches/foo@1.0.0.patch\n\
+             dependencies:\n  foo:\n    specifier: 1.0.0\n    version: 1.0.0\n\
+             packages:\n  /foo@1.0.0:\n    resolution: {{integrity: {integrity}, tarball: '{url}'}}\n"
+        );
+        eprintln!("LOCKFILE:\n{lockfile}");
+        let Lockfile { project_snapshot, packages, patched_dependencies, .. } =
+            serde_yaml::from_str(&lockfile).unwrap();
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        fs::create_dir_all(dir.path().join("patches")).unwrap();
+        fs::write(
+            dir.path().join("patches/foo@1.0.0.patch"),
+            "--- a/index.js\n+++ b/index.js\n@@ -1 +1 @@\n-module.exports = 'original'\n+module.exports = 'patched'\n",
+        )
+        .unwrap();
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir.clone();
+        let config = config.leak();
+
+        let http_client = ThrottledClient::default();
+        let install = |patched_dependencies| InstallFrozenLockfile {
+            http_client: &http_client,
+            config,
+            project_snapshot: &project_snapshot,
+            packages: packages.as_ref(),
+            dependency_groups: [DependencyGroup::Prod],
+            patched_dependencies,
+            lockfile_dir: dir.path(),
+        };
+
+        install(patched_dependencies.as_ref()).run().await.unwrap();
+        let content = fs::read_to_string(modules_dir.join("foo/index.js")).unwrap();
+        assert_eq!(content, "module.exports = 'patched'\n");
+        assert_eq!(
+            get_filenames_in_folder(&modules_dir.join(".pacquet")),
+            ["foo@1.0.0_patch_hash=HASH"],
+        );
+
+        eprintln!("CASE: reinstalling doesn't apply the patch twice");
+        fs::remove_file(modules_dir.join(".pacquet-state.yaml")).unwrap();
+        install(patched_dependencies.as_ref()).run().await.unwrap();
+        let content = fs::read_to_string(modules_dir.join("foo/index.js")).unwrap();
+        assert_eq!(content, "module.exports = 'patched'\n");
+
+        eprintln!("CASE: a changed patch is applied to a fresh directory");
+        fs::write(
+            dir.path().join("patches/foo@1.0.0.patch"),
+            "--- a/index.js\n+++ b/index.js\n@@ -1 +1 @@\n-module.exports = 'original'\n+module.exports = 'patched again'\n",
+        )
+        .unwrap();
+        let mut changed_patches = patched_dependencies.clone().unwrap();
+        changed_patches.get_mut("foo@1.0.0").unwrap().hash = "CHANGED".to_string();
+        install(Some(&changed_patches)).run().await.unwrap();
+        let content = fs::read_to_string(modules_dir.join("foo/index.js")).unwrap();
+        assert_eq!(content, "module.exports = 'patched again'\n");
 
-        SymlinkDirectDependencies { config, project_snapshot, dependency_groups }.run();
+        drop((dir, registry)); // cleanup
     }
 }
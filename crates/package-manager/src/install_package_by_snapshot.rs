@@ -1,12 +1,14 @@
-use crate::{CreateVirtualDirBySnapshot, CreateVirtualDirError};
+use crate::{tarball_retry_opts, CreateVirtualDirBySnapshot, CreateVirtualDirError};
 use derive_more::{Display, Error};
 use miette::Diagnostic;
-use pacquet_lockfile::{DependencyPath, LockfileResolution, PackageSnapshot, PkgNameVerPeer};
+use pacquet_lockfile::{
+    DependencyPath, LockfileResolution, PackageSnapshot, PatchInfo, PkgNameVerPeer,
+};
 use pacquet_network::ThrottledClient;
 use pacquet_npmrc::Npmrc;
-use pacquet_tarball::{DownloadTarballToStore, TarballError};
+use pacquet_tarball::{DownloadTarballToStore, TarballError, UnpackedSizeLimit};
 use pipe_trait::Pipe;
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, path::Path};
 
 /// This subroutine downloads a package tarball, extracts it, installs it to a virtual dir,
 /// then creates the symlink layout for the package.
@@ -16,6 +18,8 @@ pub struct InstallPackageBySnapshot<'a> {
     pub config: &'static Npmrc,
     pub dependency_path: &'a DependencyPath,
     pub package_snapshot: &'a PackageSnapshot,
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    pub lockfile_dir: &'a Path,
 }
 
 /// Error type of [`InstallPackageBySnapshot`].
@@ -28,8 +32,14 @@ pub enum InstallPackageBySnapshotError {
 impl<'a> InstallPackageBySnapshot<'a> {
     /// Execute the subroutine.
     pub async fn run(self) -> Result<(), InstallPackageBySnapshotError> {
-        let InstallPackageBySnapshot { http_client, config, dependency_path, package_snapshot } =
-            self;
+        let InstallPackageBySnapshot {
+            http_client,
+            config,
+            dependency_path,
+            package_snapshot,
+            patched_dependencies,
+            lockfile_dir,
+        } = self;
         let PackageSnapshot { resolution, .. } = package_snapshot;
         let DependencyPath { custom_registry, package_specifier } = dependency_path;
 
@@ -42,9 +52,12 @@ impl<'a> InstallPackageBySnapshot<'a> {
                 (tarball_resolution.tarball.as_str().pipe(Cow::Borrowed), integrity)
             }
             LockfileResolution::Registry(registry_resolution) => {
-                let registry = custom_registry.as_ref().unwrap_or(&config.registry);
-                let registry = registry.strip_suffix('/').unwrap_or(registry);
                 let PkgNameVerPeer { name, suffix: ver_peer } = package_specifier;
+                let registry = match custom_registry {
+                    Some(registry) => registry.as_str(),
+                    None => config.registry_for(&name.to_string()),
+                };
+                let registry = registry.strip_suffix('/').unwrap_or(registry);
                 let version = ver_peer.version();
                 let bare_name = name.bare.as_str();
                 let tarball_url = format!("{registry}/{name}/-/{bare_name}-{version}.tgz");
@@ -56,13 +69,15 @@ impl<'a> InstallPackageBySnapshot<'a> {
             }
         };
 
-        // TODO: skip when already exists in store?
         let cas_paths = DownloadTarballToStore {
             http_client,
             store_dir: &config.store_dir,
             package_integrity: integrity,
             package_unpacked_size: None,
             package_url: &tarball_url,
+            retry_opts: tarball_retry_opts(config),
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
         }
         .run_without_mem_cache()
         .await
@@ -74,6 +89,8 @@ impl<'a> InstallPackageBySnapshot<'a> {
             import_method: config.package_import_method,
             dependency_path,
             package_snapshot,
+            patched_dependencies,
+            lockfile_dir,
         }
         .run()
         .map_err(InstallPackageBySnapshotError::CreateVirtualDir)?;
@@ -1,16 +1,32 @@
-use crate::{create_cas_files, symlink_package, CreateCasFilesError, SymlinkPackageError};
+use crate::{
+    apply_patch, create_cas_files, find_package_patch, link_bins, metadata_cache,
+    package_virtual_store_name, symlink_package, tarball_retry_opts, ApplyPatchError,
+    CreateCasFilesError, LinkBinsError, SymlinkPackageError,
+};
+use dashmap::DashMap;
 use derive_more::{Display, Error};
 use miette::Diagnostic;
+use pacquet_lockfile::PatchInfo;
 use pacquet_network::ThrottledClient;
 use pacquet_npmrc::Npmrc;
-use pacquet_registry::{Package, PackageTag, PackageVersion, RegistryError};
-use pacquet_tarball::{DownloadTarballToStore, MemCache, TarballError};
-use std::{path::Path, str::FromStr};
+use pacquet_registry::{Package, PackageMemCache, PackageTag, PackageVersion, RegistryError};
+use pacquet_tarball::{DownloadTarballToStore, MemCache, TarballError, UnpackedSizeLimit};
+use std::{collections::HashMap, fs, path::Path, str::FromStr, sync::Arc};
+use tokio::sync::OnceCell;
+
+/// In-memory cache for version ranges that have been resolved.
+///
+/// The key is a pair of the package name and the version range as written in a manifest.
+/// e.g. `("@pnpm.e2e/dep-1", "^1.0.0")` → `@pnpm.e2e/dep-1@1.0.0`
+pub type ResolvedVersions = DashMap<(String, String), Arc<OnceCell<PackageVersion>>>;
 
 /// This subroutine executes the following and returns the package
 /// * Retrieves the package from the registry
 /// * Extracts the tarball to global store directory (~/Library/../pacquet)
 /// * Links global store directory to virtual dir (node_modules/.pacquet/..)
+/// * Applies the patch of the package from `patched_dependencies` if any, whose path is
+///   relative to `lockfile_dir`
+/// * Links the executables of the package into the `.bin` directory of `node_modules_dir`
 ///
 /// `symlink_path` will be appended by the name of the package. Therefore,
 /// it should be resolved into the node_modules folder of a subdependency such as
@@ -18,11 +34,15 @@ use std::{path::Path, str::FromStr};
 #[must_use]
 pub struct InstallPackageFromRegistry<'a> {
     pub tarball_mem_cache: &'a MemCache,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub package_mem_cache: &'a PackageMemCache,
     pub http_client: &'a ThrottledClient,
     pub config: &'static Npmrc,
     pub node_modules_dir: &'a Path,
     pub name: &'a str,
     pub version_range: &'a str,
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    pub lockfile_dir: &'a Path,
 }
 
 /// Error type of [`InstallPackageFromRegistry`].
@@ -32,6 +52,8 @@ pub enum InstallPackageFromRegistryError {
     DownloadTarballToStore(#[error(source)] TarballError),
     CreateCasFiles(#[error(source)] CreateCasFilesError),
     SymlinkPackage(#[error(source)] SymlinkPackageError),
+    LinkBins(#[error(source)] LinkBinsError),
+    ApplyPatch(#[error(source)] ApplyPatchError),
 }
 
 impl<'a> InstallPackageFromRegistry<'a> {
@@ -40,26 +62,66 @@ impl<'a> InstallPackageFromRegistry<'a> {
     where
         Tag: FromStr + Into<PackageTag>,
     {
-        let &InstallPackageFromRegistry { http_client, config, name, version_range, .. } = &self;
+        let &InstallPackageFromRegistry { resolved_versions, name, version_range, .. } = &self;
+
+        // The same name and range may appear in many places of the dependency graph,
+        // they are only resolved once.
+        let resolved_version = Arc::clone(
+            &resolved_versions.entry((name.to_string(), version_range.to_string())).or_default(),
+        );
+        let package_version = resolved_version
+            .get_or_try_init(|| self.resolve_package_version::<Tag>())
+            .await?
+            .clone();
+
+        self.install_package_version(&package_version).await?;
+        Ok(package_version)
+    }
+
+    /// Find the package version that matches the version range or tag from the registry.
+    async fn resolve_package_version<Tag>(
+        &self,
+    ) -> Result<PackageVersion, InstallPackageFromRegistryError>
+    where
+        Tag: FromStr + Into<PackageTag>,
+    {
+        let &InstallPackageFromRegistry {
+            package_mem_cache,
+            http_client,
+            config,
+            name,
+            version_range,
+            ..
+        } = self;
+
+        tracing::info!(target: "pacquet::resolve", ?name, ?version_range, "Resolve package version");
+
+        // The packument is shared by every range and tag of the package.
+        let package = Package::fetch_from_registry_with_mem_cache(
+            name,
+            http_client,
+            config.registry_for(name),
+            Some(&metadata_cache(config)),
+            package_mem_cache,
+        )
+        .await
+        .map_err(InstallPackageFromRegistryError::FetchFromRegistry)?;
 
         Ok(if let Ok(tag) = version_range.parse::<Tag>() {
-            let package_version = PackageVersion::fetch_from_registry(
-                name,
-                tag.into(),
-                http_client,
-                &config.registry,
-            )
-            .await
-            .map_err(InstallPackageFromRegistryError::FetchFromRegistry)?;
-            self.install_package_version(&package_version).await?;
-            package_version
+            let version = match Into::<PackageTag>::into(tag) {
+                PackageTag::Latest => package.dist_tag("latest"),
+                PackageTag::Version(version) => package.versions.get(&version.to_string()),
+            };
+            let Some(version) = version else {
+                let error = RegistryError::MissingVersionRelease(
+                    version_range.to_string(),
+                    name.to_string(),
+                );
+                return Err(InstallPackageFromRegistryError::FetchFromRegistry(error));
+            };
+            version.clone()
         } else {
-            let package = Package::fetch_from_registry(name, http_client, &config.registry)
-                .await
-                .map_err(InstallPackageFromRegistryError::FetchFromRegistry)?;
-            let package_version = package.pinned_version(version_range).unwrap(); // TODO: propagate error for when no version satisfies range
-            self.install_package_version(package_version).await?;
-            package_version.clone()
+            package.pinned_version(version_range).unwrap().clone() // TODO: propagate error for when no version satisfies range
         })
     }
 
@@ -72,22 +134,25 @@ impl<'a> InstallPackageFromRegistry<'a> {
             http_client,
             config,
             node_modules_dir,
+            patched_dependencies,
+            lockfile_dir,
             ..
         } = self;
 
-        let store_folder_name = package_version.to_virtual_store_name();
+        let store_folder_name = package_virtual_store_name(package_version, patched_dependencies);
 
-        // TODO: skip when it already exists in store?
         let cas_paths = DownloadTarballToStore {
             http_client,
             store_dir: &config.store_dir,
-            package_integrity: package_version
+            package_integrity: &package_version
                 .dist
-                .integrity
-                .as_ref()
-                .expect("has integrity field"),
+                .tarball_integrity()
+                .expect("has integrity or shasum field"),
             package_unpacked_size: package_version.dist.unpacked_size,
             package_url: package_version.as_tarball_url(),
+            retry_opts: tarball_retry_opts(config),
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
         }
         .run_with_mem_cache(tarball_mem_cache)
         .await
@@ -103,12 +168,28 @@ impl<'a> InstallPackageFromRegistry<'a> {
 
         tracing::info!(target: "pacquet::import", ?save_path, ?symlink_path, "Import package");
 
+        let is_imported = save_path.exists();
         create_cas_files(config.package_import_method, &save_path, &cas_paths)
             .map_err(InstallPackageFromRegistryError::CreateCasFiles)?;
 
+        if let (false, Some(patch)) =
+            (is_imported, find_package_patch(patched_dependencies, package_version))
+        {
+            apply_patch(&save_path, &lockfile_dir.join(&patch.path)).map_err(|error| {
+                // let the next install start over instead of leaving a partially patched package
+                if let Err(error) = fs::remove_dir_all(&save_path) {
+                    tracing::warn!(target: "pacquet::install", ?error, ?save_path, "Failed to remove the partially patched package");
+                }
+                InstallPackageFromRegistryError::ApplyPatch(error)
+            })?;
+        }
+
         symlink_package(&save_path, &symlink_path)
             .map_err(InstallPackageFromRegistryError::SymlinkPackage)?;
 
+        link_bins(&save_path, &node_modules_dir.join(".bin"))
+            .map_err(InstallPackageFromRegistryError::LinkBins)?;
+
         Ok(())
     }
 }
@@ -146,6 +227,31 @@ mod tests {
             dedupe_peer_dependents: false,
             strict_peer_dependencies: false,
             resolve_peers_from_workspace_root: false,
+            certfile: None,
+            keyfile: None,
+            proxy: None,
+            https_proxy: None,
+            no_proxy: None,
+            ca: None,
+            cafile: None,
+            strict_ssl: true,
+            ignore_scripts: false,
+            unsafe_perm: false,
+            fetch_retries: 2,
+            fetch_retry_mintimeout: 10_000,
+            fetch_retry_maxtimeout: 60_000,
+            fetch_timeout: 60_000,
+            network_concurrency: None,
+            prefer_offline: false,
+            metadata_max_age: 0,
+            offline: false,
+            save_prefix: Default::default(),
+            engine_strict: false,
+            global_dir: store_dir.join("global"),
+            global_bin_dir: store_dir.join("bin"),
+            auth_tokens: Default::default(),
+            scoped_registries: Default::default(),
+            registry_client_certificates: Default::default(),
         }
     }
 
@@ -161,11 +267,15 @@ mod tests {
         let http_client = ThrottledClient::new_from_cpu_count();
         let package = InstallPackageFromRegistry {
             tarball_mem_cache: &Default::default(),
+            resolved_versions: &Default::default(),
+            package_mem_cache: &Default::default(),
             config,
             http_client: &http_client,
             name: "fast-querystring",
             version_range: "1.0.0",
             node_modules_dir: modules_dir.path(),
+            patched_dependencies: None,
+            lockfile_dir: modules_dir.path(),
         }
         .run::<Version>()
         .await
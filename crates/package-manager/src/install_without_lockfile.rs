@@ -1,14 +1,24 @@
-use crate::InstallPackageFromRegistry;
+use crate::{
+    list_peer_dependencies, metadata_cache, package_virtual_store_name, InstallPackageFromRegistry,
+    Overrides, PeerDependency, ResolvedVersions, WORKSPACE_PROTOCOL,
+};
 use async_recursion::async_recursion;
 use dashmap::DashSet;
 use futures_util::future;
 use node_semver::Version;
+use pacquet_lockfile::PatchInfo;
 use pacquet_network::ThrottledClient;
 use pacquet_npmrc::Npmrc;
 use pacquet_package_manifest::{DependencyGroup, PackageManifest};
-use pacquet_registry::PackageVersion;
+use pacquet_registry::{Package, PackageMemCache, PackageVersion};
 use pacquet_tarball::MemCache;
 use pipe_trait::Pipe;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
+use tokio::sync::OnceCell;
 
 /// In-memory cache for packages that have started resolving dependencies.
 ///
@@ -16,6 +26,11 @@ use pipe_trait::Pipe;
 /// e.g. `@pnpm.e2e/dep-1@1.0.0` →  `@pnpm.e2e+dep-1@1.0.0`
 pub type ResolvedPackages = DashSet<String>;
 
+/// Maximum depth of the dependency graph that is installed.
+///
+/// Each level adds a nested future to the call stack, so the recursion must be bounded.
+const MAX_DEPENDENCY_DEPTH: usize = 256;
+
 /// This subroutine install packages from a `package.json` without reading or writing a lockfile.
 ///
 /// **Brief overview for each package:**
@@ -25,19 +40,45 @@ pub type ResolvedPackages = DashSet<String>;
 /// * Create dependency symbolic links in `node_modules/.pacquet/{name}@{version}/node_modules/`.
 /// * Create a symbolic link at `node_modules/{name}`.
 /// * Repeat the process for the dependencies of the package.
+///
+/// The version ranges of the dependencies are rewritten by `overrides` before they are resolved.
+///
+/// The packages listed in `patched_dependencies` are patched after they are imported, the paths
+/// of the patch files are relative to `lockfile_dir`.
+///
+/// If `auto-install-peers` is enabled, the required peer dependencies of the packages are linked
+/// after the rest of the dependency graph has been installed, and the peers that the project
+/// doesn't satisfy are installed.
 #[must_use]
 pub struct InstallWithoutLockfile<'a, DependencyGroupList> {
     pub tarball_mem_cache: &'a MemCache,
     pub resolved_packages: &'a ResolvedPackages,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub package_mem_cache: &'a PackageMemCache,
     pub http_client: &'a ThrottledClient,
     pub config: &'static Npmrc,
     pub manifest: &'a PackageManifest,
+    pub overrides: &'a Overrides,
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+    pub lockfile_dir: &'a Path,
     pub dependency_groups: DependencyGroupList,
 }
 
 impl<'a, DependencyGroupList> InstallWithoutLockfile<'a, DependencyGroupList> {
     /// Execute the subroutine.
     pub async fn run(self)
+    where
+        DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+    {
+        let config = self.config;
+        self.run_in(&config.modules_dir).await;
+    }
+
+    /// Execute the subroutine, linking the direct dependencies into `modules_dir`.
+    ///
+    /// Dependencies with the `workspace:` protocol are skipped, they are linked by
+    /// [`InstallWorkspace`](crate::InstallWorkspace).
+    pub(crate) async fn run_in(self, modules_dir: &Path)
     where
         DependencyGroupList: IntoIterator<Item = DependencyGroup>,
     {
@@ -46,50 +87,83 @@ impl<'a, DependencyGroupList> InstallWithoutLockfile<'a, DependencyGroupList> {
             http_client,
             config,
             manifest,
+            overrides,
+            patched_dependencies,
+            lockfile_dir,
             dependency_groups,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
         } = self;
 
-        let _: Vec<()> = manifest
+        let installer = &InstallWithoutLockfile {
+            tarball_mem_cache,
+            http_client,
+            config,
+            manifest,
+            overrides,
+            patched_dependencies,
+            lockfile_dir,
+            dependency_groups: (),
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+        };
+
+        let direct_dependencies = manifest
             .dependencies(dependency_groups.into_iter())
-            .map(|(name, version_range)| async move {
+            .filter(|(_, version_range)| !version_range.starts_with(WORKSPACE_PROTOCOL))
+            .filter_map(|(name, version_range)| {
+                Some((name, overrides.apply(None, name, version_range)?))
+            })
+            .collect::<Vec<_>>();
+
+        let _: Vec<()> = direct_dependencies
+            .iter()
+            .map(|&(name, version_range)| async move {
                 let dependency = InstallPackageFromRegistry {
                     tarball_mem_cache,
+                    resolved_versions,
+                    package_mem_cache,
                     http_client,
                     config,
-                    node_modules_dir: &config.modules_dir,
+                    node_modules_dir: modules_dir,
                     name,
                     version_range,
+                    patched_dependencies,
+                    lockfile_dir,
                 }
                 .run::<Version>()
                 .await
                 .unwrap();
 
-                InstallWithoutLockfile {
-                    tarball_mem_cache,
-                    http_client,
-                    config,
-                    manifest,
-                    dependency_groups: (),
-                    resolved_packages,
-                }
-                .install_dependencies_from_registry(&dependency)
-                .await;
+                installer.install_dependencies_from_registry(&dependency, 1).await;
             })
             .pipe(future::join_all)
             .await;
+
+        if config.auto_install_peers {
+            installer.install_peer_dependencies(&direct_dependencies).await;
+        }
     }
 }
 
 impl<'a> InstallWithoutLockfile<'a, ()> {
     /// Install dependencies of a dependency.
+    ///
+    /// `depth` is the number of dependencies between `package` and the root project.
     #[async_recursion]
-    async fn install_dependencies_from_registry(&self, package: &PackageVersion) {
+    async fn install_dependencies_from_registry(&self, package: &PackageVersion, depth: usize) {
         let InstallWithoutLockfile {
             tarball_mem_cache,
             http_client,
             config,
+            overrides,
+            patched_dependencies,
+            lockfile_dir,
             resolved_packages,
+            resolved_versions,
+            package_mem_cache,
             ..
         } = self;
 
@@ -99,33 +173,562 @@ impl<'a> InstallWithoutLockfile<'a, ()> {
             return;
         }
 
+        if depth >= MAX_DEPENDENCY_DEPTH {
+            tracing::warn!(target: "pacquet::install", package = ?package.to_virtual_store_name(), depth, "Dependency graph is too deep, skip the dependencies");
+            return;
+        }
+
         let node_modules_path = self
             .config
             .virtual_store_dir
-            .join(package.to_virtual_store_name())
+            .join(package_virtual_store_name(package, *patched_dependencies))
             .join("node_modules");
 
         tracing::info!(target: "pacquet::install", node_modules = ?node_modules_path, "Start subset");
 
+        // Peer dependencies are resolved once the whole graph is known.
         package
-            .dependencies(self.config.auto_install_peers)
+            .dependencies(false)
+            .filter_map(|(name, version_range)| {
+                Some((name, overrides.apply(Some(package), name, version_range)?))
+            })
             .map(|(name, version_range)| async {
                 let dependency = InstallPackageFromRegistry {
                     tarball_mem_cache,
+                    resolved_versions,
+                    package_mem_cache,
                     http_client,
                     config,
                     node_modules_dir: &node_modules_path,
                     name,
                     version_range,
+                    patched_dependencies: *patched_dependencies,
+                    lockfile_dir,
                 }
                 .run::<Version>()
                 .await
                 .unwrap(); // TODO: proper error propagation
-                self.install_dependencies_from_registry(&dependency).await;
+                self.install_dependencies_from_registry(&dependency, depth + 1).await;
             })
             .pipe(future::join_all)
             .await;
 
         tracing::info!(target: "pacquet::install", node_modules = ?node_modules_path, "Complete subset");
     }
+
+    /// Link the required peer dependencies of the installed packages into their virtual
+    /// directories, installing the peers that the project doesn't satisfy.
+    ///
+    /// Installed peers may have peer dependencies of their own, so this repeats until every
+    /// peer dependency has been linked.
+    async fn install_peer_dependencies(&self, direct_dependencies: &[(&str, &str)]) {
+        let mut linked = HashSet::<(String, String)>::new();
+        loop {
+            let mut pending = list_peer_dependencies(self.resolved_versions);
+            for peers in pending.values_mut() {
+                peers.retain(|peer| {
+                    linked.insert((peer.dependent.to_virtual_store_name(), peer.name.clone()))
+                });
+            }
+            pending.retain(|_, peers| !peers.is_empty());
+            if pending.is_empty() {
+                break;
+            }
+
+            pending
+                .iter()
+                .map(|(name, peers)| self.install_peer_dependency(name, peers, direct_dependencies))
+                .pipe(future::join_all)
+                .await;
+        }
+    }
+
+    /// Link peer `name` into the virtual directories of its dependents.
+    ///
+    /// A dependent is linked to the version that the project depends on if it satisfies the
+    /// dependent. The other dependents share the highest version that satisfies all of them,
+    /// or each gets the highest version that satisfies itself if their ranges conflict.
+    async fn install_peer_dependency(
+        &self,
+        name: &str,
+        peers: &[PeerDependency],
+        direct_dependencies: &[(&str, &str)],
+    ) {
+        let InstallWithoutLockfile {
+            tarball_mem_cache,
+            http_client,
+            config,
+            patched_dependencies,
+            lockfile_dir,
+            resolved_versions,
+            package_mem_cache,
+            ..
+        } = self;
+
+        let provided = direct_dependencies
+            .iter()
+            .filter(|(dependency_name, _)| *dependency_name == name)
+            .find_map(|&(name, version_range)| {
+                let key = (name.to_string(), version_range.to_string());
+                resolved_versions.get(&key)?.get().cloned()
+            });
+        let (satisfied, unsatisfied): (Vec<_>, Vec<_>) = peers.iter().partition(|peer| {
+            provided.as_ref().is_some_and(|provided| peer.is_satisfied_by(&provided.version))
+        });
+
+        let package = match unsatisfied.is_empty() {
+            true => None,
+            false => {
+                let registry = config.registry_for(name);
+                let cache = metadata_cache(config);
+                let package = Package::fetch_from_registry_with_mem_cache(
+                    name,
+                    http_client,
+                    registry,
+                    Some(&cache),
+                    package_mem_cache,
+                );
+                match package.await {
+                    Ok(package) => Some(package),
+                    Err(error) => {
+                        tracing::warn!(target: "pacquet::install", ?name, ?error, "Failed to fetch the peer dependency");
+                        None
+                    }
+                }
+            }
+        };
+        let common = package.as_ref().and_then(|package| {
+            package
+                .pinned_version_for_all(unsatisfied.iter().map(|peer| peer.version_range.as_str()))
+        });
+
+        let choices = satisfied.into_iter().map(|peer| (peer, provided.clone())).chain(
+            unsatisfied.into_iter().map(|peer| {
+                let version = common.or_else(|| {
+                    package.as_ref()?.pinned_version_for_all([peer.version_range.as_str()])
+                });
+                (peer, version.cloned())
+            }),
+        );
+
+        choices
+            .map(|(peer, version)| async move {
+                let Some(version) = version else {
+                    tracing::warn!(target: "pacquet::install", dependent = ?peer.dependent.to_virtual_store_name(), ?name, version_range = ?peer.version_range, "No version satisfies the peer dependency");
+                    return;
+                };
+
+                // Record the choice so that the lockfile links the dependent to the same version.
+                // A range that has been resolved elsewhere keeps its resolution.
+                resolved_versions
+                    .entry((name.to_string(), peer.version_range.clone()))
+                    .or_insert_with(|| Arc::new(OnceCell::new_with(Some(version))));
+
+                let node_modules_dir = config
+                    .virtual_store_dir
+                    .join(package_virtual_store_name(&peer.dependent, *patched_dependencies))
+                    .join("node_modules");
+                let dependency = InstallPackageFromRegistry {
+                    tarball_mem_cache,
+                    resolved_versions,
+                    package_mem_cache,
+                    http_client,
+                    config,
+                    node_modules_dir: &node_modules_dir,
+                    name,
+                    version_range: &peer.version_range,
+                    patched_dependencies: *patched_dependencies,
+                    lockfile_dir,
+                }
+                .run::<Version>()
+                .await
+                .unwrap(); // TODO: proper error propagation
+                self.install_dependencies_from_registry(&dependency, 1).await;
+            })
+            .pipe(future::join_all)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_registry_mock::CustomRegistry;
+    use pacquet_testing_utils::tarball::TarballBuilder;
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use std::fs;
+    use tempfile::tempdir;
+
+    /// Serve the packument and the tarballs of `versions` of package `name`.
+    ///
+    /// Each version comes with the fields of its `package.json` besides `name` and `version`.
+    async fn publish(registry: &mut CustomRegistry, name: &str, versions: &[(&str, Value)]) {
+        let mut packument_versions = serde_json::Map::new();
+        for (version, fields) in versions {
+            let tarball = TarballBuilder::new()
+                .file(
+                    "package/package.json",
+                    json!({ "name": name, "version": version }).to_string(),
+                )
+                .build();
+            let tarball_url =
+                registry.add_tarball(&format!("{name}-{version}.tgz"), tarball.data).await;
+            let mut manifest = json!({
+                "name": name,
+                "version": version,
+                "dist": { "integrity": tarball.integrity.to_string(), "tarball": tarball_url },
+            });
+            manifest.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+            packument_versions.insert(version.to_string(), manifest);
+        }
+        let (latest, _) = versions.last().unwrap();
+        let packument = json!({
+            "name": name,
+            "dist-tags": { "latest": latest },
+            "versions": packument_versions,
+        });
+        registry.add_packument(name, &packument).await;
+    }
+
+    #[tokio::test]
+    async fn should_resolve_each_version_range_once() {
+        // diamond: the project depends on `left` and `right`, which both depend on `bottom@^1.0.0`
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for (name, dependencies) in [
+            ("left", json!({ "bottom": "^1.0.0" })),
+            ("right", json!({ "bottom": "^1.0.0" })),
+            ("bottom", json!({})),
+        ] {
+            let tarball = TarballBuilder::new()
+                .file(
+                    "package/package.json",
+                    json!({ "name": name, "version": "1.0.0" }).to_string(),
+                )
+                .build();
+            let tarball_path = format!("/{name}/-/{name}-1.0.0.tgz");
+            let packument = json!({
+                "name": name,
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": {
+                    "1.0.0": {
+                        "name": name,
+                        "version": "1.0.0",
+                        "dependencies": dependencies,
+                        "dist": {
+                            "integrity": tarball.integrity.to_string(),
+                            "tarball": format!("{}{tarball_path}", server.url()),
+                        },
+                    },
+                },
+            });
+            let packument_mock = server
+                .mock("GET", format!("/{name}").as_str())
+                .with_header("content-type", "application/json")
+                .with_body(packument.to_string())
+                .expect(1)
+                .create_async()
+                .await;
+            let tarball_mock = server
+                .mock("GET", tarball_path.as_str())
+                .with_body(tarball.data)
+                .expect(1)
+                .create_async()
+                .await;
+            mocks.extend([packument_mock, tarball_mock]);
+        }
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("left", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("right", "^1.0.0", DependencyGroup::Prod).unwrap();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir;
+        config.registry = format!("{}/", server.url());
+        let config = config.leak();
+
+        let resolved_versions = ResolvedVersions::new();
+        InstallWithoutLockfile {
+            tarball_mem_cache: &Default::default(),
+            resolved_packages: &Default::default(),
+            resolved_versions: &resolved_versions,
+            package_mem_cache: &Default::default(),
+            http_client: &Default::default(),
+            config,
+            manifest: &manifest,
+            overrides: &Default::default(),
+            patched_dependencies: None,
+            lockfile_dir: dir.path(),
+            dependency_groups: [DependencyGroup::Prod],
+        }
+        .run()
+        .await;
+
+        for mock in &mocks {
+            mock.assert_async().await;
+        }
+
+        let mut resolved = resolved_versions
+            .iter()
+            .map(|entry| {
+                let (name, version_range) = entry.key();
+                let version = entry.value().get().expect("resolved").version.to_string();
+                (name.clone(), version_range.clone(), version)
+            })
+            .collect::<Vec<_>>();
+        resolved.sort();
+        dbg!(&resolved);
+        let expected = [
+            ("bottom", "^1.0.0", "1.0.0"),
+            ("left", "^1.0.0", "1.0.0"),
+            ("right", "^1.0.0", "1.0.0"),
+        ]
+        .map(|(name, range, version)| (name.to_string(), range.to_string(), version.to_string()));
+        assert_eq!(resolved, expected);
+
+        drop(dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_install_deep_cyclic_chain_once() {
+        const CHAIN_LENGTH: usize = 32;
+        let name = |index: usize| format!("chain-{}", index % CHAIN_LENGTH);
+
+        // chain-0 -> chain-1 -> ... -> chain-31 -> chain-0
+        let mut registry = CustomRegistry::new().await;
+        for index in 0..CHAIN_LENGTH {
+            let tarball = TarballBuilder::new()
+                .file(
+                    "package/package.json",
+                    json!({ "name": name(index), "version": "1.0.0" }).to_string(),
+                )
+                .build();
+            let tarball_url =
+                registry.add_tarball(&format!("{}-1.0.0.tgz", name(index)), tarball.data).await;
+            let packument = json!({
+                "name": name(index),
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": {
+                    "1.0.0": {
+                        "name": name(index),
+                        "version": "1.0.0",
+                        "dependencies": { name(index + 1): "^1.0.0" },
+                        "dist": {
+                            "integrity": tarball.integrity.to_string(),
+                            "tarball": tarball_url,
+                        },
+                    },
+                },
+            });
+            registry.add_packument(&name(index), &packument).await;
+        }
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency(&name(0), "^1.0.0", DependencyGroup::Prod).unwrap();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir;
+        config.registry = registry.url();
+        let config = config.leak();
+
+        let resolved_packages = ResolvedPackages::new();
+        let resolved_versions = ResolvedVersions::new();
+        InstallWithoutLockfile {
+            tarball_mem_cache: &Default::default(),
+            resolved_packages: &resolved_packages,
+            resolved_versions: &resolved_versions,
+            package_mem_cache: &Default::default(),
+            http_client: &Default::default(),
+            config,
+            manifest: &manifest,
+            overrides: &Default::default(),
+            patched_dependencies: None,
+            lockfile_dir: dir.path(),
+            dependency_groups: [DependencyGroup::Prod],
+        }
+        .run()
+        .await;
+
+        eprintln!("Each package should be resolved and installed exactly once");
+        assert_eq!(resolved_versions.len(), CHAIN_LENGTH);
+        let mut installed = resolved_packages.iter().map(|name| name.clone()).collect::<Vec<_>>();
+        installed.sort();
+        let mut expected =
+            (0..CHAIN_LENGTH).map(|index| format!("{}@1.0.0", name(index))).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(installed, expected);
+
+        drop((dir, registry)); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_apply_overrides() {
+        let mut registry = CustomRegistry::new().await;
+        let no_fields = || json!({});
+        publish(&mut registry, "foo", &[("1.0.0", no_fields()), ("1.1.0", no_fields())]).await;
+        publish(&mut registry, "bar", &[("1.0.0", json!({ "dependencies": { "foo": "^1.0.0" } }))])
+            .await;
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("foo", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("bar", "1.0.0", DependencyGroup::Prod).unwrap();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir;
+        config.registry = registry.url();
+        let config = config.leak();
+
+        let overrides = Overrides::parse([("foo@^1".to_string(), "1.0.0".to_string())]);
+        let resolved_versions = ResolvedVersions::new();
+        InstallWithoutLockfile {
+            tarball_mem_cache: &Default::default(),
+            resolved_packages: &Default::default(),
+            resolved_versions: &resolved_versions,
+            package_mem_cache: &Default::default(),
+            http_client: &Default::default(),
+            config,
+            manifest: &manifest,
+            overrides: &overrides,
+            patched_dependencies: None,
+            lockfile_dir: dir.path(),
+            dependency_groups: [DependencyGroup::Prod],
+        }
+        .run()
+        .await;
+
+        eprintln!("Both the project and bar should get the overridden version");
+        assert!(config.virtual_store_dir.join("foo@1.0.0").exists());
+        assert!(!config.virtual_store_dir.join("foo@1.1.0").exists());
+        let foo = resolved_versions.get(&("foo".to_string(), "1.0.0".to_string())).unwrap();
+        assert_eq!(foo.get().unwrap().version.to_string(), "1.0.0");
+        assert!(!resolved_versions.contains_key(&("foo".to_string(), "^1.0.0".to_string())));
+
+        drop((dir, registry)); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_auto_install_missing_peers() {
+        let mut registry = CustomRegistry::new().await;
+        let no_fields = || json!({});
+        publish(
+            &mut registry,
+            "host",
+            &[("1.0.0", no_fields()), ("1.1.0", no_fields()), ("1.2.0", no_fields())],
+        )
+        .await;
+        publish(
+            &mut registry,
+            "plugin",
+            &[("1.0.0", json!({ "peerDependencies": { "host": "^1.0.0" } }))],
+        )
+        .await;
+        publish(
+            &mut registry,
+            "other-plugin",
+            &[("1.0.0", json!({ "peerDependencies": { "host": "~1.1.0" } }))],
+        )
+        .await;
+        publish(
+            &mut registry,
+            "optional-plugin",
+            &[(
+                "1.0.0",
+                json!({
+                    "peerDependencies": { "missing": "^1.0.0" },
+                    "peerDependenciesMeta": { "missing": { "optional": true } },
+                }),
+            )],
+        )
+        .await;
+
+        let install = |dependencies: &[(&str, &str)]| {
+            let dependencies =
+                dependencies.iter().map(|(name, range)| (name.to_string(), range.to_string()));
+            let dependencies = dependencies.collect::<Vec<_>>();
+            let registry_url = registry.url();
+            async move {
+                let dir = tempdir().unwrap();
+                let modules_dir = dir.path().join("node_modules");
+                let mut manifest =
+                    PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+                for (name, range) in &dependencies {
+                    manifest.add_dependency(name, range, DependencyGroup::Prod).unwrap();
+                }
+
+                let mut config = Npmrc::new();
+                config.store_dir = dir.path().join("pacquet-store").into();
+                config.virtual_store_dir = modules_dir.join(".pacquet");
+                config.modules_dir = modules_dir;
+                config.registry = registry_url;
+                config.auto_install_peers = true;
+                let config: &'static Npmrc = config.leak();
+
+                let resolved_versions = ResolvedVersions::new();
+                InstallWithoutLockfile {
+                    tarball_mem_cache: &Default::default(),
+                    resolved_packages: &Default::default(),
+                    resolved_versions: &resolved_versions,
+                    package_mem_cache: &Default::default(),
+                    http_client: &Default::default(),
+                    config,
+                    manifest: &manifest,
+                    overrides: &Overrides::default(),
+                    patched_dependencies: None,
+                    lockfile_dir: dir.path(),
+                    dependency_groups: [DependencyGroup::Prod],
+                }
+                .run()
+                .await;
+                (dir, config)
+            }
+        };
+        let linked_peer = |config: &Npmrc, dependent: &str, peer: &str| {
+            let link = config.virtual_store_dir.join(dependent).join("node_modules").join(peer);
+            let target = fs::canonicalize(link).ok()?;
+            let virtual_store_dir = fs::canonicalize(&config.virtual_store_dir).unwrap();
+            let target = target.strip_prefix(virtual_store_dir).unwrap();
+            target.components().next()?.as_os_str().to_str().map(ToString::to_string)
+        };
+
+        eprintln!("CASE: conflicting ranges share the highest common version");
+        let (dir, config) = install(&[
+            ("plugin", "1.0.0"),
+            ("other-plugin", "1.0.0"),
+            ("optional-plugin", "1.0.0"),
+        ])
+        .await;
+        assert_eq!(linked_peer(config, "plugin@1.0.0", "host").as_deref(), Some("host@1.1.0"));
+        assert_eq!(
+            linked_peer(config, "other-plugin@1.0.0", "host").as_deref(),
+            Some("host@1.1.0"),
+        );
+        assert_eq!(linked_peer(config, "optional-plugin@1.0.0", "missing"), None);
+        assert!(!config.modules_dir.join("host").exists());
+        drop(dir); // cleanup
+
+        eprintln!("CASE: the peer provided by the project is reused");
+        let (dir, config) = install(&[("plugin", "1.0.0"), ("host", "1.0.0")]).await;
+        assert_eq!(linked_peer(config, "plugin@1.0.0", "host").as_deref(), Some("host@1.0.0"));
+        assert!(!config.virtual_store_dir.join("host@1.2.0").exists());
+        drop(dir); // cleanup
+
+        drop(registry); // cleanup
+    }
 }
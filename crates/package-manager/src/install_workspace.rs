@@ -0,0 +1,294 @@
+use crate::{
+    check_engines, check_package_manager, check_peer_dependencies, link_bins, symlink_package,
+    CheckEnginesError, InstallWithoutLockfile, LinkBinsError, Overrides, ResolvedPackages,
+    ResolvedVersions, SymlinkPackageError, UnmetPeerDependencies, Workspace, WorkspaceProject,
+    WORKSPACE_PROTOCOL,
+};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use node_semver::{Range, Version};
+use pacquet_network::ThrottledClient;
+use pacquet_npmrc::{NodeLinker, Npmrc};
+use pacquet_package_manifest::{DependencyGroup, PackageManifestError};
+use pacquet_registry::PackageMemCache;
+use pacquet_tarball::MemCache;
+use std::path::{Path, PathBuf};
+
+/// This subroutine installs the selected projects of a [`Workspace`] without reading or writing
+/// a lockfile.
+///
+/// The projects share the store directory and the virtual store directory, while the direct
+/// dependencies of each project are linked into the `node_modules` directory of the project.
+/// Dependencies with the `workspace:` protocol are symlinked to the directories of the projects
+/// instead of being fetched from the registry.
+///
+/// Only `node-linker=isolated` is supported.
+#[must_use]
+pub struct InstallWorkspace<'a, DependencyGroupList> {
+    pub tarball_mem_cache: &'a MemCache,
+    pub resolved_packages: &'a ResolvedPackages,
+    pub resolved_versions: &'a ResolvedVersions,
+    pub package_mem_cache: &'a PackageMemCache,
+    pub http_client: &'a ThrottledClient,
+    pub config: &'static Npmrc,
+    pub workspace: &'a Workspace,
+    /// Projects of `workspace` to install.
+    pub projects: &'a [&'a WorkspaceProject],
+    pub dependency_groups: DependencyGroupList,
+}
+
+/// Error type of [`InstallWorkspace`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum InstallWorkspaceError {
+    #[display("Workspaces are only supported with node-linker=isolated")]
+    #[diagnostic(code(pacquet_package_manager::unsupported_node_linker))]
+    UnsupportedNodeLinker,
+
+    #[display(
+        "{project:?} depends on {name}@{specifier}, but no project of the workspace matches"
+    )]
+    #[diagnostic(code(pacquet_package_manager::missing_workspace_project))]
+    MissingWorkspaceProject { project: PathBuf, name: String, specifier: String },
+
+    #[diagnostic(transparent)]
+    CheckEngines(#[error(source)] CheckEnginesError),
+
+    #[diagnostic(transparent)]
+    ReadOverrides(#[error(source)] PackageManifestError),
+
+    #[diagnostic(transparent)]
+    ReadDependencies(#[error(source)] PackageManifestError),
+
+    #[diagnostic(transparent)]
+    SymlinkPackage(#[error(source)] SymlinkPackageError),
+
+    #[diagnostic(transparent)]
+    LinkBins(#[error(source)] LinkBinsError),
+
+    #[diagnostic(transparent)]
+    UnmetPeerDependencies(#[error(source)] UnmetPeerDependencies),
+}
+
+impl<'a, DependencyGroupList> InstallWorkspace<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    /// Execute the subroutine.
+    pub async fn run(self) -> Result<(), InstallWorkspaceError> {
+        let InstallWorkspace {
+            tarball_mem_cache,
+            resolved_packages,
+            resolved_versions,
+            package_mem_cache,
+            http_client,
+            config,
+            workspace,
+            projects,
+            dependency_groups,
+        } = self;
+
+        if config.node_linker != NodeLinker::Isolated {
+            return Err(InstallWorkspaceError::UnsupportedNodeLinker);
+        }
+
+        // Like pnpm, only the overrides of the root project apply to the workspace.
+        let overrides = match workspace.find_root_project() {
+            Some(root) => {
+                check_package_manager(&root.manifest);
+                Overrides::from_manifest(&root.manifest)
+                    .map_err(InstallWorkspaceError::ReadOverrides)?
+            }
+            None => Overrides::default(),
+        };
+
+        let dependency_groups = dependency_groups.into_iter().collect::<Vec<_>>();
+        for project in projects {
+            tracing::info!(target: "pacquet::install", project = ?project.dir, "Install workspace project");
+            let modules_dir = project.dir.join("node_modules");
+
+            check_engines(config, &project.manifest)
+                .map_err(InstallWorkspaceError::CheckEngines)?;
+            project
+                .manifest
+                .try_dependencies(DependencyGroup::all())
+                .map_err(InstallWorkspaceError::ReadDependencies)?;
+            link_workspace_dependencies(workspace, project, &modules_dir, &dependency_groups)?;
+
+            InstallWithoutLockfile {
+                tarball_mem_cache,
+                resolved_packages,
+                resolved_versions,
+                package_mem_cache,
+                http_client,
+                config,
+                manifest: &project.manifest,
+                overrides: &overrides,
+                // the workspace doesn't have a lockfile to read the patches from yet
+                patched_dependencies: None,
+                lockfile_dir: &workspace.root_dir,
+                dependency_groups: dependency_groups.iter().copied(),
+            }
+            .run_in(&modules_dir)
+            .await;
+        }
+
+        check_peer_dependencies(config, resolved_versions)
+            .map_err(InstallWorkspaceError::UnmetPeerDependencies)
+    }
+}
+
+/// Symlink the `workspace:` dependencies of `project` into `modules_dir`.
+fn link_workspace_dependencies(
+    workspace: &Workspace,
+    project: &WorkspaceProject,
+    modules_dir: &Path,
+    dependency_groups: &[DependencyGroup],
+) -> Result<(), InstallWorkspaceError> {
+    let workspace_dependencies = project
+        .manifest
+        .dependencies(dependency_groups.iter().copied())
+        .filter_map(|(name, specifier)| Some((name, specifier.strip_prefix(WORKSPACE_PROTOCOL)?)));
+    for (name, version_range) in workspace_dependencies {
+        let target = workspace
+            .find_project(name)
+            .filter(|target| satisfies_workspace_range(target, version_range))
+            .ok_or_else(|| InstallWorkspaceError::MissingWorkspaceProject {
+                project: project.dir.clone(),
+                name: name.to_string(),
+                specifier: format!("{WORKSPACE_PROTOCOL}{version_range}"),
+            })?;
+        symlink_package(&target.dir, &modules_dir.join(name))
+            .map_err(InstallWorkspaceError::SymlinkPackage)?;
+        link_bins(&target.dir, &modules_dir.join(".bin"))
+            .map_err(InstallWorkspaceError::LinkBins)?;
+    }
+    Ok(())
+}
+
+/// Whether the version of `project` satisfies the range after `workspace:`.
+///
+/// `*`, `^`, and `~` accept any version of the project.
+fn satisfies_workspace_range(project: &WorkspaceProject, version_range: &str) -> bool {
+    if matches!(version_range, "*" | "^" | "~") {
+        return true;
+    }
+    let version = project.version().and_then(|version| version.parse::<Version>().ok());
+    let range = version_range.parse::<Range>().ok();
+    match (version, range) {
+        (Some(version), Some(range)) => version.satisfies(&range),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_registry_mock::CustomRegistry;
+    use pacquet_testing_utils::{fs::is_symlink_or_junction, tarball::TarballBuilder};
+    use serde_json::json;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn should_install_workspace_projects() {
+        let mut registry = CustomRegistry::new().await;
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .build();
+        let tarball_url = registry.add_tarball("foo-1.0.0.tgz", tarball.data).await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": {
+                    "name": "foo",
+                    "version": "1.0.0",
+                    "dist": { "integrity": tarball.integrity.to_string(), "tarball": tarball_url },
+                },
+            },
+        });
+        registry.add_packument("foo", &packument).await;
+
+        let dir = tempdir().unwrap();
+        let write_manifest = |path: &str, manifest: serde_json::Value| {
+            let project_dir = dir.path().join(path);
+            fs::create_dir_all(&project_dir).unwrap();
+            fs::write(project_dir.join("package.json"), manifest.to_string()).unwrap();
+        };
+        write_manifest("", json!({ "name": "root", "private": true }));
+        write_manifest(
+            "packages/app",
+            json!({
+                "name": "app",
+                "version": "1.0.0",
+                "dependencies": { "lib": "workspace:*", "foo": "^1.0.0" },
+            }),
+        );
+        write_manifest(
+            "packages/lib",
+            json!({ "name": "lib", "version": "2.0.0", "dependencies": { "foo": "1.0.0" } }),
+        );
+        fs::write(dir.path().join("pnpm-workspace.yaml"), "packages:\n  - 'packages/*'\n").unwrap();
+        let workspace = Workspace::load(dir.path()).unwrap();
+        let projects = workspace.projects.iter().collect::<Vec<_>>();
+
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = dir.path().join("node_modules/.pacquet");
+        config.modules_dir = dir.path().join("node_modules");
+        config.registry = registry.url();
+        let config = config.leak();
+
+        let tarball_mem_cache = MemCache::default();
+        let resolved_packages = ResolvedPackages::default();
+        let resolved_versions = ResolvedVersions::default();
+        let package_mem_cache = PackageMemCache::default();
+        let http_client = ThrottledClient::default();
+        let install = || InstallWorkspace {
+            tarball_mem_cache: &tarball_mem_cache,
+            resolved_packages: &resolved_packages,
+            resolved_versions: &resolved_versions,
+            package_mem_cache: &package_mem_cache,
+            http_client: &http_client,
+            config,
+            workspace: &workspace,
+            projects: &projects,
+            dependency_groups: [DependencyGroup::Prod],
+        };
+        install().run().await.unwrap();
+
+        eprintln!("Workspace dependencies should be symlinked to the projects");
+        let app_modules = dir.path().join("packages/app/node_modules");
+        assert!(is_symlink_or_junction(&app_modules.join("lib")).unwrap());
+        assert_eq!(
+            fs::canonicalize(app_modules.join("lib")).unwrap(),
+            fs::canonicalize(dir.path().join("packages/lib")).unwrap(),
+        );
+
+        eprintln!("Registry dependencies should share the virtual store");
+        assert!(is_symlink_or_junction(&app_modules.join("foo")).unwrap());
+        let lib_modules = dir.path().join("packages/lib/node_modules");
+        assert!(is_symlink_or_junction(&lib_modules.join("foo")).unwrap());
+        assert!(config.virtual_store_dir.join("foo@1.0.0/node_modules/foo/package.json").is_file());
+
+        eprintln!("Workspace dependencies that no project matches should fail");
+        write_manifest(
+            "packages/app",
+            json!({ "name": "app", "version": "1.0.0", "dependencies": { "lib": "workspace:^1.0.0" } }),
+        );
+        let workspace = Workspace::load(dir.path()).unwrap();
+        let projects = workspace.projects.iter().collect::<Vec<_>>();
+        let error = InstallWorkspace { workspace: &workspace, projects: &projects, ..install() }
+            .run()
+            .await
+            .unwrap_err();
+        dbg!(&error);
+        assert!(matches!(
+            error,
+            InstallWorkspaceError::MissingWorkspaceProject { name, .. } if name == "lib"
+        ));
+
+        drop((dir, registry)); // cleanup
+    }
+}
@@ -1,5 +1,8 @@
 mod add;
+mod apply_patch;
+mod check_engines;
 mod create_cas_files;
+mod create_lockfile;
 mod create_symlink_layout;
 mod create_virtual_dir_by_snapshot;
 mod create_virtual_store;
@@ -8,12 +11,28 @@ mod install_frozen_lockfile;
 mod install_package_by_snapshot;
 mod install_package_from_registry;
 mod install_without_lockfile;
+mod install_workspace;
+mod link_bins;
 mod link_file;
+mod list;
+mod metadata_cache;
+mod outdated;
+mod overrides;
+mod peer_dependencies;
+mod prune_dev_dependencies;
+mod remove;
+mod resolve_hoisted_layout;
+mod retry_opts;
+mod store_status;
 mod symlink_direct_dependencies;
 mod symlink_package;
+mod workspace;
 
 pub use add::*;
+pub use apply_patch::*;
+pub use check_engines::*;
 pub use create_cas_files::*;
+pub use create_lockfile::*;
 pub use create_symlink_layout::*;
 pub use create_virtual_dir_by_snapshot::*;
 pub use create_virtual_store::*;
@@ -22,6 +41,19 @@ pub use install_frozen_lockfile::*;
 pub use install_package_by_snapshot::*;
 pub use install_package_from_registry::*;
 pub use install_without_lockfile::*;
+pub use install_workspace::*;
+pub use link_bins::*;
 pub use link_file::*;
+pub use list::*;
+pub use metadata_cache::*;
+pub use outdated::*;
+pub use overrides::*;
+pub use peer_dependencies::*;
+pub use prune_dev_dependencies::*;
+pub use remove::*;
+pub use resolve_hoisted_layout::*;
+pub use retry_opts::*;
+pub use store_status::*;
 pub use symlink_direct_dependencies::*;
 pub use symlink_package::*;
+pub use workspace::*;
@@ -0,0 +1,409 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_diagnostics::warn;
+use pacquet_package_manifest::{PackageManifest, PackageManifestError};
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Error type of [`link_bins`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum LinkBinsError {
+    #[diagnostic(transparent)]
+    ReadManifest(#[error(source)] PackageManifestError),
+
+    #[display("Failed to create directory at {dir:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::create_bin_dir))]
+    CreateBinDir {
+        dir: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to make {path:?} executable: {error}")]
+    #[diagnostic(code(pacquet_package_manager::make_bin_executable))]
+    MakeExecutable {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to create a command at {bin_path:?} for {target:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::create_bin))]
+    CreateBin {
+        bin_path: PathBuf,
+        target: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to remove the command at {bin_path:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::remove_bin))]
+    RemoveBin {
+        bin_path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+}
+
+/// Create the commands declared in the `bin` field of the package in `package_dir` in `bin_dir`
+/// (usually a `node_modules/.bin` directory).
+///
+/// * The commands are symlinks to the executables on Unix, and `.cmd` shims on Windows.
+/// * The executables are made executable.
+/// * Commands that already exist are skipped, unless they run an executable that no longer
+///   exists or an executable of another version of the same package, which are replaced.
+/// * Like pnpm, problems of the package are shown as warnings instead of failing the install:
+///   a malformed `package.json`, invalid command names, and executables that are outside of
+///   the package or missing are skipped.
+pub fn link_bins(package_dir: &Path, bin_dir: &Path) -> Result<(), LinkBinsError> {
+    let manifest_path = package_dir.join("package.json");
+    if !manifest_path.is_file() {
+        return Ok(());
+    }
+    let read_bins = || -> Result<_, PackageManifestError> {
+        let manifest = PackageManifest::from_path(manifest_path)?;
+        Ok((package_name(&manifest), manifest.bins()?))
+    };
+    let (package_name, bins) = match read_bins() {
+        Ok(bins) => bins,
+        Err(PackageManifestError::Io(error)) => {
+            return Err(LinkBinsError::ReadManifest(PackageManifestError::Io(error)));
+        }
+        Err(error) => {
+            warn(format_args!("Skip the commands of {package_dir:?}: {error}"));
+            return Ok(());
+        }
+    };
+    if bins.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(bin_dir)
+        .map_err(|error| LinkBinsError::CreateBinDir { dir: bin_dir.to_path_buf(), error })?;
+
+    for (name, path) in bins {
+        if !is_valid_command_name(&name) || !is_inside_package(Path::new(&path)) {
+            warn(format_args!("Skip the invalid command {name:?} of {package_dir:?} at {path:?}"));
+            continue;
+        }
+        let target = package_dir.join(&path);
+        match make_executable(&target) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                warn(format_args!(
+                    "Skip the command {name:?} of {package_dir:?} because {path:?} doesn't exist"
+                ));
+                continue;
+            }
+            Err(error) => return Err(LinkBinsError::MakeExecutable { path: target, error }),
+        }
+        match create_command(&target, bin_dir, &name) {
+            Ok(()) => {}
+            Err((bin_path, error)) if error.kind() == io::ErrorKind::AlreadyExists => {
+                if is_outdated_command(&bin_path, &target, package_name.as_deref()) {
+                    tracing::info!(target: "pacquet::install", ?bin_path, ?target, "Replace outdated command");
+                    replace_command(&target, &bin_path)
+                        .map_err(|error| LinkBinsError::CreateBin { bin_path, target, error })?;
+                }
+            }
+            Err((bin_path, error)) => {
+                return Err(LinkBinsError::CreateBin { bin_path, target, error });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the commands declared in the `bin` field of the package in `package_dir` from `bin_dir`.
+///
+/// Commands that don't exist or that run the executables of another package are left untouched.
+pub fn unlink_bins(package_dir: &Path, bin_dir: &Path) -> Result<(), LinkBinsError> {
+    let manifest_path = package_dir.join("package.json");
+    if !manifest_path.is_file() {
+        return Ok(());
+    }
+    let bins = PackageManifest::from_path(manifest_path)
+        .and_then(|manifest| manifest.bins())
+        .map_err(LinkBinsError::ReadManifest)?;
+
+    for (name, path) in bins {
+        if !is_valid_command_name(&name) || !is_inside_package(Path::new(&path)) {
+            continue;
+        }
+        let target = package_dir.join(&path);
+        let bin_path = command_path(bin_dir, &name);
+        if !is_command_of(&bin_path, &target) {
+            continue;
+        }
+        fs::remove_file(&bin_path).map_err(|error| LinkBinsError::RemoveBin { bin_path, error })?;
+    }
+
+    Ok(())
+}
+
+/// Value of the `name` field of `manifest`.
+fn package_name(manifest: &PackageManifest) -> Option<String> {
+    manifest.value().get("name")?.as_str().map(ToString::to_string)
+}
+
+/// Whether `name` can be used as a file name in the bin directory.
+fn is_valid_command_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// Whether `path` is relative and doesn't escape the package directory.
+fn is_inside_package(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Add the executable bits to the mode of `path`.
+fn make_executable(path: &Path) -> io::Result<()> {
+    let file = fs::File::open(path)?;
+    pacquet_fs::file_mode::make_file_executable(&file)
+}
+
+/// Path of the command named `name` in `bin_dir`.
+pub fn command_path(bin_dir: &Path, name: &str) -> PathBuf {
+    #[cfg(unix)]
+    return bin_dir.join(name);
+
+    #[cfg(windows)]
+    return bin_dir.join(format!("{name}.cmd"));
+}
+
+/// Content of the `.cmd` shim that runs `target`.
+#[cfg(windows)]
+fn command_shim(target: &Path) -> String {
+    format!("@node \"{}\" %*\r\n", target.display())
+}
+
+/// Executable that the command at `bin_path` runs, if it was created by [`link_bins`].
+fn command_target(bin_path: &Path) -> Option<PathBuf> {
+    #[cfg(unix)]
+    return fs::read_link(bin_path).ok();
+
+    #[cfg(windows)]
+    return fs::read_to_string(bin_path)
+        .ok()?
+        .strip_prefix("@node \"")?
+        .split('"')
+        .next()
+        .map(PathBuf::from);
+}
+
+/// Whether the command at `bin_path` runs `target`.
+fn is_command_of(bin_path: &Path, target: &Path) -> bool {
+    command_target(bin_path).is_some_and(|current| current == target)
+}
+
+/// Whether the existing command at `bin_path` should be replaced by a command that runs `target`
+/// of the package named `package_name`.
+///
+/// That is the case when the command runs an executable that no longer exists, or an executable
+/// of another version of the same package (e.g. after an upgrade). Commands of other packages
+/// are kept.
+fn is_outdated_command(bin_path: &Path, target: &Path, package_name: Option<&str>) -> bool {
+    let Some(current) = command_target(bin_path) else { return false };
+    if current == target {
+        return false;
+    }
+    if !current.exists() {
+        return true;
+    }
+    let Some(package_name) = package_name else { return false };
+    current
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("package.json"))
+        .find(|manifest_path| manifest_path.is_file())
+        .and_then(|manifest_path| PackageManifest::from_path(manifest_path).ok())
+        .is_some_and(|manifest| self::package_name(&manifest).as_deref() == Some(package_name))
+}
+
+/// Replace the command at `bin_path` with a command that runs `target`.
+fn replace_command(target: &Path, bin_path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        fs::remove_file(bin_path)?;
+        std::os::unix::fs::symlink(target, bin_path)
+    }
+
+    #[cfg(windows)]
+    {
+        pacquet_fs::overwrite_file(bin_path, command_shim(target).as_bytes(), None)
+            .map(drop)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))
+    }
+}
+
+/// Create a command named `name` in `bin_dir` that runs `target`.
+fn create_command(target: &Path, bin_dir: &Path, name: &str) -> Result<(), (PathBuf, io::Error)> {
+    let bin_path = command_path(bin_dir, name);
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, &bin_path).map_err(|error| (bin_path, error))
+    }
+
+    #[cfg(windows)]
+    {
+        use std::io::Write;
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&bin_path)
+            .and_then(|mut file| file.write_all(command_shim(target).as_bytes()))
+            .map_err(|error| (bin_path, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn validate_bins() {
+        assert!(is_valid_command_name("foo"));
+        assert!(!is_valid_command_name(""));
+        assert!(!is_valid_command_name(".."));
+        assert!(!is_valid_command_name("foo/bar"));
+        assert!(is_inside_package(Path::new("./bin/foo.js")));
+        assert!(!is_inside_package(Path::new("../foo.js")));
+        assert!(!is_inside_package(Path::new("/usr/bin/foo")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_link_bins_of_package() {
+        use pretty_assertions::assert_eq;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let package_dir = dir.path().join("node_modules/.pacquet/foo@1.0.0/node_modules/foo");
+        let bin_dir = dir.path().join("node_modules/.bin");
+        fs::create_dir_all(package_dir.join("bin")).unwrap();
+        fs::write(
+            package_dir.join("package.json"),
+            r#"{ "name": "foo", "bin": { "foo": "bin/foo.js", "evil": "../../evil.js" } }"#,
+        )
+        .unwrap();
+        fs::write(package_dir.join("bin/foo.js"), "#!/usr/bin/env node\n").unwrap();
+
+        link_bins(&package_dir, &bin_dir).unwrap();
+        link_bins(&package_dir, &bin_dir).unwrap(); // existing commands are skipped
+
+        let bin_path = bin_dir.join("foo");
+        assert_eq!(fs::read_link(&bin_path).unwrap(), package_dir.join("bin/foo.js"));
+        let mode = fs::metadata(&bin_path).unwrap().permissions().mode();
+        assert!(pacquet_fs::file_mode::is_all_exec(mode));
+        assert!(!bin_dir.join("evil").exists());
+
+        drop(dir); // cleanup
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_unlink_bins_of_package() {
+        let dir = tempdir().unwrap();
+        let bin_dir = dir.path().join("node_modules/.bin");
+        let create_package = |name: &str| {
+            let package_dir = dir.path().join(format!("node_modules/.pacquet/{name}"));
+            fs::create_dir_all(&package_dir).unwrap();
+            fs::write(
+                package_dir.join("package.json"),
+                format!(
+                    r#"{{ "name": "{name}", "bin": {{ "{name}": "cli.js", "shared": "cli.js" }} }}"#
+                ),
+            )
+            .unwrap();
+            fs::write(package_dir.join("cli.js"), "#!/usr/bin/env node\n").unwrap();
+            package_dir
+        };
+        let foo_dir = create_package("foo");
+        let bar_dir = create_package("bar");
+        link_bins(&bar_dir, &bin_dir).unwrap();
+        link_bins(&foo_dir, &bin_dir).unwrap(); // "shared" belongs to bar
+
+        unlink_bins(&foo_dir, &bin_dir).unwrap();
+
+        assert!(!bin_dir.join("foo").exists());
+        assert!(bin_dir.join("bar").exists());
+        assert!(bin_dir.join("shared").exists());
+
+        drop(dir); // cleanup
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_replace_command_of_another_version() {
+        use pretty_assertions::assert_eq;
+
+        let dir = tempdir().unwrap();
+        let bin_dir = dir.path().join("node_modules/.bin");
+        let create_package = |version: &str, bin: &str| {
+            let package_dir =
+                dir.path().join(format!("node_modules/.pacquet/foo@{version}/node_modules/foo"));
+            fs::create_dir_all(&package_dir).unwrap();
+            fs::write(
+                package_dir.join("package.json"),
+                format!(
+                    r#"{{ "name": "foo", "version": "{version}", "bin": {{ "foo": "{bin}" }} }}"#
+                ),
+            )
+            .unwrap();
+            fs::write(package_dir.join(bin), "#!/usr/bin/env node\n").unwrap();
+            package_dir
+        };
+        let old_dir = create_package("1.0.0", "cli.js");
+        let new_dir = create_package("2.0.0", "main.js");
+
+        link_bins(&old_dir, &bin_dir).unwrap();
+        link_bins(&new_dir, &bin_dir).unwrap();
+        assert_eq!(fs::read_link(bin_dir.join("foo")).unwrap(), new_dir.join("main.js"));
+
+        eprintln!("CASE: the executable no longer exists");
+        fs::remove_dir_all(&new_dir).unwrap();
+        link_bins(&old_dir, &bin_dir).unwrap();
+        assert_eq!(fs::read_link(bin_dir.join("foo")).unwrap(), old_dir.join("cli.js"));
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn should_skip_broken_bins() {
+        let dir = tempdir().unwrap();
+        let bin_dir = dir.path().join("node_modules/.bin");
+
+        eprintln!("CASE: missing executable");
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{ "name": "foo", "bin": { "foo": "missing.js", "bar": "bar.js" } }"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("bar.js"), "#!/usr/bin/env node\n").unwrap();
+        link_bins(dir.path(), &bin_dir).unwrap();
+        assert!(!command_path(&bin_dir, "foo").exists());
+        assert!(command_path(&bin_dir, "bar").exists());
+
+        eprintln!("CASE: malformed bin field");
+        fs::write(dir.path().join("package.json"), r#"{ "name": "foo", "bin": 42 }"#).unwrap();
+        link_bins(dir.path(), &bin_dir).unwrap();
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn should_skip_package_without_bins() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("package.json"), r#"{ "name": "foo" }"#).unwrap();
+        let bin_dir = dir.path().join("node_modules/.bin");
+        link_bins(dir.path(), &bin_dir).unwrap();
+        assert!(!bin_dir.exists());
+        drop(dir); // cleanup
+    }
+}
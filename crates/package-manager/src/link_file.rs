@@ -1,5 +1,6 @@
 use derive_more::{Display, Error};
 use miette::Diagnostic;
+use pacquet_npmrc::PackageImportMethod;
 use std::{
     fs, io,
     path::{Path, PathBuf},
@@ -23,11 +24,18 @@ pub enum LinkFileError {
     },
 }
 
-/// Reflink or copy a single file.
+/// Import a single file from the store according to `import_method`.
 ///
 /// * If `target_link` already exists, do nothing.
 /// * If parent dir of `target_link` doesn't exist, it will be created.
-pub fn link_file(source_file: &Path, target_link: &Path) -> Result<(), LinkFileError> {
+/// * [`PackageImportMethod::Hardlink`] falls back to copying when the store and `target_link`
+///   are on different filesystems.
+/// * [`PackageImportMethod::Auto`] tries cloning, then hardlinking, then copying.
+pub fn link_file(
+    import_method: PackageImportMethod,
+    source_file: &Path,
+    target_link: &Path,
+) -> Result<(), LinkFileError> {
     if target_link.exists() {
         return Ok(());
     }
@@ -39,16 +47,37 @@ pub fn link_file(source_file: &Path, target_link: &Path) -> Result<(), LinkFileE
         })?;
     }
 
-    // TODO: add hardlink (https://github.com/pnpm/pacquet/issues/174)
     // NOTE: do not hardlink packages with postinstall
 
-    reflink_copy::reflink_or_copy(source_file, target_link).map_err(|error| {
-        LinkFileError::CreateLink {
-            from: source_file.to_path_buf(),
-            to: target_link.to_path_buf(),
-            error,
+    let result = match import_method {
+        PackageImportMethod::Auto => reflink_copy::reflink(source_file, target_link)
+            .or_else(|_| hardlink_or_copy(source_file, target_link)),
+        PackageImportMethod::Hardlink => hardlink_or_copy(source_file, target_link),
+        PackageImportMethod::Copy => fs::copy(source_file, target_link).map(drop),
+        PackageImportMethod::Clone => reflink_copy::reflink(source_file, target_link),
+        PackageImportMethod::CloneOrCopy => {
+            reflink_copy::reflink_or_copy(source_file, target_link).map(drop)
         }
-    })?;
+    };
+    result.map_err(|error| LinkFileError::CreateLink {
+        from: source_file.to_path_buf(),
+        to: target_link.to_path_buf(),
+        error,
+    })
+}
 
-    Ok(())
+/// Hardlink a file, or copy it if the hardlink would cross filesystem boundaries.
+fn hardlink_or_copy(source_file: &Path, target_link: &Path) -> io::Result<()> {
+    match fs::hard_link(source_file, target_link) {
+        Err(error) if error.raw_os_error() == Some(CROSS_DEVICE_LINK) => {
+            fs::copy(source_file, target_link).map(drop)
+        }
+        result => result,
+    }
 }
+
+/// OS error code of linking across filesystems.
+#[cfg(unix)]
+const CROSS_DEVICE_LINK: i32 = 18; // EXDEV
+#[cfg(windows)]
+const CROSS_DEVICE_LINK: i32 = 17; // ERROR_NOT_SAME_DEVICE
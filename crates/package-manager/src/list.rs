@@ -0,0 +1,268 @@
+use node_semver::{Range, Version};
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A package in the tree printed by `pacquet list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListNode {
+    /// Name of the package.
+    pub name: String,
+    /// Version range declared by the dependent.
+    pub range: String,
+    /// Installed version, `None` if the package is missing.
+    pub version: Option<String>,
+    /// Whether the installed version doesn't satisfy [`range`](ListNode::range).
+    pub unmet: bool,
+    /// Installed dependencies of the package.
+    pub dependencies: Vec<ListNode>,
+}
+
+/// Dependencies of the project by group, in the order of the requested groups.
+pub type ListTree = Vec<(DependencyGroup, Vec<ListNode>)>;
+
+/// This subroutine reconstructs the tree of installed dependencies from the `node_modules` directory.
+///
+/// Dependencies are resolved the way Node.js does from the real path of each package, so both
+/// the symlinks to the virtual store and hoisted layouts are supported.
+#[must_use]
+pub struct List<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    pub config: &'static Npmrc,
+    pub manifest: &'a PackageManifest,
+    pub dependency_groups: DependencyGroupList,
+    /// How deep the tree goes, `0` only lists the dependencies of the project.
+    pub depth: usize,
+}
+
+impl<'a, DependencyGroupList> List<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    /// Execute the subroutine.
+    pub fn run(self) -> ListTree {
+        let List { config, manifest, dependency_groups, depth } = self;
+
+        dependency_groups
+            .into_iter()
+            .map(|group| {
+                let mut nodes = manifest
+                    .dependencies([group])
+                    .map(|(name, range)| {
+                        let path = config.modules_dir.join(name);
+                        ListNode::load(name, range, &path, depth, &mut Vec::new())
+                    })
+                    .collect::<Vec<_>>();
+                nodes.sort_by(|a, b| a.name.cmp(&b.name));
+                (group, nodes)
+            })
+            .filter(|(_, nodes)| !nodes.is_empty())
+            .collect()
+    }
+}
+
+impl ListNode {
+    /// Create a node for a package that isn't installed.
+    fn missing(name: &str, range: &str) -> Self {
+        ListNode {
+            name: name.to_string(),
+            range: range.to_string(),
+            version: None,
+            unmet: false,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Read the package at `path` and its dependencies up to `depth` levels deep.
+    ///
+    /// `ancestors` holds the real paths of the dependents to stop at circular dependencies.
+    fn load(
+        name: &str,
+        range: &str,
+        path: &Path,
+        depth: usize,
+        ancestors: &mut Vec<PathBuf>,
+    ) -> Self {
+        let Ok(package_dir) = fs::canonicalize(path) else {
+            return ListNode::missing(name, range);
+        };
+        let Ok(manifest) = PackageManifest::from_path(package_dir.join("package.json")) else {
+            return ListNode::missing(name, range);
+        };
+        let version = manifest.value().get("version").and_then(|version| version.as_str());
+        let unmet = match (version.map(str::parse::<Version>), range.parse::<Range>()) {
+            (Some(Ok(version)), Ok(range)) => !version.satisfies(&range),
+            _ => false, // ranges that aren't semver (e.g. `link:` or git urls) can't be checked
+        };
+
+        let mut dependencies = Vec::new();
+        if depth > 0 && !ancestors.contains(&package_dir) {
+            ancestors.push(package_dir.clone());
+            for (group, skip_missing) in
+                [(DependencyGroup::Prod, false), (DependencyGroup::Optional, true)]
+            {
+                for (dependency_name, dependency_range) in manifest.dependencies([group]) {
+                    match resolve_dependency(&package_dir, dependency_name) {
+                        Some(path) => dependencies.push(ListNode::load(
+                            dependency_name,
+                            dependency_range,
+                            &path,
+                            depth - 1,
+                            ancestors,
+                        )),
+                        None if skip_missing => {} // optional dependencies may be skipped by design
+                        None => {
+                            dependencies.push(ListNode::missing(dependency_name, dependency_range))
+                        }
+                    }
+                }
+            }
+            ancestors.pop();
+            dependencies.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        ListNode {
+            name: name.to_string(),
+            range: range.to_string(),
+            version: version.map(ToString::to_string),
+            unmet,
+            dependencies,
+        }
+    }
+
+    /// Text that describes the package in the tree.
+    fn label(&self) -> String {
+        let ListNode { name, range, version, unmet, .. } = self;
+        match version {
+            None => format!("{name} {range} (missing)"),
+            Some(version) if *unmet => format!("{name} {version} (unmet {range})"),
+            Some(version) => format!("{name} {version}"),
+        }
+    }
+}
+
+/// Find the directory of dependency `name` of the package in `package_dir` like Node.js does.
+pub(crate) fn resolve_dependency(package_dir: &Path, name: &str) -> Option<PathBuf> {
+    package_dir
+        .ancestors()
+        .filter(|dir| !dir.ends_with("node_modules"))
+        .map(|dir| dir.join("node_modules").join(name))
+        .find(|path| path.exists())
+}
+
+/// Render the tree of dependencies as text.
+pub fn render_list_tree(tree: &ListTree) -> String {
+    tree.iter()
+        .map(|(group, nodes)| {
+            let group: &str = (*group).into();
+            let mut text = format!("{group}:\n");
+            for node in nodes {
+                text += &node.label();
+                text.push('\n');
+                render_children(&node.dependencies, "", &mut text);
+            }
+            text
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render the dependencies of a package with box-drawing branches.
+fn render_children(nodes: &[ListNode], prefix: &str, text: &mut String) {
+    for (index, node) in nodes.iter().enumerate() {
+        let is_last = index + 1 == nodes.len();
+        let (branch, indent) =
+            if is_last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        *text += &format!("{prefix}{branch}{}\n", node.label());
+        render_children(&node.dependencies, &format!("{prefix}{indent}"), text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_fs::symlink_dir;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    /// Create a package in the virtual store and return its directory.
+    fn create_package(virtual_store_dir: &Path, manifest: serde_json::Value) -> PathBuf {
+        let name = manifest["name"].as_str().unwrap();
+        let version = manifest["version"].as_str().unwrap();
+        let package_dir =
+            virtual_store_dir.join(format!("{name}@{version}")).join("node_modules").join(name);
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("package.json"), manifest.to_string()).unwrap();
+        package_dir
+    }
+
+    /// Link `target` as a dependency of the package in `dependent_dir`.
+    fn link_dependency(dependent_dir: &Path, target: &Path) {
+        let name = target.file_name().unwrap();
+        symlink_dir(target, &dependent_dir.parent().unwrap().join(name)).unwrap();
+    }
+
+    #[test]
+    fn should_list_installed_dependencies() {
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let virtual_store_dir = modules_dir.join(".pacquet");
+
+        let a = create_package(
+            &virtual_store_dir,
+            json!({
+                "name": "a",
+                "version": "1.0.0",
+                "dependencies": { "c": "^1.0.0", "d": "^2.0.0" },
+                "optionalDependencies": { "skipped": "1.0.0" },
+            }),
+        );
+        let c = create_package(
+            &virtual_store_dir,
+            json!({ "name": "c", "version": "1.0.0", "dependencies": { "e": "1.0.0" } }),
+        );
+        let d = create_package(&virtual_store_dir, json!({ "name": "d", "version": "1.0.0" }));
+        let e = create_package(&virtual_store_dir, json!({ "name": "e", "version": "1.0.0" }));
+        let x = create_package(&virtual_store_dir, json!({ "name": "x", "version": "1.0.0" }));
+        link_dependency(&a, &c);
+        link_dependency(&a, &d);
+        link_dependency(&c, &e);
+        symlink_dir(&a, &modules_dir.join("a")).unwrap();
+        symlink_dir(&x, &modules_dir.join("x")).unwrap();
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("a", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("b", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("x", "1.0.0", DependencyGroup::Dev).unwrap();
+
+        let mut config = Npmrc::new();
+        config.modules_dir = modules_dir;
+        let config = config.leak();
+
+        let list = |dependency_groups: &[DependencyGroup], depth| {
+            let dependency_groups = dependency_groups.to_vec();
+            render_list_tree(&List { config, manifest: &manifest, dependency_groups, depth }.run())
+        };
+        let all_groups = [DependencyGroup::Prod, DependencyGroup::Dev, DependencyGroup::Optional];
+
+        insta::assert_snapshot!(list(&all_groups, usize::MAX));
+
+        eprintln!("CASE: top-level only");
+        assert_eq!(
+            list(&all_groups, 0),
+            "dependencies:\na 1.0.0\nb ^1.0.0 (missing)\n\ndevDependencies:\nx 1.0.0\n"
+        );
+
+        eprintln!("CASE: dev only");
+        assert_eq!(list(&[DependencyGroup::Dev], 0), "devDependencies:\nx 1.0.0\n");
+
+        drop(dir); // cleanup
+    }
+}
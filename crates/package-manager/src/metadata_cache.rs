@@ -0,0 +1,14 @@
+use pacquet_npmrc::Npmrc;
+use pacquet_registry::MetadataCache;
+use std::time::Duration;
+
+/// Locate the cache of registry metadata in the store directory, honoring `prefer-offline`
+/// and `metadata-max-age`.
+pub(crate) fn metadata_cache(config: &Npmrc) -> MetadataCache {
+    MetadataCache {
+        dir: config.store_dir.metadata(),
+        prefer_offline: config.prefer_offline,
+        max_age: (config.metadata_max_age > 0)
+            .then(|| Duration::from_millis(config.metadata_max_age)),
+    }
+}
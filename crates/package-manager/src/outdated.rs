@@ -0,0 +1,256 @@
+use crate::metadata_cache;
+use derive_more::{Display, Error};
+use futures_util::future;
+use miette::Diagnostic;
+use node_semver::{Range, Version};
+use pacquet_lockfile::{Lockfile, RootProjectSnapshot};
+use pacquet_network::ThrottledClient;
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+use pacquet_registry::{Package, RegistryError};
+use pipe_trait::Pipe;
+use std::fs;
+
+/// A dependency whose current version is behind the registry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutdatedPackage {
+    /// Name of the dependency.
+    pub name: String,
+    /// Version in the lockfile, or in `node_modules` without a lockfile. `None` if it isn't installed.
+    pub current: Option<Version>,
+    /// Highest version that satisfies the range in `package.json`.
+    pub wanted: Option<Version>,
+    /// Version of the `latest` tag.
+    pub latest: Version,
+}
+
+/// How far the latest version of an [`OutdatedPackage`] is from the current version.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateType {
+    #[display("patch")]
+    Patch,
+    #[display("minor")]
+    Minor,
+    #[display("major")]
+    Major,
+}
+
+/// Error type of [`Outdated`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum OutdatedError {
+    #[diagnostic(transparent)]
+    FetchFromRegistry(#[error(source)] RegistryError),
+}
+
+/// This subroutine does everything `pacquet outdated` is supposed to do.
+///
+/// It doesn't modify anything, it only compares the current versions of the dependencies
+/// with the versions available on the registry.
+#[must_use]
+pub struct Outdated<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    pub http_client: &'a ThrottledClient,
+    pub config: &'static Npmrc,
+    pub manifest: &'a PackageManifest,
+    pub lockfile: Option<&'a Lockfile>,
+    pub dependency_groups: DependencyGroupList,
+}
+
+impl<'a, DependencyGroupList> Outdated<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    /// Execute the subroutine, return the outdated dependencies sorted by name.
+    pub async fn run(self) -> Result<Vec<OutdatedPackage>, OutdatedError> {
+        let Outdated { http_client, config, manifest, lockfile, dependency_groups } = self;
+
+        let dependency_groups = dependency_groups.into_iter().collect::<Vec<_>>();
+        let current_version = |name: &str| match lockfile {
+            Some(lockfile) => locked_version(lockfile, &dependency_groups, name),
+            None => installed_version(config, name),
+        };
+
+        let mut outdated = manifest
+            .dependencies(dependency_groups.iter().copied())
+            .map(|(name, version_range)| (name, version_range, current_version(name)))
+            .map(|(name, version_range, current)| async move {
+                let registry = config.registry_for(name);
+                let cache = metadata_cache(config);
+                let package =
+                    Package::fetch_from_registry(name, http_client, registry, Some(&cache))
+                        .await
+                        .map_err(OutdatedError::FetchFromRegistry)?;
+                // ranges that aren't semver (e.g. `workspace:*` or git urls) have no wanted version
+                let wanted = version_range
+                    .parse::<Range>()
+                    .ok()
+                    .and_then(|_| package.pinned_version(version_range))
+                    .map(|version| version.version.clone());
+                Ok(OutdatedPackage {
+                    name: name.to_string(),
+                    current,
+                    wanted,
+                    latest: package.latest().version.clone(),
+                })
+            })
+            .pipe(future::try_join_all)
+            .await?
+            .into_iter()
+            .filter(OutdatedPackage::is_outdated)
+            .collect::<Vec<_>>();
+
+        outdated.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(outdated)
+    }
+}
+
+impl OutdatedPackage {
+    /// Whether the current version is missing or lower than the wanted or the latest version.
+    fn is_outdated(&self) -> bool {
+        let OutdatedPackage { current, wanted, latest, .. } = self;
+        let Some(current) = current else { return true };
+        current < latest || wanted.as_ref().is_some_and(|wanted| current < wanted)
+    }
+
+    /// Type of the update from the current version to the latest version.
+    ///
+    /// `None` if the current version is missing or isn't lower than the latest version.
+    pub fn update_type(&self) -> Option<UpdateType> {
+        let OutdatedPackage { current, latest, .. } = self;
+        let current = current.as_ref().filter(|current| *current < latest)?;
+        Some(if current.major != latest.major {
+            UpdateType::Major
+        } else if current.minor != latest.minor {
+            UpdateType::Minor
+        } else {
+            UpdateType::Patch
+        })
+    }
+}
+
+/// Version of a direct dependency in the lockfile.
+fn locked_version(
+    lockfile: &Lockfile,
+    dependency_groups: &[DependencyGroup],
+    name: &str,
+) -> Option<Version> {
+    let RootProjectSnapshot::Single(project_snapshot) = &lockfile.project_snapshot else {
+        return None; // TODO: support monorepo
+    };
+    project_snapshot
+        .dependencies_by_groups(dependency_groups.iter().copied())
+        .find(|(pkg_name, _)| pkg_name.to_string() == name)
+        .map(|(_, spec)| spec.version.version().clone())
+}
+
+/// Version of a direct dependency in the `node_modules` directory.
+fn installed_version(config: &Npmrc, name: &str) -> Option<Version> {
+    let manifest_path = config.modules_dir.join(name).join("package.json");
+    let manifest = PackageManifest::from_path(manifest_path).ok()?;
+    manifest.value().get("version")?.as_str()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_registry_mock::CustomRegistry;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn should_list_outdated_dependencies() {
+        let mut registry = CustomRegistry::new().await;
+        let packument = |name: &'static str, latest: &str, versions: &[&str]| {
+            let versions = versions
+                .iter()
+                .map(|&version| {
+                    let dist =
+                        json!({ "tarball": format!("{}-/{name}-{version}.tgz", registry.url()) });
+                    (version.to_string(), json!({ "name": name, "version": version, "dist": dist }))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            json!({ "name": name, "dist-tags": { "latest": latest }, "versions": versions })
+        };
+        let packuments = [
+            ("behind", packument("behind", "2.0.0", &["1.0.0", "1.2.0", "2.0.0"])),
+            ("up-to-date", packument("up-to-date", "1.0.0", &["1.0.0"])),
+            ("missing", packument("missing", "1.0.0", &["1.0.0"])),
+        ];
+        for (name, packument) in &packuments {
+            registry.add_packument(name, packument).await;
+        }
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        for (name, version) in [("behind", "1.0.0"), ("up-to-date", "1.0.0")] {
+            fs::create_dir_all(modules_dir.join(name)).unwrap();
+            fs::write(
+                modules_dir.join(name).join("package.json"),
+                json!({ "name": name, "version": version }).to_string(),
+            )
+            .unwrap();
+        }
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("behind", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("up-to-date", "^1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("missing", "^1.0.0", DependencyGroup::Dev).unwrap();
+
+        let mut config = Npmrc::new();
+        config.modules_dir = modules_dir;
+        config.registry = registry.url();
+        let config = config.leak();
+
+        let received = Outdated {
+            http_client: &Default::default(),
+            config,
+            manifest: &manifest,
+            lockfile: None,
+            dependency_groups: [DependencyGroup::Prod, DependencyGroup::Dev],
+        }
+        .run()
+        .await
+        .unwrap();
+        dbg!(&received);
+
+        let version = |version: &str| version.parse::<Version>().unwrap();
+        let expected = [
+            OutdatedPackage {
+                name: "behind".to_string(),
+                current: Some(version("1.0.0")),
+                wanted: Some(version("1.2.0")),
+                latest: version("2.0.0"),
+            },
+            OutdatedPackage {
+                name: "missing".to_string(),
+                current: None,
+                wanted: Some(version("1.0.0")),
+                latest: version("1.0.0"),
+            },
+        ];
+        assert_eq!(received, expected);
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn update_type() {
+        let outdated = |current: Option<&str>, latest: &str| OutdatedPackage {
+            name: "foo".to_string(),
+            current: current.map(|version| version.parse().unwrap()),
+            wanted: None,
+            latest: latest.parse().unwrap(),
+        };
+        assert_eq!(outdated(Some("1.0.0"), "1.0.1").update_type(), Some(UpdateType::Patch));
+        assert_eq!(outdated(Some("1.0.0-rc.1"), "1.0.0").update_type(), Some(UpdateType::Patch));
+        assert_eq!(outdated(Some("1.0.9"), "1.1.0").update_type(), Some(UpdateType::Minor));
+        assert_eq!(outdated(Some("1.9.9"), "2.0.0").update_type(), Some(UpdateType::Major));
+        assert_eq!(outdated(Some("2.0.0"), "2.0.0").update_type(), None);
+        assert_eq!(outdated(None, "2.0.0").update_type(), None);
+    }
+}
@@ -0,0 +1,207 @@
+use node_semver::Range;
+use pacquet_package_manifest::{PackageManifest, PackageManifestError};
+use pacquet_registry::PackageVersion;
+use std::collections::HashMap;
+
+/// Specifier of an override that removes the dependency.
+const REMOVE_SPECIFIER: &str = "-";
+
+/// Selects packages by name, and optionally by a version range, e.g. `foo` or `@scope/foo@^1.0.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSelector {
+    pub name: String,
+    pub version_range: Option<String>,
+}
+
+/// An entry of `pnpm.overrides`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    /// The selector as written in the manifest, e.g. `foo@1>bar`.
+    pub key: String,
+    /// The package that depends on the overridden package, e.g. `foo@1` of `foo@1>bar`.
+    pub parent: Option<PackageSelector>,
+    /// The overridden package.
+    pub target: PackageSelector,
+    /// The version specifier that replaces the one of the dependency, `-` to remove it.
+    pub specifier: String,
+}
+
+/// Overrides of the version specifiers of dependencies, read from `pnpm.overrides`.
+///
+/// Overrides with a parent selector take precedence over the ones without.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Overrides {
+    pub overrides: Vec<Override>,
+}
+
+impl PackageSelector {
+    /// Parse `name` or `name@range`. The `@` of a scope isn't a separator.
+    fn parse(selector: &str) -> Self {
+        let separator = selector.char_indices().skip(1).find(|&(_, char)| char == '@');
+        match separator {
+            Some((index, _)) => PackageSelector {
+                name: selector[..index].to_string(),
+                version_range: Some(selector[index + 1..].to_string()),
+            },
+            None => PackageSelector { name: selector.to_string(), version_range: None },
+        }
+    }
+
+    /// Whether a dependency of package `name` that wants `version_range` is selected.
+    ///
+    /// A selector with a range only selects dependencies whose ranges are within it.
+    fn selects_range(&self, name: &str, version_range: &str) -> bool {
+        if self.name != name {
+            return false;
+        }
+        let Some(selector_range) = &self.version_range else { return true };
+        if selector_range == version_range {
+            return true;
+        }
+        match (selector_range.parse::<Range>(), version_range.parse::<Range>()) {
+            (Ok(selector_range), Ok(version_range)) => selector_range.allows_all(&version_range),
+            _ => false,
+        }
+    }
+
+    /// Whether the resolved `package` is selected.
+    fn selects_package(&self, package: &PackageVersion) -> bool {
+        if self.name != package.name {
+            return false;
+        }
+        let Some(selector_range) = &self.version_range else { return true };
+        selector_range.parse::<Range>().is_ok_and(|range| package.version.satisfies(&range))
+    }
+}
+
+impl Overrides {
+    /// Parse the entries of `pnpm.overrides`.
+    pub fn parse(entries: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut overrides = entries
+            .into_iter()
+            .map(|(key, specifier)| {
+                let (parent, target) = match key.split_once('>') {
+                    Some((parent, target)) => (Some(PackageSelector::parse(parent)), target),
+                    None => (None, key.as_str()),
+                };
+                let target = PackageSelector::parse(target);
+                Override { key, parent, target, specifier }
+            })
+            .collect::<Vec<_>>();
+        overrides.sort_by_key(|entry| entry.parent.is_none()); // stable, keeps the manifest order
+        Overrides { overrides }
+    }
+
+    /// Read the overrides of `manifest`.
+    pub fn from_manifest(manifest: &PackageManifest) -> Result<Self, PackageManifestError> {
+        manifest.overrides().map(Overrides::parse)
+    }
+
+    /// Version range of dependency `name` that `parent` wants as `version_range`, after the
+    /// overrides have been applied. `parent` is `None` for the dependencies of the project.
+    ///
+    /// Returns `None` if an override removes the dependency.
+    pub fn apply<'a>(
+        &'a self,
+        parent: Option<&PackageVersion>,
+        name: &str,
+        version_range: &'a str,
+    ) -> Option<&'a str> {
+        let matching = self.overrides.iter().find(|entry| {
+            let parent_matches = match (&entry.parent, parent) {
+                (None, _) => true,
+                (Some(selector), Some(parent)) => selector.selects_package(parent),
+                (Some(_), None) => false,
+            };
+            parent_matches && entry.target.selects_range(name, version_range)
+        });
+        match matching {
+            None => Some(version_range),
+            Some(entry) if entry.specifier == REMOVE_SPECIFIER => None,
+            Some(entry) => Some(&entry.specifier),
+        }
+    }
+
+    /// The `overrides` field of the lockfile, `None` if there's no override.
+    pub fn to_lockfile(&self) -> Option<HashMap<String, String>> {
+        let overrides = self
+            .overrides
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.specifier.clone()))
+            .collect::<HashMap<_, _>>();
+        (!overrides.is_empty()).then_some(overrides)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn package(name: &str, version: &str) -> PackageVersion {
+        serde_json::from_value(json!({
+            "name": name,
+            "version": version,
+            "dist": { "tarball": format!("https://registry.npmjs.org/{name}/-/{name}-{version}.tgz") },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_selectors() {
+        let overrides = Overrides::parse([
+            ("foo".to_string(), "1.0.0".to_string()),
+            ("@scope/bar@^1".to_string(), "1.2.0".to_string()),
+            ("parent@2>@scope/baz".to_string(), "-".to_string()),
+        ]);
+        let selector = |name: &str, version_range: Option<&str>| PackageSelector {
+            name: name.to_string(),
+            version_range: version_range.map(ToString::to_string),
+        };
+        let targets = overrides
+            .overrides
+            .iter()
+            .map(|entry| (entry.parent.clone(), entry.target.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                (Some(selector("parent", Some("2"))), selector("@scope/baz", None)),
+                (None, selector("foo", None)),
+                (None, selector("@scope/bar", Some("^1"))),
+            ],
+        );
+    }
+
+    #[test]
+    fn apply_overrides() {
+        let overrides = Overrides::parse([
+            ("foo".to_string(), "1.0.0".to_string()),
+            ("bar@^1".to_string(), "1.5.0".to_string()),
+            ("parent>foo".to_string(), "2.0.0".to_string()),
+            ("parent@1>removed".to_string(), "-".to_string()),
+        ]);
+        let parent = package("parent", "1.0.0");
+        let other = package("other", "1.0.0");
+
+        macro_rules! case {
+            ($parent:expr, $name:literal, $range:literal => $output:expr) => {{
+                eprintln!("CASE: {:?} {:?}", $name, $range);
+                assert_eq!(overrides.apply($parent, $name, $range), $output);
+            }};
+        }
+
+        case!(None, "foo", "^0.1.0" => Some("1.0.0"));
+        case!(Some(&other), "foo", "^0.1.0" => Some("1.0.0"));
+        case!(Some(&parent), "foo", "^0.1.0" => Some("2.0.0"));
+        case!(None, "bar", "^1.2.0" => Some("1.5.0"));
+        case!(None, "bar", "^2.0.0" => Some("^2.0.0"));
+        case!(Some(&parent), "removed", "1" => None);
+        case!(Some(&other), "removed", "1" => Some("1"));
+        case!(None, "unrelated", "1" => Some("1"));
+
+        assert_eq!(Overrides::default().to_lockfile(), None);
+        assert_eq!(overrides.to_lockfile().unwrap().get("parent>foo").unwrap(), "2.0.0");
+    }
+}
@@ -0,0 +1,229 @@
+use crate::ResolvedVersions;
+use derive_more::Error;
+use miette::Diagnostic;
+use node_semver::{Range, Version};
+use pacquet_diagnostics::warn;
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::PackageManifest;
+use pacquet_registry::PackageVersion;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+/// A required peer dependency of a resolved package.
+#[derive(Debug, Clone)]
+pub struct PeerDependency {
+    /// The package that declares the peer dependency.
+    pub dependent: PackageVersion,
+    /// Name of the peer.
+    pub name: String,
+    /// Version range of the peer that the dependent wants.
+    pub version_range: String,
+}
+
+impl PeerDependency {
+    /// Whether `version` of the peer satisfies the dependent.
+    ///
+    /// Invalid version ranges are never satisfied.
+    pub fn is_satisfied_by(&self, version: &Version) -> bool {
+        self.version_range.parse::<Range>().is_ok_and(|range| version.satisfies(&range))
+    }
+}
+
+/// List the required peer dependencies of the packages in `resolved_versions`, grouped by
+/// the names of the peers.
+///
+/// The dependents of each peer are sorted by their names and versions.
+pub fn list_peer_dependencies(
+    resolved_versions: &ResolvedVersions,
+) -> HashMap<String, Vec<PeerDependency>> {
+    let mut visited = HashSet::new();
+    let mut peers = HashMap::<String, Vec<PeerDependency>>::new();
+    for entry in resolved_versions.iter() {
+        let Some(dependent) = entry.value().get() else { continue };
+        if !visited.insert(dependent.to_virtual_store_name()) {
+            continue;
+        }
+        for (name, version_range) in dependent.required_peer_dependencies() {
+            peers.entry(name.to_string()).or_default().push(PeerDependency {
+                dependent: dependent.clone(),
+                name: name.to_string(),
+                version_range: version_range.to_string(),
+            });
+        }
+    }
+    for dependents in peers.values_mut() {
+        dependents.sort_by_key(|peer| peer.dependent.to_virtual_store_name());
+    }
+    peers
+}
+
+/// A required peer dependency that is missing or resolved outside of its range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmetPeerDependency {
+    /// Name and version of the package that declares the peer dependency, e.g. `foo@1.0.0`.
+    pub dependent: String,
+    /// Name of the peer.
+    pub peer: String,
+    /// Version range of the peer that the dependent wants.
+    pub wanted: String,
+    /// Version of the peer that the dependent resolves, `None` if it's missing.
+    pub found: Option<String>,
+}
+
+/// Error when `strict-peer-dependencies` is enabled and some peer dependencies are unmet.
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(pacquet_package_manager::unmet_peer_dependencies),
+    help("Add the peers to package.json, or set strict-peer-dependencies=false to only warn about them")
+)]
+pub struct UnmetPeerDependencies {
+    /// Unmet peer dependencies sorted by dependent, then by peer.
+    pub peers: Vec<UnmetPeerDependency>,
+}
+
+impl fmt::Display for UnmetPeerDependencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unmet peer dependencies:")?;
+        for UnmetPeerDependency { dependent, peer, wanted, found } in &self.peers {
+            match found {
+                Some(found) => write!(f, "\n{dependent}: {peer}@{wanted} wanted, found {found}")?,
+                None => write!(f, "\n{dependent}: missing {peer}@{wanted}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the required peer dependencies of the installed packages that are missing or resolved
+/// outside of their ranges.
+///
+/// A dependent resolves a peer from its own directory in the virtual store, or else from the
+/// `node_modules` directory of the project.
+pub fn find_unmet_peer_dependencies(
+    config: &Npmrc,
+    resolved_versions: &ResolvedVersions,
+) -> Vec<UnmetPeerDependency> {
+    let mut unmet = list_peer_dependencies(resolved_versions)
+        .into_values()
+        .flatten()
+        .filter_map(|peer| {
+            let dependent = peer.dependent.to_virtual_store_name();
+            let linked = config.virtual_store_dir.join(&dependent).join("node_modules");
+            let found = installed_version(&linked.join(&peer.name))
+                .or_else(|| installed_version(&config.modules_dir.join(&peer.name)));
+            if found.as_ref().is_some_and(|found| peer.is_satisfied_by(found)) {
+                return None;
+            }
+            Some(UnmetPeerDependency {
+                dependent,
+                peer: peer.name,
+                wanted: peer.version_range,
+                found: found.map(|found| found.to_string()),
+            })
+        })
+        .collect::<Vec<_>>();
+    unmet.sort_by(|a, b| (&a.dependent, &a.peer).cmp(&(&b.dependent, &b.peer)));
+    unmet
+}
+
+/// Fail on unmet peer dependencies if `strict-peer-dependencies` is enabled, otherwise only
+/// show a warning.
+pub fn check_peer_dependencies(
+    config: &Npmrc,
+    resolved_versions: &ResolvedVersions,
+) -> Result<(), UnmetPeerDependencies> {
+    let peers = find_unmet_peer_dependencies(config, resolved_versions);
+    if peers.is_empty() {
+        return Ok(());
+    }
+    let error = UnmetPeerDependencies { peers };
+    if config.strict_peer_dependencies {
+        return Err(error);
+    }
+    warn(error);
+    Ok(())
+}
+
+/// Read the version of the package installed at `package_dir`.
+fn installed_version(package_dir: &Path) -> Option<Version> {
+    let manifest = PackageManifest::from_path(package_dir.join("package.json")).ok()?;
+    manifest.value().get("version")?.as_str()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::{fs, sync::Arc};
+    use tempfile::tempdir;
+    use tokio::sync::OnceCell;
+
+    /// Record that package `name` was resolved to `version` with `peer_dependencies`.
+    fn resolve(
+        resolved_versions: &ResolvedVersions,
+        name: &str,
+        version: &str,
+        peer_dependencies: serde_json::Value,
+    ) {
+        let package: PackageVersion = serde_json::from_value(json!({
+            "name": name,
+            "version": version,
+            "dist": { "tarball": format!("https://registry.npmjs.org/{name}/-/{name}-{version}.tgz") },
+            "peerDependencies": peer_dependencies,
+        }))
+        .unwrap();
+        let key = (name.to_string(), version.to_string());
+        resolved_versions.insert(key, Arc::new(OnceCell::new_with(Some(package))));
+    }
+
+    /// Write the `package.json` of an installed package.
+    fn install(package_dir: &Path, name: &str, version: &str) {
+        fs::create_dir_all(package_dir).unwrap();
+        let manifest = json!({ "name": name, "version": version }).to_string();
+        fs::write(package_dir.join("package.json"), manifest).unwrap();
+    }
+
+    #[test]
+    fn should_find_unmet_peer_dependencies() {
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let mut config = Npmrc::new();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir.clone();
+        let config = config.leak();
+
+        let resolved_versions = ResolvedVersions::new();
+        resolve(&resolved_versions, "a", "1.0.0", json!({ "linked": "^1.0.0", "root": "^2.0.0" }));
+        resolve(&resolved_versions, "b", "1.0.0", json!({ "root": "^3.0.0", "missing": "1" }));
+        install(&config.virtual_store_dir.join("a@1.0.0/node_modules/linked"), "linked", "1.2.0");
+        install(&modules_dir.join("root"), "root", "2.1.0");
+
+        let unmet = find_unmet_peer_dependencies(config, &resolved_versions);
+        dbg!(&unmet);
+        let expected =
+            [("b@1.0.0", "missing", "1", None), ("b@1.0.0", "root", "^3.0.0", Some("2.1.0"))].map(
+                |(dependent, peer, wanted, found)| UnmetPeerDependency {
+                    dependent: dependent.to_string(),
+                    peer: peer.to_string(),
+                    wanted: wanted.to_string(),
+                    found: found.map(ToString::to_string),
+                },
+            );
+        assert_eq!(unmet, expected);
+
+        eprintln!("CASE: only a warning without strict-peer-dependencies");
+        assert!(check_peer_dependencies(config, &resolved_versions).is_ok());
+
+        eprintln!("CASE: an error with strict-peer-dependencies");
+        config.strict_peer_dependencies = true;
+        let error = check_peer_dependencies(config, &resolved_versions).unwrap_err();
+        eprintln!("{error}");
+        assert_eq!(error.peers, expected);
+
+        drop(dir); // cleanup
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{resolve_dependency, unlink_package, RemoveError};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_npmrc::{NodeLinker, Npmrc};
+use pacquet_package_manifest::{DependencyGroup, PackageManifest};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Dependency groups of the project that a production install keeps.
+const PRODUCTION_GROUPS: [DependencyGroup; 2] = [DependencyGroup::Prod, DependencyGroup::Optional];
+
+/// Dependency groups of the installed packages, whose devDependencies are never installed.
+const PACKAGE_GROUPS: [DependencyGroup; 3] =
+    [DependencyGroup::Prod, DependencyGroup::Optional, DependencyGroup::Peer];
+
+/// This subroutine removes the packages that only the devDependencies of the project need,
+/// like `pnpm install --prod` does.
+///
+/// The symlinks and commands of the devDependencies are removed from `node_modules`, then the
+/// entries of the virtual store that the remaining dependencies can't reach are removed.
+#[must_use]
+pub struct PruneDevDependencies<'a> {
+    pub config: &'static Npmrc,
+    pub manifest: &'a PackageManifest,
+}
+
+/// Error type of [`PruneDevDependencies`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum PruneDevDependenciesError {
+    #[diagnostic(transparent)]
+    UnlinkPackage(#[error(source)] RemoveError),
+
+    #[display("Failed to remove {path:?} from the virtual store: {error}")]
+    #[diagnostic(code(pacquet_package_manager::remove_virtual_store_entry))]
+    RemoveVirtualStoreEntry {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+}
+
+impl<'a> PruneDevDependencies<'a> {
+    /// Execute the subroutine.
+    pub fn run(self) -> Result<(), PruneDevDependenciesError> {
+        let PruneDevDependencies { config, manifest } = self;
+
+        if config.node_linker != NodeLinker::Isolated {
+            tracing::debug!(target: "pacquet::install", "Skip pruning devDependencies because node-linker isn't isolated");
+            return Ok(());
+        }
+
+        let production =
+            manifest.dependencies(PRODUCTION_GROUPS).map(|(name, _)| name).collect::<HashSet<_>>();
+        for (name, _) in manifest.dependencies([DependencyGroup::Dev]) {
+            if !production.contains(name) {
+                tracing::info!(target: "pacquet::install", ?name, "Remove dev dependency");
+                unlink_package(config, name).map_err(PruneDevDependenciesError::UnlinkPackage)?;
+            }
+        }
+
+        let Ok(virtual_store_dir) = fs::canonicalize(&config.virtual_store_dir) else {
+            return Ok(()); // nothing is installed
+        };
+        let reachable = reachable_packages(&config.modules_dir, production);
+        let kept_entries = reachable
+            .iter()
+            .filter_map(|package_dir| package_dir.strip_prefix(&virtual_store_dir).ok())
+            .filter_map(|path| path.components().next())
+            .map(|entry| entry.as_os_str().to_os_string())
+            .collect::<HashSet<OsString>>();
+
+        let Ok(entries) = fs::read_dir(&virtual_store_dir) else { return Ok(()) };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // skip the files of the virtual store and the directory of hoisted packages
+            let is_package_entry =
+                entry.file_name() != "node_modules" && path.join("node_modules").is_dir();
+            if !is_package_entry || kept_entries.contains(&entry.file_name()) {
+                continue;
+            }
+            tracing::info!(target: "pacquet::install", ?path, "Remove orphaned package from the virtual store");
+            fs::remove_dir_all(&path).map_err(|error| {
+                PruneDevDependenciesError::RemoveVirtualStoreEntry { path, error }
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Real paths of the packages that the dependencies `names` of the project reach through the
+/// dependencies, optional dependencies, and peer dependencies of the installed packages.
+fn reachable_packages<'a>(
+    modules_dir: &Path,
+    names: impl IntoIterator<Item = &'a str>,
+) -> HashSet<PathBuf> {
+    let mut reachable = HashSet::new();
+    let mut pending = names
+        .into_iter()
+        .filter_map(|name| fs::canonicalize(modules_dir.join(name)).ok())
+        .collect::<Vec<_>>();
+    while let Some(package_dir) = pending.pop() {
+        if !reachable.insert(package_dir.clone()) {
+            continue;
+        }
+        let Ok(manifest) = PackageManifest::from_path(package_dir.join("package.json")) else {
+            continue;
+        };
+        let dependencies = manifest
+            .dependencies(PACKAGE_GROUPS)
+            .filter_map(|(name, _)| resolve_dependency(&package_dir, name))
+            .filter_map(|path| fs::canonicalize(path).ok());
+        pending.extend(dependencies);
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_fs::symlink_dir;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    /// Create `name@1.0.0` in the virtual store with symlinks to its `dependencies`.
+    fn create_package(virtual_store_dir: &Path, name: &str, dependencies: &[&str]) -> PathBuf {
+        let entry_modules_dir = virtual_store_dir.join(format!("{name}@1.0.0/node_modules"));
+        let package_dir = entry_modules_dir.join(name);
+        fs::create_dir_all(&package_dir).unwrap();
+        let manifest = json!({
+            "name": name,
+            "version": "1.0.0",
+            "dependencies": dependencies
+                .iter()
+                .map(|dependency| (dependency.to_string(), json!("1.0.0")))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        fs::write(package_dir.join("package.json"), manifest.to_string()).unwrap();
+        for dependency in dependencies {
+            let target = virtual_store_dir.join(format!("{dependency}@1.0.0/node_modules"));
+            symlink_dir(&target.join(dependency), &entry_modules_dir.join(dependency)).unwrap();
+        }
+        package_dir
+    }
+
+    #[test]
+    fn should_prune_dev_dependencies() {
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let virtual_store_dir = modules_dir.join(".pacquet");
+        create_package(&virtual_store_dir, "shared", &[]);
+        create_package(&virtual_store_dir, "dev-only", &[]);
+        let prod = create_package(&virtual_store_dir, "prod", &["shared"]);
+        let dev = create_package(&virtual_store_dir, "dev", &["shared", "dev-only"]);
+        symlink_dir(&prod, &modules_dir.join("prod")).unwrap();
+        symlink_dir(&dev, &modules_dir.join("dev")).unwrap();
+
+        let manifest_path = dir.path().join("package.json");
+        let manifest = json!({
+            "dependencies": { "prod": "1.0.0" },
+            "devDependencies": { "dev": "1.0.0" },
+        });
+        fs::write(&manifest_path, manifest.to_string()).unwrap();
+        let manifest = PackageManifest::from_path(manifest_path).unwrap();
+
+        let mut config = Npmrc::new();
+        config.modules_dir = modules_dir.clone();
+        config.virtual_store_dir = virtual_store_dir.clone();
+        let config = config.leak();
+
+        PruneDevDependencies { config, manifest: &manifest }.run().unwrap();
+
+        eprintln!("CASE: dev dependencies are unlinked");
+        assert!(modules_dir.join("prod").exists());
+        assert!(fs::symlink_metadata(modules_dir.join("dev")).is_err());
+
+        eprintln!("CASE: only the reachable packages stay in the virtual store");
+        let mut entries = fs::read_dir(&virtual_store_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, ["prod@1.0.0", "shared@1.0.0"]);
+
+        drop(dir); // cleanup
+    }
+}
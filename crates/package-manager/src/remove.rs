@@ -0,0 +1,339 @@
+use crate::{unlink_bins, LinkBinsError};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_fs::remove_symlink_dir;
+use pacquet_lockfile::{Lockfile, PkgName, RootProjectSnapshot, SaveLockfileError};
+use pacquet_npmrc::Npmrc;
+use pacquet_package_manifest::{DependencyGroup, PackageManifest, PackageManifestError};
+use std::{fs, io, path::PathBuf};
+
+/// Dependency groups whose packages are linked into the `node_modules` directory.
+const INSTALLED_DEPENDENCY_GROUPS: [DependencyGroup; 3] =
+    [DependencyGroup::Prod, DependencyGroup::Dev, DependencyGroup::Optional];
+
+/// This subroutine does everything `pacquet remove` is supposed to do.
+///
+/// The package is removed from the targeted dependency groups of `package.json` and of the lockfile.
+/// Its symlink and commands are only removed from `node_modules` when no other group still references it.
+#[must_use]
+pub struct Remove<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    pub config: &'static Npmrc,
+    pub manifest: &'a mut PackageManifest,
+    pub lockfile: Option<&'a mut Lockfile>,
+    pub dependency_groups: DependencyGroupList,
+    pub package_name: &'a str, // TODO: support multiple arguments
+}
+
+/// Error type of [`Remove`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum RemoveError {
+    #[display("Cannot remove {package_name:?}: no such dependency found")]
+    #[diagnostic(code(pacquet_package_manager::no_such_dependency))]
+    NoSuchDependency {
+        #[error(not(source))]
+        package_name: String,
+    },
+
+    #[display("Removing dependencies from a lockfile of multiple projects is not supported yet")]
+    #[diagnostic(code(pacquet_package_manager::unsupported_multi_project_lockfile))]
+    UnsupportedMultiProjectLockfile,
+
+    #[diagnostic(transparent)]
+    UnlinkBins(#[error(source)] LinkBinsError),
+
+    #[display("Failed to remove symlink at {symlink_path:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::remove_symlink))]
+    RemoveSymlink {
+        symlink_path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed save the manifest file: {_0}")]
+    SaveManifest(#[error(source)] PackageManifestError),
+
+    #[diagnostic(transparent)]
+    SaveLockfile(#[error(source)] SaveLockfileError),
+}
+
+impl<'a, DependencyGroupList> Remove<'a, DependencyGroupList>
+where
+    DependencyGroupList: IntoIterator<Item = DependencyGroup>,
+{
+    /// Execute the subroutine.
+    pub fn run(self) -> Result<(), RemoveError> {
+        let Remove { config, manifest, lockfile, dependency_groups, package_name } = self;
+
+        let mut removed_groups = Vec::new();
+        for group in dependency_groups {
+            if manifest.remove_dependency(package_name, group) {
+                removed_groups.push(group);
+            }
+        }
+        if removed_groups.is_empty() {
+            return Err(RemoveError::NoSuchDependency { package_name: package_name.to_string() });
+        }
+
+        let still_installed = manifest
+            .dependencies(INSTALLED_DEPENDENCY_GROUPS)
+            .any(|(name, _)| name == package_name);
+
+        if let Some(lockfile) = lockfile {
+            remove_from_lockfile(lockfile, package_name, &removed_groups, still_installed)?;
+            let lockfile_dir = manifest.path().parent().expect("manifest path has a parent");
+            lockfile.write_to(lockfile_dir).map_err(RemoveError::SaveLockfile)?;
+        }
+
+        if !still_installed {
+            unlink_package(config, package_name)?;
+        }
+
+        manifest.save().map_err(RemoveError::SaveManifest)?;
+
+        Ok(())
+    }
+}
+
+/// Remove `package_name` from the root project of the lockfile.
+///
+/// The specifier is kept if the package is still referenced by another dependency group.
+/// The packages that are no longer reachable from the project are removed.
+fn remove_from_lockfile(
+    lockfile: &mut Lockfile,
+    package_name: &str,
+    removed_groups: &[DependencyGroup],
+    still_referenced: bool,
+) -> Result<(), RemoveError> {
+    let RootProjectSnapshot::Single(project_snapshot) = &mut lockfile.project_snapshot else {
+        return Err(RemoveError::UnsupportedMultiProjectLockfile);
+    };
+    let Ok(name) = PkgName::parse(package_name) else {
+        return Ok(()); // an invalid name can't be in the lockfile
+    };
+    for &group in removed_groups {
+        project_snapshot.remove_dependency(&name, group);
+    }
+    if !still_referenced {
+        if let Some(specifiers) = &mut project_snapshot.specifiers {
+            specifiers.remove(package_name);
+        }
+    }
+    lockfile.prune_packages();
+    Ok(())
+}
+
+/// Remove the symlink of `package_name` and its commands from the `node_modules` directory.
+pub(crate) fn unlink_package(config: &Npmrc, package_name: &str) -> Result<(), RemoveError> {
+    let symlink_path = config.modules_dir.join(package_name);
+    let Ok(package_dir) = fs::read_link(&symlink_path) else {
+        return Ok(()); // not installed
+    };
+
+    unlink_bins(&package_dir, &config.modules_dir.join(".bin")).map_err(RemoveError::UnlinkBins)?;
+
+    remove_symlink_dir(&symlink_path).map_err(|error| RemoveError::RemoveSymlink {
+        symlink_path: symlink_path.clone(),
+        error,
+    })?;
+
+    // remove the scope directory of a scoped package if it is now empty
+    if let Some(scope_dir) = symlink_path.parent().filter(|dir| *dir != config.modules_dir) {
+        let _ = fs::remove_dir(scope_dir); // fails when the directory is not empty, which is fine
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacquet_fs::symlink_dir;
+    use pacquet_testing_utils::fs::is_symlink_or_junction;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const INTEGRITY: &str = "sha512-Sq1itGUKUX1ap7GgZlrzdBydjbsJL/NSQt/4wkAxUJ7/OS5c2WkoN6WSpWc2Yc5wtKMZOUA8VCs/j2XJadN3HA==";
+
+    fn install_fake_package(modules_dir: &Path, name: &str) -> PathBuf {
+        let package_dir = modules_dir.join(".pacquet").join(name).join("node_modules").join(name);
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("package.json"), format!(r#"{{ "name": "{name}" }}"#)).unwrap();
+        let symlink_path = modules_dir.join(name);
+        fs::create_dir_all(symlink_path.parent().unwrap()).unwrap();
+        symlink_dir(&package_dir, &symlink_path).unwrap();
+        symlink_path
+    }
+
+    #[test]
+    fn should_keep_packages_referenced_by_other_groups() {
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let foo_path = install_fake_package(&modules_dir, "foo");
+        let bar_path = install_fake_package(&modules_dir, "@scope/bar");
+
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("foo", "1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("foo", "1.0.0", DependencyGroup::Dev).unwrap();
+        manifest.add_dependency("@scope/bar", "1.0.0", DependencyGroup::Prod).unwrap();
+
+        let lockfile_yaml = r#"lockfileVersion: '6.0'
+dependencies:
+  foo:
+    specifier: 1.0.0
+    version: 1.0.0
+  '@scope/bar':
+    specifier: 1.0.0
+    version: 1.0.0
+devDependencies:
+  foo:
+    specifier: 1.0.0
+    version: 1.0.0
+packages:
+  /foo@1.0.0:
+    resolution: {integrity: INTEGRITY}
+    dependencies:
+      shared: 1.0.0
+  /@scope/bar@1.0.0:
+    resolution: {integrity: INTEGRITY}
+    dependencies:
+      shared: 1.0.0
+      only-bar: 1.0.0
+  /shared@1.0.0:
+    resolution: {integrity: INTEGRITY}
+  /only-bar@1.0.0:
+    resolution: {integrity: INTEGRITY}
+"#;
+        let mut lockfile: Lockfile =
+            serde_yaml::from_str(&lockfile_yaml.replace("INTEGRITY", INTEGRITY)).unwrap();
+
+        let mut config = Npmrc::new();
+        config.modules_dir = modules_dir.clone();
+        let config = config.leak();
+
+        let dependencies = |manifest: &PackageManifest, group| {
+            manifest.dependencies([group]).map(|(name, _)| name.to_string()).collect::<Vec<_>>()
+        };
+        let package_keys = |lockfile: &Lockfile| {
+            let mut keys = lockfile
+                .packages
+                .iter()
+                .flatten()
+                .map(|(dependency_path, _)| dependency_path.to_string())
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        eprintln!("CASE: remove from the dev group only");
+        Remove {
+            config,
+            manifest: &mut manifest,
+            lockfile: Some(&mut lockfile),
+            dependency_groups: [DependencyGroup::Dev],
+            package_name: "foo",
+        }
+        .run()
+        .unwrap();
+        assert_eq!(dependencies(&manifest, DependencyGroup::Prod).len(), 2);
+        assert!(dependencies(&manifest, DependencyGroup::Dev).is_empty());
+        assert!(is_symlink_or_junction(&foo_path).unwrap());
+        assert_eq!(
+            package_keys(&lockfile),
+            ["/@scope/bar@1.0.0", "/foo@1.0.0", "/only-bar@1.0.0", "/shared@1.0.0"],
+        );
+
+        eprintln!("CASE: remove from all groups");
+        Remove {
+            config,
+            manifest: &mut manifest,
+            lockfile: Some(&mut lockfile),
+            dependency_groups: INSTALLED_DEPENDENCY_GROUPS,
+            package_name: "foo",
+        }
+        .run()
+        .unwrap();
+        assert!(!foo_path.exists());
+        eprintln!("The packages that bar still depends on are kept");
+        assert_eq!(
+            package_keys(&lockfile),
+            ["/@scope/bar@1.0.0", "/only-bar@1.0.0", "/shared@1.0.0"],
+        );
+        Remove {
+            config,
+            manifest: &mut manifest,
+            lockfile: Some(&mut lockfile),
+            dependency_groups: INSTALLED_DEPENDENCY_GROUPS,
+            package_name: "@scope/bar",
+        }
+        .run()
+        .unwrap();
+        assert!(dependencies(&manifest, DependencyGroup::Prod).is_empty());
+        assert!(!bar_path.exists());
+        assert!(!modules_dir.join("@scope").exists());
+        assert!(modules_dir.join(".pacquet/foo/node_modules/foo").is_dir());
+        assert_eq!(package_keys(&lockfile), Vec::<String>::new());
+
+        eprintln!("CASE: the lockfile is saved");
+        let saved: Lockfile =
+            serde_yaml::from_str(&fs::read_to_string(dir.path().join("pnpm-lock.yaml")).unwrap())
+                .unwrap();
+        assert_eq!(saved, lockfile);
+
+        eprintln!("CASE: the manifest is saved");
+        let saved = PackageManifest::from_path(dir.path().join("package.json")).unwrap();
+        assert_eq!(saved.dependencies(INSTALLED_DEPENDENCY_GROUPS).count(), 0);
+
+        eprintln!("CASE: the package is no longer a dependency");
+        let error = Remove {
+            config,
+            manifest: &mut manifest,
+            lockfile: None,
+            dependency_groups: INSTALLED_DEPENDENCY_GROUPS,
+            package_name: "foo",
+        }
+        .run()
+        .unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, RemoveError::NoSuchDependency { .. }));
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn should_refuse_multi_project_lockfile() {
+        let dir = tempdir().unwrap();
+        let mut manifest =
+            PackageManifest::create_if_needed(dir.path().join("package.json")).unwrap();
+        manifest.add_dependency("foo", "1.0.0", DependencyGroup::Prod).unwrap();
+        let mut lockfile: Lockfile =
+            serde_yaml::from_str("lockfileVersion: '6.0'\nimporters: {}\n").unwrap();
+
+        let mut config = Npmrc::new();
+        config.modules_dir = dir.path().join("node_modules");
+        let config = config.leak();
+
+        let error = Remove {
+            config,
+            manifest: &mut manifest,
+            lockfile: Some(&mut lockfile),
+            dependency_groups: INSTALLED_DEPENDENCY_GROUPS,
+            package_name: "foo",
+        }
+        .run()
+        .unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, RemoveError::UnsupportedMultiProjectLockfile));
+
+        eprintln!("The manifest is left untouched");
+        let saved = PackageManifest::from_path(dir.path().join("package.json")).unwrap();
+        assert_eq!(saved.dependencies([DependencyGroup::Prod]).count(), 0);
+
+        drop(dir); // cleanup
+    }
+}
@@ -0,0 +1,278 @@
+use node_semver::Version;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Dependencies of a package, the key is the name and the value is the exact version.
+pub type DependencyVersions = HashMap<String, String>;
+
+/// Placement of every package in a hoisted `node_modules`.
+///
+/// The key is the location of the package relative to the root `node_modules`,
+/// e.g. `foo` for `node_modules/foo` or `foo/node_modules/bar` for `node_modules/foo/node_modules/bar`.
+/// The value is the version of the package installed at that location.
+pub type HoistedLayout = BTreeMap<String, String>;
+
+/// This subroutine computes the filesystem layout of `node-linker=hoisted`.
+///
+/// **Brief overview:**
+/// * Only the packages that are reachable from the root project are placed.
+/// * Direct dependencies of the root project are placed at the root.
+/// * For every other package name, the version with the most dependents is placed at the root.
+///   Ties are resolved in favor of the highest version.
+/// * A dependency whose version doesn't match the one visible from its dependent
+///   is nested under the `node_modules` of the dependent.
+#[must_use]
+pub struct ResolveHoistedLayout<'a> {
+    /// Direct dependencies of the root project.
+    pub root_dependencies: &'a DependencyVersions,
+    /// Dependencies of every package in the graph, keyed by name and version.
+    pub packages: &'a HashMap<(String, String), DependencyVersions>,
+}
+
+impl<'a> ResolveHoistedLayout<'a> {
+    /// Execute the subroutine.
+    pub fn run(self) -> HoistedLayout {
+        let ResolveHoistedLayout { root_dependencies, packages } = self;
+
+        let mut dependent_counts = HashMap::<&str, HashMap<&str, usize>>::new();
+        for dependencies in reachable_packages(root_dependencies, packages) {
+            for (name, version) in dependencies {
+                *dependent_counts.entry(name).or_default().entry(version).or_default() += 1;
+            }
+        }
+
+        let mut layout: HoistedLayout = root_dependencies.clone().into_iter().collect();
+        for (name, counts) in dependent_counts {
+            if layout.contains_key(name) {
+                continue;
+            }
+            let version = counts
+                .into_iter()
+                .max_by(|(a_version, a_count), (b_version, b_count)| {
+                    a_count.cmp(b_count).then_with(|| compare_versions(a_version, b_version))
+                })
+                .map(|(version, _)| version)
+                .expect("each name has at least one version");
+            layout.insert(name.to_string(), version.to_string());
+        }
+
+        let hoisted = layout.clone();
+        for (name, version) in &hoisted {
+            let mut ancestors = vec![(name.as_str(), version.as_str())];
+            self.place_dependencies(name, &mut ancestors, &mut layout);
+        }
+
+        layout
+    }
+
+    /// Nest the dependencies of the package at `location` if the versions visible from it don't match.
+    fn place_dependencies<'b>(
+        &self,
+        location: &str,
+        ancestors: &mut Vec<(&'b str, &'b str)>,
+        layout: &mut HoistedLayout,
+    ) where
+        'a: 'b,
+    {
+        let &(name, version) = ancestors.last().expect("ancestors is not empty");
+        let Some(dependencies) = self.packages.get(&(name.to_string(), version.to_string())) else {
+            return;
+        };
+        let dependencies: &'b DependencyVersions = dependencies;
+
+        let mut dependencies = dependencies.iter().collect::<Vec<_>>();
+        dependencies.sort(); // make the result deterministic
+
+        for (dependency_name, dependency_version) in dependencies {
+            let visible = visible_locations(location, dependency_name)
+                .find_map(|location| layout.get(&location));
+            if visible == Some(dependency_version) {
+                continue;
+            }
+
+            let nested_location = format!("{location}/node_modules/{dependency_name}");
+            layout.insert(nested_location.clone(), dependency_version.clone());
+
+            let dependency = (dependency_name.as_str(), dependency_version.as_str());
+            if ancestors.contains(&dependency) {
+                continue; // circular dependency
+            }
+            ancestors.push(dependency);
+            self.place_dependencies(&nested_location, ancestors, layout);
+            ancestors.pop();
+        }
+    }
+}
+
+/// List the dependencies of the packages that are reachable from `root_dependencies`.
+fn reachable_packages<'a>(
+    root_dependencies: &'a DependencyVersions,
+    packages: &'a HashMap<(String, String), DependencyVersions>,
+) -> Vec<&'a DependencyVersions> {
+    let mut visited = HashSet::<(&str, &str)>::new();
+    let mut queue = root_dependencies.iter().collect::<Vec<_>>();
+    let mut reachable = Vec::new();
+    while let Some((name, version)) = queue.pop() {
+        if !visited.insert((name.as_str(), version.as_str())) {
+            continue;
+        }
+        if let Some(dependencies) = packages.get(&(name.clone(), version.clone())) {
+            queue.extend(dependencies);
+            reachable.push(dependencies);
+        }
+    }
+    reachable
+}
+
+/// List the locations from which a package at `location` could find `name`, nearest first.
+fn visible_locations<'a>(location: &'a str, name: &'a str) -> impl Iterator<Item = String> + 'a {
+    let segments = location.split("/node_modules/").collect::<Vec<_>>();
+    (0..=segments.len()).rev().map(move |count| match count {
+        0 => name.to_string(),
+        _ => format!("{}/node_modules/{name}", segments[..count].join("/node_modules/")),
+    })
+}
+
+/// Compare versions by semver, fallback to string comparison for invalid versions.
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<Version>(), b.parse::<Version>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn versions<const N: usize>(list: [(&str, &str); N]) -> DependencyVersions {
+        list.into_iter().map(|(name, version)| (name.to_string(), version.to_string())).collect()
+    }
+
+    fn packages<const N: usize>(
+        list: [(&str, &str, DependencyVersions); N],
+    ) -> HashMap<(String, String), DependencyVersions> {
+        list.into_iter()
+            .map(|(name, version, dependencies)| {
+                ((name.to_string(), version.to_string()), dependencies)
+            })
+            .collect()
+    }
+
+    fn layout<const N: usize>(list: [(&str, &str); N]) -> HoistedLayout {
+        list.into_iter()
+            .map(|(location, version)| (location.to_string(), version.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn conflicting_versions() {
+        let root_dependencies = versions([("a", "1.0.0"), ("b", "1.0.0"), ("d", "1.0.0")]);
+        let packages = packages([
+            ("a", "1.0.0", versions([("c", "1.0.0")])),
+            ("b", "1.0.0", versions([("c", "2.0.0")])),
+            ("d", "1.0.0", versions([("c", "2.0.0")])),
+            ("c", "1.0.0", versions([])),
+            ("c", "2.0.0", versions([])),
+        ]);
+        let received =
+            ResolveHoistedLayout { root_dependencies: &root_dependencies, packages: &packages }
+                .run();
+        dbg!(&received);
+        let expected = layout([
+            ("a", "1.0.0"),
+            ("a/node_modules/c", "1.0.0"),
+            ("b", "1.0.0"),
+            ("c", "2.0.0"),
+            ("d", "1.0.0"),
+        ]);
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn root_dependencies_take_precedence() {
+        let root_dependencies = versions([("a", "1.0.0"), ("c", "1.0.0")]);
+        let packages = packages([
+            ("a", "1.0.0", versions([("b", "1.0.0")])),
+            ("b", "1.0.0", versions([("c", "2.0.0")])),
+            ("c", "1.0.0", versions([])),
+            ("c", "2.0.0", versions([])),
+        ]);
+        let received =
+            ResolveHoistedLayout { root_dependencies: &root_dependencies, packages: &packages }
+                .run();
+        dbg!(&received);
+        let expected =
+            layout([("a", "1.0.0"), ("b", "1.0.0"), ("b/node_modules/c", "2.0.0"), ("c", "1.0.0")]);
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn deeply_nested_conflicts() {
+        let root_dependencies =
+            versions([("a", "1.0.0"), ("c", "1.0.0"), ("d", "1.0.0"), ("e", "1.0.0")]);
+        let packages = packages([
+            ("a", "1.0.0", versions([("b", "2.0.0")])),
+            ("b", "1.0.0", versions([])),
+            ("b", "2.0.0", versions([("c", "2.0.0")])),
+            ("c", "1.0.0", versions([])),
+            ("c", "2.0.0", versions([])),
+            ("d", "1.0.0", versions([("b", "1.0.0")])),
+            ("e", "1.0.0", versions([("b", "1.0.0")])),
+        ]);
+        let received =
+            ResolveHoistedLayout { root_dependencies: &root_dependencies, packages: &packages }
+                .run();
+        dbg!(&received);
+        let expected = layout([
+            ("a", "1.0.0"),
+            ("a/node_modules/b", "2.0.0"),
+            ("a/node_modules/b/node_modules/c", "2.0.0"),
+            ("b", "1.0.0"),
+            ("c", "1.0.0"),
+            ("d", "1.0.0"),
+            ("e", "1.0.0"),
+        ]);
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn circular_dependencies_terminate() {
+        let root_dependencies = versions([("a", "1.0.0"), ("b", "2.0.0")]);
+        let packages = packages([
+            ("a", "1.0.0", versions([("b", "1.0.0")])),
+            ("b", "1.0.0", versions([("a", "2.0.0")])),
+            ("a", "2.0.0", versions([("b", "1.0.0")])),
+            ("b", "2.0.0", versions([])),
+        ]);
+        let received =
+            ResolveHoistedLayout { root_dependencies: &root_dependencies, packages: &packages }
+                .run();
+        dbg!(&received);
+        let expected = layout([
+            ("a", "1.0.0"),
+            ("a/node_modules/b", "1.0.0"),
+            ("a/node_modules/b/node_modules/a", "2.0.0"),
+            ("b", "2.0.0"),
+        ]);
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn unreachable_packages_are_ignored() {
+        let root_dependencies = versions([("a", "1.0.0")]);
+        let packages = packages([
+            ("a", "1.0.0", versions([("c", "1.0.0")])),
+            ("c", "1.0.0", versions([])),
+            ("c", "2.0.0", versions([])),
+            ("x", "1.0.0", versions([("c", "2.0.0")])),
+            ("y", "1.0.0", versions([("c", "2.0.0")])),
+        ]);
+        let received =
+            ResolveHoistedLayout { root_dependencies: &root_dependencies, packages: &packages }
+                .run();
+        dbg!(&received);
+        let expected = layout([("a", "1.0.0"), ("c", "1.0.0")]);
+        assert_eq!(received, expected);
+    }
+}
@@ -0,0 +1,12 @@
+use pacquet_npmrc::Npmrc;
+use pacquet_tarball::RetryOpts;
+use std::time::Duration;
+
+/// Read the options for retrying tarball downloads from the `fetch-retry*` settings.
+pub(crate) fn tarball_retry_opts(config: &Npmrc) -> RetryOpts {
+    RetryOpts {
+        retries: config.fetch_retries,
+        min_timeout: Duration::from_millis(config.fetch_retry_mintimeout),
+        max_timeout: Duration::from_millis(config.fetch_retry_maxtimeout),
+    }
+}
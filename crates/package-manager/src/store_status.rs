@@ -0,0 +1,134 @@
+use pacquet_lockfile::{DependencyPath, Lockfile};
+use pacquet_npmrc::Npmrc;
+use pacquet_store_dir::PackageFilesIssue;
+
+/// An installed package that differs from its files in the store directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifiedPackage {
+    /// Key of the package in the lockfile.
+    pub dependency_path: DependencyPath,
+    /// Discrepancies of the package, sorted by file path.
+    pub issues: Vec<PackageFilesIssue>,
+}
+
+/// This subroutine checks the packages in the virtual store at `node_modules/.pacquet` against
+/// the store directory.
+///
+/// Packages of the lockfile that aren't installed in the virtual store are skipped, and so are
+/// packages whose resolutions have no integrity.
+#[must_use]
+pub struct StoreStatus<'a> {
+    pub config: &'static Npmrc,
+    pub lockfile: &'a Lockfile,
+}
+
+impl<'a> StoreStatus<'a> {
+    /// Execute the subroutine.
+    pub fn run(self) -> Vec<ModifiedPackage> {
+        let StoreStatus { config, lockfile } = self;
+
+        let mut modified = lockfile
+            .packages
+            .iter()
+            .flatten()
+            .filter_map(|(dependency_path, package_snapshot)| {
+                let integrity = package_snapshot.resolution.integrity()?;
+                let package_specifier = &dependency_path.package_specifier;
+                // node_modules/.pacquet/pkg-name@x.y.z/node_modules/pkg-name
+                let package_dir = config
+                    .virtual_store_dir
+                    .join(package_specifier.to_virtual_store_name())
+                    .join("node_modules")
+                    .join(package_specifier.name.to_string());
+                if !package_dir.is_dir() {
+                    return None;
+                }
+                let issues = config.store_dir.check_package_files(integrity, &package_dir);
+                (!issues.is_empty())
+                    .then(|| ModifiedPackage { dependency_path: dependency_path.clone(), issues })
+            })
+            .collect::<Vec<_>>();
+        modified.sort_by_key(|package| package.dependency_path.to_string());
+        modified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InstallFrozenLockfile;
+    use pacquet_npmrc::PackageImportMethod;
+    use pacquet_package_manifest::DependencyGroup;
+    use pacquet_registry_mock::CustomRegistry;
+    use pacquet_testing_utils::tarball::TarballBuilder;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn should_report_modified_packages() {
+        let mut registry = CustomRegistry::new().await;
+        let mut package_entries = String::new();
+        for name in ["foo", "bar"] {
+            let tarball = TarballBuilder::new()
+                .file("package/package.json", format!(r#"{{"name":"{name}","version":"1.0.0"}}"#))
+                .file("package/index.js", format!("module.exports = '{name}'\n"))
+                .build();
+            let integrity = tarball.integrity.to_string();
+            let url = registry.add_tarball(&format!("{name}-1.0.0.tgz"), tarball.data).await;
+            package_entries += &format!(
+                "  /{name}@1.0.0:\n    resolution: {{integrity: {integrity}, tarball: '{url}'}}\n"
+            );
+        }
+        let lockfile = format!(
+            "lockfileVersion: '6.0'\n\
+             dependencies:\n  foo:\n    specifier: 1.0.0\n    version: 1.0.0\n\
+             devDependencies:\n  bar:\n    specifier: 1.0.0\n    version: 1.0.0\n\
+             packages:\n{package_entries}"
+        );
+        eprintln!("LOCKFILE:\n{lockfile}");
+        let lockfile: Lockfile = serde_yaml::from_str(&lockfile).unwrap();
+
+        let dir = tempdir().unwrap();
+        let modules_dir = dir.path().join("node_modules");
+        let mut config = Npmrc::new();
+        config.store_dir = dir.path().join("pacquet-store").into();
+        config.virtual_store_dir = modules_dir.join(".pacquet");
+        config.modules_dir = modules_dir.clone();
+        config.package_import_method = PackageImportMethod::Copy; // keep the store intact
+        let config = config.leak();
+
+        InstallFrozenLockfile {
+            http_client: &Default::default(),
+            config,
+            project_snapshot: &lockfile.project_snapshot,
+            packages: lockfile.packages.as_ref(),
+            dependency_groups: [DependencyGroup::Prod, DependencyGroup::Dev],
+            patched_dependencies: None,
+            lockfile_dir: dir.path(),
+        }
+        .run()
+        .await
+        .unwrap();
+
+        eprintln!("CASE: untouched packages");
+        let modified = StoreStatus { config, lockfile: &lockfile }.run();
+        dbg!(&modified);
+        assert!(modified.is_empty());
+
+        eprintln!("CASE: a modified package");
+        fs::write(modules_dir.join(".pacquet/foo@1.0.0/node_modules/foo/index.js"), "modified")
+            .unwrap();
+        let modified = StoreStatus { config, lockfile: &lockfile }.run();
+        dbg!(&modified);
+        assert_eq!(
+            modified,
+            [ModifiedPackage {
+                dependency_path: "/foo@1.0.0".parse().unwrap(),
+                issues: vec![PackageFilesIssue::ModifiedFile { path: "index.js".to_string() }],
+            }],
+        );
+
+        drop((dir, registry)); // cleanup
+    }
+}
@@ -1,8 +1,11 @@
-use crate::symlink_package;
-use pacquet_lockfile::{PkgName, PkgNameVerPeer, RootProjectSnapshot};
+use crate::{link_bins, symlink_package, virtual_store_name, LinkBinsError, SymlinkPackageError};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_lockfile::{PatchInfo, PkgName, PkgNameVerPeer, RootProjectSnapshot};
 use pacquet_npmrc::Npmrc;
 use pacquet_package_manifest::DependencyGroup;
 use rayon::prelude::*;
+use std::collections::HashMap;
 
 /// This subroutine creates symbolic links in the `node_modules` directory for
 /// the direct dependencies. The targets of the link are the virtual directories.
@@ -10,6 +13,8 @@ use rayon::prelude::*;
 /// If package `foo@x.y.z` is declared as a dependency in `package.json`,
 /// symlink `foo -> .pacquet/foo@x.y.z/node_modules/foo` shall be created
 /// in the `node_modules` directory.
+///
+/// The executables of the direct dependencies are linked into `node_modules/.bin`.
 #[must_use]
 pub struct SymlinkDirectDependencies<'a, DependencyGroupList>
 where
@@ -18,6 +23,17 @@ where
     pub config: &'static Npmrc,
     pub project_snapshot: &'a RootProjectSnapshot,
     pub dependency_groups: DependencyGroupList,
+    pub patched_dependencies: Option<&'a HashMap<String, PatchInfo>>,
+}
+
+/// Error type of [`SymlinkDirectDependencies`].
+#[derive(Debug, Display, Error, Diagnostic)]
+pub enum SymlinkDirectDependenciesError {
+    #[diagnostic(transparent)]
+    SymlinkPackage(#[error(source)] SymlinkPackageError),
+
+    #[diagnostic(transparent)]
+    LinkBins(#[error(source)] LinkBinsError),
 }
 
 impl<'a, DependencyGroupList> SymlinkDirectDependencies<'a, DependencyGroupList>
@@ -25,8 +41,13 @@ where
     DependencyGroupList: IntoIterator<Item = DependencyGroup>,
 {
     /// Execute the subroutine.
-    pub fn run(self) {
-        let SymlinkDirectDependencies { config, project_snapshot, dependency_groups } = self;
+    pub fn run(self) -> Result<(), SymlinkDirectDependenciesError> {
+        let SymlinkDirectDependencies {
+            config,
+            project_snapshot,
+            dependency_groups,
+            patched_dependencies,
+        } = self;
 
         let RootProjectSnapshot::Single(project_snapshot) = project_snapshot else {
             panic!("Monorepo is not yet supported"); // TODO: properly propagate this error
@@ -36,22 +57,23 @@ where
             .dependencies_by_groups(dependency_groups)
             .collect::<Vec<_>>()
             .par_iter()
-            .for_each(|(name, spec)| {
+            .try_for_each(|(name, spec)| {
                 // TODO: the code below is not optimal
+                let package_specifier =
+                    PkgNameVerPeer::new(PkgName::clone(name), spec.version.clone());
                 let virtual_store_name =
-                    PkgNameVerPeer::new(PkgName::clone(name), spec.version.clone())
-                        .to_virtual_store_name();
+                    virtual_store_name(&package_specifier, patched_dependencies);
 
                 let name_str = name.to_string();
-                symlink_package(
-                    &config
-                        .virtual_store_dir
-                        .join(virtual_store_name)
-                        .join("node_modules")
-                        .join(&name_str),
-                    &config.modules_dir.join(&name_str),
-                )
-                .expect("symlink pkg"); // TODO: properly propagate this error
-            });
+                let package_dir = config
+                    .virtual_store_dir
+                    .join(virtual_store_name)
+                    .join("node_modules")
+                    .join(&name_str);
+                symlink_package(&package_dir, &config.modules_dir.join(&name_str))
+                    .map_err(SymlinkDirectDependenciesError::SymlinkPackage)?;
+                link_bins(&package_dir, &config.modules_dir.join(".bin"))
+                    .map_err(SymlinkDirectDependenciesError::LinkBins)
+            })
     }
 }
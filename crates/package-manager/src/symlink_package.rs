@@ -1,6 +1,6 @@
 use derive_more::{Display, Error};
 use miette::Diagnostic;
-use pacquet_fs::symlink_dir;
+use pacquet_fs::{remove_symlink_dir, symlink_dir};
 use std::{
     fs,
     io::{self, ErrorKind},
@@ -29,7 +29,8 @@ pub enum SymlinkPackageError {
 /// Create symlink for a package.
 ///
 /// * If ancestors of `symlink_path` don't exist, they will be created recursively.
-/// * If `symlink_path` already exists, skip.
+/// * If `symlink_path` already exists, skip, unless it is a symlink to another target (e.g. a
+///   previous version of the package), which is replaced.
 /// * If `symlink_path` doesn't exist, a symlink pointing to `symlink_target` will be created.
 pub fn symlink_package(
     symlink_target: &Path,
@@ -43,17 +44,52 @@ pub fn symlink_package(
             error,
         })?;
     }
-    if let Err(error) = symlink_dir(symlink_target, symlink_path) {
-        match error.kind() {
-            ErrorKind::AlreadyExists => {}
-            _ => {
-                return Err(SymlinkPackageError::SymlinkDir {
-                    symlink_target: symlink_target.to_path_buf(),
-                    symlink_path: symlink_path.to_path_buf(),
-                    error,
-                })
+    let result = match symlink_dir(symlink_target, symlink_path) {
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            let is_outdated =
+                fs::read_link(symlink_path).is_ok_and(|current| current != symlink_target);
+            if !is_outdated {
+                return Ok(());
             }
+            remove_symlink_dir(symlink_path)
+                .and_then(|()| symlink_dir(symlink_target, symlink_path))
         }
+        result => result,
+    };
+    result.map_err(|error| SymlinkPackageError::SymlinkDir {
+        symlink_target: symlink_target.to_path_buf(),
+        symlink_path: symlink_path.to_path_buf(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_replace_symlink_to_another_target() {
+        let dir = tempdir().unwrap();
+        let old_target = dir.path().join(".pacquet/foo@1.0.0/node_modules/foo");
+        let new_target = dir.path().join(".pacquet/foo@2.0.0/node_modules/foo");
+        fs::create_dir_all(&old_target).unwrap();
+        fs::create_dir_all(&new_target).unwrap();
+        let symlink_path = dir.path().join("foo");
+
+        symlink_package(&old_target, &symlink_path).unwrap();
+        assert_eq!(fs::read_link(&symlink_path).unwrap(), old_target);
+
+        eprintln!("CASE: same target");
+        symlink_package(&old_target, &symlink_path).unwrap();
+        assert_eq!(fs::read_link(&symlink_path).unwrap(), old_target);
+
+        eprintln!("CASE: another target");
+        symlink_package(&new_target, &symlink_path).unwrap();
+        assert_eq!(fs::read_link(&symlink_path).unwrap(), new_target);
+        assert!(old_target.is_dir());
+
+        drop(dir); // cleanup
     }
-    Ok(())
 }
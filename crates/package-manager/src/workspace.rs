@@ -0,0 +1,271 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_package_manifest::{DependencyGroup, PackageManifest, PackageManifestError};
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Name of the file that marks the root of a workspace.
+pub const WORKSPACE_MANIFEST_NAME: &str = "pnpm-workspace.yaml";
+
+/// Prefix of the version specifiers that refer to workspace projects, e.g. `workspace:*`.
+pub const WORKSPACE_PROTOCOL: &str = "workspace:";
+
+/// Content of `pnpm-workspace.yaml`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct WorkspaceManifest {
+    /// Globs of the project directories relative to the workspace root.
+    /// Globs that start with `!` exclude the matching directories.
+    #[serde(default)]
+    pub packages: Vec<String>,
+}
+
+/// A workspace of projects that are installed together.
+pub struct Workspace {
+    /// Directory of `pnpm-workspace.yaml`.
+    pub root_dir: PathBuf,
+    /// Projects of the workspace sorted by their directories, starting with the root project.
+    pub projects: Vec<WorkspaceProject>,
+}
+
+/// A project of a [`Workspace`].
+pub struct WorkspaceProject {
+    /// Directory of the project.
+    pub dir: PathBuf,
+    /// Content of `package.json` of the project.
+    pub manifest: PackageManifest,
+}
+
+/// Error type of [`Workspace::find`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[non_exhaustive]
+pub enum WorkspaceError {
+    #[display("Failed to read {path:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::read_workspace_manifest))]
+    ReadWorkspaceManifest {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to parse {path:?}: {error}")]
+    #[diagnostic(code(pacquet_package_manager::parse_workspace_manifest))]
+    ParseWorkspaceManifest {
+        path: PathBuf,
+        #[error(source)]
+        error: serde_yaml::Error,
+    },
+
+    #[diagnostic(transparent)]
+    LoadProjectManifest(#[error(source)] PackageManifestError),
+}
+
+impl WorkspaceManifest {
+    /// Whether the directory at `path` relative to the workspace root is a project of the workspace.
+    ///
+    /// `path` must use `/` as the separator.
+    pub fn includes(&self, path: &str) -> bool {
+        let (excludes, includes): (Vec<_>, Vec<_>) =
+            self.packages.iter().partition(|pattern| pattern.starts_with('!'));
+        includes.iter().any(|pattern| matches_glob(pattern, path))
+            && !excludes.iter().any(|pattern| matches_glob(&pattern[1..], path))
+    }
+}
+
+impl Workspace {
+    /// Find the workspace that contains `dir` by looking for `pnpm-workspace.yaml` in `dir`
+    /// and its ancestors, then load the manifests of its projects.
+    ///
+    /// Returns `None` if `dir` isn't in a workspace.
+    pub fn find(dir: &Path) -> Result<Option<Self>, WorkspaceError> {
+        let Some(root_dir) =
+            dir.ancestors().find(|dir| dir.join(WORKSPACE_MANIFEST_NAME).is_file())
+        else {
+            return Ok(None);
+        };
+        Workspace::load(root_dir).map(Some)
+    }
+
+    /// Load the workspace at `root_dir`.
+    pub fn load(root_dir: &Path) -> Result<Self, WorkspaceError> {
+        let path = root_dir.join(WORKSPACE_MANIFEST_NAME);
+        let content = fs::read_to_string(&path)
+            .map_err(|error| WorkspaceError::ReadWorkspaceManifest { path: path.clone(), error })?;
+        let workspace_manifest: WorkspaceManifest = match content.trim().is_empty() {
+            true => WorkspaceManifest::default(),
+            false => serde_yaml::from_str(&content)
+                .map_err(|error| WorkspaceError::ParseWorkspaceManifest { path, error })?,
+        };
+
+        let mut project_dirs = WalkDir::new(root_dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != "node_modules")
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_dir())
+            .filter(|entry| entry.path().join("package.json").is_file())
+            .filter_map(|entry| {
+                let path = entry.path().strip_prefix(root_dir).ok()?;
+                let path = path.components().map(|part| part.as_os_str().to_string_lossy());
+                let path = path.collect::<Vec<_>>().join("/");
+                workspace_manifest.includes(&path).then(|| entry.into_path())
+            })
+            .collect::<Vec<_>>();
+        project_dirs.sort();
+        let root_manifest = root_dir.join("package.json");
+        if root_manifest.is_file() {
+            project_dirs.insert(0, root_dir.to_path_buf());
+        }
+
+        let projects = project_dirs
+            .into_iter()
+            .map(|dir| {
+                let manifest = PackageManifest::from_path(dir.join("package.json"))
+                    .map_err(WorkspaceError::LoadProjectManifest)?;
+                Ok(WorkspaceProject { dir, manifest })
+            })
+            .collect::<Result<Vec<_>, WorkspaceError>>()?;
+
+        Ok(Workspace { root_dir: root_dir.to_path_buf(), projects })
+    }
+
+    /// Find the project whose `package.json` has `name`.
+    pub fn find_project(&self, name: &str) -> Option<&WorkspaceProject> {
+        self.projects.iter().find(|project| project.name() == Some(name))
+    }
+
+    /// Find the project at the root of the workspace, if the root has a `package.json`.
+    pub fn find_root_project(&self) -> Option<&WorkspaceProject> {
+        self.projects.iter().find(|project| project.dir == self.root_dir)
+    }
+
+    /// List the projects that `project` depends on with the `workspace:` protocol.
+    pub fn workspace_dependencies(&self, project: &WorkspaceProject) -> Vec<&WorkspaceProject> {
+        project
+            .manifest
+            .dependencies([DependencyGroup::Prod, DependencyGroup::Dev, DependencyGroup::Optional])
+            .filter(|(_, specifier)| specifier.starts_with(WORKSPACE_PROTOCOL))
+            .filter_map(|(name, _)| self.find_project(name))
+            .collect()
+    }
+}
+
+impl WorkspaceProject {
+    /// Value of the `name` field of `package.json`.
+    pub fn name(&self) -> Option<&str> {
+        self.manifest.value().get("name")?.as_str()
+    }
+
+    /// Value of the `version` field of `package.json`.
+    pub fn version(&self) -> Option<&str> {
+        self.manifest.value().get("version")?.as_str()
+    }
+}
+
+/// Match `path` against a glob `pattern`, both of which use `/` as the separator.
+///
+/// `**` matches any number of directories, and `*` and `?` match within a single directory name.
+/// Scoped package names such as `@scope/name` can be matched the same way.
+pub fn matches_glob(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let path = path.split('/').collect::<Vec<_>>();
+    matches_segments(&pattern, &path)
+}
+
+/// Match the directory names of a path against the segments of a glob.
+fn matches_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            matches_segments(rest, path)
+                || (!path.is_empty() && matches_segments(pattern, &path[1..]))
+        }
+        (Some((segment, rest)), Some((name, path))) => {
+            matches_name(segment.as_bytes(), name.as_bytes()) && matches_segments(rest, path)
+        }
+        _ => false,
+    }
+}
+
+/// Match a single directory name against a glob segment.
+fn matches_name(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            matches_name(rest, name) || (!name.is_empty() && matches_name(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name))) => matches_name(rest, name),
+        (Some((expected, rest)), Some((actual, name))) => {
+            expected == actual && matches_name(rest, name)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn glob_matching() {
+        macro_rules! case {
+            ($pattern:literal, $path:literal => $output:literal) => {{
+                eprintln!("CASE: {:?}, {:?} => {:?}", $pattern, $path, $output);
+                assert_eq!(matches_glob($pattern, $path), $output);
+            }};
+        }
+
+        case!("packages/*", "packages/foo" => true);
+        case!("packages/*", "packages/foo/bar" => false);
+        case!("packages/*", "apps/foo" => false);
+        case!("./packages/*/", "packages/foo" => true);
+        case!("packages/**", "packages/foo/bar" => true);
+        case!("**/lib-*", "packages/nested/lib-foo" => true);
+        case!("**/lib-*", "packages/nested/app-foo" => false);
+        case!("app?", "app1" => true);
+        case!("app?", "app10" => false);
+    }
+
+    #[test]
+    fn should_load_workspace_projects() {
+        let dir = tempdir().unwrap();
+        let write_manifest = |path: &str, name: &str| {
+            let project_dir = dir.path().join(path);
+            fs::create_dir_all(&project_dir).unwrap();
+            let manifest = json!({ "name": name, "version": "1.0.0" }).to_string();
+            fs::write(project_dir.join("package.json"), manifest).unwrap();
+        };
+        write_manifest("", "root");
+        write_manifest("packages/foo", "foo");
+        write_manifest("packages/bar", "bar");
+        write_manifest("packages/bar/node_modules/dependency", "dependency");
+        write_manifest("packages/test-fixture", "test-fixture");
+        write_manifest("unlisted", "unlisted");
+        fs::write(
+            dir.path().join(WORKSPACE_MANIFEST_NAME),
+            "packages:\n  - 'packages/*'\n  - '!**/test-*'\n",
+        )
+        .unwrap();
+
+        eprintln!("CASE: not in a workspace");
+        let outside = tempdir().unwrap();
+        assert!(Workspace::find(outside.path()).unwrap().is_none());
+
+        eprintln!("CASE: found from a project directory");
+        let workspace = Workspace::find(&dir.path().join("packages/foo")).unwrap().unwrap();
+        assert_eq!(workspace.root_dir, dir.path());
+        let names = workspace.projects.iter().map(|project| project.name()).collect::<Vec<_>>();
+        assert_eq!(names, [Some("root"), Some("bar"), Some("foo")]);
+        assert_eq!(workspace.find_project("foo").unwrap().dir, dir.path().join("packages/foo"));
+        assert!(workspace.find_project("unlisted").is_none());
+
+        drop((dir, outside)); // cleanup
+    }
+}
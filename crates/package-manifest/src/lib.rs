@@ -7,7 +7,7 @@ use std::{
 use derive_more::{Display, Error, From};
 use miette::Diagnostic;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Map, Value};
+use serde_json::{json, ser::PrettyFormatter, Map, Value};
 use strum::IntoStaticStr;
 
 #[derive(Debug, Display, Error, From, Diagnostic)]
@@ -54,17 +54,215 @@ pub enum DependencyGroup {
     Peer,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+impl DependencyGroup {
+    /// Every dependency group, in the order of `dependencies`, `devDependencies`,
+    /// `optionalDependencies`, and `peerDependencies`.
+    pub fn all() -> impl Iterator<Item = DependencyGroup> {
+        [
+            DependencyGroup::Prod,
+            DependencyGroup::Dev,
+            DependencyGroup::Optional,
+            DependencyGroup::Peer,
+        ]
+        .into_iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum BundleDependencies {
     Boolean(bool),
     List(Vec<String>),
 }
 
+/// Value of the `bin` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageBin {
+    /// `"bin": "./cli.js"`, a single executable named after the package.
+    Single(String),
+    /// `"bin": { "name": "./cli.js" }`, pairs of names and paths in the order of the manifest.
+    Map(Vec<(String, String)>),
+}
+
+/// Value of the `packageManager` field, e.g. `pnpm@8.6.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageManager {
+    /// Name of the package manager, e.g. `pnpm`.
+    pub name: String,
+    /// Version of the package manager without the hash that corepack allows after `+`.
+    pub version: String,
+}
+
+/// Value of the `engines` field, the version ranges of the runtimes that the package supports.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Engines {
+    /// Version range of Node.js.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// Version range of pnpm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pnpm: Option<String>,
+}
+
+/// Merge the dependencies of `group` from the manifest object `from` into the manifest object `into`.
+///
+/// **Precedence:**
+/// * Entries that only exist in `into` are kept.
+/// * Entries that only exist in `from` are added.
+/// * Entries that exist in both are replaced by the ones from `from`.
+///
+/// If `from` doesn't have `group`, `into` is left untouched.
+pub fn merge_dependencies(
+    into: &mut Map<String, Value>,
+    from: &Map<String, Value>,
+    group: DependencyGroup,
+) -> Result<(), PackageManifestError> {
+    let dependency_type: &str = group.into();
+    let invalid_attribute =
+        || PackageManifestError::InvalidAttribute(format!("{dependency_type} should be an object"));
+
+    let Some(source) = from.get(dependency_type) else { return Ok(()) };
+    let source = source.as_object().ok_or_else(invalid_attribute)?;
+
+    let target = into
+        .entry(dependency_type)
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(invalid_attribute)?;
+
+    for (name, version) in source {
+        target.insert(name.clone(), version.clone());
+    }
+
+    Ok(())
+}
+
+/// Order of the top-level fields that npm writes to `package.json`.
+///
+/// New fields are inserted before the first existing field that comes after them in this list.
+const FIELD_ORDER: &[&str] = &[
+    "name",
+    "version",
+    "private",
+    "description",
+    "keywords",
+    "homepage",
+    "bugs",
+    "repository",
+    "funding",
+    "license",
+    "author",
+    "contributors",
+    "type",
+    "exports",
+    "main",
+    "module",
+    "types",
+    "bin",
+    "files",
+    "workspaces",
+    "scripts",
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "peerDependenciesMeta",
+    "optionalDependencies",
+    "bundleDependencies",
+    "engines",
+    "packageManager",
+    "publishConfig",
+];
+
+/// Formatting of a `package.json` file, which is reproduced when the manifest is saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFormat {
+    /// Indentation of a single level, e.g. two spaces or a tab.
+    pub indent: String,
+    /// Whether the file ends with a newline.
+    pub trailing_newline: bool,
+}
+
+impl Default for ManifestFormat {
+    fn default() -> Self {
+        ManifestFormat { indent: "  ".to_string(), trailing_newline: false }
+    }
+}
+
+impl ManifestFormat {
+    /// Detect the formatting of the content of a `package.json` file.
+    ///
+    /// The indentation is taken from the first indented line, which is a top-level field.
+    /// Files without indented lines get the default indentation.
+    pub fn detect(contents: &str) -> Self {
+        let indent = contents.lines().find_map(|line| {
+            let content = line.trim_start_matches([' ', '\t']);
+            let indent = &line[..line.len() - content.len()];
+            (!indent.is_empty() && !content.is_empty()).then(|| indent.to_string())
+        });
+        ManifestFormat {
+            indent: indent.unwrap_or_else(|| ManifestFormat::default().indent),
+            trailing_newline: contents.ends_with('\n'),
+        }
+    }
+
+    /// Serialize `value` with this formatting.
+    pub fn serialize(&self, value: &Value) -> Result<String, serde_json::Error> {
+        let mut contents = Vec::new();
+        let formatter = PrettyFormatter::with_indent(self.indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(&mut contents, formatter);
+        value.serialize(&mut serializer)?;
+        if self.trailing_newline {
+            contents.push(b'\n');
+        }
+        Ok(String::from_utf8(contents).expect("serde_json only writes valid UTF-8"))
+    }
+}
+
+/// Insert `value` at `index` of `map`, shifting the following entries.
+fn insert_at(map: &mut Map<String, Value>, index: usize, key: String, value: Value) {
+    let mut entries = std::mem::take(map).into_iter();
+    map.extend(entries.by_ref().take(index));
+    map.insert(key, value);
+    map.extend(entries);
+}
+
+/// Insert a top-level field into a manifest object, following [`FIELD_ORDER`] if the field is new.
+fn insert_field(manifest: &mut Map<String, Value>, key: &str, value: Value) {
+    if let Some(existing) = manifest.get_mut(key) {
+        *existing = value;
+        return;
+    }
+    let rank = |key: &str| FIELD_ORDER.iter().position(|field| *field == key);
+    let index = rank(key)
+        .and_then(|new_rank| {
+            manifest.keys().position(|key| rank(key).is_some_and(|rank| rank > new_rank))
+        })
+        .unwrap_or(manifest.len());
+    insert_at(manifest, index, key.to_string(), value);
+}
+
+/// Insert a dependency into a dependency group.
+///
+/// New dependencies are inserted in alphabetical order if the group is already sorted, and
+/// appended otherwise.
+fn insert_dependency(dependencies: &mut Map<String, Value>, name: &str, version: Value) {
+    if let Some(existing) = dependencies.get_mut(name) {
+        *existing = version;
+        return;
+    }
+    let is_sorted = dependencies.keys().zip(dependencies.keys().skip(1)).all(|(a, b)| a <= b);
+    let index = is_sorted
+        .then(|| dependencies.keys().position(|key| key.as_str() > name))
+        .flatten()
+        .unwrap_or(dependencies.len());
+    insert_at(dependencies, index, name.to_string(), version);
+}
+
 /// Content of the `package.json` files and its path.
 pub struct PackageManifest {
     path: PathBuf,
     value: Value, // TODO: convert this into a proper struct + an array of keys order
+    format: ManifestFormat,
 }
 
 impl PackageManifest {
@@ -95,9 +293,10 @@ impl PackageManifest {
         Ok((manifest, contents))
     }
 
-    fn read_from_file(path: &Path) -> Result<Value, PackageManifestError> {
+    fn read_from_file(path: &Path) -> Result<(Value, ManifestFormat), PackageManifestError> {
         let contents = fs::read_to_string(path)?;
-        serde_json::from_str(&contents).map_err(PackageManifestError::from)
+        let value = serde_json::from_str(&contents).map_err(PackageManifestError::from)?;
+        Ok((value, ManifestFormat::detect(&contents)))
     }
 
     pub fn init(path: &Path) -> Result<(), PackageManifestError> {
@@ -114,18 +313,19 @@ impl PackageManifest {
             return Err(PackageManifestError::NoImporterManifestFound(path.display().to_string()));
         }
 
-        let value = PackageManifest::read_from_file(&path)?;
-        Ok(PackageManifest { path, value })
+        let (value, format) = PackageManifest::read_from_file(&path)?;
+        Ok(PackageManifest { path, value, format })
     }
 
     pub fn create_if_needed(path: PathBuf) -> Result<PackageManifest, PackageManifestError> {
-        let value = if path.exists() {
+        let (value, format) = if path.exists() {
             PackageManifest::read_from_file(&path)?
         } else {
-            PackageManifest::write_to_file(&path).map(|(value, _)| value)?
+            let (value, contents) = PackageManifest::write_to_file(&path)?;
+            (value, ManifestFormat::detect(&contents))
         };
 
-        Ok(PackageManifest { path, value })
+        Ok(PackageManifest { path, value, format })
     }
 
     pub fn path(&self) -> &'_ Path {
@@ -136,19 +336,26 @@ impl PackageManifest {
         &self.value
     }
 
+    /// Formatting of the file, which [`save`](PackageManifest::save) reproduces.
+    pub fn format(&self) -> &'_ ManifestFormat {
+        &self.format
+    }
+
     pub fn save(&self) -> Result<(), PackageManifestError> {
         let mut file = fs::File::create(&self.path)?;
-        let contents = serde_json::to_string_pretty(&self.value)?;
+        let contents = self.format.serialize(&self.value)?;
         file.write_all(contents.as_bytes())?;
         Ok(())
     }
 
+    /// Names and version ranges of the dependencies in `groups`.
+    ///
+    /// Groups that aren't objects and versions that aren't strings are skipped, use
+    /// [`try_dependencies`](PackageManifest::try_dependencies) to report them.
     pub fn dependencies<'a>(
         &'a self,
         groups: impl IntoIterator<Item = DependencyGroup> + 'a,
     ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
-        // TODO: add error when `dependencies` is found to not be an object
-        // TODO: add error when `version` is found to not be a string
         groups
             .into_iter()
             .flat_map(|group| self.value.get::<&str>(group.into()))
@@ -157,6 +364,31 @@ impl PackageManifest {
             .flat_map(|(name, version)| version.as_str().map(|value| (name.as_str(), value)))
     }
 
+    /// Like [`dependencies`](PackageManifest::dependencies), but fail if a group isn't an
+    /// object or a version isn't a string.
+    pub fn try_dependencies(
+        &self,
+        groups: impl IntoIterator<Item = DependencyGroup>,
+    ) -> Result<Vec<(&str, &str)>, PackageManifestError> {
+        let mut result = Vec::new();
+        for group in groups {
+            let field: &str = group.into();
+            let Some(dependencies) = self.value.get(field) else { continue };
+            let dependencies = dependencies.as_object().ok_or_else(|| {
+                PackageManifestError::InvalidAttribute(format!("{field} should be an object"))
+            })?;
+            for (name, version) in dependencies {
+                let version = version.as_str().ok_or_else(|| {
+                    PackageManifestError::InvalidAttribute(format!(
+                        "{field}.{name} should be a string"
+                    ))
+                })?;
+                result.push((name.as_str(), version));
+            }
+        }
+        Ok(result)
+    }
+
     pub fn bundle_dependencies(&self) -> Result<Option<BundleDependencies>, serde_json::Error> {
         self.value
             .get("bundleDependencies")
@@ -166,6 +398,110 @@ impl PackageManifest {
             .transpose()
     }
 
+    /// Version ranges of the `engines` field. Missing ranges are `None`.
+    pub fn engines(&self) -> Result<Engines, PackageManifestError> {
+        match self.value.get("engines") {
+            None | Some(Value::Null) => Ok(Engines::default()),
+            Some(engines) => serde_json::from_value(engines.clone()).map_err(|error| {
+                PackageManifestError::InvalidAttribute(format!("engines is invalid: {error}"))
+            }),
+        }
+    }
+
+    /// Value of the `bin` field, `None` if it's missing.
+    pub fn bin(&self) -> Result<Option<PackageBin>, PackageManifestError> {
+        let invalid_attribute = || {
+            PackageManifestError::InvalidAttribute(
+                "bin should be a string or an object".to_string(),
+            )
+        };
+        match self.value.get("bin") {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(path)) => Ok(Some(PackageBin::Single(path.clone()))),
+            Some(Value::Object(bins)) => bins
+                .iter()
+                .map(|(name, path)| {
+                    let path = path.as_str().ok_or_else(invalid_attribute)?;
+                    Ok((name.clone(), path.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(PackageBin::Map)
+                .map(Some),
+            Some(_) => Err(invalid_attribute()),
+        }
+    }
+
+    /// Executables declared by the `bin` field, as pairs of command names and paths relative to the package.
+    ///
+    /// Like npm, a command is named after the last segment of its key, and the string form
+    /// (`"bin": "./cli.js"`) is named after the package without its scope.
+    pub fn bins(&self) -> Result<Vec<(String, String)>, PackageManifestError> {
+        let command_name = |name: &str| name.rsplit('/').next().unwrap_or(name).to_string();
+        match self.bin()? {
+            None => Ok(Vec::new()),
+            Some(PackageBin::Single(path)) => {
+                let name = self.value.get("name").and_then(Value::as_str).ok_or_else(|| {
+                    PackageManifestError::InvalidAttribute(
+                        "name is required when bin is a string".to_string(),
+                    )
+                })?;
+                Ok(vec![(command_name(name), path)])
+            }
+            Some(PackageBin::Map(bins)) => {
+                Ok(bins.into_iter().map(|(name, path)| (command_name(&name), path)).collect())
+            }
+        }
+    }
+
+    /// Entries of the `pnpm.overrides` field, as pairs of selectors and version specifiers
+    /// in the order of the manifest.
+    pub fn overrides(&self) -> Result<Vec<(String, String)>, PackageManifestError> {
+        let invalid_attribute = || {
+            PackageManifestError::InvalidAttribute(
+                "pnpm.overrides should be an object of strings".to_string(),
+            )
+        };
+        let Some(overrides) = self.value.get("pnpm").and_then(|pnpm| pnpm.get("overrides")) else {
+            return Ok(Vec::new());
+        };
+        overrides
+            .as_object()
+            .ok_or_else(invalid_attribute)?
+            .iter()
+            .map(|(selector, specifier)| {
+                let specifier = specifier.as_str().ok_or_else(invalid_attribute)?;
+                Ok((selector.clone(), specifier.to_string()))
+            })
+            .collect()
+    }
+
+    /// Value of the `packageManager` field, `None` if it's missing.
+    pub fn package_manager(&self) -> Result<Option<PackageManager>, PackageManifestError> {
+        let invalid_attribute = || {
+            PackageManifestError::InvalidAttribute(
+                "packageManager should be a string like pnpm@8.6.0".to_string(),
+            )
+        };
+        let Some(package_manager) = self.value.get("packageManager") else { return Ok(None) };
+        let package_manager = package_manager.as_str().ok_or_else(invalid_attribute)?;
+        let (name, version) = package_manager
+            .rsplit_once('@')
+            .filter(|(name, version)| !name.is_empty() && !version.is_empty())
+            .ok_or_else(invalid_attribute)?;
+        let version = version.split_once('+').map_or(version, |(version, _)| version);
+        Ok(Some(PackageManager { name: name.to_string(), version: version.to_string() }))
+    }
+
+    /// Value of the `main` field, the entry point of the package.
+    pub fn main(&self) -> Option<&str> {
+        self.value.get("main")?.as_str()
+    }
+
+    /// Value of the `module` field, the ES module entry point of the package.
+    pub fn module(&self) -> Option<&str> {
+        self.value.get("module")?.as_str()
+    }
+
     pub fn add_dependency(
         &mut self,
         name: &str,
@@ -173,22 +509,46 @@ impl PackageManifest {
         dependency_group: DependencyGroup,
     ) -> Result<(), PackageManifestError> {
         let dependency_type: &str = dependency_group.into();
+        let version = Value::String(version.to_string());
         if let Some(field) = self.value.get_mut(dependency_type) {
             if let Some(dependencies) = field.as_object_mut() {
-                dependencies.insert(name.to_string(), Value::String(version.to_string()));
+                insert_dependency(dependencies, name, version);
             } else {
                 return Err(PackageManifestError::InvalidAttribute(
                     "dependencies attribute should be an object".to_string(),
                 ));
             }
         } else {
+            let manifest = self.value.as_object_mut().ok_or_else(|| {
+                PackageManifestError::InvalidAttribute(
+                    "package.json should be an object".to_string(),
+                )
+            })?;
             let mut dependencies = Map::<String, Value>::new();
-            dependencies.insert(name.to_string(), Value::String(version.to_string()));
-            self.value[dependency_type] = Value::Object(dependencies);
+            dependencies.insert(name.to_string(), version);
+            insert_field(manifest, dependency_type, Value::Object(dependencies));
         }
         Ok(())
     }
 
+    /// Remove `name` from `dependency_group`, return whether the dependency was there.
+    ///
+    /// The group itself is removed from the manifest once it is empty.
+    pub fn remove_dependency(&mut self, name: &str, dependency_group: DependencyGroup) -> bool {
+        let dependency_type: &str = dependency_group.into();
+        let Some(dependencies) = self.value.get_mut(dependency_type).and_then(Value::as_object_mut)
+        else {
+            return false;
+        };
+        let removed = dependencies.remove(name).is_some();
+        if removed && dependencies.is_empty() {
+            if let Some(manifest) = self.value.as_object_mut() {
+                manifest.remove(dependency_type);
+            }
+        }
+        removed
+    }
+
     pub fn script(
         &self,
         command: &str,
@@ -209,6 +569,18 @@ impl PackageManifest {
             Err(PackageManifestError::NoScript(command.to_string()))
         }
     }
+
+    /// Scripts of the `scripts` field, as pairs of names and commands in the order of the manifest.
+    ///
+    /// Entries whose commands aren't strings are skipped.
+    pub fn scripts(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.value
+            .get("scripts")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .flat_map(|(name, script)| script.as_str().map(|script| (name.as_str(), script)))
+    }
 }
 
 #[cfg(test)]
@@ -260,6 +632,242 @@ mod tests {
         assert!(read_to_string(tmp).unwrap().contains("fastify"));
     }
 
+    #[test]
+    fn should_preserve_format_on_save() {
+        macro_rules! case {
+            ($input:expr => $output:expr) => {{
+                let input: &str = $input;
+                eprintln!("CASE: {input:?}");
+                let dir = tempdir().unwrap();
+                let path = dir.path().join("package.json");
+                fs::write(&path, input).unwrap();
+                let mut manifest = PackageManifest::from_path(path.clone()).unwrap();
+                manifest.add_dependency("b", "1.0.0", DependencyGroup::Prod).unwrap();
+                manifest.save().unwrap();
+                let output: &str = $output;
+                assert_eq!(read_to_string(&path).unwrap(), output);
+                drop(dir); // cleanup
+            }};
+        }
+
+        case!("{\n    \"name\": \"foo\"\n}\n" => "{\n    \"name\": \"foo\",\n    \"dependencies\": {\n        \"b\": \"1.0.0\"\n    }\n}\n");
+        case!("{\n\t\"name\": \"foo\"\n}" => "{\n\t\"name\": \"foo\",\n\t\"dependencies\": {\n\t\t\"b\": \"1.0.0\"\n\t}\n}");
+        case!("{\"dependencies\":{\"a\":\"1.0.0\",\"c\":\"1.0.0\"}}" => "{\n  \"dependencies\": {\n    \"a\": \"1.0.0\",\n    \"b\": \"1.0.0\",\n    \"c\": \"1.0.0\"\n  }\n}");
+    }
+
+    #[test]
+    fn should_insert_fields_in_npm_order() {
+        let mut manifest = PackageManifest {
+            path: PathBuf::from("package.json"),
+            value: json!({ "name": "foo", "scripts": {}, "devDependencies": {}, "custom": true }),
+            format: ManifestFormat::default(),
+        };
+        manifest.add_dependency("a", "1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("a", "1.0.0", DependencyGroup::Optional).unwrap();
+        let keys = manifest.value().as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "name",
+                "scripts",
+                "dependencies",
+                "devDependencies",
+                "custom",
+                "optionalDependencies"
+            ],
+        );
+
+        eprintln!("CASE: unsorted dependencies are appended");
+        let mut manifest = PackageManifest {
+            path: PathBuf::from("package.json"),
+            value: json!({ "dependencies": { "c": "1.0.0", "a": "1.0.0" } }),
+            format: ManifestFormat::default(),
+        };
+        manifest.add_dependency("b", "1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("c", "2.0.0", DependencyGroup::Prod).unwrap();
+        let dependencies = manifest.dependencies([DependencyGroup::Prod]).collect::<Vec<_>>();
+        assert_eq!(dependencies, [("c", "2.0.0"), ("a", "1.0.0"), ("b", "1.0.0")]);
+    }
+
+    #[test]
+    fn should_remove_dependency() {
+        let dir = tempdir().unwrap();
+        let tmp = dir.path().join("package.json");
+        let mut manifest = PackageManifest::create_if_needed(tmp).unwrap();
+        manifest.add_dependency("fastify", "1.0.0", DependencyGroup::Prod).unwrap();
+        manifest.add_dependency("fastify", "1.0.0", DependencyGroup::Dev).unwrap();
+        manifest.add_dependency("typescript", "5.0.0", DependencyGroup::Dev).unwrap();
+
+        assert!(!manifest.remove_dependency("fastify", DependencyGroup::Optional));
+        assert!(!manifest.remove_dependency("react", DependencyGroup::Prod));
+
+        assert!(manifest.remove_dependency("fastify", DependencyGroup::Prod));
+        assert!(!manifest.remove_dependency("fastify", DependencyGroup::Prod));
+        assert_eq!(manifest.value().get("dependencies"), None);
+
+        assert!(manifest.remove_dependency("fastify", DependencyGroup::Dev));
+        assert_eq!(manifest.value()["devDependencies"], json!({ "typescript": "5.0.0" }));
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn should_read_bins() {
+        macro_rules! case {
+            ($manifest:expr => $output:expr) => {{
+                let manifest = $manifest;
+                eprintln!("CASE: {manifest}");
+                let manifest = PackageManifest {
+                    path: PathBuf::from("package.json"),
+                    value: manifest,
+                    format: ManifestFormat::default(),
+                };
+                let output: Vec<(&str, &str)> = $output;
+                let output: Vec<_> = output
+                    .into_iter()
+                    .map(|(name, path)| (name.to_string(), path.to_string()))
+                    .collect();
+                assert_eq!(manifest.bins().unwrap(), output);
+            }};
+        }
+
+        case!(json!({ "name": "foo" }) => vec![]);
+        case!(json!({ "name": "foo", "bin": "./cli.js" }) => vec![("foo", "./cli.js")]);
+        case!(json!({ "name": "@scope/foo", "bin": "cli.js" }) => vec![("foo", "cli.js")]);
+        case!(json!({ "name": "foo", "bin": { "foo": "bin/foo.js", "@scope/bar": "bin/bar.js" } }) => vec![("foo", "bin/foo.js"), ("bar", "bin/bar.js")]);
+
+        let manifest = PackageManifest {
+            path: PathBuf::from("package.json"),
+            value: json!({ "bin": 123 }),
+            format: ManifestFormat::default(),
+        };
+        assert!(matches!(manifest.bins(), Err(PackageManifestError::InvalidAttribute(_))));
+    }
+
+    #[test]
+    fn should_read_bin_main_and_module() {
+        let manifest = |value: Value| PackageManifest {
+            path: PathBuf::from("package.json"),
+            value,
+            format: ManifestFormat::default(),
+        };
+
+        eprintln!("CASE: missing fields");
+        let empty = manifest(json!({ "name": "foo" }));
+        assert_eq!(empty.bin().unwrap(), None);
+        assert_eq!((empty.main(), empty.module()), (None, None));
+
+        eprintln!("CASE: string bin");
+        let single =
+            manifest(json!({ "bin": "./cli.js", "main": "index.js", "module": "index.mjs" }));
+        assert_eq!(single.bin().unwrap(), Some(PackageBin::Single("./cli.js".to_string())));
+        assert_eq!((single.main(), single.module()), (Some("index.js"), Some("index.mjs")));
+
+        eprintln!("CASE: object bin");
+        let map = manifest(json!({ "bin": { "foo": "bin/foo.js", "@scope/bar": "bin/bar.js" } }));
+        let expected = [("foo", "bin/foo.js"), ("@scope/bar", "bin/bar.js")]
+            .map(|(name, path)| (name.to_string(), path.to_string()))
+            .to_vec();
+        assert_eq!(map.bin().unwrap(), Some(PackageBin::Map(expected)));
+
+        eprintln!("CASE: invalid bin");
+        let invalid = manifest(json!({ "bin": { "foo": 123 } }));
+        assert!(matches!(invalid.bin(), Err(PackageManifestError::InvalidAttribute(_))));
+    }
+
+    #[test]
+    fn should_read_overrides() {
+        let manifest = |value: Value| PackageManifest {
+            path: PathBuf::from("package.json"),
+            value,
+            format: ManifestFormat::default(),
+        };
+
+        assert_eq!(manifest(json!({})).overrides().unwrap(), Vec::<(String, String)>::new());
+        let overrides = manifest(json!({
+            "pnpm": { "overrides": { "foo": "1.0.0", "bar@^1>baz": "2.0.0" } },
+        }));
+        assert_eq!(
+            overrides.overrides().unwrap(),
+            [
+                ("foo".to_string(), "1.0.0".to_string()),
+                ("bar@^1>baz".to_string(), "2.0.0".to_string()),
+            ],
+        );
+
+        let invalid = manifest(json!({ "pnpm": { "overrides": { "foo": 1 } } }));
+        assert!(matches!(invalid.overrides(), Err(PackageManifestError::InvalidAttribute(_))));
+    }
+
+    #[test]
+    fn should_read_package_manager() {
+        macro_rules! case {
+            ($manifest:expr => $output:expr) => {{
+                let manifest = $manifest;
+                eprintln!("CASE: {manifest}");
+                let manifest = PackageManifest {
+                    path: PathBuf::from("package.json"),
+                    value: manifest,
+                    format: ManifestFormat::default(),
+                };
+                let output: Option<(&str, &str)> = $output;
+                let output = output.map(|(name, version)| PackageManager {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                });
+                assert_eq!(manifest.package_manager().unwrap(), output);
+            }};
+        }
+
+        case!(json!({}) => None);
+        case!(json!({ "packageManager": "pnpm@8.6.0" }) => Some(("pnpm", "8.6.0")));
+        case!(json!({ "packageManager": "yarn@3.6.1+sha224.abcdef" }) => Some(("yarn", "3.6.1")));
+
+        for invalid in [json!({ "packageManager": "pnpm" }), json!({ "packageManager": 8 })] {
+            eprintln!("CASE: {invalid}");
+            let manifest = PackageManifest {
+                path: PathBuf::from("package.json"),
+                value: invalid,
+                format: ManifestFormat::default(),
+            };
+            let error = manifest.package_manager().unwrap_err();
+            assert!(matches!(error, PackageManifestError::InvalidAttribute(_)));
+        }
+    }
+
+    #[test]
+    fn should_read_engines() {
+        macro_rules! case {
+            ($manifest:expr => $node:expr, $pnpm:expr) => {{
+                let manifest = $manifest;
+                eprintln!("CASE: {manifest}");
+                let manifest = PackageManifest {
+                    path: PathBuf::from("package.json"),
+                    value: manifest,
+                    format: ManifestFormat::default(),
+                };
+                let node: Option<&str> = $node;
+                let pnpm: Option<&str> = $pnpm;
+                let expected = Engines {
+                    node: node.map(ToString::to_string),
+                    pnpm: pnpm.map(ToString::to_string),
+                };
+                assert_eq!(manifest.engines().unwrap(), expected);
+            }};
+        }
+
+        case!(json!({}) => None, None);
+        case!(json!({ "engines": { "node": ">=18" } }) => Some(">=18"), None);
+        case!(json!({ "engines": { "node": "^20", "pnpm": ">=8", "vscode": "^1.80.0" } }) => Some("^20"), Some(">=8"));
+
+        let manifest = PackageManifest {
+            path: PathBuf::from("package.json"),
+            value: json!({ "engines": { "node": 18 } }),
+            format: ManifestFormat::default(),
+        };
+        assert!(matches!(manifest.engines(), Err(PackageManifestError::InvalidAttribute(_))));
+    }
+
     #[test]
     fn should_throw_on_missing_command() {
         let dir = tempdir().unwrap();
@@ -285,6 +893,25 @@ mod tests {
         manifest.script("invalid", true).unwrap();
     }
 
+    #[test]
+    fn list_scripts() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("package.json");
+        let manifest = json!({
+            "scripts": { "test": "jest", "build": "tsc", "invalid": 1 },
+        });
+        fs::write(&manifest_path, manifest.to_string()).unwrap();
+        let manifest = PackageManifest::from_path(manifest_path).unwrap();
+        let scripts = manifest.scripts().collect::<Vec<_>>();
+        assert_eq!(scripts, [("test", "jest"), ("build", "tsc")]);
+
+        eprintln!("CASE: no scripts");
+        let manifest = PackageManifest::create_if_needed(dir.path().join("empty.json")).unwrap();
+        assert_eq!(manifest.scripts().count(), 1); // the default `test` script
+
+        drop(dir); // cleanup
+    }
+
     #[test]
     fn get_dependencies_should_return_peers() {
         let data = r#"
@@ -305,6 +932,57 @@ mod tests {
         assert!(dependencies([DependencyGroup::Prod]).contains_key("fastify"));
     }
 
+    #[test]
+    fn should_report_malformed_dependencies() {
+        let dir = tempdir().unwrap();
+        let manifest_path = dir.path().join("package.json");
+        let try_dependencies = |manifest: Value| {
+            fs::write(&manifest_path, manifest.to_string()).unwrap();
+            let manifest = PackageManifest::from_path(manifest_path.clone()).unwrap();
+            manifest
+                .try_dependencies(DependencyGroup::all())
+                .map(|dependencies| {
+                    dependencies
+                        .into_iter()
+                        .map(|(name, version)| format!("{name}@{version}"))
+                        .collect::<Vec<_>>()
+                })
+                .map_err(|error| error.to_string())
+        };
+
+        eprintln!("CASE: valid");
+        let received = try_dependencies(json!({
+            "dependencies": { "foo": "^1.0.0" },
+            "peerDependencies": { "bar": "2" },
+        }));
+        assert_eq!(received, Ok(vec!["foo@^1.0.0".to_string(), "bar@2".to_string()]));
+
+        eprintln!("CASE: group isn't an object");
+        let received = try_dependencies(json!({ "devDependencies": ["foo"] }));
+        assert_eq!(
+            received,
+            Err("invalid attribute: devDependencies should be an object".to_string()),
+        );
+
+        eprintln!("CASE: version isn't a string");
+        let received = try_dependencies(json!({ "dependencies": { "foo": 1 } }));
+        assert_eq!(
+            received,
+            Err("invalid attribute: dependencies.foo should be a string".to_string()),
+        );
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn list_all_dependency_groups() {
+        let received = DependencyGroup::all().map(<&str>::from).collect::<Vec<_>>();
+        assert_eq!(
+            received,
+            ["dependencies", "devDependencies", "optionalDependencies", "peerDependencies"],
+        );
+    }
+
     #[test]
     fn bundle_dependencies() {
         fn bundle_list<List>(list: List) -> BundleDependencies
@@ -335,4 +1013,65 @@ mod tests {
         case!(r#"{ "bundledDependencies": true }"# => true.pipe(BundleDependencies::Boolean).pipe(Some));
         case!(r#"{}"# => None);
     }
+
+    #[test]
+    fn merge_dependencies_precedence() {
+        let object = |value: Value| value.as_object().unwrap().clone();
+
+        let mut into = object(json!({
+            "name": "foo",
+            "dependencies": {
+                "kept": "1.0.0",
+                "replaced": "1.0.0",
+            },
+            "devDependencies": {
+                "untouched": "1.0.0",
+            },
+        }));
+        let from = object(json!({
+            "dependencies": {
+                "replaced": "2.0.0",
+                "added": "3.0.0",
+            },
+            "devDependencies": {
+                "ignored": "1.0.0",
+            },
+        }));
+        merge_dependencies(&mut into, &from, DependencyGroup::Prod).unwrap();
+        assert_eq!(
+            Value::Object(into),
+            json!({
+                "name": "foo",
+                "dependencies": {
+                    "kept": "1.0.0",
+                    "replaced": "2.0.0",
+                    "added": "3.0.0",
+                },
+                "devDependencies": {
+                    "untouched": "1.0.0",
+                },
+            }),
+        );
+
+        eprintln!("CASE: the group is missing from the target");
+        let mut into = object(json!({ "name": "foo" }));
+        let from = object(json!({ "optionalDependencies": { "added": "1.0.0" } }));
+        merge_dependencies(&mut into, &from, DependencyGroup::Optional).unwrap();
+        assert_eq!(
+            Value::Object(into),
+            json!({ "name": "foo", "optionalDependencies": { "added": "1.0.0" } }),
+        );
+
+        eprintln!("CASE: the group is missing from the source");
+        let mut into = object(json!({ "peerDependencies": { "kept": "1.0.0" } }));
+        merge_dependencies(&mut into, &Map::new(), DependencyGroup::Peer).unwrap();
+        assert_eq!(Value::Object(into), json!({ "peerDependencies": { "kept": "1.0.0" } }));
+
+        eprintln!("CASE: the group of the target is not an object");
+        let mut into = object(json!({ "dependencies": "invalid" }));
+        let from = object(json!({ "dependencies": { "added": "1.0.0" } }));
+        let error = merge_dependencies(&mut into, &from, DependencyGroup::Prod).unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, PackageManifestError::InvalidAttribute(_)));
+    }
 }
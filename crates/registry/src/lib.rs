@@ -1,22 +1,32 @@
+mod metadata_cache;
 mod package;
 mod package_distribution;
 mod package_tag;
 mod package_version;
 
-pub use package::Package;
+pub use metadata_cache::MetadataCache;
+pub use package::{Package, PackageMemCache};
 pub use package_distribution::PackageDistribution;
 pub use package_tag::PackageTag;
-pub use package_version::PackageVersion;
+pub use package_version::{PackageVersion, PeerDependencyMeta};
 
 use derive_more::{Display, Error, From};
+use metadata_cache::CachedMetadata;
 use miette::Diagnostic;
+use pacquet_network::{OfflineModeError, RequestError, ThrottledClient};
+use reqwest::{
+    header::{ACCEPT, ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use serde::de::DeserializeOwned;
+use ssri::{Algorithm, IntegrityOpts};
 
 #[derive(Debug, Display, Error)]
 #[display("Failed to request {url}: {error}")]
 pub struct NetworkError {
     pub url: String,
     #[error(source)]
-    pub error: reqwest::Error,
+    pub error: RequestError,
 }
 
 #[derive(Debug, Display, Error, From, Diagnostic)]
@@ -42,4 +52,98 @@ pub enum RegistryError {
     #[display("Serialization failed: {_0}")]
     #[diagnostic(code(pacquet_registry::serialization_error))]
     Serialization(#[error(not(source))] String),
+
+    #[from(ignore)] // TODO: remove this after derive(From) has been removed
+    #[diagnostic(transparent)]
+    Offline(#[error(source)] OfflineModeError),
+}
+
+/// `Accept` header that prefers the abbreviated metadata, which omits the fields that aren't
+/// needed for installation such as `readme`.
+const ABBREVIATED_METADATA: &str =
+    "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
+
+/// `Accept` header of the full metadata.
+const FULL_METADATA: &str = "application/json";
+
+/// Fetch metadata from a registry, preferring the abbreviated format.
+///
+/// The request is sent again for the full metadata if the registry responds with
+/// `406 Not Acceptable`.
+///
+/// If `cache` is specified, the cached metadata is revalidated with its `ETag` and reused when
+/// the registry responds with `304 Not Modified` or with a body identical to the cached one,
+/// or reused right away if
+/// [`prefer_offline`](MetadataCache::prefer_offline) is set and the metadata is younger than
+/// [`max_age`](MetadataCache::max_age).
+///
+/// If `http_client` is [offline](ThrottledClient::offline), only the cache is used, and
+/// [`RegistryError::Offline`] names the `package` on a cache miss.
+async fn fetch_metadata<Metadata: DeserializeOwned>(
+    http_client: &ThrottledClient,
+    package: &str,
+    url: &str,
+    cache: Option<&MetadataCache>,
+) -> Result<Metadata, RegistryError> {
+    let network_error = |error| NetworkError { error, url: url.to_string() };
+    let parse = |data| {
+        serde_json::from_value::<Metadata>(data)
+            .map_err(|error| RegistryError::Serialization(error.to_string()))
+    };
+
+    let cached = cache.and_then(|cache| cache.load(url));
+    if let (Some(cache), Some(cached)) = (cache, &cached) {
+        if (cache.prefer_offline && cache.is_fresh(cached)) || http_client.offline() {
+            return parse(cached.data.clone());
+        }
+    }
+    if http_client.offline() {
+        return Err(RegistryError::Offline(OfflineModeError { package: package.to_string() }));
+    }
+
+    let etag = cached.as_ref().and_then(|cached| cached.etag.as_deref());
+    let send = |accept: &'static str| {
+        let request = http_client.get(url).header(ACCEPT, accept);
+        let request = match etag {
+            Some(etag) => request.header(IF_NONE_MATCH, etag),
+            None => request,
+        };
+        http_client.run_with_permit(move |_| http_client.with_read_timeout(request.send()))
+    };
+
+    let mut response = send(ABBREVIATED_METADATA).await.map_err(network_error)?;
+    if response.status() == StatusCode::NOT_ACCEPTABLE {
+        response = send(FULL_METADATA).await.map_err(network_error)?;
+    }
+    let cached = match (response.status(), cached) {
+        (StatusCode::NOT_MODIFIED, Some(cached)) => {
+            let cached = CachedMetadata::fetched_now(cached.etag, cached.integrity, cached.data);
+            if let Some(cache) = cache {
+                cache.save(url, &cached); // restart the window of `max_age`
+            }
+            return parse(cached.data);
+        }
+        (_, cached) => cached,
+    };
+
+    let etag =
+        response.headers().get(ETAG).and_then(|etag| etag.to_str().ok()).map(ToString::to_string);
+    let mut body = Vec::new();
+    while let Some(chunk) =
+        http_client.with_read_timeout(response.chunk()).await.map_err(network_error)?
+    {
+        body.extend_from_slice(&chunk);
+    }
+    let integrity = IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(&body).result();
+    let integrity = integrity.to_string();
+    let data = match cached.filter(|cached| cached.integrity.as_ref() == Some(&integrity)) {
+        // the registry ignored the conditional request, but the metadata hasn't changed
+        Some(cached) => cached.data,
+        None => serde_json::from_slice::<serde_json::Value>(&body)
+            .map_err(|error| RegistryError::Serialization(error.to_string()))?,
+    };
+    if let Some(cache) = cache {
+        cache.save(url, &CachedMetadata::fetched_now(etag, Some(integrity), data.clone()));
+    }
+    parse(data)
 }
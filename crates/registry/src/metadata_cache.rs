@@ -0,0 +1,174 @@
+use pacquet_diagnostics::tracing;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// On-disk cache of the metadata fetched from registries, keyed by the request URL, which
+/// consists of the registry and the package name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataCache {
+    /// Directory of the cache files.
+    pub dir: PathBuf,
+    /// Use the cached metadata without revalidating it with the registry.
+    pub prefer_offline: bool,
+    /// How long [`prefer_offline`](MetadataCache::prefer_offline) reuses the cached metadata
+    /// before revalidating it, [`None`] for no limit.
+    pub max_age: Option<Duration>,
+}
+
+/// Content of a cache file.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct CachedMetadata {
+    /// `ETag` header of the response, sent back in `If-None-Match` to revalidate the cache.
+    pub etag: Option<String>,
+    /// Integrity of the body of the response, which tells whether a full response of a registry
+    /// that ignores `If-None-Match` is identical to the cache without parsing it.
+    #[serde(default)]
+    pub integrity: Option<String>,
+    /// Body of the response.
+    pub data: Value,
+    /// When the metadata was last fetched or revalidated, in milliseconds since the Unix epoch.
+    /// Missing from the cache files of older versions of pacquet.
+    #[serde(default)]
+    pub fetched_at: Option<u128>,
+}
+
+impl CachedMetadata {
+    /// Create the cache entry of metadata that has just been fetched or revalidated.
+    pub(crate) fn fetched_now(
+        etag: Option<String>,
+        integrity: Option<String>,
+        data: Value,
+    ) -> Self {
+        let fetched_at = UNIX_EPOCH.elapsed().ok().map(|x| x.as_millis());
+        CachedMetadata { etag, integrity, data, fetched_at }
+    }
+}
+
+impl MetadataCache {
+    /// Path to the cache file of `url`.
+    fn path(&self, url: &str) -> PathBuf {
+        let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let file_name = url
+            .chars()
+            .map(|char| match char {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '@' | '.' | '-' | '_' => char,
+                _ => '+',
+            })
+            .collect::<String>();
+        self.dir.join(format!("{file_name}.json"))
+    }
+
+    /// Load the cached metadata of `url`, `None` if it's missing or corrupted.
+    pub(crate) fn load(&self, url: &str) -> Option<CachedMetadata> {
+        let content = fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Whether `cached` is recent enough to be reused without revalidation.
+    pub(crate) fn is_fresh(&self, cached: &CachedMetadata) -> bool {
+        let Some(max_age) = self.max_age else {
+            return true;
+        };
+        let (Some(fetched_at), Ok(now)) = (cached.fetched_at, UNIX_EPOCH.elapsed()) else {
+            return false;
+        };
+        now.as_millis().saturating_sub(fetched_at) <= max_age.as_millis()
+    }
+
+    /// Save the metadata of `url`.
+    ///
+    /// Failing to save is only logged because the cache is merely an optimization.
+    pub(crate) fn save(&self, url: &str, metadata: &CachedMetadata) {
+        let path = self.path(url);
+        let content = serde_json::to_string(metadata).expect("serialize cached metadata");
+        let result = fs::create_dir_all(&self.dir).and_then(|()| fs::write(&path, content));
+        if let Err(error) = result {
+            tracing::warn!(target: "pacquet::registry", ?path, ?error, "Failed to cache metadata");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn path() {
+        let cache =
+            MetadataCache { dir: PathBuf::from("CACHE"), prefer_offline: false, max_age: None };
+        let received = cache.path("https://registry.npmjs.org/@types/node");
+        let expected = PathBuf::from("CACHE").join("registry.npmjs.org+@types+node.json");
+        assert_eq!(received, expected);
+        let received = cache.path("http://localhost:4873/../foo");
+        let expected = PathBuf::from("CACHE").join("localhost+4873+..+foo.json");
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempdir().unwrap();
+        let cache = MetadataCache {
+            dir: dir.path().join("metadata"),
+            prefer_offline: false,
+            max_age: None,
+        };
+        let url = "https://registry.npmjs.org/foo";
+
+        assert!(cache.load(url).is_none());
+
+        let metadata = CachedMetadata::fetched_now(
+            Some("\"abc\"".to_string()),
+            None,
+            json!({ "name": "foo" }),
+        );
+        cache.save(url, &metadata);
+        let loaded = cache.load(url).unwrap();
+        assert_eq!(loaded.etag, metadata.etag);
+        assert_eq!(loaded.data, metadata.data);
+        assert_eq!(loaded.fetched_at, metadata.fetched_at);
+
+        eprintln!("CASE: cache files of older versions have no timestamp");
+        fs::write(cache.path(url), r#"{"etag":null,"data":{}}"#).unwrap();
+        assert_eq!(cache.load(url).unwrap().fetched_at, None);
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn is_fresh() {
+        let minute = Duration::from_secs(60);
+        let fetched_ago = |age: Duration| {
+            let fetched_at = UNIX_EPOCH.elapsed().unwrap().saturating_sub(age).as_millis();
+            CachedMetadata {
+                etag: None,
+                integrity: None,
+                data: json!({}),
+                fetched_at: Some(fetched_at),
+            }
+        };
+        let cache =
+            |max_age| MetadataCache { dir: PathBuf::from("CACHE"), prefer_offline: true, max_age };
+
+        eprintln!("CASE: no limit");
+        assert!(cache(None).is_fresh(&fetched_ago(60 * minute)));
+        assert!(cache(None).is_fresh(&CachedMetadata { fetched_at: None, ..fetched_ago(minute) }));
+
+        eprintln!("CASE: within the window");
+        assert!(cache(Some(10 * minute)).is_fresh(&fetched_ago(minute)));
+
+        eprintln!("CASE: past the window");
+        assert!(!cache(Some(10 * minute)).is_fresh(&fetched_ago(60 * minute)));
+
+        eprintln!("CASE: unknown age");
+        let unknown = CachedMetadata { fetched_at: None, ..fetched_ago(minute) };
+        assert!(!cache(Some(10 * minute)).is_fresh(&unknown));
+    }
+}
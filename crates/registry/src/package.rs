@@ -3,11 +3,12 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+use dashmap::DashMap;
 use pacquet_network::ThrottledClient;
-use pipe_trait::Pipe;
 use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
 
-use crate::{package_version::PackageVersion, NetworkError, RegistryError};
+use crate::{fetch_metadata, package_version::PackageVersion, MetadataCache, RegistryError};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Package {
@@ -20,6 +21,12 @@ pub struct Package {
     pub mutex: Arc<Mutex<u8>>,
 }
 
+/// In-memory cache of the fetched packuments, keyed by the registry and the package name.
+///
+/// Concurrent fetches of the same packument wait for the first one instead of sending
+/// their own requests, see [`Package::fetch_from_registry_with_mem_cache`].
+pub type PackageMemCache = DashMap<(String, String), Arc<OnceCell<Arc<Package>>>>;
+
 impl PartialEq for Package {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
@@ -31,25 +38,31 @@ impl Package {
         name: &str,
         http_client: &ThrottledClient,
         registry: &str,
+        cache: Option<&MetadataCache>,
     ) -> Result<Self, RegistryError> {
-        let url = || format!("{registry}{name}"); // TODO: use reqwest URL directly
-        let network_error = |error| NetworkError { error, url: url() };
-        http_client
-            .run_with_permit(|client| {
-                client
-                    .get(url())
-                    .header(
-                        "accept",
-                        "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*",
-                    )
-                    .send()
+        let url = format!("{registry}{name}"); // TODO: use reqwest URL directly
+        fetch_metadata(http_client, name, &url, cache).await
+    }
+
+    /// Fetch the packument like [`Package::fetch_from_registry`], but only once per registry
+    /// and name across the users of `mem_cache`.
+    ///
+    /// A failed fetch isn't cached, so the next caller tries again.
+    pub async fn fetch_from_registry_with_mem_cache(
+        name: &str,
+        http_client: &ThrottledClient,
+        registry: &str,
+        cache: Option<&MetadataCache>,
+        mem_cache: &PackageMemCache,
+    ) -> Result<Arc<Self>, RegistryError> {
+        let package =
+            Arc::clone(&mem_cache.entry((registry.to_string(), name.to_string())).or_default());
+        package
+            .get_or_try_init(|| async {
+                Package::fetch_from_registry(name, http_client, registry, cache).await.map(Arc::new)
             })
             .await
-            .map_err(network_error)?
-            .json::<Package>()
-            .await
-            .map_err(network_error)?
-            .pipe(Ok)
+            .map(Arc::clone)
     }
 
     pub fn pinned_version(&self, version_range: &str) -> Option<&PackageVersion> {
@@ -67,6 +80,35 @@ impl Package {
         satisfied_versions.last().copied()
     }
 
+    /// Find the highest version that satisfies every range of `version_ranges`.
+    pub fn pinned_version_for_all<'a>(
+        &self,
+        version_ranges: impl IntoIterator<Item = &'a str>,
+    ) -> Option<&PackageVersion> {
+        let ranges = version_ranges
+            .into_iter()
+            .map(|range| range.parse::<node_semver::Range>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        self.versions
+            .values()
+            .filter(|v| ranges.iter().all(|range| v.version.satisfies(range)))
+            .max_by(|a, b| a.version.partial_cmp(&b.version).unwrap())
+    }
+
+    /// Find the version that a dist-tag such as `latest` or `next` points to.
+    pub fn dist_tag(&self, tag: &str) -> Option<&PackageVersion> {
+        self.versions.get(self.dist_tags.get(tag)?)
+    }
+
+    /// Find the version that `spec` refers to: the highest version that satisfies it if it's
+    /// a version range, otherwise the version that the dist-tag `spec` points to.
+    pub fn resolve_spec(&self, spec: &str) -> Option<&PackageVersion> {
+        match spec.parse::<node_semver::Range>() {
+            Ok(_) => self.pinned_version(spec),
+            Err(_) => self.dist_tag(spec),
+        }
+    }
+
     pub fn latest(&self) -> &PackageVersion {
         let version =
             self.dist_tags.get("latest").expect("latest tag is expected but not found for package");
@@ -79,10 +121,15 @@ mod tests {
     use std::collections::HashMap;
 
     use node_semver::Version;
+    use pacquet_package_manifest::BundleDependencies;
+    use pacquet_registry_mock::CustomRegistry;
     use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::tempdir;
 
     use super::*;
-    use crate::package_distribution::PackageDistribution;
+    use crate::{metadata_cache::CachedMetadata, package_distribution::PackageDistribution};
 
     #[test]
     pub fn package_version_should_include_peers() {
@@ -97,6 +144,8 @@ mod tests {
             dependencies: Some(dependencies),
             dev_dependencies: None,
             peer_dependencies: Some(peer_dependencies),
+            peer_dependencies_meta: None,
+            bundle_dependencies: None,
         };
 
         let dependencies = |peer| version.dependencies(peer).collect::<HashMap<_, _>>();
@@ -107,6 +156,78 @@ mod tests {
         assert!(!dependencies(true).contains_key("hello-world"));
     }
 
+    #[test]
+    pub fn package_version_should_exclude_optional_peers() {
+        let version: PackageVersion = serde_json::from_value(json!({
+            "name": "foo",
+            "version": "1.0.0",
+            "dist": { "tarball": "https://registry.npmjs.org/foo/-/foo-1.0.0.tgz" },
+            "dependencies": { "bar": "^1.0.0" },
+            "peerDependencies": { "bar": "^1.0.0", "baz": "^2.0.0", "qux": "^3.0.0" },
+            "peerDependenciesMeta": { "qux": { "optional": true } },
+        }))
+        .unwrap();
+        assert!(version.is_optional_peer("qux"));
+        assert!(!version.is_optional_peer("baz"));
+        let peers = version.required_peer_dependencies().collect::<HashMap<_, _>>();
+        dbg!(&peers);
+        assert_eq!(peers, HashMap::from([("baz", "^2.0.0")]));
+        let dependencies = version.dependencies(true).collect::<HashMap<_, _>>();
+        assert_eq!(dependencies, HashMap::from([("bar", "^1.0.0"), ("baz", "^2.0.0")]));
+    }
+
+    #[test]
+    pub fn package_version_should_exclude_bundled_dependencies() {
+        let version: PackageVersion = serde_json::from_str(
+            r#"{
+                "name": "foo",
+                "version": "1.0.0",
+                "dist": {
+                    "tarball": "https://registry.npmjs.org/foo/-/foo-1.0.0.tgz"
+                },
+                "dependencies": {
+                    "bar": "^1.0.0",
+                    "baz": "^2.0.0"
+                },
+                "bundledDependencies": ["bar"]
+            }"#,
+        )
+        .unwrap();
+        assert!(version.is_bundled("bar"));
+        assert!(!version.is_bundled("baz"));
+        let dependencies = version.dependencies(false).collect::<HashMap<_, _>>();
+        dbg!(&dependencies);
+        assert_eq!(dependencies, HashMap::from([("baz", "^2.0.0")]));
+
+        let version = PackageVersion {
+            bundle_dependencies: Some(BundleDependencies::Boolean(true)),
+            ..version
+        };
+        assert!(version.is_bundled("bar"));
+        assert!(version.is_bundled("baz"));
+        assert_eq!(version.dependencies(false).count(), 0);
+    }
+
+    #[test]
+    pub fn tarball_integrity_should_fall_back_to_shasum() {
+        let dist: PackageDistribution = serde_json::from_value(json!({
+            "shasum": "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3",
+            "tarball": "https://registry.npmjs.org/foo/-/foo-1.0.0.tgz",
+        }))
+        .unwrap();
+        let integrity = dist.tarball_integrity().unwrap();
+        dbg!(&integrity);
+        assert_eq!(integrity.to_string(), "sha1-qUqP5cyxm6YcTAhz05Hph5gvu9M=");
+
+        eprintln!("CASE: integrity takes precedence over shasum");
+        let dist = PackageDistribution { integrity: Some("sha512-AAAA".parse().unwrap()), ..dist };
+        assert_eq!(dist.tarball_integrity().unwrap().to_string(), "sha512-AAAA");
+
+        eprintln!("CASE: neither integrity nor shasum");
+        let dist = PackageDistribution { integrity: None, shasum: None, ..dist };
+        assert_eq!(dist.tarball_integrity(), None);
+    }
+
     #[test]
     pub fn serialized_according_to_params() {
         let version = PackageVersion {
@@ -116,9 +237,327 @@ mod tests {
             dependencies: None,
             dev_dependencies: None,
             peer_dependencies: None,
+            peer_dependencies_meta: None,
+            bundle_dependencies: None,
+        };
+
+        assert_eq!(version.serialize(""), "3.2.1");
+        assert_eq!(version.serialize("^"), "^3.2.1");
+        assert_eq!(version.serialize("~"), "~3.2.1");
+    }
+
+    #[tokio::test]
+    async fn pinned_version_from_injected_packument() {
+        let mut registry = CustomRegistry::new().await;
+        let version = |version: &str| {
+            json!({
+                "name": "foo",
+                "version": version,
+                "dist": {
+                    "tarball": format!("{}-/foo-{version}.tgz", registry.url()),
+                },
+            })
+        };
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.2.0", "next": "2.0.0-rc.0" },
+            "versions": {
+                "1.0.0": version("1.0.0"),
+                "1.2.0": version("1.2.0"),
+                "2.0.0-rc.0": version("2.0.0-rc.0"),
+            },
+        });
+        registry.add_packument("foo", &packument).await;
+
+        let package =
+            Package::fetch_from_registry("foo", &Default::default(), &registry.url(), None)
+                .await
+                .unwrap();
+        dbg!(&package);
+
+        assert_eq!(package.name, "foo");
+        assert_eq!(package.latest().version.to_string(), "1.2.0");
+        assert_eq!(package.pinned_version("^1.0.0").unwrap().version.to_string(), "1.2.0");
+        assert_eq!(package.pinned_version("~1.0.0").unwrap().version.to_string(), "1.0.0");
+        assert!(package.pinned_version("^3.0.0").is_none());
+
+        let resolve_spec =
+            |spec: &str| package.resolve_spec(spec).map(|version| version.version.to_string());
+        assert_eq!(resolve_spec("next").as_deref(), Some("2.0.0-rc.0"));
+        assert_eq!(resolve_spec("1.0.0").as_deref(), Some("1.0.0"));
+        assert_eq!(resolve_spec("^1.0.0").as_deref(), Some("1.2.0"));
+        assert_eq!(resolve_spec("missing-tag"), None);
+
+        let pinned_for_all = |ranges: &[&str]| {
+            package
+                .pinned_version_for_all(ranges.iter().copied())
+                .map(|version| version.version.to_string())
+        };
+        assert_eq!(pinned_for_all(&["^1.0.0", ">=1.1.0"]).as_deref(), Some("1.2.0"));
+        assert_eq!(pinned_for_all(&["^1.0.0", "~1.0.0"]).as_deref(), Some("1.0.0"));
+        assert_eq!(pinned_for_all(&["~1.0.0", "^1.2.0"]), None);
+    }
+
+    #[tokio::test]
+    async fn fetch_full_metadata_when_abbreviated_is_not_acceptable() {
+        let mut registry = CustomRegistry::new().await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "readme": "# foo",
+            "versions": {
+                "1.0.0": {
+                    "name": "foo",
+                    "version": "1.0.0",
+                    "dist": { "tarball": format!("{}foo/-/foo-1.0.0.tgz", registry.url()) },
+                },
+            },
+        });
+        registry.add_full_packument("foo", &packument).await;
+
+        let package =
+            Package::fetch_from_registry("foo", &Default::default(), &registry.url(), None)
+                .await
+                .unwrap();
+        dbg!(&package);
+
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn fetch_with_metadata_cache() {
+        let mut registry = CustomRegistry::new().await;
+        let version = |version: &str| {
+            json!({
+                "name": "foo",
+                "version": version,
+                "dist": { "tarball": format!("{}-/foo-{version}.tgz", registry.url()) },
+            })
         };
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": { "1.0.0": version("1.0.0"), "2.0.0": version("2.0.0") },
+        });
+        registry.add_packument_with_etag("foo", &packument, "\"v1\"").await;
+
+        let dir = tempdir().unwrap();
+        let mut cache =
+            MetadataCache { dir: dir.path().to_path_buf(), prefer_offline: false, max_age: None };
+        let http_client = ThrottledClient::default();
+        let url = format!("{}foo", registry.url());
+        let registry_url = registry.url();
+
+        eprintln!("CASE: the first request populates the cache");
+        let package =
+            Package::fetch_from_registry("foo", &http_client, &registry_url, Some(&cache))
+                .await
+                .unwrap();
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
+        let cached = cache.load(&url).unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"v1\""));
+
+        eprintln!("CASE: the cached metadata is reused on 304 Not Modified");
+        let mut data = cached.data;
+        data["dist-tags"]["latest"] = json!("2.0.0");
+        cache.save(&url, &CachedMetadata { data, ..cached });
+        let package =
+            Package::fetch_from_registry("foo", &http_client, &registry_url, Some(&cache))
+                .await
+                .unwrap();
+        assert_eq!(package.latest().version.to_string(), "2.0.0");
+
+        eprintln!("CASE: prefer offline doesn't send any request");
+        drop(registry);
+        cache.prefer_offline = true;
+        let package =
+            Package::fetch_from_registry("foo", &http_client, &registry_url, Some(&cache))
+                .await
+                .unwrap();
+        assert_eq!(package.latest().version.to_string(), "2.0.0");
+
+        eprintln!("CASE: offline mode reuses the cache and fails on a cache miss");
+        cache.prefer_offline = false;
+        let http_client = ThrottledClient::default().with_offline(true);
+        let package =
+            Package::fetch_from_registry("foo", &http_client, &registry_url, Some(&cache))
+                .await
+                .unwrap();
+        assert_eq!(package.latest().version.to_string(), "2.0.0");
+        let error = Package::fetch_from_registry("bar", &http_client, &registry_url, Some(&cache))
+            .await
+            .unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, RegistryError::Offline(error) if error.package == "bar"));
+
+        drop(dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn prefer_offline_within_max_age() {
+        let mut server = mockito::Server::new_async().await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": {
+                    "name": "foo",
+                    "version": "1.0.0",
+                    "dist": { "tarball": format!("{}/foo/-/foo-1.0.0.tgz", server.url()) },
+                },
+            },
+        });
+
+        let dir = tempdir().unwrap();
+        let cache = MetadataCache {
+            dir: dir.path().to_path_buf(),
+            prefer_offline: true,
+            max_age: Some(Duration::from_secs(60 * 60)),
+        };
+        let http_client = ThrottledClient::default();
+        let registry_url = format!("{}/", server.url());
+        let url = format!("{registry_url}foo");
+        let fetch =
+            || Package::fetch_from_registry("foo", &http_client, &registry_url, Some(&cache));
+
+        eprintln!("CASE: metadata within the window is reused without any request");
+        let any_request = server.mock("GET", "/foo").expect(0).create_async().await;
+        cache.save(
+            &url,
+            &CachedMetadata::fetched_now(Some("\"v1\"".to_string()), None, packument.clone()),
+        );
+        let package = fetch().await.unwrap();
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
+        any_request.assert_async().await;
+        any_request.remove_async().await;
+
+        eprintln!("CASE: metadata past the window is revalidated with a conditional request");
+        let not_modified = server
+            .mock("GET", "/foo")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .expect(1)
+            .create_async()
+            .await;
+        let two_hours_ago = UNIX_EPOCH.elapsed().unwrap() - Duration::from_secs(2 * 60 * 60);
+        let stale = CachedMetadata {
+            etag: Some("\"v1\"".to_string()),
+            integrity: None,
+            data: packument,
+            fetched_at: Some(two_hours_ago.as_millis()),
+        };
+        cache.save(&url, &stale);
+        let package = fetch().await.unwrap();
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
+        not_modified.assert_async().await;
+        eprintln!("The revalidation restarts the window");
+        assert!(cache.is_fresh(&cache.load(&url).unwrap()));
+
+        drop(dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn reuse_identical_body_without_conditional_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": {
+                    "name": "foo",
+                    "version": "1.0.0",
+                    "dist": { "tarball": format!("{}/foo/-/foo-1.0.0.tgz", server.url()) },
+                },
+            },
+        });
+        let always_ok = server
+            .mock("GET", "/foo")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body(packument.to_string())
+            .expect(3)
+            .create_async()
+            .await;
+
+        let dir = tempdir().unwrap();
+        let cache =
+            MetadataCache { dir: dir.path().to_path_buf(), prefer_offline: false, max_age: None };
+        let http_client = ThrottledClient::default();
+        let registry_url = format!("{}/", server.url());
+        let url = format!("{registry_url}foo");
+        let fetch =
+            || Package::fetch_from_registry("foo", &http_client, &registry_url, Some(&cache));
+
+        eprintln!("CASE: the first request populates the cache without an ETag");
+        let package = fetch().await.unwrap();
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
+        let cached = cache.load(&url).unwrap();
+        assert_eq!(cached.etag, None);
+        assert!(cached.integrity.is_some());
+
+        eprintln!("CASE: an identical body isn't parsed again");
+        let mut data = cached.data;
+        data["dist-tags"]["latest"] = json!("2.0.0");
+        data["versions"]["2.0.0"] = data["versions"]["1.0.0"].clone();
+        data["versions"]["2.0.0"]["version"] = json!("2.0.0");
+        cache.save(&url, &CachedMetadata { data, ..cached });
+        let package = fetch().await.unwrap();
+        assert_eq!(package.latest().version.to_string(), "2.0.0");
+
+        eprintln!("CASE: a different body replaces the cached metadata");
+        let cached = cache.load(&url).unwrap();
+        cache.save(
+            &url,
+            &CachedMetadata { integrity: Some("sha512-outdated".to_string()), ..cached },
+        );
+        let package = fetch().await.unwrap();
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
+        assert_eq!(cache.load(&url).unwrap().data, packument);
+
+        always_ok.assert_async().await;
+        drop(dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn fetch_once_with_mem_cache() {
+        let mut registry = CustomRegistry::new().await;
+        let packument = json!({
+            "name": "foo",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": {
+                    "name": "foo",
+                    "version": "1.0.0",
+                    "dist": { "tarball": format!("{}foo/-/foo-1.0.0.tgz", registry.url()) },
+                },
+            },
+        });
+        registry.add_packument("foo", &packument).await;
+
+        let http_client = ThrottledClient::default();
+        let mem_cache = PackageMemCache::new();
+        let registry_url = registry.url();
+        let fetch = |name: &'static str| {
+            Package::fetch_from_registry_with_mem_cache(
+                name,
+                &http_client,
+                &registry_url,
+                None,
+                &mem_cache,
+            )
+        };
+
+        eprintln!("CASE: concurrent fetches share the same packument");
+        let (first, second) = tokio::join!(fetch("foo"), fetch("foo"));
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+
+        eprintln!("CASE: the packument is reused without any request");
+        drop(registry);
+        let package = fetch("foo").await.unwrap();
+        assert_eq!(package.latest().version.to_string(), "1.0.0");
 
-        assert_eq!(version.serialize(true), "3.2.1");
-        assert_eq!(version.serialize(false), "^3.2.1");
+        eprintln!("CASE: failures aren't cached");
+        fetch("bar").await.unwrap_err();
+        assert!(mem_cache.get(&(registry_url.clone(), "bar".to_string())).unwrap().get().is_none());
     }
 }
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use ssri::Integrity;
+use ssri::{Algorithm, Integrity};
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -11,6 +11,18 @@ pub struct PackageDistribution {
     pub unpacked_size: Option<usize>,
 }
 
+impl PackageDistribution {
+    /// Integrity of the tarball.
+    ///
+    /// Falls back to the SHA-1 `shasum` for registries that don't provide `integrity`.
+    pub fn tarball_integrity(&self) -> Option<Integrity> {
+        if let Some(integrity) = &self.integrity {
+            return Some(integrity.clone());
+        }
+        Integrity::from_hex(self.shasum.as_ref()?, Algorithm::Sha1).ok()
+    }
+}
+
 impl PartialEq for PackageDistribution {
     fn eq(&self, other: &Self) -> bool {
         self.integrity == other.integrity
@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 
 use pacquet_network::ThrottledClient;
-use pipe_trait::Pipe;
+use pacquet_package_manifest::BundleDependencies;
 use serde::{Deserialize, Serialize};
 
-use crate::{package_distribution::PackageDistribution, NetworkError, PackageTag, RegistryError};
+use crate::{
+    fetch_metadata, package_distribution::PackageDistribution, MetadataCache, PackageTag,
+    RegistryError,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +18,17 @@ pub struct PackageVersion {
     pub dependencies: Option<HashMap<String, String>>,
     pub dev_dependencies: Option<HashMap<String, String>>,
     pub peer_dependencies: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_dependencies_meta: Option<HashMap<String, PeerDependencyMeta>>,
+    #[serde(default, alias = "bundledDependencies", skip_serializing_if = "Option::is_none")]
+    pub bundle_dependencies: Option<BundleDependencies>,
+}
+
+/// Value of `peerDependenciesMeta`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeerDependencyMeta {
+    #[serde(default)]
+    pub optional: bool,
 }
 
 impl PartialEq for PackageVersion {
@@ -29,26 +43,10 @@ impl PackageVersion {
         tag: PackageTag,
         http_client: &ThrottledClient,
         registry: &str,
+        cache: Option<&MetadataCache>,
     ) -> Result<Self, RegistryError> {
-        let url = || format!("{registry}{name}/{tag}");
-        let network_error = |error| NetworkError { error, url: url() };
-
-        http_client
-            .run_with_permit(|client| {
-                client
-                    .get(url())
-                    .header(
-                        "accept",
-                        "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*",
-                    )
-                    .send()
-            })
-            .await
-            .map_err(network_error)?
-            .json::<PackageVersion>()
-            .await
-            .map_err(network_error)?
-            .pipe(Ok)
+        let url = format!("{registry}{name}/{tag}");
+        fetch_metadata(http_client, &format!("{name}@{tag}"), &url, cache).await
     }
 
     pub fn to_virtual_store_name(&self) -> String {
@@ -59,25 +57,66 @@ impl PackageVersion {
         self.dist.tarball.as_str()
     }
 
+    /// Whether a dependency is shipped inside the tarball of this package.
+    pub fn is_bundled(&self, name: &str) -> bool {
+        match &self.bundle_dependencies {
+            None | Some(BundleDependencies::Boolean(false)) => false,
+            Some(BundleDependencies::Boolean(true)) => self
+                .dependencies
+                .as_ref()
+                .is_some_and(|dependencies| dependencies.contains_key(name)),
+            Some(BundleDependencies::List(list)) => list.iter().any(|bundled| bundled == name),
+        }
+    }
+
+    /// Whether a peer dependency is marked as optional in `peerDependenciesMeta`.
+    pub fn is_optional_peer(&self, name: &str) -> bool {
+        self.peer_dependencies_meta
+            .as_ref()
+            .and_then(|meta| meta.get(name))
+            .is_some_and(|meta| meta.optional)
+    }
+
+    /// List the peer dependencies that aren't optional.
+    ///
+    /// Peers that are also regular or bundled dependencies are excluded because they are
+    /// installed along with this package anyway.
+    pub fn required_peer_dependencies(&self) -> impl Iterator<Item = (&'_ str, &'_ str)> {
+        self.peer_dependencies
+            .iter()
+            .flatten()
+            .filter(|(name, _)| !self.is_optional_peer(name) && !self.is_bundled(name))
+            .filter(|(name, _)| {
+                self.dependencies
+                    .as_ref()
+                    .map_or(true, |dependencies| !dependencies.contains_key(name.as_str()))
+            })
+            .map(|(name, version)| (name.as_str(), version.as_str()))
+    }
+
+    /// List the dependencies that need to be installed.
+    ///
+    /// Bundled dependencies are excluded because they are extracted along with the tarball.
+    /// Optional peer dependencies are never included.
     pub fn dependencies(
         &self,
         with_peer_dependencies: bool,
     ) -> impl Iterator<Item = (&'_ str, &'_ str)> {
-        let dependencies = self.dependencies.iter().flatten();
-
-        let peer_dependencies = with_peer_dependencies
-            .then_some(&self.peer_dependencies)
-            .into_iter()
+        let dependencies = self
+            .dependencies
+            .iter()
             .flatten()
-            .flatten();
+            .filter(|(name, _)| !self.is_bundled(name))
+            .map(|(name, version)| (name.as_str(), version.as_str()));
 
-        dependencies
-            .chain(peer_dependencies)
-            .map(|(name, version)| (name.as_str(), version.as_str()))
+        let peer_dependencies =
+            with_peer_dependencies.then(|| self.required_peer_dependencies()).into_iter().flatten();
+
+        dependencies.chain(peer_dependencies)
     }
 
-    pub fn serialize(&self, save_exact: bool) -> String {
-        let prefix = if save_exact { "" } else { "^" };
-        format!("{0}{1}", prefix, self.version)
+    /// The version range to save to `package.json`, e.g. `^1.2.3` for the prefix `^`.
+    pub fn serialize(&self, save_prefix: &str) -> String {
+        format!("{0}{1}", save_prefix, self.version)
     }
 }
@@ -0,0 +1,92 @@
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+/// Error type of [`write_file_atomically`].
+#[derive(Debug, Display, Error, Diagnostic)]
+pub enum WriteFileAtomicallyError {
+    #[display("Failed to create the directory at {dir:?}: {error}")]
+    CreateDir {
+        dir: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+    #[display("Failed to write a temporary file in {dir:?}: {error}")]
+    WriteTempFile {
+        dir: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+    #[display("Failed to move a temporary file to {file_path:?}: {error}")]
+    PersistFile {
+        file_path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+}
+
+/// Write `content` to a temporary file next to `file_path`, which then replaces `file_path`,
+/// so that an interrupted write never leaves a partial file behind.
+///
+/// Ancestor directories will be created if they don't already exist.
+pub(crate) fn write_file_atomically(
+    file_path: &Path,
+    content: &[u8],
+    #[cfg_attr(windows, allow(unused))] mode: u32,
+) -> Result<(), WriteFileAtomicallyError> {
+    let dir = file_path.parent().expect("file path has a parent");
+    fs::create_dir_all(dir)
+        .map_err(|error| WriteFileAtomicallyError::CreateDir { dir: dir.to_path_buf(), error })?;
+
+    let write_temp_file = || -> io::Result<NamedTempFile> {
+        let mut temp_file = NamedTempFile::new_in(dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp_file.as_file().set_permissions(fs::Permissions::from_mode(mode))?;
+        }
+        temp_file.write_all(content)?;
+        Ok(temp_file)
+    };
+    let temp_file = write_temp_file().map_err(|error| WriteFileAtomicallyError::WriteTempFile {
+        dir: dir.to_path_buf(),
+        error,
+    })?;
+
+    temp_file.persist(file_path).map_err(|error| WriteFileAtomicallyError::PersistFile {
+        file_path: file_path.to_path_buf(),
+        error: error.error,
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn replace_existing_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("a/b/file.txt");
+
+        write_file_atomically(&file_path, b"partial", 0o644).unwrap();
+        write_file_atomically(&file_path, b"complete content", 0o644).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "complete content");
+        assert_eq!(fs::read_dir(dir.path().join("a/b")).unwrap().count(), 1); // no temporary file is left
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(fs::metadata(&file_path).unwrap().mode() & 0o777, 0o644);
+        }
+
+        drop(dir); // cleanup
+    }
+}
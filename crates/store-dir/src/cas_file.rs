@@ -1,9 +1,10 @@
-use crate::{FileHash, StoreDir};
+use crate::{atomic_file::write_file_atomically, FileHash, StoreDir, WriteFileAtomicallyError};
 use derive_more::{Display, Error};
 use miette::Diagnostic;
-use pacquet_fs::{ensure_file, file_mode::EXEC_MODE, EnsureFileError};
+use pacquet_fs::file_mode::{EXEC_MODE, FILE_MODE};
 use sha2::{Digest, Sha512};
-use std::path::PathBuf;
+use ssri::{Algorithm, Integrity};
+use std::{fs, path::PathBuf};
 
 impl StoreDir {
     /// Path to a file in the store directory.
@@ -12,16 +13,37 @@ impl StoreDir {
         let suffix = if executable { "-exec" } else { "" };
         self.file_path_by_hex_str(&hex, suffix)
     }
+
+    /// Path to the CAS file of an entry of an index file.
+    ///
+    /// Files are addressed by their SHA-512 hashes, so this returns `None` if `integrity`
+    /// doesn't have a SHA-512 hash.
+    pub fn cas_file_path_by_integrity(
+        &self,
+        integrity: &Integrity,
+        executable: bool,
+    ) -> Option<PathBuf> {
+        let (Algorithm::Sha512, hex) = integrity.to_hex() else {
+            return None;
+        };
+        let suffix = if executable { "-exec" } else { "" };
+        Some(self.file_path_by_hex_str(&hex, suffix))
+    }
 }
 
 /// Error type of [`StoreDir::write_cas_file`].
 #[derive(Debug, Display, Error, Diagnostic)]
 pub enum WriteCasFileError {
-    WriteFile(EnsureFileError),
+    WriteFile(WriteFileAtomicallyError),
 }
 
 impl StoreDir {
     /// Write a file from an npm package to the store directory.
+    ///
+    /// Executable files get [`EXEC_MODE`], other files get [`FILE_MODE`].
+    ///
+    /// The file is written atomically. An existing file is kept unless its size differs,
+    /// which means that it was left incomplete by an older version of pacquet.
     pub fn write_cas_file(
         &self,
         buffer: &[u8],
@@ -29,8 +51,13 @@ impl StoreDir {
     ) -> Result<(PathBuf, FileHash), WriteCasFileError> {
         let file_hash = Sha512::digest(buffer);
         let file_path = self.cas_file_path(file_hash, executable);
-        let mode = executable.then_some(EXEC_MODE);
-        ensure_file(&file_path, buffer, mode).map_err(WriteCasFileError::WriteFile)?;
+        let mode = if executable { EXEC_MODE } else { FILE_MODE };
+        let is_complete = fs::metadata(&file_path)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.len() == buffer.len() as u64);
+        if !is_complete {
+            write_file_atomically(&file_path, buffer, mode)
+                .map_err(WriteCasFileError::WriteFile)?;
+        }
         Ok((file_path, file_hash))
     }
 }
@@ -63,4 +90,22 @@ mod tests {
             "STORE_DIR/v3/files/30/9ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f-exec",
         );
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_cas_file_with_mode() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store_dir = StoreDir::new(dir.path());
+        let mode = |executable: bool| {
+            let (file_path, _) = store_dir.write_cas_file(b"hello world", executable).unwrap();
+            std::fs::metadata(file_path).unwrap().mode() & 0o777
+        };
+
+        assert_eq!(mode(false), 0o644);
+        assert_eq!(mode(true), 0o755);
+
+        drop(dir); // cleanup
+    }
 }
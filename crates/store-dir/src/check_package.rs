@@ -0,0 +1,130 @@
+use crate::{PackageFileInfo, PackageFilesIndex, StoreDir};
+use pacquet_fs::file_mode;
+use ssri::Integrity;
+use std::{fs, path::Path};
+
+/// Discrepancy between the store directory and a package that was imported from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageFilesIssue {
+    /// The store directory has no index file for the package.
+    MissingIndex,
+    /// The store directory lacks the CAS file of `path`.
+    MissingCasFile { path: String },
+    /// The CAS file of `path` doesn't match its integrity in the index file.
+    ModifiedCasFile { path: String },
+    /// The file at `path` in the package directory doesn't match its integrity in the index file.
+    ModifiedFile { path: String },
+}
+
+impl StoreDir {
+    /// Compare the files of a package in `package_dir` and in the store directory against
+    /// the index file of the tarball.
+    ///
+    /// The issues are sorted by the paths of the files.
+    pub fn check_package_files(
+        &self,
+        tarball_integrity: &Integrity,
+        package_dir: &Path,
+    ) -> Vec<PackageFilesIssue> {
+        let Some(PackageFilesIndex { files }) = self.read_index_file(tarball_integrity) else {
+            return vec![PackageFilesIssue::MissingIndex];
+        };
+
+        let mut files = files.into_iter().collect::<Vec<_>>();
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut issues = Vec::new();
+        for (path, PackageFileInfo { integrity, mode, .. }) in files {
+            let integrity = integrity.parse::<Integrity>().ok();
+            let cas_file = integrity.as_ref().and_then(|integrity| {
+                self.cas_file_path_by_integrity(integrity, file_mode::is_all_exec(mode))
+            });
+            let (Some(integrity), Some(cas_file)) = (integrity, cas_file) else {
+                issues.push(PackageFilesIssue::MissingCasFile { path });
+                continue;
+            };
+            let matches =
+                |file: &Path| fs::read(file).is_ok_and(|content| integrity.check(content).is_ok());
+
+            if !cas_file.is_file() {
+                issues.push(PackageFilesIssue::MissingCasFile { path: path.clone() });
+            } else if !matches(&cas_file) {
+                issues.push(PackageFilesIssue::ModifiedCasFile { path: path.clone() });
+            }
+
+            if !matches(&package_dir.join(&path)) {
+                issues.push(PackageFilesIssue::ModifiedFile { path });
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use sha2::{Digest, Sha512};
+    use ssri::{Algorithm, IntegrityOpts};
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn check_package_files() {
+        let dir = tempdir().unwrap();
+        let store_dir = StoreDir::new(dir.path().join("store"));
+        let package_dir = dir.path().join("package");
+        let tarball_integrity =
+            IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(b"TARBALL CONTENT").result();
+
+        eprintln!("CASE: missing index file");
+        assert_eq!(
+            store_dir.check_package_files(&tarball_integrity, &package_dir),
+            [PackageFilesIssue::MissingIndex],
+        );
+
+        eprintln!("Populate the store and the package directory");
+        let mut files = HashMap::new();
+        for (path, content) in [("a.js", "A"), ("b.js", "B"), ("c.js", "C")] {
+            let (cas_path, _) = store_dir.write_cas_file(content.as_bytes(), false).unwrap();
+            fs::create_dir_all(&package_dir).unwrap();
+            fs::copy(cas_path, package_dir.join(path)).unwrap();
+            let info = PackageFileInfo {
+                checked_at: None,
+                integrity: IntegrityOpts::new()
+                    .algorithm(Algorithm::Sha512)
+                    .chain(content)
+                    .result()
+                    .to_string(),
+                mode: 0o644,
+                size: Some(1),
+            };
+            files.insert(path.to_string(), info);
+        }
+        store_dir.write_index_file(&tarball_integrity, &PackageFilesIndex { files }).unwrap();
+
+        eprintln!("CASE: untouched package");
+        let issues = store_dir.check_package_files(&tarball_integrity, &package_dir);
+        dbg!(&issues);
+        assert!(issues.is_empty());
+
+        eprintln!("CASE: modified and missing files");
+        let cas_file = |content: &str| {
+            let hash = Sha512::digest(content);
+            store_dir.cas_file_path(hash, false)
+        };
+        fs::write(package_dir.join("a.js"), "modified").unwrap();
+        fs::write(cas_file("B"), "corrupted").unwrap();
+        fs::remove_file(cas_file("C")).unwrap();
+        assert_eq!(
+            store_dir.check_package_files(&tarball_integrity, &package_dir),
+            [
+                PackageFilesIssue::ModifiedFile { path: "a.js".to_string() },
+                PackageFilesIssue::ModifiedCasFile { path: "b.js".to_string() },
+                PackageFilesIssue::MissingCasFile { path: "c.js".to_string() },
+            ],
+        );
+
+        drop(dir); // cleanup
+    }
+}
@@ -1,23 +1,55 @@
 use crate::StoreDir;
+use crate::{atomic_file::write_file_atomically, WriteFileAtomicallyError};
 use derive_more::{Display, Error};
 use miette::Diagnostic;
-use pacquet_fs::{ensure_file, EnsureFileError};
+use pacquet_fs::file_mode::FILE_MODE;
 use serde::{Deserialize, Serialize};
 use ssri::{Algorithm, Integrity};
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, fs, path::PathBuf};
 
 impl StoreDir {
     /// Path to an index file of a tarball.
     pub fn index_file_path(&self, tarball_integrity: &Integrity) -> PathBuf {
         let (algorithm, hex) = tarball_integrity.to_hex();
         assert!(
-            matches!(algorithm, Algorithm::Sha512 | Algorithm::Sha1),
-            "Only Sha1 and Sha512 are supported. {algorithm} isn't",
+            matches!(algorithm, Algorithm::Sha512 | Algorithm::Sha256 | Algorithm::Sha1),
+            "Only Sha1, Sha256, and Sha512 are supported. {algorithm} isn't",
         ); // TODO: propagate this error
-        self.file_path_by_hex_str(&hex, "-index.json")
+        self.file_path_by_hex_str(&hex, INDEX_FILE_SUFFIX)
+    }
+
+    /// List the integrities of all packages whose index files are in the store.
+    ///
+    /// Entries whose names aren't valid index file names are skipped.
+    pub fn stored_integrities(&self) -> impl Iterator<Item = Integrity> {
+        fs::read_dir(self.files())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|head| {
+                let head_name = head.file_name().into_string().ok()?;
+                let tails = fs::read_dir(head.path()).ok()?;
+                Some(tails.flatten().map(move |tail| (head_name.clone(), tail)))
+            })
+            .flatten()
+            .filter_map(|(head, tail)| {
+                let tail = tail.file_name().into_string().ok()?;
+                let tail = tail.strip_suffix(INDEX_FILE_SUFFIX)?;
+                let hex = format!("{head}{tail}");
+                let algorithm = match hex.len() {
+                    128 => Algorithm::Sha512,
+                    64 => Algorithm::Sha256,
+                    40 => Algorithm::Sha1,
+                    _ => return None,
+                };
+                Integrity::from_hex(hex, algorithm).ok()
+            })
     }
 }
 
+/// Suffix of the names of index files.
+pub(crate) const INDEX_FILE_SUFFIX: &str = "-index.json";
+
 /// Content of an index file (`$STORE_DIR/v3/files/*/*-index.json`).
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -31,6 +63,8 @@ pub struct PackageFilesIndex {
 pub struct PackageFileInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub checked_at: Option<u128>,
+    /// SHA-512 integrity of the content of the file, which is its address in the store.
+    /// It doesn't depend on the algorithm of the integrity of the tarball.
     pub integrity: String,
     pub mode: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -40,11 +74,22 @@ pub struct PackageFileInfo {
 /// Error type of [`StoreDir::write_index_file`].
 #[derive(Debug, Display, Error, Diagnostic)]
 pub enum WriteIndexFileError {
-    WriteFile(EnsureFileError),
+    WriteFile(WriteFileAtomicallyError),
 }
 
 impl StoreDir {
+    /// Read the index file of a tarball from the store directory.
+    ///
+    /// Returns `None` if the index file is missing or isn't a valid index.
+    pub fn read_index_file(&self, integrity: &Integrity) -> Option<PackageFilesIndex> {
+        let index_content = fs::read_to_string(self.index_file_path(integrity)).ok()?;
+        serde_json::from_str(&index_content).ok()
+    }
+
     /// Write a JSON file that indexes files in a tarball to the store directory.
+    ///
+    /// The index is written to a temporary file which is then renamed, so that an interrupted
+    /// write never leaves a partial index behind. An existing index file is replaced.
     pub fn write_index_file(
         &self,
         integrity: &Integrity,
@@ -53,7 +98,7 @@ impl StoreDir {
         let file_path = self.index_file_path(integrity);
         let index_content =
             serde_json::to_string(&index_content).expect("convert a TarballIndex to JSON");
-        ensure_file(&file_path, index_content.as_bytes(), Some(0o666))
+        write_file_atomically(&file_path, index_content.as_bytes(), FILE_MODE)
             .map_err(WriteIndexFileError::WriteFile)
     }
 }
@@ -61,7 +106,9 @@ impl StoreDir {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pretty_assertions::assert_eq;
     use ssri::IntegrityOpts;
+    use tempfile::tempdir;
 
     #[test]
     fn index_file_path() {
@@ -73,4 +120,73 @@ mod tests {
         let expected: PathBuf = expected.split('/').collect();
         assert_eq!(&received, &expected);
     }
+
+    #[test]
+    fn read_index_file() {
+        let dir = tempdir().unwrap();
+        let store_dir = StoreDir::new(dir.path());
+        let integrity =
+            IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(b"TARBALL CONTENT").result();
+
+        eprintln!("Missing index file");
+        assert!(store_dir.read_index_file(&integrity).is_none());
+
+        eprintln!("Valid index file");
+        let file_info = PackageFileInfo {
+            checked_at: None,
+            integrity: "sha512-AAAA".to_string(),
+            mode: 0o644,
+            size: Some(3),
+        };
+        let index = PackageFilesIndex { files: [("index.js".to_string(), file_info)].into() };
+        store_dir.write_index_file(&integrity, &index).unwrap();
+        let received = store_dir.read_index_file(&integrity).unwrap();
+        dbg!(&received);
+        assert_eq!(received.files.keys().collect::<Vec<_>>(), ["index.js"]);
+        assert_eq!(received.files["index.js"].integrity, "sha512-AAAA");
+
+        eprintln!("Corrupted index file");
+        fs::write(store_dir.index_file_path(&integrity), "{ not json").unwrap();
+        assert!(store_dir.read_index_file(&integrity).is_none());
+
+        eprintln!("Corrupted index file is replaced without leaving temporary files");
+        store_dir.write_index_file(&integrity, &index).unwrap();
+        assert!(store_dir.read_index_file(&integrity).is_some());
+        let index_dir = store_dir.index_file_path(&integrity).parent().unwrap().to_path_buf();
+        assert_eq!(fs::read_dir(index_dir).unwrap().count(), 1);
+
+        drop(dir); // cleanup
+    }
+
+    #[test]
+    fn stored_integrities() {
+        let dir = tempdir().unwrap();
+        let store_dir = StoreDir::new(dir.path());
+        let integrity = |content: &[u8], algorithm: Algorithm| {
+            IntegrityOpts::new().algorithm(algorithm).chain(content).result()
+        };
+        let index = || PackageFilesIndex { files: HashMap::new() };
+
+        eprintln!("Empty store");
+        assert_eq!(store_dir.stored_integrities().count(), 0);
+
+        eprintln!("Populate the store");
+        let mut expected = [
+            integrity(b"FIRST TARBALL", Algorithm::Sha512),
+            integrity(b"SECOND TARBALL", Algorithm::Sha512),
+            integrity(b"THIRD TARBALL", Algorithm::Sha1),
+        ];
+        for integrity in &expected {
+            store_dir.write_index_file(integrity, &index()).unwrap();
+        }
+        let unrelated_dir = store_dir.files().join("bc");
+        fs::create_dir_all(&unrelated_dir).unwrap();
+        fs::write(unrelated_dir.join("d60799116ebef600-exec"), "not an index file").unwrap();
+
+        let mut received = store_dir.stored_integrities().collect::<Vec<_>>();
+        received.sort_by_key(ToString::to_string);
+        expected.sort_by_key(ToString::to_string);
+        dbg!(&received);
+        assert_eq!(received, expected);
+    }
 }
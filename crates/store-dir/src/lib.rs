@@ -1,9 +1,15 @@
+mod atomic_file;
 mod cas_file;
+mod check_package;
 mod index_file;
 mod prune;
 mod store_dir;
+mod verify;
 
+pub use atomic_file::WriteFileAtomicallyError;
 pub use cas_file::*;
+pub use check_package::*;
 pub use index_file::*;
 pub use prune::*;
 pub use store_dir::*;
+pub use verify::*;
@@ -1,15 +1,237 @@
-use crate::StoreDir;
+use crate::{PackageFilesIndex, StoreDir, INDEX_FILE_SUFFIX};
 use derive_more::{Display, Error};
 use miette::Diagnostic;
+use pacquet_fs::file_mode;
+use ssri::Integrity;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 /// Error type of [`StoreDir::prune`].
 #[derive(Debug, Display, Error, Diagnostic)]
-pub enum PruneError {}
+#[non_exhaustive]
+pub enum PruneError {
+    #[display("Failed to read the store directory at {dir:?}: {error}")]
+    #[diagnostic(code(pacquet_store_dir::read_store_dir))]
+    ReadDir {
+        dir: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+
+    #[display("Failed to remove {path:?} from the store: {error}")]
+    #[diagnostic(code(pacquet_store_dir::remove_store_file))]
+    RemoveFile {
+        path: PathBuf,
+        #[error(source)]
+        error: io::Error,
+    },
+}
+
+/// Summary of the files removed by [`StoreDir::prune`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PruneStats {
+    /// Number of removed files.
+    pub removed_files: usize,
+    /// Total size of the removed files in bytes.
+    pub removed_bytes: u64,
+}
 
 impl StoreDir {
-    /// Remove all files in the store that don't have reference elsewhere.
-    pub fn prune(&self) -> Result<(), PruneError> {
+    /// Remove the files in the store that aren't referenced by any index file, the index files
+    /// that can't be read, and the index files of the packages whose files are missing, e.g.
+    /// after `pacquet store verify --repair`. The leftovers of interrupted downloads in the
+    /// temporary directory of the store are removed too.
+    ///
+    /// Every index file is read before anything is removed, so this must not run while
+    /// packages are being added to the store.
+    pub fn prune(&self) -> Result<PruneStats, PruneError> {
         // Ref: https://pnpm.io/cli/store#prune
-        todo!("remove orphaned files")
+        let (index_files, cas_files) =
+            self.list_store_files()?.into_iter().partition::<Vec<_>, _>(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.ends_with(INDEX_FILE_SUFFIX))
+            });
+
+        // mark: collect the files that are referenced by the complete packages
+        let mut referenced = HashSet::<PathBuf>::new();
+        let mut orphans = Vec::<PathBuf>::new();
+        for path in index_files {
+            let index = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str::<PackageFilesIndex>(&content).ok());
+            let Some(PackageFilesIndex { files }) = index else {
+                orphans.push(path);
+                continue;
+            };
+            let package_files = files
+                .values()
+                .map(|info| {
+                    let integrity = info.integrity.parse::<Integrity>().ok()?;
+                    self.cas_file_path_by_integrity(&integrity, file_mode::is_all_exec(info.mode))
+                })
+                .collect::<Option<Vec<_>>>()
+                .filter(|package_files| package_files.iter().all(|path| path.is_file()));
+            match package_files {
+                Some(package_files) => referenced.extend(package_files),
+                None => orphans.push(path),
+            }
+        }
+
+        // sweep: remove everything else
+        orphans.extend(cas_files.into_iter().filter(|path| !referenced.contains(path)));
+        let mut stats = PruneStats::default();
+        for path in orphans {
+            remove_recursively(&path, &mut stats)?;
+        }
+
+        let tmp_dir = self.tmp();
+        if tmp_dir.exists() {
+            for path in read_dir(&tmp_dir)? {
+                remove_recursively(&path, &mut stats)?;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// List the paths of all files in `{store}/v3/files/*/`.
+    fn list_store_files(&self) -> Result<Vec<PathBuf>, PruneError> {
+        let files_dir = self.files();
+        if !files_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut files = Vec::new();
+        for head in read_dir(&files_dir)? {
+            if head.is_dir() {
+                files.extend(read_dir(&head)?.into_iter().filter(|path| path.is_file()));
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// List the paths of the entries of `dir`.
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, PruneError> {
+    let to_error = |error| PruneError::ReadDir { dir: dir.to_path_buf(), error };
+    fs::read_dir(dir)
+        .map_err(to_error)?
+        .map(|entry| entry.map(|entry| entry.path()).map_err(to_error))
+        .collect()
+}
+
+/// Remove the file or the directory at `path` and add the removed files to `stats`.
+fn remove_recursively(path: &Path, stats: &mut PruneStats) -> Result<(), PruneError> {
+    let remove_error = |error| PruneError::RemoveFile { path: path.to_path_buf(), error };
+    let metadata = fs::symlink_metadata(path).map_err(remove_error)?;
+    if metadata.is_dir() {
+        for entry in read_dir(path)? {
+            remove_recursively(&entry, stats)?;
+        }
+        return fs::remove_dir(path).map_err(remove_error);
+    }
+    fs::remove_file(path).map_err(remove_error)?;
+    stats.removed_files += 1;
+    stats.removed_bytes += metadata.len();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageFileInfo;
+    use pretty_assertions::assert_eq;
+    use ssri::{Algorithm, IntegrityOpts};
+    use tempfile::tempdir;
+
+    #[test]
+    fn prune() {
+        let dir = tempdir().unwrap();
+        let store_dir = StoreDir::new(dir.path());
+
+        eprintln!("CASE: empty store");
+        assert_eq!(store_dir.prune().unwrap(), PruneStats::default());
+
+        eprintln!("Populate the store");
+        let file_info = |content: &str, mode: u32| PackageFileInfo {
+            checked_at: None,
+            integrity: IntegrityOpts::new()
+                .algorithm(Algorithm::Sha512)
+                .chain(content)
+                .result()
+                .to_string(),
+            mode,
+            size: Some(content.len() as u64),
+        };
+        let (referenced, _) = store_dir.write_cas_file(b"referenced", false).unwrap();
+        let (referenced_exec, _) = store_dir.write_cas_file(b"executable", true).unwrap();
+        let (orphan, _) = store_dir.write_cas_file(b"orphan", false).unwrap();
+        let index = PackageFilesIndex {
+            files: [
+                ("index.js".to_string(), file_info("referenced", 0o644)),
+                ("bin.js".to_string(), file_info("executable", 0o755)),
+            ]
+            .into(),
+        };
+        let tarball_integrity = |content: &[u8]| {
+            IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(content).result()
+        };
+        let index_file = store_dir.index_file_path(&tarball_integrity(b"TARBALL"));
+        store_dir.write_index_file(&tarball_integrity(b"TARBALL"), &index).unwrap();
+        let corrupted_index_file = store_dir.index_file_path(&tarball_integrity(b"CORRUPTED"));
+        fs::create_dir_all(corrupted_index_file.parent().unwrap()).unwrap();
+        fs::write(&corrupted_index_file, "{ not json").unwrap();
+
+        eprintln!("CASE: remove the orphan and the corrupted index file");
+        let stats = store_dir.prune().unwrap();
+        dbg!(&stats);
+        assert_eq!(
+            stats,
+            PruneStats {
+                removed_files: 2,
+                removed_bytes: ("orphan".len() + "{ not json".len()) as u64,
+            },
+        );
+        assert!(referenced.exists());
+        assert!(referenced_exec.exists());
+        assert!(index_file.exists());
+        assert!(!orphan.exists());
+        assert!(!corrupted_index_file.exists());
+
+        eprintln!("CASE: nothing left to remove");
+        assert_eq!(store_dir.prune().unwrap(), PruneStats::default());
+
+        eprintln!("CASE: remove the index file and the other files of an incomplete package");
+        fs::remove_file(&referenced_exec).unwrap();
+        let index_file_size = fs::metadata(&index_file).unwrap().len();
+        let stats = store_dir.prune().unwrap();
+        dbg!(&stats);
+        assert_eq!(
+            stats,
+            PruneStats {
+                removed_files: 2,
+                removed_bytes: index_file_size + "referenced".len() as u64,
+            },
+        );
+        assert!(!index_file.exists());
+        assert!(!referenced.exists());
+
+        eprintln!("CASE: remove the leftovers in the temporary directory");
+        fs::create_dir_all(store_dir.tmp().join("nested")).unwrap();
+        fs::write(store_dir.tmp().join("foo.tgz.partial"), "partial").unwrap();
+        fs::write(store_dir.tmp().join("nested/bar"), "bar").unwrap();
+        let stats = store_dir.prune().unwrap();
+        dbg!(&stats);
+        assert_eq!(
+            stats,
+            PruneStats { removed_files: 2, removed_bytes: ("partial".len() + "bar".len()) as u64 },
+        );
+        assert!(store_dir.tmp().exists());
+        assert_eq!(fs::read_dir(store_dir.tmp()).unwrap().count(), 0);
+
+        drop(dir); // cleanup
     }
 }
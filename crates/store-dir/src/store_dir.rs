@@ -38,7 +38,7 @@ impl StoreDir {
     }
 
     /// The directory that contains all files from the once-installed packages.
-    fn files(&self) -> PathBuf {
+    pub(crate) fn files(&self) -> PathBuf {
         self.v3().join("files")
     }
 
@@ -59,6 +59,11 @@ impl StoreDir {
         self.file_path_by_head_tail(head, &tail)
     }
 
+    /// Path to the directory of the cached registry metadata inside the store.
+    pub fn metadata(&self) -> PathBuf {
+        self.v3().join("metadata")
+    }
+
     /// Path to the temporary directory inside the store.
     pub fn tmp(&self) -> PathBuf {
         self.v3().join("tmp")
@@ -82,6 +87,13 @@ mod tests {
         assert_eq!(&received, &expected);
     }
 
+    #[test]
+    fn metadata() {
+        let received = StoreDir::new("/home/user/.local/share/pnpm/store").metadata();
+        let expected = PathBuf::from("/home/user/.local/share/pnpm/store/v3/metadata");
+        assert_eq!(&received, &expected);
+    }
+
     #[test]
     fn tmp() {
         let received = StoreDir::new("/home/user/.local/share/pnpm/store").tmp();
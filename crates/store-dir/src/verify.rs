@@ -0,0 +1,159 @@
+use crate::{PackageFileInfo, PackageFilesIndex, StoreDir};
+use derive_more::{Display, Error};
+use miette::Diagnostic;
+use pacquet_fs::file_mode;
+use rayon::prelude::*;
+use ssri::Integrity;
+use std::{fs, io, path::PathBuf};
+
+/// A CAS file that doesn't match the integrity recorded in an index file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptedCasFile {
+    /// Integrity of the tarball whose index file references the CAS file.
+    pub tarball_integrity: Integrity,
+    /// Path of the file in the tarball.
+    pub path: String,
+    /// Path to the CAS file in the store directory.
+    pub cas_file: PathBuf,
+    /// Whether the CAS file is missing rather than modified.
+    pub missing: bool,
+}
+
+/// Error type of [`StoreDir::repair`].
+#[derive(Debug, Display, Error, Diagnostic)]
+#[display("Failed to remove the corrupted file at {path:?}: {error}")]
+#[diagnostic(code(pacquet_store_dir::remove_corrupted_file))]
+pub struct RepairError {
+    pub path: PathBuf,
+    #[error(source)]
+    pub error: io::Error,
+}
+
+impl StoreDir {
+    /// Hash the CAS files referenced by every index file in the store and compare them against
+    /// the integrities recorded in the index files.
+    ///
+    /// Entries whose integrities aren't SHA-512 are skipped. The result is sorted by tarball
+    /// integrity, then by path.
+    pub fn verify(&self) -> Vec<CorruptedCasFile> {
+        let entries = self
+            .stored_integrities()
+            .filter_map(|tarball_integrity| {
+                let PackageFilesIndex { files } = self.read_index_file(&tarball_integrity)?;
+                Some(
+                    files
+                        .into_iter()
+                        .map(move |(path, info)| (tarball_integrity.clone(), path, info)),
+                )
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+
+        let mut corrupted = entries
+            .into_par_iter()
+            .filter_map(|(tarball_integrity, path, PackageFileInfo { integrity, mode, .. })| {
+                let integrity = integrity.parse::<Integrity>().ok()?;
+                let cas_file =
+                    self.cas_file_path_by_integrity(&integrity, file_mode::is_all_exec(mode))?;
+                let missing = match fs::read(&cas_file) {
+                    Ok(content) if integrity.check(content).is_ok() => return None,
+                    Ok(_) => false,
+                    Err(_) => true,
+                };
+                Some(CorruptedCasFile { tarball_integrity, path, cas_file, missing })
+            })
+            .collect::<Vec<_>>();
+        corrupted.sort_by(|a, b| {
+            (a.tarball_integrity.to_string(), &a.path)
+                .cmp(&(b.tarball_integrity.to_string(), &b.path))
+        });
+        corrupted
+    }
+
+    /// Remove the modified files among `corrupted` so that the next install fetches their
+    /// packages again.
+    ///
+    /// Returns the number of removed files.
+    pub fn repair(&self, corrupted: &[CorruptedCasFile]) -> Result<usize, RepairError> {
+        let mut removed = 0;
+        for CorruptedCasFile { cas_file, missing, .. } in corrupted {
+            if *missing {
+                continue;
+            }
+            match fs::remove_file(cas_file) {
+                Ok(()) => removed += 1,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(RepairError { path: cas_file.clone(), error }),
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use ssri::{Algorithm, IntegrityOpts};
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn verify_and_repair() {
+        let dir = tempdir().unwrap();
+        let store_dir = StoreDir::new(dir.path());
+        let integrity = |content: &str| {
+            IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(content).result()
+        };
+
+        eprintln!("Populate the store");
+        let mut cas_files = Vec::new();
+        let mut files = HashMap::new();
+        for (path, content) in [("a.js", "A"), ("b.js", "B"), ("c.js", "C")] {
+            let (cas_file, _) = store_dir.write_cas_file(content.as_bytes(), false).unwrap();
+            let info = PackageFileInfo {
+                checked_at: None,
+                integrity: integrity(content).to_string(),
+                mode: 0o644,
+                size: Some(1),
+            };
+            files.insert(path.to_string(), info);
+            cas_files.push(cas_file);
+        }
+        let tarball_integrity = integrity("TARBALL");
+        store_dir.write_index_file(&tarball_integrity, &PackageFilesIndex { files }).unwrap();
+
+        eprintln!("CASE: intact store");
+        assert!(store_dir.verify().is_empty());
+
+        eprintln!("CASE: modified and missing files");
+        fs::write(&cas_files[0], "modified").unwrap();
+        fs::remove_file(&cas_files[2]).unwrap();
+        let corrupted = store_dir.verify();
+        dbg!(&corrupted);
+        assert_eq!(
+            corrupted,
+            [
+                CorruptedCasFile {
+                    tarball_integrity: tarball_integrity.clone(),
+                    path: "a.js".to_string(),
+                    cas_file: cas_files[0].clone(),
+                    missing: false,
+                },
+                CorruptedCasFile {
+                    tarball_integrity: tarball_integrity.clone(),
+                    path: "c.js".to_string(),
+                    cas_file: cas_files[2].clone(),
+                    missing: true,
+                },
+            ],
+        );
+
+        eprintln!("CASE: repair");
+        assert_eq!(store_dir.repair(&corrupted).unwrap(), 1);
+        assert!(!cas_files[0].exists());
+        assert!(cas_files[1].exists());
+
+        drop(dir); // cleanup
+    }
+}
@@ -1,32 +1,39 @@
 use std::{
+    cell::Cell,
     collections::HashMap,
-    io::{Cursor, Read},
-    path::PathBuf,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
     sync::Arc,
-    time::UNIX_EPOCH,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use base64::{engine::general_purpose::STANDARD as BASE64_STD, Engine};
 use dashmap::DashMap;
 use derive_more::{Display, Error, From};
+use flate2::read::GzDecoder;
 use miette::Diagnostic;
 use pacquet_fs::file_mode;
-use pacquet_network::ThrottledClient;
+use pacquet_network::{OfflineModeError, RequestError, ThrottledClient};
 use pacquet_store_dir::{
     PackageFileInfo, PackageFilesIndex, StoreDir, WriteCasFileError, WriteIndexFileError,
 };
 use pipe_trait::Pipe;
-use ssri::Integrity;
+use rand::Rng;
+use reqwest::{
+    header::{ACCEPT_RANGES, RANGE},
+    StatusCode,
+};
+use ssri::{Algorithm, Hash, Integrity, IntegrityChecker};
 use tar::Archive;
 use tokio::sync::{Notify, RwLock};
 use tracing::instrument;
-use zune_inflate::{errors::InflateDecodeErrors, DeflateDecoder, DeflateOptions};
 
 #[derive(Debug, Display, Error, Diagnostic)]
 #[display("Failed to fetch {url}: {error}")]
 pub struct NetworkError {
     pub url: String,
-    pub error: reqwest::Error,
+    pub error: RequestError,
 }
 
 #[derive(Debug, Display, Error, Diagnostic)]
@@ -43,17 +50,84 @@ pub enum TarballError {
     #[diagnostic(code(pacquet_tarball::fetch_tarball))]
     FetchTarball(NetworkError),
 
+    #[from(ignore)]
+    #[display("Failed to fetch {url} after {attempts} attempts: {error}")]
+    #[diagnostic(code(pacquet_tarball::fetch_tarball_retries_exhausted))]
+    FetchTarballRetriesExhausted {
+        url: String,
+        attempts: u64,
+        #[error(source)]
+        error: RequestError,
+    },
+
+    #[from(ignore)]
+    #[display("Failed to read the tarball at {path:?}: {error}")]
+    #[diagnostic(code(pacquet_tarball::read_tarball_file))]
+    ReadTarballFile {
+        path: PathBuf,
+        #[error(source)]
+        error: std::io::Error,
+    },
+
     #[from(ignore)]
     #[diagnostic(code(pacquet_tarball::io_error))]
     ReadTarballEntries(std::io::Error),
 
+    #[from(ignore)]
+    #[display("Failed to read the mode of a tarball entry: {_0}")]
+    #[diagnostic(code(pacquet_tarball::invalid_entry_mode))]
+    InvalidEntryMode(std::io::Error),
+
+    #[from(ignore)]
+    #[display("Failed to read the path of a tarball entry: {_0}")]
+    #[diagnostic(code(pacquet_tarball::invalid_entry_path))]
+    InvalidEntryPath(std::io::Error),
+
+    #[from(ignore)]
+    #[display(
+        "The path of a tarball entry is not valid UTF-8: {:?}",
+        String::from_utf8_lossy(path)
+    )]
+    #[diagnostic(code(pacquet_tarball::non_utf8_entry_path))]
+    NonUtf8EntryPath {
+        /// Raw bytes of the path.
+        #[error(not(source))]
+        path: Vec<u8>,
+    },
+
     #[diagnostic(code(pacquet_tarball::verify_checksum_error))]
     Checksum(VerifyChecksumError),
 
+    #[from(ignore)]
+    #[display("Unsupported compression format of the tarball at {url}")]
+    #[diagnostic(
+        code(pacquet_tarball::unsupported_format),
+        help("Tarballs must be compressed with gzip, zstd, or brotli")
+    )]
+    UnsupportedFormat { url: String },
+
     #[from(ignore)]
     #[display("Failed to decode gzip: {_0}")]
     #[diagnostic(code(pacquet_tarball::decode_gzip))]
-    DecodeGzip(InflateDecodeErrors),
+    DecodeGzip(std::io::Error),
+
+    #[from(ignore)]
+    #[display("Failed to decode zstd: {_0}")]
+    #[diagnostic(code(pacquet_tarball::decode_zstd))]
+    DecodeZstd(std::io::Error),
+
+    #[from(ignore)]
+    #[display("Failed to decode brotli: {_0}")]
+    #[diagnostic(code(pacquet_tarball::decode_brotli))]
+    DecodeBrotli(std::io::Error),
+
+    #[from(ignore)]
+    #[display("The decompressed tarball exceeds the limit of {limit} bytes")]
+    #[diagnostic(
+        code(pacquet_tarball::unpacked_size_exceeded),
+        help("The tarball might be a decompression bomb")
+    )]
+    UnpackedSizeExceeded { limit: usize },
 
     #[from(ignore)]
     #[display("Failed to write cafs: {_0}")]
@@ -68,6 +142,19 @@ pub enum TarballError {
     #[from(ignore)]
     #[diagnostic(code(pacquet_tarball::task_join_error))]
     TaskJoin(tokio::task::JoinError),
+
+    #[from(ignore)]
+    #[display("Failed to write the partially downloaded tarball to {path:?}: {error}")]
+    #[diagnostic(code(pacquet_tarball::write_partial_download))]
+    WritePartialDownload {
+        path: PathBuf,
+        #[error(source)]
+        error: std::io::Error,
+    },
+
+    #[from(ignore)]
+    #[diagnostic(transparent)]
+    Offline(#[error(source)] OfflineModeError),
 }
 
 /// Value of the cache.
@@ -84,19 +171,451 @@ pub enum CacheValue {
 /// The key of this hashmap is the url of each tarball.
 pub type MemCache = DashMap<String, Arc<RwLock<CacheValue>>>;
 
-#[instrument(skip(gz_data), fields(gz_data_len = gz_data.len()))]
-fn decompress_gzip(gz_data: &[u8], unpacked_size: Option<usize>) -> Result<Vec<u8>, TarballError> {
-    let mut options = DeflateOptions::default().set_confirm_checksum(false);
+/// Limit of the total size of the files in a decompressed tarball, which guards against decompression bombs.
+///
+/// Only the content of the files is counted, like the unpacked size advertised by the registry,
+/// so the tar headers and padding don't get tiny packages rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnpackedSizeLimit {
+    /// Maximum ratio of the decompressed size to the unpacked size advertised by the registry.
+    pub factor: usize,
+    /// Maximum decompressed size in bytes when the registry doesn't advertise the unpacked size.
+    pub max_size: usize,
+}
+
+impl Default for UnpackedSizeLimit {
+    fn default() -> Self {
+        UnpackedSizeLimit { factor: 10, max_size: 1 << 30 }
+    }
+}
+
+impl UnpackedSizeLimit {
+    /// Maximum decompressed size in bytes of a tarball whose advertised unpacked size is `unpacked_size`.
+    fn max_unpacked_size(self, unpacked_size: Option<usize>) -> usize {
+        match unpacked_size {
+            Some(unpacked_size) => unpacked_size.saturating_mul(self.factor),
+            None => self.max_size,
+        }
+    }
+}
+
+/// Compression format of a tarball.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TarballFormat {
+    Gzip,
+    Zstd,
+    Brotli,
+}
+
+impl TarballFormat {
+    /// Detect the format from the magic bytes at the start of the tarball.
+    ///
+    /// Brotli has no magic bytes, so it is detected from the `.br` extension of `package_url`.
+    fn detect(magic: &[u8], package_url: &str) -> Option<Self> {
+        const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+        const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+        if magic.starts_with(GZIP_MAGIC) {
+            return Some(TarballFormat::Gzip);
+        }
+        if magic.starts_with(ZSTD_MAGIC) {
+            return Some(TarballFormat::Zstd);
+        }
+        let url_path = package_url.split(['?', '#']).next().unwrap_or(package_url);
+        url_path.ends_with(".br").then_some(TarballFormat::Brotli)
+    }
+
+    /// Error of a failed decoder of this format.
+    fn decode_error(self, error: io::Error) -> TarballError {
+        match self {
+            TarballFormat::Gzip => TarballError::DecodeGzip(error),
+            TarballFormat::Zstd => TarballError::DecodeZstd(error),
+            TarballFormat::Brotli => TarballError::DecodeBrotli(error),
+        }
+    }
+}
+
+/// Reader that keeps the error of the decoder `inner`, so that it can be told apart
+/// from the errors of the tar archive, which only sees a copy.
+struct DecoderReader<'a, Inner> {
+    inner: Inner,
+    error: &'a Cell<Option<io::Error>>,
+}
+
+impl<'a, Inner: Read> Read for DecoderReader<'a, Inner> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|error| {
+            let copy = io::Error::new(error.kind(), error.to_string());
+            self.error.set(Some(error));
+            copy
+        })
+    }
+}
+
+/// Create a decoder that decompresses `tarball` of the given `format` as it is read.
+fn decompress_tarball<'a>(
+    tarball: impl BufRead + 'a,
+    format: TarballFormat,
+) -> Result<Box<dyn Read + 'a>, TarballError> {
+    const BROTLI_BUFFER_SIZE: usize = 4096;
+    Ok(match format {
+        TarballFormat::Gzip => Box::new(GzDecoder::new(tarball)),
+        TarballFormat::Zstd => zstd::stream::read::Decoder::with_buffer(tarball)
+            .map_err(TarballError::DecodeZstd)?
+            .pipe(Box::new),
+        TarballFormat::Brotli => Box::new(brotli::Decompressor::new(tarball, BROTLI_BUFFER_SIZE)),
+    })
+}
+
+/// Compute the checksum of the tarball in `file` without loading it into memory.
+fn check_tarball_file(
+    file: &mut File,
+    integrity: &Integrity,
+) -> io::Result<Result<(), ssri::Error>> {
+    let mut checker = IntegrityChecker::new(integrity.clone());
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            size => checker.input(&buffer[..size]),
+        }
+    }
+    Ok(checker.result().map(drop))
+}
+
+/// Extract a compressed tarball downloaded from `package_url` to the store directory one entry at a time.
+///
+/// Extraction is aborted before the total size of the files exceeds `limit` bytes.
+#[instrument(skip(tarball, store_dir, on_progress))]
+fn extract_tarball(
+    mut tarball: impl BufRead,
+    package_url: &str,
+    limit: usize,
+    store_dir: &StoreDir,
+    on_progress: Option<&ProgressCallback>,
+) -> Result<(HashMap<String, PathBuf>, PackageFilesIndex), TarballError> {
+    let magic = tarball.fill_buf().map_err(TarballError::ReadTarballEntries)?;
+    let format = TarballFormat::detect(magic, package_url)
+        .ok_or_else(|| TarballError::UnsupportedFormat { url: package_url.to_string() })?;
+
+    let decode_error = Cell::new(None);
+    let decoder = decompress_tarball(tarball, format)?;
+    let decoder = DecoderReader { inner: decoder, error: &decode_error };
+    extract_entries(Archive::new(decoder), limit, store_dir, on_progress).map_err(|error| {
+        match decode_error.take() {
+            Some(decode_error) => format.decode_error(decode_error),
+            None => error,
+        }
+    })
+}
+
+/// Write the entries of `archive` to the store directory and index them.
+///
+/// Fails without reading the content of an entry that would bring the total size of the files over `limit`.
+fn extract_entries(
+    mut archive: Archive<impl Read>,
+    limit: usize,
+    store_dir: &StoreDir,
+    on_progress: Option<&ProgressCallback>,
+) -> Result<(HashMap<String, PathBuf>, PackageFilesIndex), TarballError> {
+    let entries = archive.entries().map_err(TarballError::ReadTarballEntries)?;
+
+    let ((_, Some(capacity)) | (capacity, None)) = entries.size_hint();
+    let mut cas_paths = HashMap::<String, PathBuf>::with_capacity(capacity);
+    let mut pkg_files_idx = PackageFilesIndex { files: HashMap::with_capacity(capacity) };
+    let mut unpacked_size = 0u64;
+
+    for entry in entries {
+        let mut entry = entry.map_err(TarballError::ReadTarballEntries)?;
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+
+        unpacked_size = unpacked_size.saturating_add(entry.size());
+        if unpacked_size > limit as u64 {
+            return Err(TarballError::UnpackedSizeExceeded { limit });
+        }
+
+        let file_mode = entry.header().mode().map_err(TarballError::InvalidEntryMode)?;
+        let file_is_executable = file_mode::is_all_exec(file_mode);
+
+        // Read the contents of the entry
+        let mut buffer = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut buffer).map_err(TarballError::ReadTarballEntries)?;
+
+        let entry_path = entry.path().map_err(TarballError::InvalidEntryPath)?;
+        let cleaned_entry_path = entry_path
+            .components()
+            .skip(1)
+            .collect::<PathBuf>()
+            .into_os_string()
+            .into_string()
+            .map_err(|_| TarballError::NonUtf8EntryPath {
+                path: entry.path_bytes().into_owned(),
+            })?;
+        let (file_path, file_hash) = store_dir
+            .write_cas_file(&buffer, file_is_executable)
+            .map_err(TarballError::WriteCasFile)?;
+
+        if let Some(previous) = cas_paths.insert(cleaned_entry_path.clone(), file_path) {
+            tracing::warn!(?previous, "Duplication detected. Old entry has been ejected");
+        }
+        if let Some(on_progress) = on_progress {
+            on_progress(TarballProgress::Extracted { extracted_files: cas_paths.len() });
+        }
+
+        let checked_at = UNIX_EPOCH.elapsed().ok().map(|x| x.as_millis());
+        let file_size = entry.header().size().ok();
+        // The store is addressed by SHA-512 like pnpm's, whatever algorithm verified the tarball.
+        let file_integrity = Integrity {
+            hashes: vec![Hash {
+                algorithm: Algorithm::Sha512,
+                digest: BASE64_STD.encode(file_hash),
+            }],
+        };
+        let file_attrs = PackageFileInfo {
+            checked_at,
+            integrity: file_integrity.to_string(),
+            mode: file_mode,
+            size: file_size,
+        };
+
+        if let Some(previous) = pkg_files_idx.files.insert(cleaned_entry_path, file_attrs) {
+            tracing::warn!(?previous, "Duplication detected. Old entry has been ejected");
+        }
+    }
+
+    Ok((cas_paths, pkg_files_idx))
+}
+
+/// Growth factor of the delay between consecutive attempts of a download.
+const RETRY_BACKOFF_FACTOR: f64 = 10.0;
+
+/// Options for retrying failed tarball downloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryOpts {
+    /// Number of times a failed download is retried before giving up.
+    pub retries: u64,
+    /// Delay before the first retry.
+    pub min_timeout: Duration,
+    /// Upper bound of the delay between retries.
+    pub max_timeout: Duration,
+}
+
+impl Default for RetryOpts {
+    fn default() -> Self {
+        RetryOpts {
+            retries: 2,
+            min_timeout: Duration::from_secs(10),
+            max_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryOpts {
+    /// Delay before the `retry`-th retry (starting from 1).
+    ///
+    /// `jitter` is a random factor in `1.0..2.0` that prevents concurrent downloads
+    /// from retrying in lockstep.
+    fn backoff(&self, retry: u64, jitter: f64) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u64) as i32;
+        let delay = self.min_timeout.as_secs_f64() * RETRY_BACKOFF_FACTOR.powi(exponent) * jitter;
+        delay.min(self.max_timeout.as_secs_f64()).pipe(Duration::from_secs_f64)
+    }
+}
+
+/// Error of a single attempt of [`fetch_tarball`].
+enum FetchAttemptError {
+    /// The connection was dropped while receiving the body.
+    Interrupted { resumable: bool, error: RequestError },
+    /// The registry couldn't be reached, timed out, or responded with a server error.
+    Unavailable(RequestError),
+    /// The attempt shouldn't be repeated.
+    Fatal(TarballError),
+}
+
+/// Download a tarball into `partial_path`.
+///
+/// Failed attempts are retried according to `retry_opts` with an exponential backoff.
+/// If the connection drops in the middle of the download, the download is resumed with an
+/// HTTP `Range` request when the server advertises `Accept-Ranges: bytes`, or restarted
+/// from scratch otherwise.
+async fn fetch_tarball(
+    http_client: &ThrottledClient,
+    retry_opts: RetryOpts,
+    on_progress: Option<&ProgressCallback>,
+    partial_path: &Path,
+    package_url: &str,
+) -> Result<(), TarballError> {
+    let io_error =
+        |error| TarballError::WritePartialDownload { path: partial_path.to_path_buf(), error };
+    if let Some(parent) = partial_path.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    remove_partial_download(partial_path).map_err(io_error)?;
+
+    let mut retries = 0;
+    loop {
+        let error =
+            match fetch_tarball_once(http_client, on_progress, partial_path, package_url).await {
+                Ok(()) => break,
+                Err(FetchAttemptError::Interrupted { resumable, error }) => {
+                    if !resumable {
+                        remove_partial_download(partial_path).map_err(io_error)?;
+                    }
+                    error
+                }
+                Err(FetchAttemptError::Unavailable(error)) => error,
+                Err(FetchAttemptError::Fatal(error)) => {
+                    remove_partial_download(partial_path).map_err(io_error)?;
+                    return Err(error);
+                }
+            };
+
+        if retries >= retry_opts.retries {
+            remove_partial_download(partial_path).map_err(io_error)?;
+            return Err(TarballError::FetchTarballRetriesExhausted {
+                url: package_url.to_string(),
+                attempts: retries + 1,
+                error,
+            });
+        }
+
+        retries += 1;
+        let delay = retry_opts.backoff(retries, rand::thread_rng().gen_range(1.0..2.0));
+        tracing::warn!(target: "pacquet::download", ?package_url, ?error, ?delay, retries, "Retry download");
+        tokio::time::sleep(delay).await;
+    }
+
+    Ok(())
+}
+
+/// Send a request for the part of the tarball that isn't yet in `partial_path` and append the response to it.
+async fn fetch_tarball_once(
+    http_client: &ThrottledClient,
+    on_progress: Option<&ProgressCallback>,
+    partial_path: &Path,
+    package_url: &str,
+) -> Result<(), FetchAttemptError> {
+    let network_error = |error: RequestError| {
+        if error.is_connect() || error.is_timeout() {
+            return FetchAttemptError::Unavailable(error);
+        }
+        FetchAttemptError::Fatal(TarballError::FetchTarball(NetworkError {
+            url: package_url.to_string(),
+            error,
+        }))
+    };
+    let io_error = |error| {
+        FetchAttemptError::Fatal(TarballError::WritePartialDownload {
+            path: partial_path.to_path_buf(),
+            error,
+        })
+    };
+
+    let offset = fs::metadata(partial_path).map_or(0, |metadata| metadata.len());
+    let request = http_client.get(package_url);
+    let request = match offset {
+        0 => request,
+        _ => request.header(RANGE, format!("bytes={offset}-")),
+    };
+    let mut response = http_client
+        .run_with_permit(|_| http_client.with_read_timeout(request.send()))
+        .await
+        .map_err(network_error)?;
+
+    if response.status().is_server_error() {
+        let error = response.error_for_status().expect_err("server error status");
+        return Err(FetchAttemptError::Unavailable(RequestError::Network(error)));
+    }
+    response = response.error_for_status().map_err(RequestError::Network).map_err(network_error)?;
+
+    let resumable = response.headers().get(ACCEPT_RANGES).is_some_and(|value| value == "bytes");
+    let append = response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(partial_path)
+        .map_err(io_error)?;
+
+    let mut downloaded_bytes = if append { offset } else { 0 };
+    let total_bytes = response.content_length().map(|length| downloaded_bytes + length);
+    while let Some(chunk) = http_client
+        .with_read_timeout(response.chunk())
+        .await
+        .map_err(|error| FetchAttemptError::Interrupted { resumable, error })?
+    {
+        file.write_all(&chunk).map_err(io_error)?;
+        if let Some(on_progress) = on_progress {
+            downloaded_bytes += chunk.len() as u64;
+            on_progress(TarballProgress::Downloaded { downloaded_bytes, total_bytes });
+        }
+    }
 
-    if let Some(size) = unpacked_size {
-        options = options.set_size_hint(size);
+    Ok(())
+}
+
+/// Path of a tarball referenced by a `file:` URL, whose relative path is resolved from the current directory.
+fn local_tarball_path(tarball_path: &str) -> PathBuf {
+    tarball_path.strip_prefix("//").unwrap_or(tarball_path).into()
+}
+
+/// Remove the partially downloaded tarball if it exists.
+fn remove_partial_download(partial_path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(partial_path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
     }
+}
+
+/// Reconstruct the CAS map of a tarball that was previously extracted to the store directory.
+///
+/// Returns `None` if the index file is missing or corrupted, or if any of the indexed files
+/// is no longer in the store or has a different size than indexed.
+fn load_cas_paths_from_store(
+    store_dir: &StoreDir,
+    package_integrity: &Integrity,
+) -> Option<HashMap<String, PathBuf>> {
+    let PackageFilesIndex { files } = store_dir.read_index_file(package_integrity)?;
+    files
+        .into_iter()
+        .map(|(entry_path, PackageFileInfo { integrity, mode, size, .. })| {
+            let integrity = integrity.parse::<Integrity>().ok()?;
+            let file_path =
+                store_dir.cas_file_path_by_integrity(&integrity, file_mode::is_all_exec(mode))?;
+            let metadata = fs::metadata(&file_path).ok()?;
+            let is_complete =
+                metadata.is_file() && size.map_or(true, |size| metadata.len() == size);
+            is_complete.then_some((entry_path, file_path))
+        })
+        .collect()
+}
 
-    DeflateDecoder::new_with_options(gz_data, options)
-        .decode_gzip()
-        .map_err(TarballError::DecodeGzip)
+/// Progress of [`DownloadTarballToStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarballProgress {
+    /// A chunk of the tarball was received.
+    Downloaded {
+        /// Number of bytes received so far.
+        downloaded_bytes: u64,
+        /// Size of the tarball according to `Content-Length`, if the server sent it.
+        total_bytes: Option<u64>,
+    },
+    /// A file of the tarball was written to the store directory.
+    Extracted {
+        /// Number of files extracted so far.
+        extracted_files: usize,
+    },
 }
 
+/// Callback that receives the [progress](TarballProgress) of [`DownloadTarballToStore`].
+///
+/// Download events are emitted from the async task that awaits the subroutine, but extraction
+/// runs in a separate tokio task, so the callback may be invoked from any worker thread.
+/// It is invoked synchronously, so it should return quickly and never block.
+pub type ProgressCallback = Arc<dyn Fn(TarballProgress) + Send + Sync>;
+
 /// This subroutine downloads and extracts a tarball to the store directory.
 ///
 /// It returns a CAS map of files in the tarball.
@@ -107,6 +626,12 @@ pub struct DownloadTarballToStore<'a> {
     pub package_integrity: &'a Integrity,
     pub package_unpacked_size: Option<usize>,
     pub package_url: &'a str,
+    pub retry_opts: RetryOpts,
+    pub unpacked_size_limit: UnpackedSizeLimit,
+    /// Receives the progress of the download and extraction.
+    ///
+    /// It isn't invoked when the tarball is reused from the store or from the in-memory cache.
+    pub on_progress: Option<ProgressCallback>,
 }
 
 impl<'a> DownloadTarballToStore<'a> {
@@ -117,9 +642,6 @@ impl<'a> DownloadTarballToStore<'a> {
     ) -> Result<Arc<HashMap<String, PathBuf>>, TarballError> {
         let &DownloadTarballToStore { package_url, .. } = &self;
 
-        // QUESTION: I see no copying from existing store_dir, is there such mechanism?
-        // TODO: If it's not implemented yet, implement it
-
         if let Some(cache_lock) = mem_cache.get(package_url) {
             let notify = match &*cache_lock.write().await {
                 CacheValue::Available(cas_paths) => {
@@ -160,21 +682,39 @@ impl<'a> DownloadTarballToStore<'a> {
             package_integrity,
             package_unpacked_size,
             package_url,
-            ..
+            retry_opts,
+            unpacked_size_limit,
+            ref on_progress,
         } = self;
 
+        if let Some(cas_paths) = load_cas_paths_from_store(store_dir, package_integrity) {
+            tracing::info!(target: "pacquet::download", ?package_url, "Found in store");
+            return Ok(cas_paths);
+        }
+
         tracing::info!(target: "pacquet::download", ?package_url, "New cache");
 
-        let network_error = |error| {
-            TarballError::FetchTarball(NetworkError { url: package_url.to_string(), error })
+        let (_, integrity_hex) = package_integrity.to_hex();
+        let partial_path = store_dir.tmp().join(format!("{integrity_hex}.tgz.partial"));
+        let is_local = package_url.starts_with("file:");
+        let tarball_path = match package_url.strip_prefix("file:") {
+            Some(tarball_path) => local_tarball_path(tarball_path),
+            None if http_client.offline() => {
+                let package = package_url.to_string();
+                return Err(TarballError::Offline(OfflineModeError { package }));
+            }
+            None => {
+                fetch_tarball(
+                    http_client,
+                    retry_opts,
+                    on_progress.as_ref(),
+                    &partial_path,
+                    package_url,
+                )
+                .await?;
+                partial_path.clone()
+            }
         };
-        let response = http_client
-            .run_with_permit(|client| client.get(package_url).send())
-            .await
-            .map_err(network_error)?
-            .bytes()
-            .await
-            .map_err(network_error)?;
 
         tracing::info!(target: "pacquet::download", ?package_url, "Download completed");
 
@@ -182,6 +722,8 @@ impl<'a> DownloadTarballToStore<'a> {
         // 1. Use an Arc and convert this line to Arc::clone.
         // 2. Replace ssri with base64 and serde magic (which supports Copy).
         let package_integrity = package_integrity.clone();
+        let on_progress = on_progress.clone();
+        let url = package_url.to_string();
 
         #[derive(Debug, From)]
         enum TaskError {
@@ -189,67 +731,22 @@ impl<'a> DownloadTarballToStore<'a> {
             Other(TarballError),
         }
         let cas_paths = tokio::task::spawn(async move {
-            package_integrity.check(&response).map_err(TaskError::Checksum)?;
-
-            // TODO: move tarball extraction to its own function
-            // TODO: test it
-            // TODO: test the duplication of entries
-
-            let mut archive = decompress_gzip(&response, package_unpacked_size)
-                .map_err(TaskError::Other)?
-                .pipe(Cursor::new)
-                .pipe(Archive::new);
-
-            let entries = archive
-                .entries()
-                .map_err(TarballError::ReadTarballEntries)
-                .map_err(TaskError::Other)?
-                .filter(|entry| !entry.as_ref().unwrap().header().entry_type().is_dir());
-
-            let ((_, Some(capacity)) | (capacity, None)) = entries.size_hint();
-            let mut cas_paths = HashMap::<String, PathBuf>::with_capacity(capacity);
-            let mut pkg_files_idx = PackageFilesIndex { files: HashMap::with_capacity(capacity) };
-
-            for entry in entries {
-                let mut entry = entry.unwrap();
-
-                let file_mode = entry.header().mode().expect("get mode"); // TODO: properly propagate this error
-                let file_is_executable = file_mode::is_all_exec(file_mode);
-
-                // Read the contents of the entry
-                let mut buffer = Vec::with_capacity(entry.size() as usize);
-                entry.read_to_end(&mut buffer).unwrap();
-
-                let entry_path = entry.path().unwrap();
-                let cleaned_entry_path = entry_path
-                    .components()
-                    .skip(1)
-                    .collect::<PathBuf>()
-                    .into_os_string()
-                    .into_string()
-                    .expect("entry path must be valid UTF-8");
-                let (file_path, file_hash) = store_dir
-                    .write_cas_file(&buffer, file_is_executable)
-                    .map_err(TarballError::WriteCasFile)?;
-
-                if let Some(previous) = cas_paths.insert(cleaned_entry_path.clone(), file_path) {
-                    tracing::warn!(?previous, "Duplication detected. Old entry has been ejected");
-                }
+            let read_error =
+                |error| TarballError::ReadTarballFile { path: tarball_path.clone(), error };
+            let mut file = File::open(&tarball_path).map_err(read_error)?;
+            check_tarball_file(&mut file, &package_integrity)
+                .map_err(read_error)?
+                .map_err(TaskError::Checksum)?;
+            file.rewind().map_err(read_error)?;
 
-                let checked_at = UNIX_EPOCH.elapsed().ok().map(|x| x.as_millis());
-                let file_size = entry.header().size().ok();
-                let file_integrity = format!("sha512-{}", BASE64_STD.encode(file_hash));
-                let file_attrs = PackageFileInfo {
-                    checked_at,
-                    integrity: file_integrity,
-                    mode: file_mode,
-                    size: file_size,
-                };
-
-                if let Some(previous) = pkg_files_idx.files.insert(cleaned_entry_path, file_attrs) {
-                    tracing::warn!(?previous, "Duplication detected. Old entry has been ejected");
-                }
-            }
+            let limit = unpacked_size_limit.max_unpacked_size(package_unpacked_size);
+            let (cas_paths, pkg_files_idx) = extract_tarball(
+                BufReader::new(file),
+                &url,
+                limit,
+                store_dir,
+                on_progress.as_ref(),
+            )?;
 
             store_dir
                 .write_index_file(&package_integrity, &pkg_files_idx)
@@ -264,7 +761,14 @@ impl<'a> DownloadTarballToStore<'a> {
                 TarballError::Checksum(VerifyChecksumError { url: package_url.to_string(), error })
             }
             TaskError::Other(error) => error,
-        })?;
+        });
+
+        if !is_local {
+            remove_partial_download(&partial_path).map_err(|error| {
+                TarballError::WritePartialDownload { path: partial_path.clone(), error }
+            })?;
+        }
+        let cas_paths = cas_paths?;
 
         tracing::info!(target: "pacquet::download", ?package_url, "Checksum verified");
 
@@ -274,12 +778,31 @@ impl<'a> DownloadTarballToStore<'a> {
 
 #[cfg(test)]
 mod tests {
+    use pacquet_network::ClientOptions;
+    use pacquet_testing_utils::tarball::TarballBuilder;
     use pipe_trait::Pipe;
     use pretty_assertions::assert_eq;
+    use ssri::{Algorithm, IntegrityOpts};
+    use std::{
+        collections::BTreeMap,
+        net::{TcpListener, TcpStream},
+        thread,
+    };
     use tempfile::{tempdir, TempDir};
 
     use super::*;
 
+    /// Retry immediately to keep the tests fast.
+    const NO_DELAY: RetryOpts =
+        RetryOpts { retries: 2, min_timeout: Duration::ZERO, max_timeout: Duration::ZERO };
+
+    /// Decompress a gzip tarball into a plain tar.
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut tar).unwrap();
+        tar
+    }
+
     fn integrity(integrity_str: &str) -> Integrity {
         integrity_str.parse().expect("parse integrity string")
     }
@@ -310,7 +833,10 @@ mod tests {
             store_dir: store_path,
             package_integrity: &integrity("sha512-dj7vjIn1Ar8sVXj2yAXiMNCJDmS9MQ9XMlIecX2dIzzhjSHCyKo4DdXjXMs7wKW2kj6yvVRSpuQjOZ3YLrh56w=="),
             package_unpacked_size: Some(16697),
-            package_url: "https://registry.npmjs.org/@fastify/error/-/error-3.3.0.tgz"
+            package_url: "https://registry.npmjs.org/@fastify/error/-/error-3.3.0.tgz",
+            retry_opts: RetryOpts::default(),
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
         }
         .run_without_mem_cache()
         .await
@@ -343,13 +869,24 @@ mod tests {
 
     #[tokio::test]
     async fn should_throw_error_on_checksum_mismatch() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n")
+            .build();
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/foo-1.0.0.tgz").with_body(&tarball.data).create_async().await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+
         let (store_dir, store_path) = tempdir_with_leaked_path();
         DownloadTarballToStore {
             http_client: &Default::default(),
             store_dir: store_path,
             package_integrity: &integrity("sha512-aaaan1Ar8sVXj2yAXiMNCJDmS9MQ9XMlIecX2dIzzhjSHCyKo4DdXjXMs7wKW2kj6yvVRSpuQjOZ3YLrh56w=="),
-            package_unpacked_size: Some(16697),
-            package_url: "https://registry.npmjs.org/@fastify/error/-/error-3.3.0.tgz",
+            package_unpacked_size: Some(tarball.unpacked_size),
+            package_url: &package_url,
+            retry_opts: RetryOpts::default(),
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
         }
         .run_without_mem_cache()
         .await
@@ -357,4 +894,781 @@ mod tests {
 
         drop(store_dir);
     }
+
+    #[tokio::test]
+    async fn should_fail_cleanly_on_truncated_tarball() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n".repeat(100))
+            .build();
+        let tar = gunzip(&tarball.data);
+        let truncated_gzip = tarball.data[..tarball.data.len() / 2].to_vec();
+        let truncated_tar = zstd::encode_all(&tar[..tar.len() / 2], 3).unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        for (file_name, data) in
+            [("foo-1.0.0.tgz", truncated_gzip), ("foo-1.0.0.tar.zst", truncated_tar)]
+        {
+            eprintln!("CASE: {file_name}");
+            server
+                .mock("GET", format!("/{file_name}").as_str())
+                .with_body(&data)
+                .create_async()
+                .await;
+            let package_url = format!("{}/{file_name}", server.url());
+            let package_integrity =
+                IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(&data).result();
+
+            let (store_dir, store_path) = tempdir_with_leaked_path();
+            let error = DownloadTarballToStore {
+                http_client: &Default::default(),
+                store_dir: store_path,
+                package_integrity: &package_integrity,
+                package_unpacked_size: Some(tarball.unpacked_size),
+                package_url: &package_url,
+                retry_opts: NO_DELAY,
+                unpacked_size_limit: UnpackedSizeLimit::default(),
+                on_progress: None,
+            }
+            .run_without_mem_cache()
+            .await
+            .expect_err("truncated tarball");
+
+            // the decoder may either fail or stop early, in which case the tar archive fails
+            dbg!(&error);
+            assert!(matches!(
+                error,
+                TarballError::ReadTarballEntries(_)
+                    | TarballError::DecodeGzip(_)
+                    | TarballError::DecodeZstd(_)
+            ));
+
+            drop(store_dir); // cleanup
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_unsupported_or_corrupted_tarball() {
+        let corrupted_gzip = [0x1F, 0x8B, 0x08, 0, 0, 0, 0, 0, 0, 0xFF]
+            .into_iter()
+            .chain([0xFF; 64])
+            .collect::<Vec<u8>>();
+        let plain_text = b"not a tarball".to_vec();
+
+        let mut server = mockito::Server::new_async().await;
+        for (file_name, data) in [("foo-1.0.0.tgz", corrupted_gzip), ("foo-1.0.0.txt", plain_text)]
+        {
+            eprintln!("CASE: {file_name}");
+            server
+                .mock("GET", format!("/{file_name}").as_str())
+                .with_body(&data)
+                .create_async()
+                .await;
+            let package_url = format!("{}/{file_name}", server.url());
+            let package_integrity =
+                IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(&data).result();
+
+            let (store_dir, store_path) = tempdir_with_leaked_path();
+            let error = DownloadTarballToStore {
+                http_client: &Default::default(),
+                store_dir: store_path,
+                package_integrity: &package_integrity,
+                package_unpacked_size: None,
+                package_url: &package_url,
+                retry_opts: NO_DELAY,
+                unpacked_size_limit: UnpackedSizeLimit::default(),
+                on_progress: None,
+            }
+            .run_without_mem_cache()
+            .await
+            .expect_err("invalid tarball");
+
+            dbg!(&error);
+            match file_name {
+                "foo-1.0.0.tgz" => assert!(matches!(error, TarballError::DecodeGzip(_))),
+                _ => assert!(matches!(error, TarballError::UnsupportedFormat { .. })),
+            }
+
+            drop(store_dir); // cleanup
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_decompression_bomb() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/zeros", vec![0u8; 1 << 20])
+            .build();
+        let tar = gunzip(&tarball.data);
+        let zstd = zstd::encode_all(tar.as_slice(), 3).unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let cases = [
+            ("foo-1.0.0.tgz", tarball.data.clone(), Some(1000)),
+            ("foo-1.0.0.tar.zst", zstd.clone(), Some(1000)),
+            ("foo-1.0.0.tar.zst", zstd, None),
+        ];
+        for (file_name, data, package_unpacked_size) in cases {
+            eprintln!("CASE: {file_name}, {package_unpacked_size:?}");
+            server
+                .mock("GET", format!("/{file_name}").as_str())
+                .with_body(&data)
+                .create_async()
+                .await;
+            let package_url = format!("{}/{file_name}", server.url());
+            let package_integrity =
+                IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(&data).result();
+
+            let (store_dir, store_path) = tempdir_with_leaked_path();
+            let error = DownloadTarballToStore {
+                http_client: &Default::default(),
+                store_dir: store_path,
+                package_integrity: &package_integrity,
+                package_unpacked_size,
+                package_url: &package_url,
+                retry_opts: NO_DELAY,
+                unpacked_size_limit: UnpackedSizeLimit { factor: 10, max_size: 64 * 1024 },
+                on_progress: None,
+            }
+            .run_without_mem_cache()
+            .await
+            .expect_err("decompression bomb");
+
+            dbg!(&error);
+            assert!(matches!(error, TarballError::UnpackedSizeExceeded { .. }));
+
+            drop(store_dir); // cleanup
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_reject_non_utf8_entry_path() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let non_utf8_path = b"package/caf\xe9.txt";
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file(Path::new(OsStr::from_bytes(non_utf8_path)), "")
+            .build();
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+
+        let tar = gunzip(&tarball.data);
+        let error = extract_entries(Archive::new(tar.as_slice()), usize::MAX, store_path, None)
+            .expect_err("non-UTF-8 entry path");
+
+        dbg!(&error);
+        assert!(matches!(
+            &error,
+            TarballError::NonUtf8EntryPath { path } if path == non_utf8_path
+        ));
+
+        drop(store_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_verify_sha1_integrity() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n")
+            .build();
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/foo-1.0.0.tgz").with_body(&tarball.data).create_async().await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+        let package_integrity =
+            IntegrityOpts::new().algorithm(Algorithm::Sha1).chain(&tarball.data).result();
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let http_client = ThrottledClient::default();
+        let download = |package_integrity| DownloadTarballToStore {
+            http_client: &http_client,
+            store_dir: store_path,
+            package_integrity,
+            package_unpacked_size: Some(tarball.unpacked_size),
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        };
+
+        eprintln!("CASE: matching sha1 integrity");
+        let cas_paths = download(&package_integrity).run_without_mem_cache().await.unwrap();
+        assert_eq!(cas_paths.len(), 2);
+        let PackageFilesIndex { files } = store_path.read_index_file(&package_integrity).unwrap();
+        dbg!(&files);
+
+        eprintln!("The files are addressed by SHA-512 whatever the algorithm of the tarball");
+        for (path, info) in &files {
+            let content = fs::read(&cas_paths[path]).unwrap();
+            let expected =
+                IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(&content).result();
+            assert_eq!(info.integrity, expected.to_string());
+        }
+
+        eprintln!("CASE: mismatching sha1 integrity");
+        let wrong_integrity =
+            IntegrityOpts::new().algorithm(Algorithm::Sha1).chain(b"WRONG").result();
+        let error = download(&wrong_integrity).run_without_mem_cache().await.unwrap_err();
+        dbg!(&error);
+        assert!(matches!(error, TarballError::Checksum(_)));
+
+        drop(store_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_report_progress() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n")
+            .build();
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/foo-1.0.0.tgz").with_body(&tarball.data).create_async().await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_progress: ProgressCallback = {
+            let events = Arc::clone(&events);
+            Arc::new(move |progress| events.lock().unwrap().push(progress))
+        };
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &tarball.integrity,
+            package_unpacked_size: Some(tarball.unpacked_size),
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: Some(on_progress),
+        }
+        .run_without_mem_cache()
+        .await
+        .unwrap();
+
+        let events = events.lock().unwrap();
+        dbg!(&events);
+        let total_bytes = tarball.data.len() as u64;
+        let downloaded = events
+            .iter()
+            .filter_map(|event| match *event {
+                TarballProgress::Downloaded { downloaded_bytes, total_bytes } => {
+                    Some((downloaded_bytes, total_bytes))
+                }
+                TarballProgress::Extracted { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(downloaded.last(), Some(&(total_bytes, Some(total_bytes))));
+        let extracted = events
+            .iter()
+            .filter(|event| matches!(event, TarballProgress::Extracted { .. }))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            extracted,
+            [
+                TarballProgress::Extracted { extracted_files: 1 },
+                TarballProgress::Extracted { extracted_files: 2 },
+            ]
+        );
+
+        drop(store_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_extract_local_tarball() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n")
+            .build();
+        let tarball_dir = tempdir().unwrap();
+        let tarball_path = tarball_dir.path().join("foo-1.0.0.tgz");
+        fs::write(&tarball_path, &tarball.data).unwrap();
+
+        for package_url in [
+            format!("file:{}", tarball_path.display()),
+            format!("file://{}", tarball_path.display()),
+        ] {
+            eprintln!("CASE: {package_url}");
+            let (store_dir, store_path) = tempdir_with_leaked_path();
+            let cas_files = DownloadTarballToStore {
+                http_client: &Default::default(),
+                store_dir: store_path,
+                package_integrity: &tarball.integrity,
+                package_unpacked_size: Some(tarball.unpacked_size),
+                package_url: &package_url,
+                retry_opts: NO_DELAY,
+                unpacked_size_limit: UnpackedSizeLimit::default(),
+                on_progress: None,
+            }
+            .run_without_mem_cache()
+            .await
+            .unwrap();
+
+            let mut filenames = cas_files.keys().collect::<Vec<_>>();
+            filenames.sort();
+            assert_eq!(filenames, ["index.js", "package.json"]);
+            let index = &cas_files["index.js"];
+            assert_eq!(fs::read_to_string(index).unwrap(), "module.exports = 'foo'\n");
+
+            drop(store_dir); // cleanup
+        }
+
+        drop(tarball_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_fail_on_missing_local_tarball() {
+        let tarball_dir = tempdir().unwrap();
+        let package_url = format!("file:{}", tarball_dir.path().join("missing.tgz").display());
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let error = DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &integrity("sha512-dj7vjIn1Ar8sVXj2yAXiMNCJDmS9MQ9XMlIecX2dIzzhjSHCyKo4DdXjXMs7wKW2kj6yvVRSpuQjOZ3YLrh56w=="),
+            package_unpacked_size: None,
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .expect_err("missing tarball");
+
+        dbg!(&error);
+        assert!(matches!(error, TarballError::ReadTarballFile { .. }));
+
+        drop((store_dir, tarball_dir)); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_preserve_bundled_dependencies() {
+        let tarball = include_bytes!("../fixtures/with-bundled-dependency-1.0.0.tgz");
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/with-bundled-dependency-1.0.0.tgz")
+            .with_body(tarball)
+            .create_async()
+            .await;
+        let package_url = format!("{}/with-bundled-dependency-1.0.0.tgz", server.url());
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let cas_files = DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &integrity("sha512-GRixBojfWyboK+QVIKV1UwlG4XHEn4u4tXv85pcLz88W9HfZ8y18W+RXOW7TctgkzdfALbLLGo7kl4+8GbcmcQ=="),
+            package_unpacked_size: Some(335),
+            package_url: &package_url,
+            retry_opts: RetryOpts::default(),
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .unwrap();
+
+        let mut filenames = cas_files.keys().collect::<Vec<_>>();
+        filenames.sort();
+        assert_eq!(
+            filenames,
+            [
+                "index.js",
+                "node_modules/bundled-dep/index.js",
+                "node_modules/bundled-dep/package.json",
+                "package.json",
+            ]
+        );
+
+        let bundled_main = &cas_files["node_modules/bundled-dep/index.js"];
+        assert_eq!(fs::read_to_string(bundled_main).unwrap(), "module.exports = 'bundled'\n");
+
+        drop(store_dir);
+    }
+
+    #[tokio::test]
+    async fn should_reuse_tarball_from_store() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n")
+            .build();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/foo-1.0.0.tgz")
+            .with_body(&tarball.data)
+            .expect(4)
+            .create_async()
+            .await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let http_client = ThrottledClient::default();
+        let download = || DownloadTarballToStore {
+            http_client: &http_client,
+            store_dir: store_path,
+            package_integrity: &tarball.integrity,
+            package_unpacked_size: Some(tarball.unpacked_size),
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        };
+
+        eprintln!("Cold store");
+        let downloaded = download().run_without_mem_cache().await.unwrap();
+
+        eprintln!("Warm store");
+        let reused = download().run_without_mem_cache().await.unwrap();
+        assert_eq!(reused, downloaded);
+
+        eprintln!("Corrupted index file");
+        fs::write(store_path.index_file_path(&tarball.integrity), "{ not json").unwrap();
+        let redownloaded = download().run_without_mem_cache().await.unwrap();
+        assert_eq!(redownloaded, downloaded);
+
+        eprintln!("Corrupted index file should have been replaced");
+        let reused = download().run_without_mem_cache().await.unwrap();
+        assert_eq!(reused, downloaded);
+
+        eprintln!("Partially written index file");
+        let index_path = store_path.index_file_path(&tarball.integrity);
+        let index = fs::read(&index_path).unwrap();
+        fs::write(&index_path, &index[..index.len() / 2]).unwrap();
+        let redownloaded = download().run_without_mem_cache().await.unwrap();
+        assert_eq!(redownloaded, downloaded);
+        assert!(store_path.read_index_file(&tarball.integrity).is_some());
+
+        eprintln!("Partially written CAS file");
+        let cas_path = &downloaded["index.js"];
+        fs::write(cas_path, "module.").unwrap();
+        let redownloaded = download().run_without_mem_cache().await.unwrap();
+        assert_eq!(redownloaded, downloaded);
+        assert_eq!(fs::read_to_string(cas_path).unwrap(), "module.exports = 'foo'\n");
+
+        mock.assert_async().await;
+
+        drop(store_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_only_use_store_in_offline_mode() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .build();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/foo-1.0.0.tgz")
+            .with_body(&tarball.data)
+            .expect(1)
+            .create_async()
+            .await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let online_client = ThrottledClient::default();
+        let offline_client = ThrottledClient::default().with_offline(true);
+        let download = |http_client| DownloadTarballToStore {
+            http_client,
+            store_dir: store_path,
+            package_integrity: &tarball.integrity,
+            package_unpacked_size: Some(tarball.unpacked_size),
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        };
+
+        eprintln!("CASE: cold store");
+        let error = download(&offline_client).run_without_mem_cache().await.unwrap_err();
+        dbg!(&error);
+        assert!(matches!(&error, TarballError::Offline(error) if error.package == package_url));
+
+        eprintln!("CASE: warm store");
+        let downloaded = download(&online_client).run_without_mem_cache().await.unwrap();
+        let reused = download(&offline_client).run_without_mem_cache().await.unwrap();
+        assert_eq!(reused, downloaded);
+
+        mock.assert_async().await;
+
+        drop(store_dir); // cleanup
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let retry_opts = RetryOpts {
+            retries: 5,
+            min_timeout: Duration::from_millis(100),
+            max_timeout: Duration::from_secs(5),
+        };
+        assert_eq!(retry_opts.backoff(1, 1.0), Duration::from_millis(100));
+        assert_eq!(retry_opts.backoff(1, 1.5), Duration::from_millis(150));
+        assert_eq!(retry_opts.backoff(2, 1.0), Duration::from_secs(1));
+        assert_eq!(retry_opts.backoff(3, 1.0), Duration::from_secs(5));
+        assert_eq!(retry_opts.backoff(u64::MAX, 1.0), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn should_give_up_after_retrying_server_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let mock =
+            server.mock("GET", "/foo-1.0.0.tgz").with_status(503).expect(3).create_async().await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let error = DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &integrity("sha512-dj7vjIn1Ar8sVXj2yAXiMNCJDmS9MQ9XMlIecX2dIzzhjSHCyKo4DdXjXMs7wKW2kj6yvVRSpuQjOZ3YLrh56w=="),
+            package_unpacked_size: None,
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .expect_err("server error");
+
+        dbg!(&error);
+        assert!(matches!(error, TarballError::FetchTarballRetriesExhausted { attempts: 3, .. }));
+        mock.assert_async().await;
+
+        drop(store_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_retry_timed_out_downloads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let package_url = format!("http://{}/foo-1.0.0.tgz", listener.local_addr().unwrap());
+        let (done_sender, done_receiver) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            // keep the connections open without responding
+            let streams = (0..3).map(|_| listener.accept().unwrap().0).collect::<Vec<_>>();
+            done_receiver.recv().ok();
+            streams.len()
+        });
+
+        let options =
+            ClientOptions { timeout: Some(Duration::from_millis(100)), ..Default::default() };
+        let http_client = ThrottledClient::new_with_options(&options).unwrap();
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let error = DownloadTarballToStore {
+            http_client: &http_client,
+            store_dir: store_path,
+            package_integrity: &integrity("sha512-dj7vjIn1Ar8sVXj2yAXiMNCJDmS9MQ9XMlIecX2dIzzhjSHCyKo4DdXjXMs7wKW2kj6yvVRSpuQjOZ3YLrh56w=="),
+            package_unpacked_size: None,
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .expect_err("timeout");
+
+        dbg!(&error);
+        assert!(matches!(
+            &error,
+            TarballError::FetchTarballRetriesExhausted { attempts: 3, error, .. } if error.is_timeout()
+        ));
+        done_sender.send(()).unwrap();
+        assert_eq!(server.join().unwrap(), 3);
+
+        drop(store_dir); // cleanup
+    }
+
+    #[tokio::test]
+    async fn should_not_retry_client_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let mock =
+            server.mock("GET", "/foo-1.0.0.tgz").with_status(404).expect(1).create_async().await;
+        let package_url = format!("{}/foo-1.0.0.tgz", server.url());
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let error = DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &integrity("sha512-dj7vjIn1Ar8sVXj2yAXiMNCJDmS9MQ9XMlIecX2dIzzhjSHCyKo4DdXjXMs7wKW2kj6yvVRSpuQjOZ3YLrh56w=="),
+            package_unpacked_size: None,
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .expect_err("not found");
+
+        dbg!(&error);
+        assert!(matches!(error, TarballError::FetchTarball(_)));
+        mock.assert_async().await;
+
+        drop(store_dir); // cleanup
+    }
+
+    /// Read the request from `stream` and return the value of its `Range` header.
+    fn read_range_header(stream: &TcpStream) -> Option<String> {
+        BufReader::new(stream)
+            .lines()
+            .map(Result::unwrap)
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("range").then(|| value.trim().to_string())
+            })
+    }
+
+    #[tokio::test]
+    async fn should_resume_interrupted_download() {
+        let tarball = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n".repeat(100))
+            .build();
+        let data = tarball.data.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let package_url = format!("http://{}/foo-1.0.0.tgz", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let total = data.len();
+            let mut ranges = Vec::new();
+
+            eprintln!("Announce the whole tarball but only send half of it");
+            let (mut stream, _) = listener.accept().unwrap();
+            ranges.push(read_range_header(&stream));
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {total}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n"
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&data[..total / 2]).unwrap();
+            drop(stream);
+
+            eprintln!("Send the rest of the tarball");
+            let (mut stream, _) = listener.accept().unwrap();
+            let range = read_range_header(&stream);
+            let offset = range
+                .as_deref()
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.strip_suffix('-'))
+                .map_or(0, |offset| offset.parse::<usize>().unwrap());
+            ranges.push(range);
+            let header = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: {length}\r\nContent-Range: bytes {offset}-{last}/{total}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+                length = total - offset,
+                last = total - 1,
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&data[offset..]).unwrap();
+
+            ranges
+        });
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let cas_files = DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &tarball.integrity,
+            package_unpacked_size: Some(tarball.unpacked_size),
+            package_url: &package_url,
+            retry_opts: NO_DELAY,
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .unwrap();
+
+        let mut filenames = cas_files.keys().collect::<Vec<_>>();
+        filenames.sort();
+        assert_eq!(filenames, ["index.js", "package.json"]);
+
+        let ranges = server.join().unwrap();
+        dbg!(&ranges);
+        assert_eq!(ranges, [None, Some(format!("bytes={}-", tarball.data.len() / 2))]);
+
+        eprintln!("The partial download should have been removed");
+        assert_eq!(fs::read_dir(store_path.tmp()).unwrap().count(), 0);
+
+        drop(store_dir);
+    }
+
+    /// Serve `data` from `server`, extract it into a fresh store, and list
+    /// the CAS paths relative to that store.
+    async fn extract_relative_cas_paths(
+        server: &mut mockito::ServerGuard,
+        file_name: &str,
+        data: &[u8],
+        unpacked_size: usize,
+    ) -> BTreeMap<String, PathBuf> {
+        server.mock("GET", format!("/{file_name}").as_str()).with_body(data).create_async().await;
+        let package_url = format!("{}/{file_name}", server.url());
+        let package_integrity =
+            IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(data).result();
+
+        let (store_dir, store_path) = tempdir_with_leaked_path();
+        let cas_files = DownloadTarballToStore {
+            http_client: &Default::default(),
+            store_dir: store_path,
+            package_integrity: &package_integrity,
+            package_unpacked_size: Some(unpacked_size),
+            package_url: &package_url,
+            retry_opts: RetryOpts::default(),
+            unpacked_size_limit: UnpackedSizeLimit::default(),
+            on_progress: None,
+        }
+        .run_without_mem_cache()
+        .await
+        .unwrap();
+        let cas_files = cas_files
+            .into_iter()
+            .map(|(name, path)| (name, path.strip_prefix(store_dir.path()).unwrap().to_path_buf()))
+            .collect();
+
+        drop(store_dir); // cleanup
+        cas_files
+    }
+
+    #[tokio::test]
+    async fn should_extract_tiny_package_within_unpacked_size_limit() {
+        // the tar headers and padding are many times larger than the content
+        let tarball = TarballBuilder::new().file("package/package.json", r#"{"name":"a"}"#).build();
+        assert!(gunzip(&tarball.data).len() > tarball.unpacked_size * 10);
+
+        let mut server = mockito::Server::new_async().await;
+        let cas_paths = extract_relative_cas_paths(
+            &mut server,
+            "a-1.0.0.tgz",
+            &tarball.data,
+            tarball.unpacked_size,
+        )
+        .await;
+        assert_eq!(cas_paths.keys().collect::<Vec<_>>(), ["package.json"]);
+    }
+
+    #[tokio::test]
+    async fn should_extract_zstd_and_brotli_tarballs_like_gzip() {
+        let gzip = TarballBuilder::new()
+            .file("package/package.json", r#"{ "name": "foo", "version": "1.0.0" }"#)
+            .file("package/index.js", "module.exports = 'foo'\n")
+            .build()
+            .data;
+        let tar = gunzip(&gzip);
+        let zstd = zstd::encode_all(tar.as_slice(), 3).unwrap();
+        let mut brotli = Vec::new();
+        brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22).write_all(&tar).unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let from_gzip =
+            extract_relative_cas_paths(&mut server, "foo-1.0.0.tgz", &gzip, tar.len()).await;
+        dbg!(&from_gzip);
+        assert_eq!(from_gzip.keys().collect::<Vec<_>>(), ["index.js", "package.json"]);
+
+        // CAS paths are derived from file contents, so equal paths mean equal contents.
+        let from_zstd =
+            extract_relative_cas_paths(&mut server, "foo-1.0.0.tar.zst", &zstd, tar.len()).await;
+        assert_eq!(from_zstd, from_gzip);
+
+        let from_brotli =
+            extract_relative_cas_paths(&mut server, "foo-1.0.0.tar.br", &brotli, tar.len()).await;
+        assert_eq!(from_brotli, from_gzip);
+    }
 }
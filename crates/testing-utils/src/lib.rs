@@ -1,3 +1,4 @@
 pub mod bin;
 pub mod fixtures;
 pub mod fs;
+pub mod tarball;
@@ -0,0 +1,129 @@
+use flate2::{write::GzEncoder, Compression};
+use ssri::{Algorithm, Integrity, IntegrityOpts};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+use tar::{EntryType, Header};
+
+/// Type of a [`TarballEntry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TarballEntryKind {
+    /// Regular file with content.
+    File(Vec<u8>),
+    /// Directory.
+    Directory,
+    /// Symbolic link to a target path.
+    Symlink(PathBuf),
+}
+
+/// Entry of a tarball built by [`TarballBuilder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TarballEntry {
+    pub path: PathBuf,
+    pub mode: u32,
+    pub kind: TarballEntryKind,
+}
+
+/// Gzipped tarball built by [`TarballBuilder`].
+#[derive(Debug, Clone)]
+pub struct Tarball {
+    /// Gzipped content of the tarball.
+    pub data: Vec<u8>,
+    /// Sha512 integrity of [`Tarball::data`].
+    pub integrity: Integrity,
+    /// Total size of the content of the files.
+    pub unpacked_size: usize,
+}
+
+/// Build an in-memory `.tgz` with specified entries.
+///
+/// Paths are used as is, so npm tarballs should prefix them with `package/`.
+#[derive(Debug, Default, Clone)]
+#[must_use]
+pub struct TarballBuilder {
+    entries: Vec<TarballEntry>,
+}
+
+impl TarballBuilder {
+    /// Start with an empty tarball.
+    pub fn new() -> Self {
+        TarballBuilder::default()
+    }
+
+    /// Add an arbitrary entry.
+    pub fn entry(mut self, entry: TarballEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    /// Add a regular non-executable file.
+    pub fn file(self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        self.file_with_mode(path, 0o644, content)
+    }
+
+    /// Add a regular file with a specific mode.
+    pub fn file_with_mode(
+        self,
+        path: impl Into<PathBuf>,
+        mode: u32,
+        content: impl Into<Vec<u8>>,
+    ) -> Self {
+        let kind = TarballEntryKind::File(content.into());
+        self.entry(TarballEntry { path: path.into(), mode, kind })
+    }
+
+    /// Add a directory.
+    pub fn directory(self, path: impl Into<PathBuf>) -> Self {
+        self.entry(TarballEntry {
+            path: path.into(),
+            mode: 0o755,
+            kind: TarballEntryKind::Directory,
+        })
+    }
+
+    /// Add a symbolic link.
+    pub fn symlink(self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        let kind = TarballEntryKind::Symlink(target.into());
+        self.entry(TarballEntry { path: path.into(), mode: 0o777, kind })
+    }
+
+    /// Create the gzipped tarball and compute its integrity.
+    pub fn build(self) -> Tarball {
+        let mut archive = tar::Builder::new(Vec::new());
+        let mut unpacked_size = 0;
+
+        for TarballEntry { path, mode, kind } in self.entries {
+            let mut header = Header::new_gnu();
+            header.set_mode(mode);
+            header.set_mtime(0);
+            match kind {
+                TarballEntryKind::File(content) => {
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_size(content.len() as u64);
+                    unpacked_size += content.len();
+                    archive.append_data(&mut header, path, content.as_slice())
+                }
+                TarballEntryKind::Directory => {
+                    header.set_entry_type(EntryType::Directory);
+                    header.set_size(0);
+                    archive.append_data(&mut header, path, io::empty())
+                }
+                TarballEntryKind::Symlink(target) => {
+                    header.set_entry_type(EntryType::Symlink);
+                    header.set_size(0);
+                    archive.append_link(&mut header, path, target)
+                }
+            }
+            .expect("append entry to tarball");
+        }
+
+        let tar = archive.into_inner().expect("finish tarball");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar).expect("compress tarball");
+        let data = encoder.finish().expect("finish compressing tarball");
+        let integrity = IntegrityOpts::new().algorithm(Algorithm::Sha512).chain(&data).result();
+
+        Tarball { data, integrity, unpacked_size }
+    }
+}
@@ -1,5 +1,4 @@
 use clap::{Args, Parser, ValueEnum};
-use pipe_trait::Pipe;
 use std::{path::PathBuf, process::Command};
 
 #[derive(Debug, Parser)]
@@ -32,6 +31,23 @@ pub struct CliArgs {
     #[clap(long, short, default_value = "bench-work-env")]
     pub work_env: PathBuf,
 
+    /// Override the directory in which each benchmark creates its store directory.
+    ///
+    /// This allows placing the store on a different filesystem (e.g. tmpfs) than the project.
+    #[clap(long)]
+    pub store_dir: Option<PathBuf>,
+
+    /// Override the directory in which each benchmark creates its virtual store directory.
+    #[clap(long)]
+    pub virtual_store_dir: Option<PathBuf>,
+
+    /// Comma-separated list of `network-concurrency` values to sweep.
+    ///
+    /// Every revision is benchmarked once per value, the exported `BENCHMARK_REPORT.md`
+    /// would then contain one row for each pair of revision and value.
+    #[clap(long, value_delimiter = ',')]
+    pub network_concurrency: Vec<usize>,
+
     /// Benchmark against pnpm.
     #[clap(long)]
     pub with_pnpm: bool,
@@ -47,22 +63,56 @@ pub enum BenchmarkScenario {
     CleanInstall,
     /// Benchmark install with a frozen lockfile and without local cache.
     FrozenLockfile,
+    /// Benchmark install with a frozen lockfile and a virtual store directory outside `node_modules`.
+    SeparateVirtualStore,
+    /// Benchmark install with a frozen lockfile and `package-import-method=hardlink`.
+    ImportHardlink,
+    /// Benchmark install with a frozen lockfile and `package-import-method=copy`.
+    ImportCopy,
+    /// Benchmark install with a frozen lockfile and `package-import-method=clone`.
+    ImportClone,
+    /// Benchmark install with a frozen lockfile and `package-import-method=clone-or-copy`.
+    ImportCloneOrCopy,
 }
 
 impl BenchmarkScenario {
+    /// Whether the scenario installs from a frozen lockfile.
+    fn frozen_lockfile(self) -> bool {
+        !matches!(self, BenchmarkScenario::CleanInstall)
+    }
+
     /// Infer CLI arguments for the install command.
     pub fn install_args(self) -> impl IntoIterator<Item = &'static str> {
-        match self {
-            BenchmarkScenario::CleanInstall => Vec::new(),
-            BenchmarkScenario::FrozenLockfile => vec!["--frozen-lockfile"],
-        }
+        self.frozen_lockfile().then_some("--frozen-lockfile")
     }
 
     /// Return `lockfile=true` or `lockfile=false` for use in generating `.npmrc`.
     pub fn npmrc_lockfile_setting(self) -> &'static str {
+        if self.frozen_lockfile() {
+            "lockfile=true"
+        } else {
+            "lockfile=false"
+        }
+    }
+
+    /// Value of `package-import-method` for use in generating `.npmrc`, if it should not be the default.
+    pub fn npmrc_package_import_method(self) -> Option<&'static str> {
         match self {
-            BenchmarkScenario::CleanInstall => "lockfile=false",
-            BenchmarkScenario::FrozenLockfile => "lockfile=true",
+            BenchmarkScenario::CleanInstall
+            | BenchmarkScenario::FrozenLockfile
+            | BenchmarkScenario::SeparateVirtualStore => None,
+            BenchmarkScenario::ImportHardlink => Some("hardlink"),
+            BenchmarkScenario::ImportCopy => Some("copy"),
+            BenchmarkScenario::ImportClone => Some("clone"),
+            BenchmarkScenario::ImportCloneOrCopy => Some("clone-or-copy"),
+        }
+    }
+
+    /// Name of the virtual store directory relative to the bench directory, if it should not be the default.
+    pub fn virtual_store_dir(self) -> Option<&'static str> {
+        match self {
+            BenchmarkScenario::SeparateVirtualStore => Some("virtual-store-dir"),
+            _ => None,
         }
     }
 
@@ -72,10 +122,7 @@ impl BenchmarkScenario {
         Text: Into<String>,
         LoadLockfile: FnOnce() -> Text,
     {
-        match self {
-            BenchmarkScenario::CleanInstall => None,
-            BenchmarkScenario::FrozenLockfile => load_lockfile().into().pipe(Some),
-        }
+        self.frozen_lockfile().then(|| load_lockfile().into())
     }
 }
 
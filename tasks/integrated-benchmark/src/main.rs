@@ -15,6 +15,9 @@ async fn main() {
         fixture_dir,
         hyperfine_options,
         work_env,
+        store_dir,
+        virtual_store_dir,
+        network_concurrency,
         with_pnpm,
         revisions,
     } = clap::Parser::parse();
@@ -23,6 +26,14 @@ async fn main() {
         std::fs::create_dir_all(&work_env).expect("create work env");
     }
     let work_env = std::fs::canonicalize(work_env).expect("get absolute path to work env");
+    let store_dir = store_dir.map(|store_dir| {
+        std::fs::create_dir_all(&store_dir).expect("create store dir");
+        std::fs::canonicalize(store_dir).expect("get absolute path to store dir")
+    });
+    let virtual_store_dir = virtual_store_dir.map(|virtual_store_dir| {
+        std::fs::create_dir_all(&virtual_store_dir).expect("create virtual store dir");
+        std::fs::canonicalize(virtual_store_dir).expect("get absolute path to virtual store dir")
+    });
     let registry = format!("http://localhost:{registry_port}/");
     let verdaccio = if verdaccio {
         verify::ensure_program("just").arg("install").pipe(verify::executor("just install"));
@@ -56,6 +67,9 @@ async fn main() {
         scenario,
         hyperfine_options,
         fixture_dir,
+        store_dir,
+        virtual_store_dir,
+        network_concurrency,
     }
     .run();
     drop(verdaccio); // terminate verdaccio if exists
@@ -27,6 +27,9 @@ pub struct WorkEnv {
     pub scenario: BenchmarkScenario,
     pub hyperfine_options: HyperfineOptions,
     pub fixture_dir: Option<PathBuf>,
+    pub store_dir: Option<PathBuf>,
+    pub virtual_store_dir: Option<PathBuf>,
+    pub network_concurrency: Vec<usize>,
 }
 
 impl WorkEnv {
@@ -42,7 +45,16 @@ impl WorkEnv {
     }
 
     fn revision_ids(&self) -> impl Iterator<Item = BenchId<'_>> + '_ {
-        self.revision_names().map(BenchId::PacquetRevision)
+        self.revision_names().flat_map(|revision| -> Vec<_> {
+            if self.network_concurrency.is_empty() {
+                vec![BenchId::PacquetRevision(revision)]
+            } else {
+                self.network_concurrency
+                    .iter()
+                    .map(|&value| BenchId::PacquetNetworkConcurrency(revision, value))
+                    .collect()
+            }
+        })
     }
 
     fn registry(&self) -> &'_ str {
@@ -57,6 +69,20 @@ impl WorkEnv {
         self.root().join(id.to_string())
     }
 
+    fn store_dir(&self, id: BenchId) -> PathBuf {
+        match &self.store_dir {
+            Some(store_dir) => store_dir.join(id.to_string()),
+            None => self.bench_dir(id).join("store-dir"),
+        }
+    }
+
+    fn virtual_store_dir(&self, id: BenchId) -> Option<PathBuf> {
+        match &self.virtual_store_dir {
+            Some(virtual_store_dir) => virtual_store_dir.join(id.to_string()).pipe(Some),
+            None => self.scenario.virtual_store_dir().map(|name| self.bench_dir(id).join(name)),
+        }
+    }
+
     fn script_path(&self, id: BenchId) -> PathBuf {
         self.bench_dir(id).join("install.bash")
     }
@@ -71,6 +97,18 @@ impl WorkEnv {
         self.bench_dir(BenchId::PacquetRevision(revision)).join("pacquet")
     }
 
+    fn install_command(&self, id: BenchId) -> String {
+        match id {
+            BenchId::Static(_) => "pnpm".to_string(),
+            BenchId::PacquetRevision(_) => "./pacquet/target/release/pacquet".to_string(),
+            BenchId::PacquetNetworkConcurrency(revision, _) => self
+                .revision_repo(revision)
+                .join("target/release/pacquet")
+                .maybe_quote()
+                .to_string(),
+        }
+    }
+
     fn resolve_revision(&self, revision: &str) -> String {
         let output = Command::new("git")
             .current_dir(self.repository())
@@ -99,11 +137,21 @@ impl WorkEnv {
         for id in id_list {
             eprintln!("ID: {id}");
             let dir = self.bench_dir(id);
-            let for_pnpm = matches!(id, BenchId::Static(_));
+            let network_concurrency = match id {
+                BenchId::PacquetNetworkConcurrency(_, value) => Some(value),
+                BenchId::PacquetRevision(_) | BenchId::Static(_) => None,
+            };
             fs::create_dir_all(&dir).expect("create directory for the revision");
             create_package_json(&dir, self.fixture_dir.as_deref());
-            create_install_script(&dir, self.scenario, for_pnpm);
-            create_npmrc(&dir, self.registry(), self.scenario);
+            create_install_script(&dir, self.scenario, &self.install_command(id));
+            create_npmrc(
+                &dir,
+                self.registry(),
+                self.scenario,
+                &self.store_dir(id),
+                self.virtual_store_dir(id).as_deref(),
+                network_concurrency,
+            );
             may_create_lockfile(&dir, self.scenario, self.fixture_dir.as_deref());
         }
 
@@ -174,8 +222,11 @@ impl WorkEnv {
     fn benchmark(&self) {
         let cleanup_targets = self
             .revision_ids()
-            .map(|revision| self.bench_dir(revision))
-            .flat_map(|revision| [revision.join("node_modules"), revision.join("store-dir")])
+            .flat_map(|revision| {
+                [self.bench_dir(revision).join("node_modules"), self.store_dir(revision)]
+                    .into_iter()
+                    .chain(self.virtual_store_dir(revision))
+            })
             .map(|path| path.maybe_quote().to_string())
             .join(" ");
         let cleanup_command = format!("rm -rf {cleanup_targets}");
@@ -217,17 +268,34 @@ fn create_package_json(dst_dir: &Path, src_dir: Option<&Path>) {
     }
 }
 
-fn create_npmrc(dir: &Path, registry: &str, scenario: BenchmarkScenario) {
+fn create_npmrc(
+    dir: &Path,
+    registry: &str,
+    scenario: BenchmarkScenario,
+    store_dir: &Path,
+    virtual_store_dir: Option<&Path>,
+    network_concurrency: Option<usize>,
+) {
     let path = dir.join(".npmrc");
-    let store_dir = dir.join("store-dir");
     let store_dir = store_dir.to_str().expect("path to store-dir is valid UTF-8");
     eprintln!("Creating config file {path:?}...");
     let mut file = File::create(path).expect("create .npmrc");
     writeln!(file, "registry={registry}").unwrap();
     writeln!(file, "store-dir={store_dir}").unwrap();
+    if let Some(virtual_store_dir) = virtual_store_dir {
+        let virtual_store_dir =
+            virtual_store_dir.to_str().expect("path to virtual-store-dir is valid UTF-8");
+        writeln!(file, "virtual-store-dir={virtual_store_dir}").unwrap();
+    }
     writeln!(file, "auto-install-peers=false").unwrap();
     writeln!(file, "ignore-scripts=true").unwrap();
     writeln!(file, "{}", scenario.npmrc_lockfile_setting()).unwrap();
+    if let Some(package_import_method) = scenario.npmrc_package_import_method() {
+        writeln!(file, "package-import-method={package_import_method}").unwrap();
+    }
+    if let Some(network_concurrency) = network_concurrency {
+        writeln!(file, "network-concurrency={network_concurrency}").unwrap();
+    }
 }
 
 fn may_create_lockfile(dst_dir: &Path, scenario: BenchmarkScenario, src_dir: Option<&Path>) {
@@ -245,7 +313,7 @@ fn may_create_lockfile(dst_dir: &Path, scenario: BenchmarkScenario, src_dir: Opt
     }
 }
 
-fn create_install_script(dir: &Path, scenario: BenchmarkScenario, for_pnpm: bool) {
+fn create_install_script(dir: &Path, scenario: BenchmarkScenario, command: &str) {
     let path = dir.join("install.bash");
 
     eprintln!("Creating script {path:?}...");
@@ -255,7 +323,6 @@ fn create_install_script(dir: &Path, scenario: BenchmarkScenario, for_pnpm: bool
     writeln!(file, "set -o errexit -o nounset -o pipefail").unwrap();
     writeln!(file, r#"cd "$(dirname "$0")""#).unwrap();
 
-    let command = if for_pnpm { "pnpm" } else { "./pacquet/target/release/pacquet" };
     write!(file, "exec {command} install").unwrap();
     for arg in scenario.install_args() {
         write!(file, " {arg}").unwrap();
@@ -268,6 +335,7 @@ fn create_install_script(dir: &Path, scenario: BenchmarkScenario, for_pnpm: bool
 #[derive(Debug, Clone, Copy)]
 enum BenchId<'a> {
     PacquetRevision(&'a str),
+    PacquetNetworkConcurrency(&'a str, usize),
     Static(&'a str),
 }
 
@@ -275,6 +343,9 @@ impl<'a> fmt::Display for BenchId<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             BenchId::PacquetRevision(revision) => write!(f, "pacquet@{revision}"),
+            BenchId::PacquetNetworkConcurrency(revision, value) => {
+                write!(f, "pacquet@{revision}+network-concurrency={value}")
+            }
             BenchId::Static(name) => write!(f, "{name}"),
         }
     }
@@ -5,7 +5,7 @@ use criterion::{Criterion, Throughput};
 use mockito::ServerGuard;
 use pacquet_network::ThrottledClient;
 use pacquet_store_dir::StoreDir;
-use pacquet_tarball::DownloadTarballToStore;
+use pacquet_tarball::{DownloadTarballToStore, RetryOpts, UnpackedSizeLimit};
 use pipe_trait::Pipe;
 use project_root::get_project_root;
 use ssri::Integrity;
@@ -42,6 +42,9 @@ fn bench_tarball(c: &mut Criterion, server: &mut ServerGuard, fixtures_folder: &
                 package_integrity: &package_integrity,
                 package_unpacked_size: Some(16697),
                 package_url: url,
+                retry_opts: RetryOpts::default(),
+                unpacked_size_limit: UnpackedSizeLimit::default(),
+                on_progress: None,
             }
             .run_without_mem_cache()
             .await
@@ -0,0 +1,126 @@
+use mockito::{Matcher, Mock, Server, ServerGuard};
+use serde_json::Value;
+
+/// Lightweight registry server whose packuments and tarballs are injected by each test.
+///
+/// Unlike [`AutoMockInstance`](crate::AutoMockInstance), which serves the fixtures of
+/// `@pnpm/registry-mock`, this server only responds to what was registered, allowing
+/// tests to control the exact metadata (versions, dist-tags, integrity, etc.).
+///
+/// The server is shut down on [drop](Drop).
+#[derive(Debug)]
+#[must_use]
+pub struct CustomRegistry {
+    server: ServerGuard,
+    mocks: Vec<Mock>,
+}
+
+impl CustomRegistry {
+    /// Start an empty registry server.
+    pub async fn new() -> Self {
+        let server = Server::new_async().await;
+        CustomRegistry { server, mocks: Vec::new() }
+    }
+
+    /// URL of the registry, with a trailing slash.
+    pub fn url(&self) -> String {
+        format!("{}/", self.server.url())
+    }
+
+    /// Serve `body` with `content_type` at `path` which is relative to the registry URL.
+    ///
+    /// Return the URL of the served resource.
+    async fn serve(&mut self, path: &str, content_type: &str, body: Vec<u8>) -> String {
+        let mock = self
+            .server
+            .mock("GET", format!("/{path}").as_str())
+            .with_status(200)
+            .with_header("content-type", content_type)
+            .with_body(body)
+            .create_async()
+            .await;
+        self.mocks.push(mock);
+        format!("{}{path}", self.url())
+    }
+
+    /// Serve the packument of a package.
+    ///
+    /// Return the URL of the packument.
+    pub async fn add_packument(&mut self, name: &str, packument: &Value) -> String {
+        let body = serde_json::to_vec(packument).expect("serialize packument");
+        self.serve(name, "application/json", body).await
+    }
+
+    /// Serve the packument of a package with an `ETag` header, responding with `304 Not Modified`
+    /// to requests whose `If-None-Match` header matches `etag`.
+    ///
+    /// Return the URL of the packument.
+    pub async fn add_packument_with_etag(
+        &mut self,
+        name: &str,
+        packument: &Value,
+        etag: &str,
+    ) -> String {
+        let path = format!("/{name}");
+        let not_modified = self
+            .server
+            .mock("GET", path.as_str())
+            .match_header("if-none-match", etag)
+            .with_status(304)
+            .create_async()
+            .await;
+        self.mocks.push(not_modified);
+        let body = serde_json::to_vec(packument).expect("serialize packument");
+        let mock = self
+            .server
+            .mock("GET", path.as_str())
+            .match_header("if-none-match", Matcher::Missing)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header("etag", etag)
+            .with_body(body)
+            .create_async()
+            .await;
+        self.mocks.push(mock);
+        format!("{}{name}", self.url())
+    }
+
+    /// Serve the packument of a package like a registry that doesn't support abbreviated metadata,
+    /// which responds with `406 Not Acceptable` to requests that prefer it.
+    ///
+    /// Return the URL of the packument.
+    pub async fn add_full_packument(&mut self, name: &str, packument: &Value) -> String {
+        let path = format!("/{name}");
+        let rejection = self
+            .server
+            .mock("GET", path.as_str())
+            .match_header(
+                "accept",
+                Matcher::Regex("application/vnd\\.npm\\.install-v1\\+json".into()),
+            )
+            .with_status(406)
+            .create_async()
+            .await;
+        self.mocks.push(rejection);
+        let body = serde_json::to_vec(packument).expect("serialize packument");
+        let mock = self
+            .server
+            .mock("GET", path.as_str())
+            .match_header("accept", "application/json")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create_async()
+            .await;
+        self.mocks.push(mock);
+        format!("{}{name}", self.url())
+    }
+
+    /// Serve a tarball under `-/{file_name}`.
+    ///
+    /// Return the URL of the tarball, which is meant to be used as `dist.tarball` of a packument.
+    pub async fn add_tarball(&mut self, file_name: &str, tarball: Vec<u8>) -> String {
+        let path = format!("-/{file_name}");
+        self.serve(&path, "application/octet-stream", tarball).await
+    }
+}
@@ -1,3 +1,4 @@
+mod custom_registry;
 mod dirs;
 mod kill_verdaccio;
 mod mock_instance;
@@ -6,6 +7,7 @@ mod port_to_url;
 mod registry_anchor;
 mod registry_info;
 
+pub use custom_registry::*;
 pub use dirs::*;
 pub use mock_instance::*;
 pub use node_registry_mock::*;